@@ -28,6 +28,52 @@ fn main() {
         "list_cloud_dashboards",
         "get_cloud_dashboard",
         "download_cloud_workspace",
+        "wait_for_services_ready",
+        "get_local_credentials",
+        "set_custom_vm_image",
+        "clear_custom_vm_image",
+        "active_imports",
+        "get_sandbox_status",
+        "note_sandbox_activity",
+        "get_guest_info",
+        "get_resource_info",
+        "get_setting",
+        "set_setting",
+        "reset_controlplane_db",
+        "put_sandbox_file",
+        "prepare_clear_workspace",
+        "clear_workspace",
+        "get_startup_timings",
+        "convert_vm_image",
+        "get_wsl_distros",
+        "prewarm_resources",
+        "get_data_dir_usage",
+        "prune_data_dir",
+        "preview_import_tree",
+        "relocate_workspace",
+        "validate_boot_set",
+        "cancel_import",
+        "suspend_all",
+        "resume_all",
+        "aggregate_health",
+        "sandbox_exec_streaming",
+        "get_vm_config",
+        "set_dev_flags",
+        "get_supported_formats",
+        "restart_sandbox_vm",
+        "benchmark_workspace_io",
+        "set_qemu_accel",
+        "get_vm_metrics",
+        "list_guest_processes",
+        "kill_guest_process",
+        "fetch_and_stage_image",
+        "cancel_image_fetch",
+        "export_folder",
+        "cancel_export",
+        "active_exports",
+        "set_bind_address",
+        "cancel_sandbox_exec",
+        "set_vm_cpu_topology",
       ]),
     ),
   )
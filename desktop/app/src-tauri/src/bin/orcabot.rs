@@ -1,4 +1,4 @@
-// REVISION: orcabot-cli-v24-import-stage-then-merge
+// REVISION: orcabot-cli-v25-guest-info-and-phase-timings
 //
 // `orcabot` — command-line control for the Orcabot desktop stack.
 //
@@ -57,7 +57,7 @@ const DEFAULT_CONTROLPLANE_PORT: u16 = 8787;
 const DEFAULT_SANDBOX_PORT: u16 = 8080;
 const DEFAULT_FRONTEND_PORT: u16 = 8788;
 const VZ_CONSOLE_LOG: &str = "/tmp/vz-console.log";
-const REVISION: &str = "orcabot-cli-v24-import-stage-then-merge";
+const REVISION: &str = "orcabot-cli-v25-guest-info-and-phase-timings";
 
 pub fn run() {
     let args: Vec<String> = std::env::args().collect();
@@ -460,8 +460,14 @@ fn cmd_down() -> i32 {
 // locally and in the cloud — so `push`/`pull` will reuse this, just retargeted.
 // Secrets/integrations are intentionally NOT included (re-add on the destination).
 
+/// `<data_dir>/workspace`, or where the desktop app's `relocate_workspace` put
+/// it (`settings.json` `workspace_dir`).
 fn workspace_dir() -> PathBuf {
-    data_dir().join("workspace")
+    std::fs::read(data_dir().join("settings.json"))
+        .ok()
+        .and_then(|b| serde_json::from_slice::<serde_json::Value>(&b).ok())
+        .and_then(|v| v.get("workspace_dir")?.as_str().map(PathBuf::from))
+        .unwrap_or_else(|| data_dir().join("workspace"))
 }
 
 fn cmd_export(rest: &[String]) -> i32 {
@@ -1994,6 +2000,33 @@ fn cmd_status() -> i32 {
         }
     );
     println!("frontend     (:{}): {}", fe_port(), if fe { "ready" } else { "down" });
+    // Guest kernel + OS, when debug-exec is reachable (stack started via `orcabot up`).
+    if sb.is_some() && read_debug_token().is_some() {
+        let out = run_in_vm("uname -srm; sed -n 's/^PRETTY_NAME=//p' /etc/os-release | tr -d '\"'");
+        let guest: Vec<&str> = out.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        println!("guest        (vm): {}", guest.join(", "));
+    }
+    // Per-phase durations of the backend's last launch (see get_startup_timings).
+    if let Some(phases) = fs::read(data_dir().join("startup-timings.json"))
+        .ok()
+        .and_then(|b| serde_json::from_slice::<Vec<serde_json::Value>>(&b).ok())
+    {
+        let parts: Vec<String> = phases
+            .iter()
+            .filter_map(|p| {
+                let failed = p.get("ok").and_then(|v| v.as_bool()) == Some(false);
+                Some(format!(
+                    "{} {}ms{}",
+                    p.get("phase")?.as_str()?,
+                    p.get("duration_ms")?.as_u64()?,
+                    if failed { " (failed)" } else { "" }
+                ))
+            })
+            .collect();
+        if !parts.is_empty() {
+            println!("startup    (timing): {}", parts.join(", "));
+        }
+    }
     if cp && sb.is_some() {
         0
     } else {
@@ -1,23 +1,85 @@
 // Copyright 2026 Rob Macrae. All rights reserved.
 // SPDX-License-Identifier: LicenseRef-Proprietary
 
-// REVISION: folder-import-v10-cloud-workspace-walk
-const MODULE_REVISION: &str = "folder-import-v10-cloud-workspace-walk";
+// REVISION: folder-import-v71-ignored-dirs-count-once
+const MODULE_REVISION: &str = "folder-import-v71-ignored-dirs-count-once";
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::path::{Component, Path, PathBuf};
 use tauri::Emitter;
 use walkdir::WalkDir;
 
 /// Managed state holding the workspace directory path.
 pub struct WorkspaceState {
-    pub workspace_path: PathBuf,
+    /// Replaced by `relocate_workspace`; read it through `path`/`available_path`.
+    workspace_path: std::sync::RwLock<PathBuf>,
+    /// In-flight imports by `import_id`, with their latest progress snapshot.
+    /// Updated on every progress emit; entries are dropped when an import ends.
+    pub active_imports: std::sync::Mutex<std::collections::HashMap<String, ImportProgress>>,
+    /// In-flight exports by `export_id`, with their latest progress (as
+    /// `active_imports`).
+    pub active_exports: std::sync::Mutex<std::collections::HashMap<String, ExportProgress>>,
+    /// Cancellation flags of in-flight imports and exports, by `import_id` /
+    /// `export_id` (`cancel_import`, `cancel_export`).
+    transfer_cancels:
+        std::sync::Mutex<std::collections::HashMap<String, std::sync::Arc<ImportCancel>>>,
+    /// Outstanding `prepare_clear_workspace` token and when it was issued.
+    clear_token: std::sync::Mutex<Option<(String, std::time::Instant)>>,
+    /// Set while `clear_workspace` runs; new imports refuse to start.
+    clearing: std::sync::atomic::AtomicBool,
+    /// Set while `relocate_workspace` runs; imports and clears refuse to start.
+    relocating: std::sync::atomic::AtomicBool,
+}
+
+impl WorkspaceState {
+    pub fn new(workspace_path: PathBuf) -> Self {
+        Self {
+            workspace_path: std::sync::RwLock::new(workspace_path),
+            active_imports: Default::default(),
+            active_exports: Default::default(),
+            transfer_cancels: Default::default(),
+            clear_token: Default::default(),
+            clearing: Default::default(),
+            relocating: Default::default(),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.workspace_path
+            .read()
+            .map(|p| p.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+
+    /// The workspace directory, or why it can't be used right now: not
+    /// configured, or a `WorkspaceUnavailable` error (its drive is disconnected
+    /// or the directory is gone).
+    pub fn available_path(&self) -> Result<PathBuf, String> {
+        let path = self.path();
+        if path.as_os_str().is_empty() {
+            return Err("Workspace path not configured".to_string());
+        }
+        crate::workspace::check_available(&path).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    /// Imports and exports still running; clearing or moving the workspace
+    /// waits for them.
+    fn active_transfers(&self) -> usize {
+        let imports = self.active_imports.lock().map(|a| a.len()).unwrap_or(0);
+        let exports = self.active_exports.lock().map(|a| a.len()).unwrap_or(0);
+        imports + exports
+    }
 }
 
 #[derive(Serialize, Clone)]
 pub struct WorkspaceInfo {
     pub path: String,
     pub exists: bool,
+    /// The `WorkspaceUnavailable` message when the workspace can't be used, with
+    /// the disconnected drive in `volume` if that is the cause.
+    pub unavailable: Option<String>,
+    pub volume: Option<String>,
 }
 
 #[derive(Serialize, Clone)]
@@ -26,7 +88,38 @@ pub struct ImportResult {
     pub files_copied: u64,
     pub bytes_copied: u64,
     pub dest_path: String,
+    /// Sorted and de-duplicated; files under a directory that couldn't be
+    /// created share one "dir ..." entry. At most `MAX_IMPORT_ERRORS` entries.
     pub errors: Vec<String>,
+    /// Distinct errors before the cap (`errors_truncated` when more than were
+    /// returned).
+    pub errors_total: u64,
+    pub errors_truncated: bool,
+    /// Files skipped by `modified_since` (mtime older than the cutoff).
+    pub files_not_modified: u64,
+    /// Files left alone because the destination already had them
+    /// (`ImportConflictMode::Skip`).
+    pub files_skipped: u64,
+    /// Entries left out by `ignore_patterns`: each ignored file, and each
+    /// ignored directory once (its contents are never walked, so not counted).
+    pub files_ignored: u64,
+    /// Source files whose paths differ only by case from an earlier one, or
+    /// from a file already at the destination ("path (destination has NAME)"),
+    /// on a case-insensitive destination: skipped ("path"), or with
+    /// `case_collisions: "rename"`, copied under a new name ("path -> new path").
+    pub collisions: Vec<String>,
+    /// The `case_collisions: "rename"` renames as source path → destination
+    /// path, both relative to the import root.
+    pub case_renames: std::collections::BTreeMap<String, String>,
+    /// FIFOs, sockets and device nodes found in the source, which are never
+    /// copied (reading a FIFO would block the import): "path (fifo)".
+    pub special_files: Vec<String>,
+    /// Whether copies were fsynced before being reported (`durable` option).
+    pub durable: bool,
+    /// Stopped by `cancel_import`: the counts cover only what was copied
+    /// before it, and empty directories, `preserve_dir_times` and `verify`
+    /// were skipped.
+    pub cancelled: bool,
 }
 
 #[derive(Serialize, Clone)]
@@ -34,8 +127,109 @@ pub struct ImportProgress {
     pub import_id: String,
     pub processed: u64,
     pub total: u64,
+    /// Bytes of the files dealt with so far and the size of every file the scan
+    /// found, so a folder of a few huge files still shows movement between
+    /// files. A file skipped, refused or failed counts with its scanned size,
+    /// so the two meet at the end.
+    pub bytes_processed: u64,
+    pub bytes_total: u64,
+    /// Estimated seconds left at the copy's byte throughput so far; only on
+    /// "copying" events, once there is enough to go on.
+    pub eta_secs: Option<u64>,
+    pub current_file: String,
+    /// `current_file`'s exact bytes, percent-encoded (see [`raw_path`]), when
+    /// the name is not valid UTF-8 and `current_file` had to replace bytes.
+    pub current_file_raw: Option<String>,
+    /// One of the `PHASE_*` values.
+    pub phase: String,
+}
+
+impl ImportResult {
+    /// A result with nothing copied into `dest`, for `do_import`'s early
+    /// returns to fill in with struct update syntax.
+    fn empty(import_id: &str, dest: &Path, durable: bool) -> Self {
+        Self {
+            import_id: import_id.to_string(),
+            files_copied: 0,
+            bytes_copied: 0,
+            dest_path: dest.display().to_string(),
+            errors: vec![],
+            errors_total: 0,
+            errors_truncated: false,
+            files_not_modified: 0,
+            files_skipped: 0,
+            files_ignored: 0,
+            collisions: vec![],
+            case_renames: Default::default(),
+            special_files: vec![],
+            durable,
+            cancelled: false,
+        }
+    }
+}
+
+/// `ImportProgress::phase` and `ExportProgress::phase` values, in the order a
+/// transfer goes through them. The frontend matches on these strings.
+const PHASE_SCANNING: &str = "scanning";
+const PHASE_COPYING: &str = "copying";
+/// Import only, with `verify`.
+const PHASE_VERIFYING: &str = "verifying";
+const PHASE_DONE: &str = "done";
+const PHASE_ERROR: &str = "error";
+/// Stopped by `cancel_import` / `cancel_export`.
+const PHASE_CANCELLED: &str = "cancelled";
+
+/// Whether `phase` ends a transfer: no more events follow it.
+fn is_terminal_phase(phase: &str) -> bool {
+    matches!(phase, PHASE_DONE | PHASE_ERROR | PHASE_CANCELLED)
+}
+
+/// What `import_folder` does with a file the destination already has.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportConflictMode {
+    /// Replace it (merge with overwrite).
+    #[default]
+    Overwrite,
+    /// Keep it and count the source file in `ImportResult::files_skipped`.
+    Skip,
+    /// Keep it and write the source file as `name (1).ext`, `name (2).ext`, ...
+    Rename,
+}
+
+/// Result of `export_folder`; the counterpart of [`ImportResult`].
+#[derive(Serialize, Clone)]
+pub struct ExportResult {
+    pub export_id: String,
+    pub files_copied: u64,
+    pub bytes_copied: u64,
+    pub dest_path: String,
+    /// As in [`ImportResult::errors`].
+    pub errors: Vec<String>,
+    pub errors_total: u64,
+    pub errors_truncated: bool,
+    /// Symlinks in the exported tree, which are neither followed nor copied.
+    pub symlinks_skipped: u64,
+    /// FIFOs, sockets and device nodes, which are never copied: "path (fifo)".
+    pub special_files: Vec<String>,
+    /// Stopped by `cancel_export`: the counts cover only what was copied
+    /// before it, and empty directories were not created.
+    pub cancelled: bool,
+}
+
+/// A `folder-export-progress` event; fields as in [`ImportProgress`].
+#[derive(Serialize, Clone)]
+pub struct ExportProgress {
+    pub export_id: String,
+    pub processed: u64,
+    pub total: u64,
+    pub bytes_processed: u64,
+    pub bytes_total: u64,
+    pub eta_secs: Option<u64>,
     pub current_file: String,
-    pub phase: String, // "scanning" | "copying" | "done" | "error"
+    pub current_file_raw: Option<String>,
+    /// One of the `PHASE_*` values.
+    pub phase: String,
 }
 
 /// Validate that a subpath is safe to join under a root directory.
@@ -46,7 +240,7 @@ fn validate_subpath(subpath: &str) -> Result<PathBuf, String> {
     // Reject absolute paths
     if path.is_absolute() {
         return Err(format!(
-            "Destination subpath must be relative, got absolute: {}",
+            "Subpath must be relative, got absolute: {}",
             subpath
         ));
     }
@@ -56,13 +250,13 @@ fn validate_subpath(subpath: &str) -> Result<PathBuf, String> {
         match component {
             Component::ParentDir => {
                 return Err(format!(
-                    "Destination subpath must not contain '..': {}",
+                    "Subpath must not contain '..': {}",
                     subpath
                 ));
             }
             Component::Prefix(_) => {
                 return Err(format!(
-                    "Destination subpath must not contain drive prefixes: {}",
+                    "Subpath must not contain drive prefixes: {}",
                     subpath
                 ));
             }
@@ -141,32 +335,65 @@ fn ensure_within_workspace(dest: &Path, workspace: &Path) -> Result<(), String>
 /// path is still within the workspace. This is the safe sequence: validate
 /// first with ensure_within_workspace (no side effects), then create dirs,
 /// then re-verify the canonical path hasn't escaped via a TOCTOU race.
-fn safe_create_parent_dirs(dest: &Path, workspace: &Path) -> Result<(), String> {
+///
+/// The error carries the directory that failed: the topmost one that couldn't
+/// be created, or the parent itself when it escaped or couldn't be resolved.
+fn safe_create_parent_dirs(dest: &Path, workspace: &Path) -> Result<(), (PathBuf, String)> {
     if let Some(parent) = dest.parent() {
-        std::fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+        std::fs::create_dir_all(parent).map_err(|e| {
+            let failed = first_missing_dir(parent);
+            let message = format!("Failed to create directory {}: {}", failed.display(), e);
+            (failed.to_path_buf(), message)
+        })?;
+        let failed = |e: String| (parent.to_path_buf(), e);
 
         // Post-creation verification: canonicalize and check containment
         let canonical_workspace = workspace
             .canonicalize()
-            .map_err(|e| format!("Cannot resolve workspace: {}", e))?;
+            .map_err(|e| failed(format!("Cannot resolve workspace: {}", e)))?;
         let canonical_parent = parent
             .canonicalize()
-            .map_err(|e| format!("Cannot resolve created parent: {}", e))?;
+            .map_err(|e| failed(format!("Cannot resolve created parent: {}", e)))?;
 
         if !canonical_parent.starts_with(&canonical_workspace) {
             // Clean up the escaped directory
             let _ = std::fs::remove_dir_all(parent);
-            return Err(format!(
+            return Err(failed(format!(
                 "Created directory {} resolves outside workspace to {}",
                 parent.display(),
                 canonical_parent.display()
-            ));
+            )));
         }
     }
     Ok(())
 }
 
+/// The topmost missing ancestor of `dir` (or `dir` itself), i.e. the first
+/// directory a failed `create_dir_all(dir)` would have had to create.
+fn first_missing_dir(dir: &Path) -> &Path {
+    topmost_missing_dir(dir).unwrap_or(dir)
+}
+
+/// The topmost missing ancestor of `dir` (or `dir` itself); `None` if `dir`
+/// exists.
+fn topmost_missing_dir(dir: &Path) -> Option<&Path> {
+    dir.ancestors()
+        .take_while(|d| std::fs::symlink_metadata(d).is_err())
+        .last()
+}
+
+/// The directories that gained an entry when `top` down to `deepest` (`top`
+/// or a descendant of it) were created: `top`'s parent and each new directory
+/// above `deepest`. A durable import syncs these as well, or a power cut could
+/// take a new directory, with every file already reported in it, back out.
+fn new_dir_parents<'a>(top: &'a Path, deepest: &'a Path) -> impl Iterator<Item = &'a Path> {
+    deepest
+        .ancestors()
+        .skip(1)
+        .take_while(move |d| d.starts_with(top))
+        .chain(top.parent())
+}
+
 /// Create a directory (and parents) within the workspace, then verify containment.
 /// Catches TOCTOU races where a parent is swapped to a symlink between
 /// ensure_within_workspace and the actual mkdir.
@@ -193,37 +420,184 @@ fn safe_create_dir(dir: &Path, workspace: &Path) -> Result<(), String> {
     Ok(())
 }
 
-/// Copy a file without following symlinks at the destination.
+/// Sibling temp path for `safe_copy_file`: same directory (so the final rename
+/// stays on one filesystem and is atomic), hidden, and unique per call. The
+/// name is short and fixed-form rather than derived from `dest`'s, which may
+/// already be near NAME_MAX.
+fn temp_sibling(dest: &Path) -> PathBuf {
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    dest.with_file_name(format!(".orcabot-tmp-{}-{}", std::process::id(), n))
+}
+
+/// Permissions for a temp file about to replace `dest`: those of the file it
+/// replaces, as an in-place overwrite would have kept them. `None` for a new
+/// destination (or anything that isn't a regular file).
+fn replaced_permissions(dest: &Path) -> Option<std::fs::Permissions> {
+    std::fs::symlink_metadata(dest)
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.permissions())
+}
+
+/// The exact bytes of a non-UTF-8 `path` (WTF-8 on Windows), percent-encoded:
+/// printable ASCII other than `%` is kept and every other byte becomes `%XX`.
+/// `None` for valid UTF-8, which the lossy display already shows exactly.
+fn raw_path(path: &Path) -> Option<String> {
+    path.to_str().is_none().then(|| {
+        path.as_os_str()
+            .as_encoded_bytes()
+            .iter()
+            .map(|&b| match b {
+                b'%' => "%25".to_string(),
+                0x20..=0x7e => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    })
+}
+
+/// `path` as import reports name it. Lossy conversion maps different
+/// non-UTF-8 names (Latin-1, Shift-JIS, ...) to the same U+FFFD string, so
+/// those also carry their raw bytes: `caf\u{FFFD}.txt [raw: caf%E9.txt]`.
+fn path_label(path: &Path) -> String {
+    match raw_path(path) {
+        Some(raw) => format!("{} [raw: {}]", path.display(), raw),
+        None => path.display().to_string(),
+    }
+}
+
+/// What kind of non-regular file this is, or None for regular files, dirs and
+/// symlinks. Imports skip these: a FIFO blocks the reader until something
+/// writes to it, and sockets and device nodes have no content to copy.
+fn special_file_kind(file_type: &std::fs::FileType) -> Option<&'static str> {
+    if file_type.is_file() || file_type.is_dir() || file_type.is_symlink() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return Some("fifo");
+        }
+        if file_type.is_socket() {
+            return Some("socket");
+        }
+        if file_type.is_block_device() || file_type.is_char_device() {
+            return Some("device");
+        }
+    }
+    Some("special file")
+}
+
+/// Copy a file without following symlinks at the destination. Refuses a source
+/// that isn't a regular file, including (on Unix) a symlink to one.
+///
+/// Writes to a sibling temp file and renames it over `dest` only once the copy
+/// completes, so an interrupted merge import leaves the previous file intact
+/// rather than half-overwritten. On Unix the temp file is created with O_EXCL |
+/// O_NOFOLLOW, and a `dest` that is a symlink is rejected before the rename
+/// (rename would replace the link itself, but an import never should).
 ///
-/// On Unix, opens the destination with O_NOFOLLOW so that if an attacker swaps
-/// the path to a symlink between validation and write, the open fails with ELOOP
-/// instead of writing through the symlink to an arbitrary location.
+/// `durable` fsyncs the data before the rename; syncing the parent directory
+/// (so the rename itself is durable) is left to the caller, which can batch it.
 #[cfg(unix)]
-fn safe_copy_file(source: &Path, dest: &Path) -> Result<u64, String> {
-    use std::fs::{File, OpenOptions};
-    use std::io;
+fn safe_copy_file(
+    source: &Path,
+    dest: &Path,
+    durable: bool,
+    cancel: Option<&ImportCancel>,
+) -> Result<u64, String> {
+    use std::fs::OpenOptions;
     use std::os::unix::fs::OpenOptionsExt;
 
-    let mut src = File::open(source)
-        .map_err(|e| format!("Cannot open source {}: {}", source.display(), e))?;
+    // O_NONBLOCK so a FIFO swapped in after the scan can't hang the open, and
+    // O_NOFOLLOW so a symlink swapped in can't send the copy to whatever it
+    // points at (an export would carry that file out of the workspace); the
+    // fstat below then refuses anything that isn't a regular file.
+    let mut src = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+        .open(source)
+        .map_err(|e| format!("Cannot open source {} (symlink?): {}", source.display(), e))?;
+    let src_type = src
+        .metadata()
+        .map_err(|e| format!("Cannot stat source {}: {}", source.display(), e))?
+        .file_type();
+    if let Some(kind) = special_file_kind(&src_type) {
+        return Err(format!("Source is a {}: {}", kind, source.display()));
+    }
 
+    let tmp = temp_sibling(dest);
     let mut dst = OpenOptions::new()
         .write(true)
-        .create(true)
-        .truncate(true)
+        .create_new(true)
         .custom_flags(libc::O_NOFOLLOW)
-        .open(dest)
+        .open(&tmp)
         .map_err(|e| format!("Cannot open destination {} (symlink?): {}", dest.display(), e))?;
 
-    io::copy(&mut src, &mut dst)
+    let result = copy_chunked(&mut src, &mut dst, cancel)
         .map_err(|e| format!("Copy failed {}: {}", dest.display(), e))
+        .and_then(|bytes| {
+            if let Some(perms) = replaced_permissions(dest) {
+                dst.set_permissions(perms)
+                    .map_err(|e| format!("Cannot keep permissions of {}: {}", dest.display(), e))?;
+            }
+            if durable {
+                dst.sync_all()
+                    .map_err(|e| format!("Sync failed {}: {}", dest.display(), e))?;
+            }
+            drop(dst);
+            if std::fs::symlink_metadata(dest).is_ok_and(|m| m.file_type().is_symlink()) {
+                return Err(format!("Destination is a symlink: {}", dest.display()));
+            }
+            std::fs::rename(&tmp, dest)
+                .map_err(|e| format!("Cannot replace {}: {}", dest.display(), e))?;
+            Ok(bytes)
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Bytes copied between checks for a forced cancel in `copy_chunked`.
+const COPY_CHUNK: u64 = 8 * 1024 * 1024;
+
+/// `io::copy` in `COPY_CHUNK` pieces, failing between pieces once `cancel` is
+/// forced so a large file doesn't hold up a `cancel_import(force)`.
+#[cfg(unix)]
+fn copy_chunked(
+    src: &mut std::fs::File,
+    dst: &mut std::fs::File,
+    cancel: Option<&ImportCancel>,
+) -> std::io::Result<u64> {
+    use std::io::Read;
+    let mut total = 0;
+    loop {
+        if cancel.is_some_and(ImportCancel::forced) {
+            return Err(std::io::Error::other("import cancelled"));
+        }
+        let n = std::io::copy(&mut src.take(COPY_CHUNK), dst)?;
+        total += n;
+        if n < COPY_CHUNK {
+            return Ok(total);
+        }
+    }
 }
 
 /// On Windows, pre/post-check with symlink_metadata to reject junctions and
 /// reparse points. Not perfectly race-free but narrows the TOCTOU window
-/// significantly combined with the caller's containment checks.
+/// significantly combined with the caller's containment checks. The copy goes
+/// through a sibling temp file renamed over `dest`, as on Unix. `std::fs::copy`
+/// can't be interrupted, so a forced cancel waits for the current file here.
 #[cfg(windows)]
-fn safe_copy_file(source: &Path, dest: &Path) -> Result<u64, String> {
+fn safe_copy_file(
+    source: &Path,
+    dest: &Path,
+    durable: bool,
+    _cancel: Option<&ImportCancel>,
+) -> Result<u64, String> {
     // Pre-check: reject if destination is a symlink/junction
     if let Ok(meta) = std::fs::symlink_metadata(dest) {
         if meta.file_type().is_symlink() {
@@ -234,8 +608,23 @@ fn safe_copy_file(source: &Path, dest: &Path) -> Result<u64, String> {
         }
     }
 
-    let bytes = std::fs::copy(source, dest)
-        .map_err(|e| format!("Copy failed {}: {}", dest.display(), e))?;
+    let tmp = temp_sibling(dest);
+    let bytes = std::fs::copy(source, &tmp)
+        .and_then(|bytes| {
+            if durable {
+                std::fs::OpenOptions::new().write(true).open(&tmp)?.sync_all()?;
+            }
+            // After the sync, which a read-only mode would refuse to open for.
+            if let Some(perms) = replaced_permissions(dest) {
+                std::fs::set_permissions(&tmp, perms)?;
+            }
+            Ok(bytes)
+        })
+        .and_then(|bytes| std::fs::rename(&tmp, dest).map(|()| bytes))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("Copy failed {}: {}", dest.display(), e)
+        })?;
 
     // Post-check: detect if dest was swapped to a symlink during copy
     if let Ok(meta) = std::fs::symlink_metadata(dest) {
@@ -252,20 +641,51 @@ fn safe_copy_file(source: &Path, dest: &Path) -> Result<u64, String> {
 }
 
 #[cfg(not(any(unix, windows)))]
-fn safe_copy_file(source: &Path, dest: &Path) -> Result<u64, String> {
-    std::fs::copy(source, dest)
-        .map_err(|e| format!("Copy failed {}: {}", dest.display(), e))
+fn safe_copy_file(
+    source: &Path,
+    dest: &Path,
+    durable: bool,
+    _cancel: Option<&ImportCancel>,
+) -> Result<u64, String> {
+    let tmp = temp_sibling(dest);
+    std::fs::copy(source, &tmp)
+        .and_then(|bytes| {
+            if durable {
+                std::fs::OpenOptions::new().write(true).open(&tmp)?.sync_all()?;
+            }
+            // After the sync, which a read-only mode would refuse to open for.
+            if let Some(perms) = replaced_permissions(dest) {
+                std::fs::set_permissions(&tmp, perms)?;
+            }
+            Ok(bytes)
+        })
+        .and_then(|bytes| std::fs::rename(&tmp, dest).map(|()| bytes))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("Copy failed {}: {}", dest.display(), e)
+        })
 }
 
-/// Returns the workspace directory path and whether it exists.
+/// Returns the workspace directory path, whether it exists, and why it is
+/// unavailable if it is (e.g. it is on an external drive that is unplugged).
 #[tauri::command]
 pub async fn get_workspace_path(
     state: tauri::State<'_, WorkspaceState>,
 ) -> Result<WorkspaceInfo, String> {
-    Ok(WorkspaceInfo {
-        path: state.workspace_path.display().to_string(),
-        exists: state.workspace_path.exists(),
-    })
+    Ok(workspace_info(&state.path()))
+}
+
+fn workspace_info(path: &Path) -> WorkspaceInfo {
+    let unavailable = crate::workspace::check_available(path).err();
+    WorkspaceInfo {
+        path: path.display().to_string(),
+        exists: path.exists(),
+        volume: unavailable
+            .as_ref()
+            .and_then(|u| u.volume.as_ref())
+            .map(|v| v.display().to_string()),
+        unavailable: unavailable.map(|u| u.to_string()),
+    }
 }
 
 /// Import a folder (or file) from source_path into the workspace.
@@ -273,29 +693,58 @@ pub async fn get_workspace_path(
 /// - If source is a directory, recursively copies all contents into
 ///   `{workspace}/{dest_subpath}/{folder_name}/`.
 /// - If source is a file, copies it into `{workspace}/{dest_subpath}/`.
-/// - Conflicts: merge with overwrite (existing files replaced, others untouched).
+/// - Conflicts: by default merge with overwrite (existing files replaced, others
+///   untouched); `conflict_mode` can skip or rename them instead (see
+///   `ImportConflictMode`). A destination entry of any kind, including a
+///   symlink, counts as existing.
 /// - Emits "folder-import-progress" events for UI progress tracking.
+/// - `modified_since` (unix seconds): skip files whose mtime is older than the
+///   cutoff (incremental, backup-style imports). Directories left empty by the
+///   filter are not created.
+/// - On a case-insensitive destination (macOS, Windows), source files whose
+///   paths differ only by case would overwrite each other, and one differing
+///   only by case from a destination file would land on that file. The first in
+///   name order wins (a destination file always does) and the rest are listed
+///   in `collisions`; `case_collisions:
+///   "rename"` copies them as `name (2).ext` instead and returns the renames in
+///   `case_renames`. The source is walked in name order, so the same tree always
+///   gets the same names.
+/// - `durable`: fsync every copied file before it replaces the destination,
+///   the directories they land in, and the parents of every directory the
+///   import creates, so a power cut can't lose a file the import already
+///   reported. Slower, especially for many small files.
+/// - `preserve_dir_times`: once everything is copied, give each imported
+///   directory its source's access/modification times (see `apply_dir_times`);
+///   otherwise they show when the import ran.
+/// - `ignore_patterns`: gitignore-style globs (see `compile_ignore_patterns`)
+///   matched against each entry's path relative to the source, e.g.
+///   `node_modules`, `.git/`, `*.log`. Matching directories are not scanned or
+///   copied; `files_ignored` counts each of them once, plus each ignored file.
+///   For a single-file import the patterns are matched against its name.
+/// - Small folders (see `small_import_limits`) skip the scanning/copying
+///   progress events; only the final "done"/"error" is emitted.
 ///
 /// Security: dest_subpath is validated to prevent workspace escape.
 /// Symlinks in the source tree are NOT followed to prevent importing
 /// files outside the user's chosen folder.
 #[tauri::command]
+// Each option is its own IPC argument, so the frontend can omit any of them.
+#[allow(clippy::too_many_arguments)]
 pub async fn import_folder(
     app: tauri::AppHandle,
     state: tauri::State<'_, WorkspaceState>,
     source_path: String,
     dest_subpath: Option<String>,
+    modified_since: Option<u64>,
+    verify: Option<bool>,
+    case_collisions: Option<String>,
+    durable: Option<bool>,
+    preserve_dir_times: Option<bool>,
+    conflict_mode: Option<ImportConflictMode>,
+    ignore_patterns: Option<Vec<String>>,
 ) -> Result<ImportResult, String> {
     // Fail closed: reject if workspace path is empty or doesn't exist
-    if state.workspace_path.as_os_str().is_empty() {
-        return Err("Workspace path not configured".to_string());
-    }
-    if !state.workspace_path.exists() {
-        return Err(format!(
-            "Workspace directory does not exist: {}",
-            state.workspace_path.display()
-        ));
-    }
+    let workspace = state.available_path()?;
 
     let source = PathBuf::from(&source_path);
     if !source.exists() {
@@ -307,285 +756,2443 @@ pub async fn import_folder(
         validate_subpath(sub)?;
     }
 
+    let ignore = compile_ignore_patterns(&ignore_patterns.unwrap_or_default())?;
+
+    let rename_case_collisions = match case_collisions.as_deref() {
+        None | Some("skip") => false,
+        Some("rename") => true,
+        Some(other) => {
+            return Err(format!(
+                "Unknown case_collisions mode {:?} (expected \"skip\" or \"rename\")",
+                other
+            ))
+        }
+    };
+
     // Generate a unique import ID for correlating progress events
-    let import_id = format!(
-        "{}-{}",
-        std::process::id(),
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis()
-    );
+    let import_id = transfer_id();
+
+    // Register before the blocking task starts so the import is visible at once,
+    // and always unregister afterwards — a panic or early return never emits a
+    // terminal phase.
+    if let Ok(mut active) = state.active_imports.lock() {
+        active.insert(
+            import_id.clone(),
+            ImportProgress {
+                import_id: import_id.clone(),
+                processed: 0,
+                total: 0,
+                bytes_processed: 0,
+                bytes_total: 0,
+                eta_secs: None,
+                current_file: String::new(),
+                current_file_raw: None,
+                phase: PHASE_SCANNING.to_string(),
+            },
+        );
+    }
+    // Checked after registering (clear_workspace checks in the opposite order),
+    // so an import and a clear can't both miss each other.
+    let busy = if state.clearing.load(std::sync::atomic::Ordering::SeqCst) {
+        Some("cleared")
+    } else if state.relocating.load(std::sync::atomic::Ordering::SeqCst) {
+        Some("moved")
+    } else {
+        None
+    };
+    if let Some(busy) = busy {
+        if let Ok(mut active) = state.active_imports.lock() {
+            active.remove(&import_id);
+        }
+        return Err(format!("The workspace is being {busy}; try again when it finishes"));
+    }
+
+    let cancel = std::sync::Arc::new(ImportCancel::default());
+    if let Ok(mut cancels) = state.transfer_cancels.lock() {
+        cancels.insert(import_id.clone(), cancel.clone());
+    }
 
-    let workspace = state.workspace_path.clone();
     let app_handle = app.clone();
+    let id = import_id.clone();
+    let options = ImportOptions {
+        modified_since,
+        verify: verify.unwrap_or(false),
+        durable: durable.unwrap_or(false),
+        preserve_dir_times: preserve_dir_times.unwrap_or(false),
+        rename_case_collisions,
+        conflict_mode: conflict_mode.unwrap_or_default(),
+        small_import: small_import_limits(),
+        ignore,
+        cancel,
+    };
 
     // Run the heavy copy work on a blocking thread
-    tauri::async_runtime::spawn_blocking(move || {
-        do_import(&app_handle, &source, &workspace, dest_subpath.as_deref(), &import_id)
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        do_import(
+            &app_handle,
+            &source,
+            &workspace,
+            dest_subpath.as_deref(),
+            &id,
+            &options,
+        )
     })
-    .await
-    .map_err(|e| format!("Import task failed: {}", e))?
+    .await;
+    if let Ok(mut active) = state.active_imports.lock() {
+        active.remove(&import_id);
+    }
+    if let Ok(mut cancels) = state.transfer_cancels.lock() {
+        cancels.remove(&import_id);
+    }
+    result.map_err(|e| format!("Import task failed: {}", e))?
 }
 
-fn emit_error(app: &tauri::AppHandle, import_id: &str, message: &str) {
-    let _ = app.emit(
-        "folder-import-progress",
-        ImportProgress {
-            import_id: import_id.to_string(),
-            processed: 0,
-            total: 0,
-            current_file: message.to_string(),
-            phase: "error".to_string(),
-        },
+/// Cancel an in-flight import. By default the file being copied is finished
+/// and no new ones are started, so the workspace only ever holds whole files;
+/// `force` also abandons the current file (its temp copy is removed, and the
+/// destination keeps whatever it had before). The import then ends with a
+/// "cancelled" progress event and returns the files copied so far, with
+/// `ImportResult::cancelled` set.
+#[tauri::command]
+pub fn cancel_import(
+    state: tauri::State<'_, WorkspaceState>,
+    import_id: String,
+    force: Option<bool>,
+) -> Result<(), String> {
+    let cancel = state
+        .transfer_cancels
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&import_id)
+        .cloned()
+        .ok_or_else(|| format!("No import {} in progress", import_id))?;
+    let force = force.unwrap_or(false);
+    eprintln!(
+        "[commands] Cancelling import {}{}",
+        import_id,
+        if force { " (forced)" } else { "" }
     );
+    cancel.request(force);
+    Ok(())
 }
 
-fn do_import(
-    app: &tauri::AppHandle,
-    source: &Path,
-    workspace: &Path,
-    dest_subpath: Option<&str>,
-    import_id: &str,
-) -> Result<ImportResult, String> {
-    eprintln!(
-        "[commands] REVISION: {} - import_folder called at {}",
-        MODULE_REVISION,
-        chrono_now()
-    );
+/// A `cancel_import` (or `cancel_export`) request, polled by `do_import` and
+/// `do_export` between files and, once forced, by the copy itself.
+#[derive(Default)]
+struct ImportCancel(std::sync::atomic::AtomicU8);
 
-    // Build destination base with path safety check
-    let dest_base = if let Some(sub) = dest_subpath {
-        // validate_subpath already called in import_folder, but belt-and-suspenders
-        let safe_sub = validate_subpath(sub).map_err(|e| {
-            emit_error(app, import_id, &e);
-            e
-        })?;
-        workspace.join(safe_sub)
-    } else {
-        workspace.to_path_buf()
-    };
+impl ImportCancel {
+    const SOFT: u8 = 1;
+    const FORCE: u8 = 2;
 
-    // Handle single file import
-    if source.is_file() {
-        let file_name = source
-            .file_name()
-            .ok_or_else(|| "Cannot determine file name".to_string())?;
-        let dest = dest_base.join(file_name);
+    /// A soft request can be upgraded to a forced one, never the reverse.
+    fn request(&self, force: bool) {
+        let level = if force { Self::FORCE } else { Self::SOFT };
+        self.0.fetch_max(level, std::sync::atomic::Ordering::SeqCst);
+    }
 
-        // Verify destination stays within workspace (no side effects)
-        ensure_within_workspace(&dest, workspace).map_err(|e| {
-            emit_error(app, import_id, &e);
-            e
-        })?;
+    fn requested(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::SeqCst) != 0
+    }
 
-        // Now safe to create dirs and re-verify
-        safe_create_parent_dirs(&dest, workspace).map_err(|e| {
-            emit_error(app, import_id, &e);
-            e
-        })?;
+    fn forced(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::SeqCst) == Self::FORCE
+    }
+}
 
-        let bytes = safe_copy_file(source, &dest).map_err(|e| {
-            emit_error(app, import_id, &e);
-            e
-        })?;
+/// Optional behaviors of `import_folder`, gathered so `do_import` doesn't grow a
+/// parameter per flag.
+#[derive(Default)]
+struct ImportOptions {
+    /// Skip files whose mtime is older than this (unix seconds).
+    modified_since: Option<u64>,
+    /// Re-walk the destination after copying and check every copied file exists
+    /// with the size we wrote.
+    verify: bool,
+    /// On a case-insensitive destination, copy case-colliding files under a
+    /// renamed path instead of skipping them.
+    rename_case_collisions: bool,
+    /// What to do with files the destination already has.
+    conflict_mode: ImportConflictMode,
+    /// fsync copied files and their directories (see `DURABLE_DIR_SYNC_EVERY`).
+    durable: bool,
+    /// Copy directory times from the source after the import (`apply_dir_times`).
+    preserve_dir_times: bool,
+    /// `(files, bytes)`: a folder with fewer files to copy than this, totalling
+    /// less, is copied without progress events. `None` always reports progress.
+    small_import: Option<(u64, u64)>,
+    /// Paths (relative to the source) to leave out; matching directories are
+    /// pruned. See `compile_ignore_patterns`.
+    ignore: Option<IgnoreSet>,
+    /// Set by `cancel_import`.
+    cancel: std::sync::Arc<ImportCancel>,
+}
 
-        let _ = app.emit(
-            "folder-import-progress",
-            ImportProgress {
-                import_id: import_id.to_string(),
-                processed: 1,
-                total: 1,
-                current_file: file_name.to_string_lossy().to_string(),
-                phase: "done".to_string(),
-            },
-        );
+/// Defaults for `small_import_limits`.
+const SMALL_IMPORT_MAX_FILES: u64 = 50;
+const SMALL_IMPORT_MAX_BYTES: u64 = 8 * 1024 * 1024;
 
-        return Ok(ImportResult {
-            import_id: import_id.to_string(),
-            files_copied: 1,
-            bytes_copied: bytes,
-            dest_path: dest.display().to_string(),
-            errors: vec![],
-        });
-    }
+/// Below these limits an import finishes before a progress UI could usefully
+/// show, so its events are only flicker. `ORCABOT_IMPORT_FAST_FILES` and
+/// `ORCABOT_IMPORT_FAST_MB` override them; either set to 0 disables the fast path.
+fn small_import_limits() -> Option<(u64, u64)> {
+    let limit = |var: &str, default: u64| {
+        std::env::var(var)
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(default)
+    };
+    let files = limit("ORCABOT_IMPORT_FAST_FILES", SMALL_IMPORT_MAX_FILES);
+    let bytes = limit("ORCABOT_IMPORT_FAST_MB", SMALL_IMPORT_MAX_BYTES / (1024 * 1024))
+        .saturating_mul(1024 * 1024);
+    (files > 0 && bytes > 0).then_some((files, bytes))
+}
 
-    // Directory import
-    if !source.is_dir() {
-        let msg = format!(
-            "Source is neither a file nor a directory: {}",
-            source.display()
-        );
-        emit_error(app, import_id, &msg);
-        return Err(msg);
-    }
+/// With `durable`, the directories touched by this many copies are fsynced
+/// together (and the rest at the end), instead of one directory sync per file.
+const DURABLE_DIR_SYNC_EVERY: usize = 64;
 
-    let folder_name = source
-        .file_name()
-        .ok_or_else(|| "Cannot determine folder name".to_string())?;
-    let dest_root = dest_base.join(folder_name);
+/// fsync a directory so the entries renamed into it survive a power loss. A
+/// no-op off Unix: Windows can't open a directory as a file, and NTFS journals
+/// the rename metadata itself.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
 
-    // Verify destination root stays within workspace (no side effects)
-    ensure_within_workspace(&dest_root, workspace).map_err(|e| {
-        emit_error(app, import_id, &e);
-        e
-    })?;
+/// Most `ImportResult::errors` entries kept; past it they are only counted,
+/// so a pathological import can't hold or hand the UI 100k near-identical
+/// strings.
+const MAX_IMPORT_ERRORS: usize = 1000;
+
+/// An import's or export's errors, sorted, de-duplicated and capped at
+/// [`MAX_IMPORT_ERRORS`] as they come in: the entries kept are the first in
+/// sort order, and the rest are only counted (a repeat of one already dropped
+/// counts again).
+#[derive(Default)]
+struct ImportErrors {
+    kept: std::collections::BTreeSet<String>,
+    dropped: u64,
+}
 
-    // Always create dest_root so even empty folders appear in the workspace.
-    // Post-creation containment check guards against TOCTOU parent swap.
-    safe_create_dir(&dest_root, workspace).map_err(|e| {
-        emit_error(app, import_id, &e);
-        e
-    })?;
+impl ImportErrors {
+    fn push(&mut self, error: String) {
+        if self.kept.contains(&error) {
+            return;
+        }
+        self.kept.insert(error);
+        if self.kept.len() > MAX_IMPORT_ERRORS {
+            self.kept.pop_last();
+            self.dropped += 1;
+        }
+    }
 
-    // Phase 1: Scan - count files
-    // follow_links(false) to prevent importing files outside the chosen source folder
-    // via symlinks. Symlinks are skipped silently.
-    let _ = app.emit(
-        "folder-import-progress",
-        ImportProgress {
-            import_id: import_id.to_string(),
-            processed: 0,
-            total: 0,
-            current_file: String::new(),
-            phase: "scanning".to_string(),
-        },
-    );
+    fn extend(&mut self, errors: impl IntoIterator<Item = String>) {
+        for error in errors {
+            self.push(error);
+        }
+    }
 
-    let mut total_files: u64 = 0;
-    let mut entries: Vec<(PathBuf, PathBuf)> = Vec::new(); // (source_abs, relative_path)
-    let mut dir_entries: Vec<PathBuf> = Vec::new(); // relative paths of directories
+    /// The errors kept, in order, and the distinct count before the cap.
+    fn finish(self) -> (Vec<String>, u64) {
+        let total = self.kept.len() as u64 + self.dropped;
+        (self.kept.into_iter().collect(), total)
+    }
+}
 
-    for entry in WalkDir::new(source).follow_links(false) {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(e) => {
-                eprintln!("[commands] Skipping unreadable entry: {}", e);
-                continue;
-            }
+/// Destination directories an import couldn't create (relative to the import
+/// root), each with its error and the number of files it kept out, so files
+/// under one bad parent add up to a single error.
+#[derive(Default)]
+struct FailedParents(std::collections::BTreeMap<PathBuf, (String, u64)>);
+
+impl FailedParents {
+    /// Count `relative` against an already-failed ancestor directory. False if
+    /// none of its ancestors failed.
+    fn absorb(&mut self, relative: &Path) -> bool {
+        let Some(dir) = relative
+            .ancestors()
+            .skip(1)
+            .find(|dir| self.0.contains_key(*dir))
+            .map(Path::to_path_buf)
+        else {
+            return false;
         };
+        if let Some((_, files)) = self.0.get_mut(&dir) {
+            *files += 1;
+        }
+        true
+    }
 
-        let relative = entry
-            .path()
+    /// Record that directory `dir` (relative, an ancestor of the file that
+    /// needed it) couldn't be created; that file is the first it kept out.
+    fn record(&mut self, dir: &Path, error: String) {
+        self.0.insert(dir.to_path_buf(), (error, 1));
+    }
+
+    fn into_errors(self, errors: &mut ImportErrors) {
+        for (dir, (error, files)) in self.0 {
+            let label = if dir.as_os_str().is_empty() { ".".to_string() } else { path_label(&dir) };
+            errors.push(format!(
+                "dir {}: {} ({} file{} not copied)",
+                label,
+                error,
+                files,
+                if files == 1 { "" } else { "s" }
+            ));
+        }
+    }
+}
+
+/// fsync each of `dirs` and clear the set, recording failures in `errors`.
+fn sync_dirs(dirs: &mut std::collections::BTreeSet<PathBuf>, errors: &mut ImportErrors) {
+    for dir in std::mem::take(dirs) {
+        if let Err(e) = sync_dir(&dir) {
+            errors.push(format!("{}: sync failed: {}", path_label(&dir), e));
+        }
+    }
+}
+
+/// Emit a `folder-import-progress` event and mirror it into
+/// `WorkspaceState::active_imports` (terminal phases remove the entry).
+fn emit_progress(app: &tauri::AppHandle, progress: ImportProgress) {
+    use tauri::Manager;
+    if let Some(state) = app.try_state::<WorkspaceState>() {
+        if let Ok(mut active) = state.active_imports.lock() {
+            if is_terminal_phase(&progress.phase) {
+                active.remove(&progress.import_id);
+            } else {
+                active.insert(progress.import_id.clone(), progress.clone());
+            }
+        }
+    }
+    let _ = app.emit("folder-import-progress", progress);
+}
+
+/// Seconds left to copy `total` bytes at the rate `done` took `elapsed`. None
+/// until a second has passed and something was copied: earlier rates are noise.
+fn eta_secs(elapsed: std::time::Duration, done: u64, total: u64) -> Option<u64> {
+    if done == 0 || elapsed < std::time::Duration::from_secs(1) {
+        return None;
+    }
+    let remaining = total.saturating_sub(done) as f64;
+    Some((remaining * elapsed.as_secs_f64() / done as f64).ceil() as u64)
+}
+
+/// In-flight imports with their latest progress, oldest first. The UI uses this
+/// to block a conflicting import and to warn before quitting mid-import.
+#[tauri::command]
+pub fn active_imports(state: tauri::State<'_, WorkspaceState>) -> Vec<ImportProgress> {
+    let mut imports: Vec<ImportProgress> = state
+        .active_imports
+        .lock()
+        .map(|active| active.values().cloned().collect())
+        .unwrap_or_default();
+    // import_id is "<pid>-<millis>-<seq>", so sort by start time, then sequence.
+    imports.sort_by_key(|p| {
+        p.import_id
+            .split('-')
+            .skip(1)
+            .map(|n| n.parse::<u128>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    });
+    imports
+}
+
+/// A new `import_id`/`export_id`: "<pid>-<millis>-<seq>". These key
+/// `active_imports` and the cancel map, so the process-wide sequence keeps two
+/// transfers started in the same millisecond apart.
+fn transfer_id() -> String {
+    static SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    format!(
+        "{}-{}-{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis(),
+        SEQ.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    )
+}
+
+/// How long a `prepare_clear_workspace` token stays valid.
+const CLEAR_TOKEN_TTL: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// Trash batches older than this are deleted for good the next time something
+/// is trashed.
+const TRASH_RETENTION: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Serialize, Clone)]
+pub struct ClearWorkspacePreview {
+    /// Pass back to `clear_workspace` to confirm. Single-use, valid for two minutes.
+    pub confirm_token: String,
+    /// Top-level entries in the workspace.
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ClearWorkspaceResult {
+    pub entries_removed: u64,
+    /// Size of what left the workspace. When it went to the trash, the disk
+    /// space is reclaimed once the trash batch expires.
+    pub bytes_freed: u64,
+    /// The trash batch holding the old contents, if they could be moved there
+    /// (otherwise they were deleted outright).
+    pub trash_path: Option<String>,
+    /// Entries that could be neither trashed nor deleted: "name: error".
+    pub errors: Vec<String>,
+}
+
+/// Total size of the files under `path`, without following symlinks.
+fn tree_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// A new trash batch directory, `<trash_root>/<unix secs>-<pid>`.
+fn new_trash_batch(trash_root: &Path) -> std::io::Result<PathBuf> {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let batch = trash_root.join(format!("{}-{}", secs, std::process::id()));
+    std::fs::create_dir_all(&batch)?;
+    Ok(batch)
+}
+
+/// Move `path` into the trash `batch`, or delete it if it can't be moved (the
+/// trash is on another filesystem, say). Returns whether it was trashed.
+fn trash_or_remove(path: &Path, batch: Option<&Path>) -> std::io::Result<bool> {
+    if let (Some(batch), Some(name)) = (batch, path.file_name()) {
+        if std::fs::rename(path, batch.join(name)).is_ok() {
+            return Ok(true);
+        }
+    }
+    // symlink_metadata: a symlink is removed itself, never its target.
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    Ok(false)
+}
+
+/// Delete trash batches older than `TRASH_RETENTION`.
+fn prune_trash(trash_root: &Path) {
+    let Ok(entries) = std::fs::read_dir(trash_root) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age > TRASH_RETENTION);
+        if expired {
+            if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+                eprintln!("[commands] failed to prune trash {}: {}", entry.path().display(), e);
+            }
+        }
+    }
+}
+
+/// Step one of "start over": count what `clear_workspace` would remove and issue
+/// the token it requires, so a stray call can't empty the workspace.
+#[tauri::command]
+pub fn prepare_clear_workspace(
+    state: tauri::State<'_, WorkspaceState>,
+) -> Result<ClearWorkspacePreview, String> {
+    let entries: Vec<PathBuf> = std::fs::read_dir(state.available_path()?)
+        .map_err(|e| format!("Cannot read workspace: {}", e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    let confirm_token = random_hex(16);
+    if let Ok(mut token) = state.clear_token.lock() {
+        *token = Some((confirm_token.clone(), std::time::Instant::now()));
+    }
+    Ok(ClearWorkspacePreview {
+        confirm_token,
+        entries: entries.len() as u64,
+        bytes: entries.iter().map(|p| tree_size(p)).sum(),
+    })
+}
+
+/// Remove everything in the workspace (the directory itself stays). Requires the
+/// token from `prepare_clear_workspace` and refuses while an import is running.
+/// Contents go to `<data_dir>/trash/` when possible and are deleted after a week.
+#[tauri::command]
+pub async fn clear_workspace(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, WorkspaceState>,
+    confirm_token: String,
+) -> Result<ClearWorkspaceResult, String> {
+    use std::sync::atomic::Ordering;
+    use tauri::Manager;
+
+    let issued = state.clear_token.lock().ok().and_then(|mut t| t.take());
+    match issued {
+        Some((token, at)) if token == confirm_token && at.elapsed() < CLEAR_TOKEN_TTL => {}
+        Some(_) => return Err("Confirmation expired or did not match; try again".to_string()),
+        None => return Err("Call prepare_clear_workspace first".to_string()),
+    }
+    let workspace = state.available_path()?;
+    if state.clearing.swap(true, Ordering::SeqCst) {
+        return Err("The workspace is already being cleared".to_string());
+    }
+    if state.relocating.load(Ordering::SeqCst) {
+        state.clearing.store(false, Ordering::SeqCst);
+        return Err("The workspace is being moved; try again when it finishes".to_string());
+    }
+    let importing = state.active_transfers();
+    if importing > 0 {
+        state.clearing.store(false, Ordering::SeqCst);
+        return Err(format!(
+            "Cannot clear the workspace while {} import(s) or export(s) are in progress",
+            importing
+        ));
+    }
+
+    let trash_root = app.path().app_data_dir().ok().map(|d| d.join("trash"));
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let entries: Vec<PathBuf> = std::fs::read_dir(&workspace)
+            .map_err(|e| format!("Cannot read workspace: {}", e))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect();
+        let batch = trash_root.as_deref().and_then(|root| {
+            prune_trash(root);
+            new_trash_batch(root)
+                .map_err(|e| eprintln!("[commands] no trash, deleting instead: {}", e))
+                .ok()
+        });
+
+        let mut result = ClearWorkspaceResult {
+            entries_removed: 0,
+            bytes_freed: 0,
+            trash_path: None,
+            errors: Vec::new(),
+        };
+        let mut trashed_any = false;
+        for path in entries {
+            let bytes = tree_size(&path);
+            match trash_or_remove(&path, batch.as_deref()) {
+                Ok(trashed) => {
+                    trashed_any |= trashed;
+                    result.entries_removed += 1;
+                    result.bytes_freed += bytes;
+                }
+                Err(e) => result.errors.push(format!(
+                    "{}: {}",
+                    path.file_name().unwrap_or_default().to_string_lossy(),
+                    e
+                )),
+            }
+        }
+        if let Some(batch) = batch {
+            if trashed_any {
+                result.trash_path = Some(batch.display().to_string());
+            } else {
+                let _ = std::fs::remove_dir(&batch);
+            }
+        }
+        eprintln!(
+            "[commands] workspace cleared: {} entries, {} bytes ({} errors)",
+            result.entries_removed,
+            result.bytes_freed,
+            result.errors.len()
+        );
+        Ok(result)
+    })
+    .await;
+    state.clearing.store(false, Ordering::SeqCst);
+    result.map_err(|e| format!("Clear task failed: {}", e))?
+}
+
+#[derive(Serialize, Clone)]
+pub struct WorkspaceRelocation {
+    /// The workspace at its new location.
+    pub workspace: WorkspaceInfo,
+    pub previous_path: String,
+    /// Whether the old workspace's contents came along (false when only
+    /// repointed, or when the old location was unavailable).
+    pub moved: bool,
+    /// Whether the sandbox VM is booting again on the new location.
+    pub vm_restarting: bool,
+    /// Problems that didn't stop the move, e.g. an old copy that could not be
+    /// removed after copying across drives.
+    pub errors: Vec<String>,
+}
+
+/// Point the workspace at `new_path` (persisted as `settings.json`
+/// `workspace_dir`), e.g. when the external drive it was on is gone for good.
+/// With `move_contents` (the default) the current workspace's files move along,
+/// by rename on the same volume or copy-then-remove across volumes, and
+/// `new_path` must be missing or an empty directory; without it, or when the
+/// old workspace is unavailable, the workspace is only repointed. The sandbox
+/// VM mounts the workspace, so it is stopped for the move and booted again on
+/// the new location. Refused while an import or clear is running.
+#[tauri::command]
+pub async fn relocate_workspace(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, WorkspaceState>,
+    new_path: String,
+    move_contents: Option<bool>,
+) -> Result<WorkspaceRelocation, String> {
+    use std::sync::atomic::Ordering;
+    use tauri::Manager;
+
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let requested = PathBuf::from(new_path.trim());
+    let (Some(parent), Some(name), true) =
+        (requested.parent(), requested.file_name(), requested.is_absolute())
+    else {
+        return Err("The new workspace location must be an absolute path to a folder".to_string());
+    };
+    crate::workspace::check_available(parent).map_err(|_| {
+        format!("{} does not exist or its drive is not connected", parent.display())
+    })?;
+    let new = parent
+        .canonicalize()
+        .map_err(|e| format!("Cannot resolve {}: {}", parent.display(), e))?
+        .join(name);
+    if new.exists() && !new.is_dir() {
+        return Err(format!("{} is not a folder", new.display()));
+    }
+
+    let old = state.path();
+    let old_available = crate::workspace::check_available(&old).is_ok();
+    let move_contents = move_contents.unwrap_or(true) && old_available;
+    if let Ok(old) = old.canonicalize() {
+        let new = new.canonicalize().unwrap_or_else(|_| new.clone());
+        if new == old {
+            return Err("The workspace is already there".to_string());
+        }
+        if new.starts_with(&old) || old.starts_with(&new) {
+            return Err("The new location can't be inside the workspace or contain it".to_string());
+        }
+    }
+    if move_contents && std::fs::read_dir(&new).is_ok_and(|mut d| d.next().is_some()) {
+        return Err(format!(
+            "{} is not empty; choose an empty folder to move the workspace into",
+            new.display()
+        ));
+    }
+
+    if state.relocating.swap(true, Ordering::SeqCst) {
+        return Err("The workspace is already being moved".to_string());
+    }
+    let importing = state.active_transfers();
+    let refusal = if state.clearing.load(Ordering::SeqCst) {
+        Some("The workspace is being cleared; try again when it finishes".to_string())
+    } else if importing > 0 {
+        Some(format!(
+            "Cannot move the workspace while {} import(s) or export(s) are in progress",
+            importing
+        ))
+    } else {
+        None
+    };
+    if let Some(refusal) = refusal {
+        state.relocating.store(false, Ordering::SeqCst);
+        return Err(refusal);
+    }
+
+    let app2 = app.clone();
+    let (old2, new2) = (old.clone(), new.clone());
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let services = app2.try_state::<std::sync::Arc<crate::DesktopServices>>();
+        let services = services.as_deref();
+        // Held while files move, so neither an idle wake nor the watchdog boots
+        // the VM onto a half-moved workspace.
+        let starting = match services.map(|s| s.vm_start.try_lock()) {
+            Some(Err(_)) => {
+                return Err("Cannot move the workspace while the sandbox VM is starting".to_string())
+            }
+            starting => starting,
+        };
+        let stopped = services
+            .and_then(|s| s.sandbox_vm.lock().ok())
+            .and_then(|mut vm| vm.take())
+            .map(|mut vm| {
+                eprintln!("[commands] stopping the sandbox VM to move the workspace");
+                let _ = vm.stop();
+            })
+            .is_some();
+
+        let mut errors = Vec::new();
+        let moved = (|| {
+            if !move_contents {
+                std::fs::create_dir_all(&new2)
+                    .map_err(|e| format!("Cannot create {}: {}", new2.display(), e))?;
+            }
+            let default = crate::workspace::default_dir(&data_dir);
+            let previous = crate::settings::update(&data_dir, |s| {
+                let is_default = default.canonicalize().is_ok_and(|d| d == new2);
+                std::mem::replace(&mut s.workspace_dir, (!is_default).then(|| new2.clone()))
+            })
+            .map_err(|e| format!("Cannot save the workspace location: {}", e))?;
+            if move_contents {
+                if let Err(e) = move_dir(&old2, &new2, &mut errors) {
+                    let _ = crate::settings::update(&data_dir, |s| s.workspace_dir = previous);
+                    return Err(e);
+                }
+            }
+            Ok(())
+        })();
+        if let Err(e) = moved {
+            // The workspace stays where it was; bring back the VM stopped for it.
+            drop(starting);
+            if stopped && services.is_some_and(|s| s.boot_sandbox_vm(&app2)) {
+                eprintln!("[commands] workspace not moved; restarting the sandbox VM");
+            }
+            return Err(e);
+        }
+        let state = app2.state::<WorkspaceState>();
+        *state.workspace_path.write().unwrap_or_else(|e| e.into_inner()) = new2.clone();
+        drop(starting);
+        eprintln!(
+            "[commands] workspace {} {} -> {}",
+            if move_contents { "moved" } else { "repointed" },
+            old2.display(),
+            new2.display()
+        );
+        let vm_restarting = services.is_some_and(|s| s.boot_sandbox_vm(&app2));
+        Ok(WorkspaceRelocation {
+            workspace: workspace_info(&new2),
+            previous_path: old2.display().to_string(),
+            moved: move_contents,
+            vm_restarting,
+            errors,
+        })
+    })
+    .await;
+    state.relocating.store(false, Ordering::SeqCst);
+    result.map_err(|e| format!("Relocate task failed: {}", e))?
+}
+
+/// Move directory `from` to `to` (missing or empty): a rename when both are on
+/// one volume, otherwise a copy, after which `from` is removed. A failed copy is
+/// undone and leaves `from` untouched; failing to remove `from` afterwards is
+/// only noted in `errors`.
+fn move_dir(from: &Path, to: &Path, errors: &mut Vec<String>) -> Result<(), String> {
+    let _ = std::fs::remove_dir(to);
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if let Err(e) = copy_tree(from, to) {
+        let _ = std::fs::remove_dir_all(to);
+        return Err(format!("Cannot copy the workspace to {}: {}", to.display(), e));
+    }
+    if let Err(e) = std::fs::remove_dir_all(from) {
+        errors.push(format!("{}: old copy not removed: {}", from.display(), e));
+    }
+    Ok(())
+}
+
+/// Copy the tree at `from` to `to`, keeping symlinks as symlinks (workspaces
+/// are full of them, e.g. `node_modules/.bin`) and skipping special files.
+fn copy_tree(from: &Path, to: &Path) -> Result<(), String> {
+    for entry in WalkDir::new(from).follow_links(false) {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let relative = entry.path().strip_prefix(from).map_err(|e| e.to_string())?;
+        let dest = to.join(relative);
+        let file_type = entry.file_type();
+        let copied = if file_type.is_dir() {
+            std::fs::create_dir_all(&dest)
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path()).map_err(|e| e.to_string())?;
+            #[cfg(unix)]
+            let linked = std::os::unix::fs::symlink(&target, &dest);
+            #[cfg(windows)]
+            let linked = if entry.path().is_dir() {
+                std::os::windows::fs::symlink_dir(&target, &dest)
+            } else {
+                std::os::windows::fs::symlink_file(&target, &dest)
+            };
+            linked
+        } else if special_file_kind(&file_type).is_some() {
+            Ok(())
+        } else {
+            std::fs::copy(entry.path(), &dest).map(|_| ())
+        };
+        copied.map_err(|e| format!("{}: {}", relative.display(), e))?;
+    }
+    Ok(())
+}
+
+/// `workspace/dest_subpath`, the directory an import lands in (the source's own
+/// name is joined onto it).
+fn import_dest_base(workspace: &Path, dest_subpath: Option<&str>) -> Result<PathBuf, String> {
+    match dest_subpath {
+        // validate_subpath already called by the commands, but belt-and-suspenders
+        Some(sub) => Ok(workspace.join(validate_subpath(sub)?)),
+        None => Ok(workspace.to_path_buf()),
+    }
+}
+
+/// What a directory import would copy, from one walk of the source. Shared by
+/// `do_import` and `preview_import_tree`.
+#[derive(Default)]
+struct ImportScan {
+    /// `(source_abs, relative_dest_path, scanned size)` of every file to copy,
+    /// in walk order.
+    entries: Vec<(PathBuf, PathBuf, u64)>,
+    /// Relative paths of subdirectories, parents before children.
+    dir_entries: Vec<PathBuf>,
+    files_not_modified: u64,
+    /// Ancestors of files dropped by `modified_since`: if nothing else lands in
+    /// them, they'd be empty shells of the filter, so don't create them.
+    filtered_dirs: std::collections::HashSet<PathBuf>,
+    collisions: Vec<String>,
+    case_renames: std::collections::BTreeMap<String, String>,
+    special_files: Vec<String>,
+    /// Total size of `entries`.
+    bytes: u64,
+    /// Entries left out by `ImportOptions::ignore`; a pruned directory counts
+    /// once.
+    ignored: u64,
+}
+
+/// Walk `source` (a directory) the way an import copies it.
+/// follow_links(false) to prevent importing files outside the chosen source folder
+/// via symlinks. Symlinks are skipped silently; FIFOs, sockets and devices
+/// are skipped and reported in `special_files`. `case_insensitive_dest`: the
+/// destination root, when it can't hold `README` and `readme` side by side.
+/// A file then also collides with a destination entry whose name differs from
+/// its own only by case; an exact match is an ordinary conflict.
+///
+/// `on_large` runs once, as soon as the files to copy reach
+/// `options.small_import` (straight away without it), so the caller learns
+/// whether this is a small import from the same walk.
+fn scan_import_source(
+    source: &Path,
+    case_insensitive_dest: Option<&Path>,
+    options: &ImportOptions,
+    on_large: impl FnOnce(),
+) -> ImportScan {
+    let mut scan = ImportScan::default();
+    let mut on_large = Some(on_large);
+    if options.small_import.is_none() {
+        if let Some(on_large) = on_large.take() {
+            on_large();
+        }
+    }
+    // Case-folded destination paths taken so far.
+    let mut case_taken: Option<std::collections::HashSet<String>> =
+        case_insensitive_dest.map(|_| std::collections::HashSet::new());
+    // Each destination directory's listing (by relative path), read on first use.
+    let mut dest_listings: std::collections::HashMap<PathBuf, CaseFoldedNames> =
+        std::collections::HashMap::new();
+    let mut ignored = 0;
+
+    // Name order, so which of a case-colliding set keeps its name (and what the
+    // others are renamed to) doesn't depend on the filesystem's listing order.
+    let walk = WalkDir::new(source)
+        .follow_links(false)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let Some(ignore) = options.ignore.as_ref() else {
+                return true;
+            };
+            let relative = entry.path().strip_prefix(source).unwrap_or(entry.path());
+            let is_dir = entry.file_type().is_dir();
+            if relative.as_os_str().is_empty() || !ignore.is_match(relative, is_dir) {
+                return true;
+            }
+            ignored += 1;
+            false
+        });
+    for entry in walk {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                eprintln!("[commands] Skipping unreadable entry: {}", e);
+                continue;
+            }
+        };
+
+        let relative = entry
+            .path()
+            .strip_prefix(source)
+            .unwrap_or(entry.path())
+            .to_path_buf();
+
+        if entry.file_type().is_file() {
+            if let Some(cutoff) = options.modified_since {
+                if entry.metadata().is_ok_and(|m| modified_before(&m, cutoff)) {
+                    scan.files_not_modified += 1;
+                    scan.filtered_dirs
+                        .extend(relative.ancestors().skip(1).map(Path::to_path_buf));
+                    continue;
+                }
+            }
+            let mut relative = relative;
+            if let (Some(dest_root), Some(taken)) = (case_insensitive_dest, case_taken.as_mut()) {
+                let parent = relative.parent().unwrap_or(Path::new("")).to_path_buf();
+                let listing = dest_listings
+                    .entry(parent)
+                    .or_insert_with_key(|parent| CaseFoldedNames::read(&dest_root.join(parent)));
+                let name = relative.file_name().unwrap_or_default().to_string_lossy();
+                let in_dest = listing.case_variant(&name).map(str::to_string);
+                if !taken.insert(case_key(&relative)) || in_dest.is_some() {
+                    let label = match in_dest {
+                        Some(existing) => {
+                            format!("{} (destination has {})", path_label(&relative), existing)
+                        }
+                        None => path_label(&relative),
+                    };
+                    if !options.rename_case_collisions {
+                        scan.collisions.push(label);
+                        continue;
+                    }
+                    let renamed = case_collision_rename(&relative, |key| {
+                        taken.contains(key) || listing.lists_key(key)
+                    });
+                    taken.insert(case_key(&renamed));
+                    scan.collisions.push(format!("{} -> {}", label, path_label(&renamed)));
+                    scan.case_renames.insert(path_label(&relative), path_label(&renamed));
+                    relative = renamed;
+                }
+            }
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            scan.bytes += size;
+            scan.entries.push((entry.path().to_path_buf(), relative, size));
+            if let Some((max_files, max_bytes)) = options.small_import {
+                let large = scan.entries.len() as u64 >= max_files || scan.bytes >= max_bytes;
+                if let Some(on_large) = on_large.take_if(|_| large) {
+                    on_large();
+                }
+            }
+        } else if entry.file_type().is_dir() && entry.path() != source {
+            // Collect subdirectories (skip the root source dir itself).
+            // WalkDir yields parents before children, preserving creation order.
+            scan.dir_entries.push(relative);
+        } else if let Some(kind) = special_file_kind(&entry.file_type()) {
+            scan.special_files.push(format!("{} ({})", path_label(&relative), kind));
+        }
+        // Symlinks (entry.file_type().is_symlink()) are silently skipped
+    }
+    scan.ignored = ignored;
+    scan
+}
+
+/// Compiled `ignore_patterns` / `exclude_globs` (see `compile_ignore_patterns`).
+struct IgnoreSet {
+    globs: globset::GlobSet,
+    /// Per glob: its pattern ended in `/`, so it only matches directories.
+    dir_only: Vec<bool>,
+}
+
+impl IgnoreSet {
+    /// Whether `relative` (to the source root) is ignored; `is_dir` says
+    /// whether it is a real directory (not a symlink to one).
+    fn is_match(&self, relative: &Path, is_dir: bool) -> bool {
+        self.globs
+            .matches(relative)
+            .into_iter()
+            .any(|i| is_dir || !self.dir_only[i])
+    }
+}
+
+/// Compile gitignore-style patterns for `ImportOptions::ignore`: a pattern
+/// without a `/` matches that name at any depth (`node_modules`, `*.log`); one
+/// with a `/` is anchored at the source root (`build/out`). A trailing `/`
+/// limits the pattern to directories (`build/` leaves a file named `build`
+/// alone), `*` never crosses a `/` (use `**`), and lines starting with `#` are
+/// comments. Negation (`!keep.log`) is not supported and is rejected rather
+/// than taken as a literal name.
+fn compile_ignore_patterns(patterns: &[String]) -> Result<Option<IgnoreSet>, String> {
+    let mut builder = globset::GlobSetBuilder::new();
+    let mut dir_only = Vec::new();
+    for raw in patterns {
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if trimmed.starts_with('!') {
+            return Err(format!("Negated pattern {:?} is not supported", raw));
+        }
+        let pattern = trimmed.trim_end_matches('/');
+        if pattern.is_empty() {
+            continue;
+        }
+        let pattern = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{}", pattern),
+        };
+        let glob = globset::GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid pattern {:?}: {}", raw, e))?;
+        builder.add(glob);
+        dir_only.push(trimmed.ends_with('/'));
+    }
+    if dir_only.is_empty() {
+        return Ok(None);
+    }
+    let globs = builder
+        .build()
+        .map_err(|e| format!("Invalid patterns: {}", e))?;
+    Ok(Some(IgnoreSet { globs, dir_only }))
+}
+
+fn emit_error(app: &tauri::AppHandle, import_id: &str, message: &str) {
+    emit_progress(
+        app,
+        ImportProgress {
+            import_id: import_id.to_string(),
+            processed: 0,
+            total: 0,
+            bytes_processed: 0,
+            bytes_total: 0,
+            eta_secs: None,
+            current_file: message.to_string(),
+            current_file_raw: None,
+            phase: PHASE_ERROR.to_string(),
+        },
+    );
+}
+
+/// Give the imported directories (and the import root) their source's access
+/// and modification times. This has to run after every file and directory is
+/// in place, since adding an entry to a directory bumps its mtime, and goes
+/// deepest first so each parent is stamped after everything beneath it.
+/// Symlinks are never followed: a destination swapped for one only has its
+/// own times changed. Directories that weren't created (filtered out) are
+/// skipped.
+fn apply_dir_times(
+    source: &Path,
+    dest_root: &Path,
+    dir_entries: &[PathBuf],
+    errors: &mut ImportErrors,
+) {
+    let stamp = |rel: &Path| -> std::io::Result<()> {
+        let dest = dest_root.join(rel);
+        if !std::fs::symlink_metadata(&dest).is_ok_and(|m| m.is_dir()) {
+            return Ok(());
+        }
+        let meta = std::fs::metadata(source.join(rel))?;
+        filetime::set_symlink_file_times(
+            &dest,
+            filetime::FileTime::from_last_access_time(&meta),
+            filetime::FileTime::from_last_modification_time(&meta),
+        )
+    };
+    let mut dirs: Vec<&Path> = dir_entries.iter().map(PathBuf::as_path).collect();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    // The import root itself goes last.
+    dirs.push(Path::new("."));
+    for rel in dirs {
+        if let Err(e) = stamp(rel) {
+            errors.push(format!("dir {}: preserving times: {}", path_label(rel), e));
+        }
+    }
+}
+
+/// Report a cancelled import (`cancel_import`).
+fn emit_cancelled(
+    app: &tauri::AppHandle,
+    import_id: &str,
+    copied: u64,
+    total: u64,
+    bytes_copied: u64,
+    bytes_total: u64,
+) {
+    let message = format!("Import cancelled after {} of {} files", copied, total);
+    eprintln!("[commands] Import {}: {}", import_id, message);
+    emit_progress(
+        app,
+        ImportProgress {
+            import_id: import_id.to_string(),
+            processed: copied,
+            total,
+            bytes_processed: bytes_copied,
+            bytes_total,
+            eta_secs: None,
+            current_file: message,
+            current_file_raw: None,
+            phase: PHASE_CANCELLED.to_string(),
+        },
+    );
+}
+
+fn do_import(
+    app: &tauri::AppHandle,
+    source: &Path,
+    workspace: &Path,
+    dest_subpath: Option<&str>,
+    import_id: &str,
+    options: &ImportOptions,
+) -> Result<ImportResult, String> {
+    let modified_since = options.modified_since;
+    eprintln!(
+        "[commands] REVISION: {} - import_folder called at {}",
+        MODULE_REVISION,
+        chrono_now()
+    );
+
+    // Build destination base with path safety check
+    let dest_base = import_dest_base(workspace, dest_subpath).map_err(|e| {
+        emit_error(app, import_id, &e);
+        e
+    })?;
+
+    // Handle single file import
+    if source.is_file() {
+        let file_name = source
+            .file_name()
+            .ok_or_else(|| "Cannot determine file name".to_string())?;
+        let dest = dest_base.join(file_name);
+        // A file left alone ends the import at once, with a single "done".
+        let unchanged = |dest: &Path| {
+            emit_progress(
+                app,
+                ImportProgress {
+                    import_id: import_id.to_string(),
+                    processed: 1,
+                    total: 1,
+                    bytes_processed: 0,
+                    bytes_total: 0,
+                    eta_secs: None,
+                    current_file: file_name.to_string_lossy().to_string(),
+                    current_file_raw: raw_path(Path::new(file_name)),
+                    phase: PHASE_DONE.to_string(),
+                },
+            );
+            ImportResult::empty(import_id, dest, options.durable)
+        };
+
+        // The patterns are matched against the file's own name, as the scan
+        // matches each entry's path relative to the source.
+        let ignored = options
+            .ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.is_match(Path::new(file_name), false));
+        if ignored {
+            return Ok(ImportResult {
+                files_ignored: 1,
+                ..unchanged(&dest)
+            });
+        }
+
+        // Checked first, as the directory scan does, so an old file is always
+        // reported as not modified rather than as a conflict.
+        if let Some(cutoff) = modified_since {
+            let old = std::fs::metadata(source).is_ok_and(|m| modified_before(&m, cutoff));
+            if old {
+                return Ok(ImportResult {
+                    files_not_modified: 1,
+                    ..unchanged(&dest)
+                });
+            }
+        }
+
+        // Verify destination stays within workspace (no side effects)
+        ensure_within_workspace(&dest, workspace).map_err(|e| {
+            emit_error(app, import_id, &e);
+            e
+        })?;
+
+        // Now safe to create dirs and re-verify
+        let new_top = if options.durable {
+            dest.parent().and_then(topmost_missing_dir).map(Path::to_path_buf)
+        } else {
+            None
+        };
+        safe_create_parent_dirs(&dest, workspace).map_err(|(_, e)| {
+            emit_error(app, import_id, &e);
+            e
+        })?;
+
+        let Some(target) = conflict_dest(dest.clone(), options.conflict_mode) else {
+            return Ok(ImportResult {
+                files_skipped: 1,
+                ..unchanged(&dest)
+            });
+        };
+        let renamed = target != dest;
+        let dest = target;
+
+        let size = std::fs::metadata(source).map(|m| m.len()).unwrap_or(0);
+        crate::vm::runtime::FreeSpaceWatch::new(workspace)
+            .check(size)
+            .map_err(|e| {
+                if renamed {
+                    let _ = std::fs::remove_file(&dest);
+                }
+                emit_error(app, import_id, &e);
+                e
+            })?;
+
+        // The file the user picked may itself be a link (safe_copy_file won't
+        // follow one); only links found inside a tree are skipped.
+        let picked = source.canonicalize().unwrap_or_else(|_| source.to_path_buf());
+        let copy = safe_copy_file(&picked, &dest, options.durable, Some(&options.cancel));
+        if copy.is_err() && renamed {
+            // Drop the empty placeholder `conflict_dest` reserved.
+            let _ = std::fs::remove_file(&dest);
+        }
+        let bytes = match copy {
+            Ok(bytes) => bytes,
+            Err(_) if options.cancel.forced() => {
+                let bytes_total = std::fs::metadata(source).map(|m| m.len()).unwrap_or(0);
+                emit_cancelled(app, import_id, 0, 1, 0, bytes_total);
+                return Ok(ImportResult {
+                    cancelled: true,
+                    ..ImportResult::empty(import_id, &dest, options.durable)
+                });
+            }
+            Err(e) => {
+                emit_error(app, import_id, &e);
+                return Err(e);
+            }
+        };
+        let mut errors = ImportErrors::default();
+        if let (true, Some(parent)) = (options.durable, dest.parent()) {
+            let mut unsynced: std::collections::BTreeSet<PathBuf> = [parent.to_path_buf()].into();
+            if let Some(top) = &new_top {
+                unsynced.extend(new_dir_parents(top, parent).map(Path::to_path_buf));
+            }
+            sync_dirs(&mut unsynced, &mut errors);
+        }
+        if options.verify {
+            if let (Some(parent), Some(landed)) = (dest.parent(), dest.file_name()) {
+                errors.extend(verify_copied_files(parent, &[(PathBuf::from(landed), source)]));
+            }
+        }
+
+        emit_progress(
+            app,
+            ImportProgress {
+                import_id: import_id.to_string(),
+                processed: 1,
+                total: 1,
+                bytes_processed: bytes,
+                bytes_total: bytes,
+                eta_secs: None,
+                current_file: file_name.to_string_lossy().to_string(),
+                current_file_raw: raw_path(Path::new(file_name)),
+                phase: PHASE_DONE.to_string(),
+            },
+        );
+
+        let (errors, errors_total) = errors.finish();
+        return Ok(ImportResult {
+            files_copied: 1,
+            bytes_copied: bytes,
+            errors_truncated: errors_total > errors.len() as u64,
+            errors_total,
+            errors,
+            ..ImportResult::empty(import_id, &dest, options.durable)
+        });
+    }
+
+    // Directory import
+    if !source.is_dir() {
+        let msg = match std::fs::metadata(source)
+            .ok()
+            .and_then(|m| special_file_kind(&m.file_type()))
+        {
+            Some(kind) => format!("Cannot import a {}: {}", kind, source.display()),
+            None => format!(
+                "Source is neither a file nor a directory: {}",
+                source.display()
+            ),
+        };
+        emit_error(app, import_id, &msg);
+        return Err(msg);
+    }
+
+    let folder_name = source
+        .file_name()
+        .ok_or_else(|| "Cannot determine folder name".to_string())?;
+    let dest_root = dest_base.join(folder_name);
+
+    // Verify destination root stays within workspace (no side effects)
+    ensure_within_workspace(&dest_root, workspace).map_err(|e| {
+        emit_error(app, import_id, &e);
+        e
+    })?;
+
+    // Always create dest_root so even empty folders appear in the workspace.
+    // Post-creation containment check guards against TOCTOU parent swap.
+    let new_root_top = if options.durable {
+        topmost_missing_dir(&dest_root).map(Path::to_path_buf)
+    } else {
+        None
+    };
+    safe_create_dir(&dest_root, workspace).map_err(|e| {
+        emit_error(app, import_id, &e);
+        e
+    })?;
+
+    // Small folders are copied without scanning/copying/verifying events: the
+    // whole import takes less time than the progress UI would be on screen.
+    // Which this is comes out of the scan, which announces itself only once
+    // the tree is past the limits.
+    let mut quiet = true;
+
+    // Phase 1: Scan - count files (see `scan_import_source`)
+    let scanning = || {
+        quiet = false;
+        emit_progress(
+            app,
+            ImportProgress {
+                import_id: import_id.to_string(),
+                processed: 0,
+                total: 0,
+                bytes_processed: 0,
+                bytes_total: 0,
+                eta_secs: None,
+                current_file: String::new(),
+                current_file_raw: None,
+                phase: PHASE_SCANNING.to_string(),
+            },
+        );
+    };
+
+    let ImportScan {
+        entries,
+        dir_entries,
+        files_not_modified,
+        filtered_dirs,
+        collisions,
+        case_renames,
+        special_files,
+        bytes: bytes_total,
+        ignored: files_ignored,
+    } = scan_import_source(
+        source,
+        is_case_insensitive(&dest_root).then_some(dest_root.as_path()),
+        options,
+        scanning,
+    );
+    let total_files = entries.len() as u64;
+    if quiet {
+        eprintln!("[commands] Import {} is small; skipping progress events", import_id);
+    }
+
+    eprintln!(
+        "[commands] Scanned {} files to import into {} ({} older than modified_since, {} ignored)",
+        total_files,
+        dest_root.display(),
+        files_not_modified,
+        files_ignored
+    );
+    if !special_files.is_empty() {
+        eprintln!(
+            "[commands] Skipping {} FIFOs/sockets/devices: {}",
+            special_files.len(),
+            special_files.join(", ")
+        );
+    }
+    if !collisions.is_empty() {
+        eprintln!(
+            "[commands] {} case collisions on a case-insensitive destination ({})",
+            collisions.len(),
+            if options.rename_case_collisions { "renamed" } else { "skipped" }
+        );
+    }
+
+    // Phase 2: Copy files
+    // files_copied counts only successful copies; files_processed drives progress
+    let mut files_copied: u64 = 0;
+    let mut files_processed: u64 = 0;
+    let mut files_skipped: u64 = 0;
+    let mut bytes_copied: u64 = 0;
+    // bytes_copied plus the scanned size of every file not copied, for progress
+    let mut bytes_processed: u64 = 0;
+    let mut errors = ImportErrors::default();
+    let mut failed_parents = FailedParents::default();
+    // (relative destination path, source path) of each successful copy, for `verify`.
+    let mut copied: Vec<(PathBuf, &Path)> = Vec::new();
+
+    // Batch progress: emit every N files to avoid flooding IPC
+    let emit_interval = if total_files > 1000 { 10 } else { 1 };
+
+    // Something else on the system can fill the disk mid-import; stop cleanly
+    // before the next write fails instead of erroring on every remaining file.
+    let mut free_space = crate::vm::runtime::FreeSpaceWatch::new(workspace);
+    // Directories holding durable copies whose renames haven't been fsynced yet.
+    let mut unsynced_dirs = std::collections::BTreeSet::new();
+    if let Some(top) = &new_root_top {
+        unsynced_dirs.extend(new_dir_parents(top, &dest_root).map(Path::to_path_buf));
+    }
+    let mut cancelled = false;
+    let copy_started = std::time::Instant::now();
+
+    for (source_file, relative, size) in &entries {
+        let dest_file = dest_root.join(relative);
+
+        // Checked only between files: a soft cancel lets the last copy finish.
+        if options.cancel.requested() {
+            cancelled = true;
+            break;
+        }
+
+        if let Err(e) = free_space.check(bytes_total.saturating_sub(bytes_processed)) {
+            eprintln!(
+                "[commands] Import {} stopped after {} of {} files: {}",
+                import_id, files_copied, total_files, e
+            );
+            emit_error(app, import_id, &e);
+            return Err(e);
+        }
+
+        // Verify each file's destination stays within workspace before creating dirs
+        if let Err(e) = ensure_within_workspace(&dest_file, workspace) {
+            errors.push(format!("{}: {}", path_label(relative), e));
+            files_processed += 1;
+            bytes_processed += size;
+            continue;
+        }
+
+        // Create parent directories with post-creation containment check. Once
+        // a directory has failed, files under it are only counted against it.
+        if failed_parents.absorb(relative) {
+            files_processed += 1;
+            bytes_processed += size;
+            continue;
+        }
+        let new_top = if options.durable {
+            dest_file.parent().and_then(topmost_missing_dir).map(Path::to_path_buf)
+        } else {
+            None
+        };
+        if let Err((dir, e)) = safe_create_parent_dirs(&dest_file, workspace) {
+            // Above the import root, every file fails the same way.
+            failed_parents.record(dir.strip_prefix(&dest_root).unwrap_or(Path::new("")), e);
+            files_processed += 1;
+            bytes_processed += size;
+            continue;
+        }
+        if let (Some(top), Some(parent)) = (&new_top, dest_file.parent()) {
+            unsynced_dirs.extend(new_dir_parents(top, parent).map(Path::to_path_buf));
+        }
+
+        // Decided per file, just before the copy, so files that appeared since
+        // the scan are caught too.
+        let Some(target) = conflict_dest(dest_file.clone(), options.conflict_mode) else {
+            files_skipped += 1;
+            files_processed += 1;
+            bytes_processed += size;
+            continue;
+        };
+        let renamed = target != dest_file;
+        let dest_file = target;
+
+        // Copy file (O_NOFOLLOW prevents writing through symlinks)
+        let copy = safe_copy_file(source_file, &dest_file, options.durable, Some(&options.cancel));
+        if copy.is_err() && renamed {
+            let _ = std::fs::remove_file(&dest_file);
+        }
+        match copy {
+            Ok(bytes) => {
+                files_copied += 1;
+                bytes_copied += bytes;
+                bytes_processed += bytes;
+                if options.durable {
+                    if let Some(parent) = dest_file.parent() {
+                        unsynced_dirs.insert(parent.to_path_buf());
+                    }
+                    if files_copied as usize % DURABLE_DIR_SYNC_EVERY == 0 {
+                        sync_dirs(&mut unsynced_dirs, &mut errors);
+                    }
+                }
+                if options.verify {
+                    let landed = dest_file.strip_prefix(&dest_root).unwrap_or(relative);
+                    copied.push((landed.to_path_buf(), source_file.as_path()));
+                }
+            }
+            Err(_) if options.cancel.forced() => {
+                cancelled = true;
+                break;
+            }
+            Err(e) => {
+                errors.push(format!("{}: {}", path_label(relative), e));
+                bytes_processed += size;
+            }
+        }
+        files_processed += 1;
+
+        // Emit progress (batched)
+        if !quiet && (files_processed % emit_interval == 0 || files_processed == total_files) {
+            emit_progress(
+                app,
+                ImportProgress {
+                    import_id: import_id.to_string(),
+                    processed: files_processed,
+                    total: total_files,
+                    bytes_processed,
+                    bytes_total,
+                    eta_secs: eta_secs(copy_started.elapsed(), bytes_processed, bytes_total),
+                    current_file: relative.display().to_string(),
+                    current_file_raw: raw_path(relative),
+                    phase: PHASE_COPYING.to_string(),
+                },
+            );
+        }
+    }
+
+    sync_dirs(&mut unsynced_dirs, &mut errors);
+    failed_parents.into_errors(&mut errors);
+
+    // A cancelled import returns what it copied so far; the finishing passes
+    // below would only touch more of a tree the user asked to stop writing.
+    if cancelled {
+        let (errors, errors_total) = errors.finish();
+        emit_cancelled(app, import_id, files_copied, total_files, bytes_processed, bytes_total);
+        return Ok(ImportResult {
+            import_id: import_id.to_string(),
+            files_copied,
+            bytes_copied,
+            dest_path: dest_root.display().to_string(),
+            errors_truncated: errors_total > errors.len() as u64,
+            errors_total,
+            errors,
+            files_not_modified,
+            files_skipped,
+            files_ignored,
+            collisions,
+            case_renames,
+            special_files,
+            durable: options.durable,
+            cancelled: true,
+        });
+    }
+
+    // Create empty directories that weren't already created as file parents.
+    // Non-empty dirs were created by safe_create_parent_dirs during file copy.
+    for rel_dir in &dir_entries {
+        let dest_dir = dest_root.join(rel_dir);
+        if dest_dir.exists() {
+            continue; // Already created as a file parent
+        }
+        if filtered_dirs.contains(rel_dir) {
+            continue; // Only held files older than modified_since
+        }
+        if let Err(e) = ensure_within_workspace(&dest_dir, workspace) {
+            errors.push(format!("dir {}: {}", path_label(rel_dir), e));
+            continue;
+        }
+        let new_top = if options.durable {
+            topmost_missing_dir(&dest_dir).map(Path::to_path_buf)
+        } else {
+            None
+        };
+        if let Err(e) = safe_create_dir(&dest_dir, workspace) {
+            errors.push(format!("dir {}: {}", path_label(rel_dir), e));
+        } else if let Some(top) = &new_top {
+            unsynced_dirs.extend(new_dir_parents(top, &dest_dir).map(Path::to_path_buf));
+        }
+    }
+    sync_dirs(&mut unsynced_dirs, &mut errors);
+
+    if options.preserve_dir_times {
+        apply_dir_times(source, &dest_root, &dir_entries, &mut errors);
+    }
+
+    // Phase 3: Verify (optional) — catch copies that reported success but didn't
+    // fully land, e.g. a disk filling up mid-write.
+    if options.verify {
+        if !quiet {
+            emit_progress(
+                app,
+                ImportProgress {
+                    import_id: import_id.to_string(),
+                    processed: files_processed,
+                    total: total_files,
+                    bytes_processed,
+                    bytes_total,
+                    eta_secs: None,
+                    current_file: String::new(),
+                    current_file_raw: None,
+                    phase: PHASE_VERIFYING.to_string(),
+                },
+            );
+        }
+        let problems = verify_copied_files(&dest_root, &copied);
+        eprintln!(
+            "[commands] Verified {} copied files: {} missing or mismatched",
+            copied.len(),
+            problems.len()
+        );
+        errors.extend(problems);
+    }
+
+    // Phase 4: Done
+    let (errors, errors_total) = errors.finish();
+    if errors_total > 0 {
+        eprintln!(
+            "[commands] Import completed with {} errors out of {} files",
+            errors_total, total_files
+        );
+    }
+
+    emit_progress(
+        app,
+        ImportProgress {
+            import_id: import_id.to_string(),
+            processed: files_processed,
+            total: total_files,
+            bytes_processed,
+            bytes_total,
+            eta_secs: None,
+            current_file: String::new(),
+            current_file_raw: None,
+            phase: PHASE_DONE.to_string(),
+        },
+    );
+
+    Ok(ImportResult {
+        import_id: import_id.to_string(),
+        files_copied,
+        bytes_copied,
+        dest_path: dest_root.display().to_string(),
+        errors_truncated: errors_total > errors.len() as u64,
+        errors_total,
+        errors,
+        files_not_modified,
+        files_skipped,
+        files_ignored,
+        collisions,
+        case_renames,
+        special_files,
+        durable: options.durable,
+        cancelled: false,
+    })
+}
+
+/// Export a file or directory from the workspace to a host directory the user
+/// chose — the reverse of `import_folder`.
+///
+/// - `source_subpath` is relative to the workspace; empty exports the whole
+///   workspace.
+/// - A directory is copied to `{dest_path}/{folder_name}/`, a file to
+///   `{dest_path}/{file_name}`. `dest_path` must be an existing directory.
+/// - Conflicts: merge with overwrite, as on import.
+/// - Emits "folder-export-progress" events (`ExportProgress`) and shows in
+///   `active_exports` while running; `cancel_export` stops it, as
+///   `cancel_import` does an import, and the files copied so far are returned.
+///
+/// Security: source_subpath is validated to stay inside the workspace, and
+/// symlinks in the exported tree are NOT followed, so an export can't carry out
+/// files a link inside the workspace points at. The destination may not be in
+/// the workspace (or contain it), and directories are created without passing
+/// through existing symlinks in the destination tree.
+#[tauri::command]
+pub async fn export_folder(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, WorkspaceState>,
+    source_subpath: String,
+    dest_path: String,
+) -> Result<ExportResult, String> {
+    let workspace = state.available_path()?;
+    let (source, dest_root) = export_paths(&workspace, &source_subpath, &dest_path)?;
+
+    let export_id = transfer_id();
+    eprintln!(
+        "[commands] Exporting {} to {} ({})",
+        source.display(),
+        dest_root.display(),
+        export_id
+    );
+
+    // Registered and checked against clear/relocate the same way as an import.
+    if let Ok(mut active) = state.active_exports.lock() {
+        active.insert(
+            export_id.clone(),
+            ExportProgress {
+                export_id: export_id.clone(),
+                processed: 0,
+                total: 0,
+                bytes_processed: 0,
+                bytes_total: 0,
+                eta_secs: None,
+                current_file: String::new(),
+                current_file_raw: None,
+                phase: PHASE_SCANNING.to_string(),
+            },
+        );
+    }
+    let busy = if state.clearing.load(std::sync::atomic::Ordering::SeqCst) {
+        Some("cleared")
+    } else if state.relocating.load(std::sync::atomic::Ordering::SeqCst) {
+        Some("moved")
+    } else {
+        None
+    };
+    if let Some(busy) = busy {
+        if let Ok(mut active) = state.active_exports.lock() {
+            active.remove(&export_id);
+        }
+        return Err(format!("The workspace is being {busy}; try again when it finishes"));
+    }
+
+    let cancel = std::sync::Arc::new(ImportCancel::default());
+    if let Ok(mut cancels) = state.transfer_cancels.lock() {
+        cancels.insert(export_id.clone(), cancel.clone());
+    }
+
+    let id = export_id.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let emit = |progress| emit_export_progress(&app, progress);
+        do_export(&emit, &source, &dest_root, &id, &cancel)
+    })
+    .await;
+    if let Ok(mut active) = state.active_exports.lock() {
+        active.remove(&export_id);
+    }
+    if let Ok(mut cancels) = state.transfer_cancels.lock() {
+        cancels.remove(&export_id);
+    }
+    result.map_err(|e| format!("Export task failed: {}", e))?
+}
+
+/// Cancel an in-flight export, as `cancel_import` does an import: by default
+/// after the current file, with `force` abandoning it too. The export then
+/// returns the files copied so far with `ExportResult::cancelled` set.
+#[tauri::command]
+pub fn cancel_export(
+    state: tauri::State<'_, WorkspaceState>,
+    export_id: String,
+    force: Option<bool>,
+) -> Result<(), String> {
+    let cancel = state
+        .transfer_cancels
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&export_id)
+        .cloned()
+        .ok_or_else(|| format!("No export {} in progress", export_id))?;
+    let force = force.unwrap_or(false);
+    eprintln!(
+        "[commands] Cancelling export {}{}",
+        export_id,
+        if force { " (forced)" } else { "" }
+    );
+    cancel.request(force);
+    Ok(())
+}
+
+/// In-flight exports with their latest progress, oldest first (as
+/// `active_imports`).
+#[tauri::command]
+pub fn active_exports(state: tauri::State<'_, WorkspaceState>) -> Vec<ExportProgress> {
+    let mut exports: Vec<ExportProgress> = state
+        .active_exports
+        .lock()
+        .map(|active| active.values().cloned().collect())
+        .unwrap_or_default();
+    exports.sort_by_key(|p| {
+        p.export_id
+            .split('-')
+            .skip(1)
+            .map(|n| n.parse::<u128>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    });
+    exports
+}
+
+/// The workspace entry `source_subpath` names and where exporting it into the
+/// host directory `dest_path` puts it (`dest_path/<name>`).
+fn export_paths(
+    workspace: &Path,
+    source_subpath: &str,
+    dest_path: &str,
+) -> Result<(PathBuf, PathBuf), String> {
+    let source = workspace.join(validate_subpath(source_subpath)?);
+    ensure_within_workspace(&source, workspace)?;
+    let meta = std::fs::symlink_metadata(&source)
+        .map_err(|e| format!("Source not found: {}: {}", source_subpath, e))?;
+    if meta.file_type().is_symlink() {
+        return Err(format!("Cannot export a symlink: {}", source_subpath));
+    }
+    if let Some(kind) = special_file_kind(&meta.file_type()) {
+        return Err(format!("Cannot export a {}: {}", kind, source_subpath));
+    }
+
+    let dest = PathBuf::from(dest_path);
+    if !dest.is_absolute() {
+        return Err(format!("Export destination must be absolute, got: {}", dest_path));
+    }
+    let dest = dest
+        .canonicalize()
+        .map_err(|e| format!("Export destination not found: {}: {}", dest_path, e))?;
+    if !dest.is_dir() {
+        return Err(format!("Export destination is not a directory: {}", dest_path));
+    }
+    let name = source
+        .canonicalize()
+        .ok()
+        .and_then(|s| s.file_name().map(|n| n.to_os_string()))
+        .ok_or_else(|| "Cannot determine the name to export under".to_string())?;
+    let dest_root = dest.join(name);
+
+    // Exporting into the workspace would copy a tree into itself; exporting
+    // over a directory that holds the workspace could overwrite files in it.
+    let canonical_workspace = workspace
+        .canonicalize()
+        .map_err(|e| format!("Cannot resolve workspace path: {}", e))?;
+    if dest_root.starts_with(&canonical_workspace) || canonical_workspace.starts_with(&dest_root) {
+        return Err(format!(
+            "Export destination {} overlaps the workspace {}",
+            dest_root.display(),
+            canonical_workspace.display()
+        ));
+    }
+    Ok((source, dest_root))
+}
+
+/// Create `root/relative` one directory at a time, refusing to go through an
+/// existing symlink (or onto a file): an export writes where the user pointed
+/// it, not wherever a link in the destination tree leads. The error carries
+/// the directory (relative to `root`) that failed.
+fn create_export_dir(root: &Path, relative: &Path) -> Result<(), (PathBuf, String)> {
+    let mut dir = root.to_path_buf();
+    let mut failed = PathBuf::new();
+    for component in relative.components() {
+        dir.push(component);
+        failed.push(component);
+        match std::fs::create_dir(&dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if !std::fs::symlink_metadata(&dir).is_ok_and(|m| m.is_dir()) {
+                    return Err((
+                        failed,
+                        format!("{} already exists and is not a directory", dir.display()),
+                    ));
+                }
+            }
+            Err(e) => {
+                return Err((
+                    failed,
+                    format!("Failed to create directory {}: {}", dir.display(), e),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Emit a `folder-export-progress` event and mirror it into
+/// `WorkspaceState::active_exports`, as `emit_progress` does for imports.
+fn emit_export_progress(app: &tauri::AppHandle, progress: ExportProgress) {
+    use tauri::Manager;
+    if let Some(state) = app.try_state::<WorkspaceState>() {
+        if let Ok(mut active) = state.active_exports.lock() {
+            if is_terminal_phase(&progress.phase) {
+                active.remove(&progress.export_id);
+            } else {
+                active.insert(progress.export_id.clone(), progress.clone());
+            }
+        }
+    }
+    let _ = app.emit("folder-export-progress", progress);
+}
+
+fn emit_export_error(emit: &dyn Fn(ExportProgress), export_id: &str, message: &str) {
+    emit(ExportProgress {
+        export_id: export_id.to_string(),
+        processed: 0,
+        total: 0,
+        bytes_processed: 0,
+        bytes_total: 0,
+        eta_secs: None,
+        current_file: message.to_string(),
+        current_file_raw: None,
+        phase: PHASE_ERROR.to_string(),
+    });
+}
+
+/// `export_folder`'s copy, on a blocking thread. `source` and `dest_root` come
+/// from `export_paths`; `cancel` is set by `cancel_export`. Progress goes to
+/// `emit` (`emit_export_progress` in the app).
+fn do_export(
+    emit: &dyn Fn(ExportProgress),
+    source: &Path,
+    dest_root: &Path,
+    export_id: &str,
+    cancel: &ImportCancel,
+) -> Result<ExportResult, String> {
+    let fail = |e: String| {
+        emit_export_error(emit, export_id, &e);
+        e
+    };
+    let progress = |processed, total, bytes_processed, bytes_total, current: &Path, phase: &str| {
+        emit(ExportProgress {
+            export_id: export_id.to_string(),
+            processed,
+            total,
+            bytes_processed,
+            bytes_total,
+            eta_secs: None,
+            current_file: current.display().to_string(),
+            current_file_raw: raw_path(current),
+            phase: phase.to_string(),
+        })
+    };
+    let dest_dir = dest_root.parent().unwrap_or(dest_root);
+    let mut free_space = crate::vm::runtime::FreeSpaceWatch::new(dest_dir);
+
+    if source.is_file() {
+        let size = std::fs::metadata(source).map(|m| m.len()).unwrap_or(0);
+        free_space.check(size).map_err(fail)?;
+        let name = dest_root.file_name().map(Path::new).unwrap_or(dest_root);
+        let (bytes, cancelled) = match safe_copy_file(source, dest_root, false, Some(cancel)) {
+            Ok(bytes) => (bytes, false),
+            Err(_) if cancel.forced() => (0, true),
+            Err(e) => return Err(fail(e)),
+        };
+        let phase = if cancelled { PHASE_CANCELLED } else { PHASE_DONE };
+        progress(u64::from(!cancelled), 1, bytes, size, name, phase);
+        return Ok(ExportResult {
+            export_id: export_id.to_string(),
+            files_copied: u64::from(!cancelled),
+            bytes_copied: bytes,
+            dest_path: dest_root.display().to_string(),
+            errors: vec![],
+            errors_total: 0,
+            errors_truncated: false,
+            symlinks_skipped: 0,
+            special_files: vec![],
+            cancelled,
+        });
+    }
+
+    create_export_dir(dest_dir, Path::new(dest_root.file_name().unwrap_or_default()))
+        .map_err(|(_, e)| fail(e))?;
+
+    // Phase 1: Scan. follow_links(false), as on import: a symlink in the
+    // workspace may point anywhere on the host.
+    progress(0, 0, 0, 0, Path::new(""), PHASE_SCANNING);
+    let mut files: Vec<(PathBuf, u64)> = Vec::new();
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let mut special_files = Vec::new();
+    let mut symlinks_skipped = 0u64;
+    let mut bytes_total = 0u64;
+    let mut errors = ImportErrors::default();
+    for entry in WalkDir::new(source).follow_links(false).sort_by_file_name() {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                errors.push(format!("unreadable entry: {}", e));
+                continue;
+            }
+        };
+        let relative = entry
+            .path()
             .strip_prefix(source)
             .unwrap_or(entry.path())
             .to_path_buf();
+        let file_type = entry.file_type();
+        if file_type.is_file() {
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            bytes_total += size;
+            files.push((relative, size));
+        } else if file_type.is_dir() {
+            if entry.path() != source {
+                dirs.push(relative);
+            }
+        } else if file_type.is_symlink() {
+            symlinks_skipped += 1;
+        } else if let Some(kind) = special_file_kind(&file_type) {
+            special_files.push(format!("{} ({})", path_label(&relative), kind));
+        }
+    }
+    let total_files = files.len() as u64;
+    eprintln!(
+        "[commands] Scanned {} files to export to {} ({} symlinks skipped)",
+        total_files,
+        dest_root.display(),
+        symlinks_skipped
+    );
+
+    // Phase 2: Copy
+    let mut files_copied: u64 = 0;
+    let mut files_processed: u64 = 0;
+    let mut bytes_copied: u64 = 0;
+    let mut bytes_processed: u64 = 0;
+    let mut failed_parents = FailedParents::default();
+    let mut cancelled = false;
+    let emit_interval = if total_files > 1000 { 10 } else { 1 };
+    let copy_started = std::time::Instant::now();
+
+    for (relative, size) in &files {
+        // Checked only between files: a soft cancel lets the last copy finish.
+        if cancel.requested() {
+            cancelled = true;
+            break;
+        }
+        if let Err(e) = free_space.check(bytes_total.saturating_sub(bytes_processed)) {
+            eprintln!(
+                "[commands] Export {} stopped after {} of {} files: {}",
+                export_id, files_copied, total_files, e
+            );
+            return Err(fail(e));
+        }
+        files_processed += 1;
+        if failed_parents.absorb(relative) {
+            bytes_processed += size;
+            continue;
+        }
+        if let Err((dir, e)) =
+            create_export_dir(dest_root, relative.parent().unwrap_or(Path::new("")))
+        {
+            failed_parents.record(&dir, e);
+            bytes_processed += size;
+            continue;
+        }
+        let dest_file = dest_root.join(relative);
+        match safe_copy_file(&source.join(relative), &dest_file, false, Some(cancel)) {
+            Ok(bytes) => {
+                files_copied += 1;
+                bytes_copied += bytes;
+                bytes_processed += bytes;
+            }
+            Err(_) if cancel.forced() => {
+                cancelled = true;
+                break;
+            }
+            Err(e) => {
+                errors.push(format!("{}: {}", path_label(relative), e));
+                bytes_processed += size;
+            }
+        }
+
+        if files_processed % emit_interval == 0 || files_processed == total_files {
+            emit(ExportProgress {
+                export_id: export_id.to_string(),
+                processed: files_processed,
+                total: total_files,
+                bytes_processed,
+                bytes_total,
+                eta_secs: eta_secs(copy_started.elapsed(), bytes_processed, bytes_total),
+                current_file: relative.display().to_string(),
+                current_file_raw: raw_path(relative),
+                phase: PHASE_COPYING.to_string(),
+            });
+        }
+    }
+    failed_parents.into_errors(&mut errors);
+
+    // Empty directories weren't created as file parents. A cancelled export
+    // stops writing instead.
+    if !cancelled {
+        for relative in &dirs {
+            if let Err((_, e)) = create_export_dir(dest_root, relative) {
+                errors.push(format!("dir {}: {}", path_label(relative), e));
+            }
+        }
+    }
+
+    // Phase 3: Done (or cancelled)
+    let (errors, errors_total) = errors.finish();
+    if cancelled {
+        eprintln!(
+            "[commands] Export {} cancelled after {} of {} files",
+            export_id, files_copied, total_files
+        );
+    } else if errors_total > 0 {
+        eprintln!(
+            "[commands] Export completed with {} errors out of {} files",
+            errors_total, total_files
+        );
+    }
+    let phase = if cancelled { PHASE_CANCELLED } else { PHASE_DONE };
+    progress(files_processed, total_files, bytes_processed, bytes_total, Path::new(""), phase);
+
+    Ok(ExportResult {
+        export_id: export_id.to_string(),
+        files_copied,
+        bytes_copied,
+        dest_path: dest_root.display().to_string(),
+        errors_truncated: errors_total > errors.len() as u64,
+        errors_total,
+        errors,
+        symlinks_skipped,
+        special_files,
+        cancelled,
+    })
+}
+
+/// Default and ceiling for `preview_import_tree`'s `max_nodes`.
+const PREVIEW_DEFAULT_NODES: usize = 1000;
+const PREVIEW_MAX_NODES: usize = 20_000;
+
+/// A file or directory in an import preview.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ImportTreeNode {
+    pub name: String,
+    pub is_dir: bool,
+    /// File size; `None` for directories.
+    pub bytes: Option<u64>,
+    pub children: Vec<ImportTreeNode>,
+}
+
+impl ImportTreeNode {
+    fn dir(name: String) -> Self {
+        Self {
+            name,
+            is_dir: true,
+            bytes: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Add `relative` below this node, creating missing directories on the way,
+    /// unless that would take the tree past `*budget` more nodes. Returns false
+    /// (adding nothing) when it would.
+    fn insert(&mut self, relative: &Path, bytes: Option<u64>, budget: &mut usize) -> bool {
+        let names: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let mut node = &mut *self;
+        let mut missing = names.len();
+        for name in &names {
+            match node.children.iter().position(|c| c.is_dir && &c.name == name) {
+                Some(i) => {
+                    node = &mut node.children[i];
+                    missing -= 1;
+                }
+                None => break,
+            }
+        }
+        if missing == 0 {
+            return true; // already there (a directory seen as a file's parent)
+        }
+        if missing > *budget {
+            return false;
+        }
+        *budget -= missing;
+        let (leaf, dirs) = names[names.len() - missing..].split_last().expect("missing > 0");
+        for name in dirs {
+            node.children.push(ImportTreeNode::dir(name.clone()));
+            node = node.children.last_mut().expect("just pushed");
+        }
+        node.children.push(ImportTreeNode {
+            name: leaf.clone(),
+            is_dir: bytes.is_none(),
+            bytes,
+            children: Vec::new(),
+        });
+        true
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ImportTreePreview {
+    /// Where the import would land (the `dest_path` `import_folder` reports).
+    pub dest_path: String,
+    /// The destination root: the source folder (or file) under its own name.
+    pub root: ImportTreeNode,
+    /// Totals for the whole import, including anything past the node cap.
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+    /// The tree stops at `max_nodes`; directories are placed before files.
+    pub truncated: bool,
+    /// Entries left out by `exclude_globs`; an excluded directory counts once.
+    pub excluded: u64,
+    pub collisions: Vec<String>,
+    pub case_renames: std::collections::BTreeMap<String, String>,
+    pub special_files: Vec<String>,
+}
+
+/// The destination tree an import of `source_path` into `dest_subpath` would
+/// create, computed from the same scan `import_folder` runs and without
+/// writing anything. `exclude_globs` are gitignore-style (see
+/// `compile_ignore_patterns`). The tree is capped at `max_nodes` nodes (default
+/// 1000, at most 20000) and flagged `truncated` past that; the totals always
+/// cover everything.
+#[tauri::command]
+pub async fn preview_import_tree(
+    state: tauri::State<'_, WorkspaceState>,
+    source_path: String,
+    dest_subpath: Option<String>,
+    exclude_globs: Option<Vec<String>>,
+    max_nodes: Option<usize>,
+) -> Result<ImportTreePreview, String> {
+    let workspace = state.available_path()?;
+    let source = PathBuf::from(&source_path);
+    if !source.exists() {
+        return Err(format!("Source not found: {}", source_path));
+    }
+    let options = ImportOptions {
+        ignore: compile_ignore_patterns(&exclude_globs.unwrap_or_default())?,
+        ..Default::default()
+    };
+    let max_nodes = max_nodes
+        .unwrap_or(PREVIEW_DEFAULT_NODES)
+        .clamp(1, PREVIEW_MAX_NODES);
+    tauri::async_runtime::spawn_blocking(move || {
+        let dest_base = import_dest_base(&workspace, dest_subpath.as_deref())?;
+        let name = source
+            .file_name()
+            .ok_or_else(|| "Cannot determine source name".to_string())?;
+        let dest_root = dest_base.join(name);
+        ensure_within_workspace(&dest_root, &workspace)?;
+        Ok(preview_tree(&source, &dest_root, &workspace, &options, max_nodes))
+    })
+    .await
+    .map_err(|e| format!("Preview task failed: {}", e))?
+}
 
-        if entry.file_type().is_file() {
-            let abs = entry.path().to_path_buf();
-            entries.push((abs, relative));
-            total_files += 1;
-        } else if entry.file_type().is_dir() && entry.path() != source {
-            // Collect subdirectories (skip the root source dir itself).
-            // WalkDir yields parents before children, preserving creation order.
-            dir_entries.push(relative);
-        }
-        // Symlinks (entry.file_type().is_symlink()) are silently skipped
+fn preview_tree(
+    source: &Path,
+    dest_root: &Path,
+    workspace: &Path,
+    options: &ImportOptions,
+    max_nodes: usize,
+) -> ImportTreePreview {
+    let name = dest_root
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if source.is_file() {
+        let bytes = std::fs::metadata(source).map(|m| m.len()).unwrap_or(0);
+        return ImportTreePreview {
+            dest_path: dest_root.display().to_string(),
+            root: ImportTreeNode {
+                name,
+                is_dir: false,
+                bytes: Some(bytes),
+                children: Vec::new(),
+            },
+            files: 1,
+            dirs: 0,
+            bytes,
+            truncated: false,
+            excluded: 0,
+            collisions: Vec::new(),
+            case_renames: Default::default(),
+            special_files: Vec::new(),
+        };
     }
 
-    eprintln!(
-        "[commands] Scanned {} files to import into {}",
-        total_files,
-        dest_root.display()
-    );
+    // The destination root may not exist yet; the workspace it lands in is on
+    // the same filesystem.
+    // A preview must not write, so no probe file: see `probe_case_insensitive`.
+    let case_insensitive_dest = probe_case_insensitive(workspace).then_some(dest_root);
+    let scan = scan_import_source(source, case_insensitive_dest, options, || ());
+    let mut root = ImportTreeNode::dir(name);
+    let mut budget = max_nodes.saturating_sub(1); // the root itself
+    let mut truncated = false;
+    let dirs: Vec<&PathBuf> = scan
+        .dir_entries
+        .iter()
+        .filter(|d| !scan.filtered_dirs.contains(*d))
+        .collect();
+    for dir in &dirs {
+        if !truncated {
+            truncated = !root.insert(dir, None, &mut budget);
+        }
+    }
+    let mut bytes = 0;
+    for (_, relative, size) in &scan.entries {
+        let size = *size;
+        bytes += size;
+        if !truncated {
+            truncated = !root.insert(relative, Some(size), &mut budget);
+        }
+    }
+    ImportTreePreview {
+        dest_path: dest_root.display().to_string(),
+        root,
+        files: scan.entries.len() as u64,
+        dirs: dirs.len() as u64,
+        bytes,
+        truncated,
+        excluded: scan.ignored,
+        collisions: scan.collisions,
+        case_renames: scan.case_renames,
+        special_files: scan.special_files,
+    }
+}
 
-    // Phase 2: Copy files
-    // files_copied counts only successful copies; files_processed drives progress
-    let mut files_copied: u64 = 0;
-    let mut files_processed: u64 = 0;
-    let mut bytes_copied: u64 = 0;
-    let mut errors: Vec<String> = Vec::new();
+/// Whether `dir`'s filesystem matches names case-insensitively (the default on
+/// APFS/HFS+ and NTFS): create a probe file and look it up with its case flipped.
+/// Falls back to the platform default if the probe can't be written.
+fn is_case_insensitive(dir: &Path) -> bool {
+    let name = format!(".orcabot-Case-Probe-{}", std::process::id());
+    let probe = dir.join(&name);
+    if std::fs::write(&probe, b"").is_err() {
+        return cfg!(any(target_os = "macos", windows));
+    }
+    let insensitive = std::fs::symlink_metadata(dir.join(flip_ascii_case(&name))).is_ok();
+    let _ = std::fs::remove_file(&probe);
+    insensitive
+}
 
-    // Batch progress: emit every N files to avoid flooding IPC
-    let emit_interval = if total_files > 1000 { 10 } else { 1 };
+/// [`is_case_insensitive`] without writing anything: looks an entry already in
+/// `dir` up under its case-flipped name (one whose flipped name isn't also
+/// listed). Falls back to the platform default when `dir` has no such entry.
+fn probe_case_insensitive(dir: &Path) -> bool {
+    let names: std::collections::HashSet<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+    let flipped = names
+        .iter()
+        .map(|name| flip_ascii_case(name))
+        .find(|flipped| !names.contains(flipped));
+    match flipped {
+        Some(flipped) => std::fs::symlink_metadata(dir.join(flipped)).is_ok(),
+        None => cfg!(any(target_os = "macos", windows)),
+    }
+}
 
-    for (source_file, relative) in &entries {
-        let dest_file = dest_root.join(relative);
+fn flip_ascii_case(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_uppercase() {
+                c.to_ascii_lowercase()
+            } else {
+                c.to_ascii_uppercase()
+            }
+        })
+        .collect()
+}
 
-        // Verify each file's destination stays within workspace before creating dirs
-        if let Err(e) = ensure_within_workspace(&dest_file, workspace) {
-            errors.push(format!("{}: {}", relative.display(), e));
-            files_processed += 1;
-            continue;
-        }
+/// Case-folded form of a relative path, for collision checks.
+fn case_key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
 
-        // Create parent directories with post-creation containment check
-        if let Err(e) = safe_create_parent_dirs(&dest_file, workspace) {
-            errors.push(format!("{}: {}", relative.display(), e));
-            files_processed += 1;
-            continue;
-        }
+/// A directory's entry names, keyed by their case-folded form.
+struct CaseFoldedNames(std::collections::HashMap<String, String>);
+
+impl CaseFoldedNames {
+    /// `dir`'s listing; empty if it can't be read (e.g. it doesn't exist yet).
+    fn read(dir: &Path) -> Self {
+        let names = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().to_string_lossy().into_owned();
+                Some((name.to_lowercase(), name))
+            })
+            .collect();
+        Self(names)
+    }
 
-        // Copy file (O_NOFOLLOW prevents writing through symlinks)
-        match safe_copy_file(source_file, &dest_file) {
-            Ok(bytes) => {
-                files_copied += 1;
-                bytes_copied += bytes;
-            }
-            Err(e) => {
-                errors.push(format!("{}: {}", relative.display(), e));
-            }
-        }
-        files_processed += 1;
+    /// The listed name matching `name` except for case, if it isn't `name` itself.
+    fn case_variant(&self, name: &str) -> Option<&str> {
+        self.0
+            .get(&name.to_lowercase())
+            .map(String::as_str)
+            .filter(|listed| *listed != name)
+    }
 
-        // Emit progress (batched)
-        if files_processed % emit_interval == 0 || files_processed == total_files {
-            let _ = app.emit(
-                "folder-import-progress",
-                ImportProgress {
-                    import_id: import_id.to_string(),
-                    processed: files_processed,
-                    total: total_files,
-                    current_file: relative.display().to_string(),
-                    phase: "copying".to_string(),
-                },
-            );
-        }
+    /// Whether the last component of `key` (a [`case_key`] of a path in this
+    /// directory) is listed.
+    fn lists_key(&self, key: &str) -> bool {
+        let name = key.rsplit(['/', '\\']).next().unwrap_or(key);
+        self.0.contains_key(name)
     }
+}
 
-    // Create empty directories that weren't already created as file parents.
-    // Non-empty dirs were created by safe_create_parent_dirs during file copy.
-    for rel_dir in &dir_entries {
-        let dest_dir = dest_root.join(rel_dir);
-        if dest_dir.exists() {
-            continue; // Already created as a file parent
-        }
-        if let Err(e) = ensure_within_workspace(&dest_dir, workspace) {
-            errors.push(format!("dir {}: {}", rel_dir.display(), e));
-            continue;
+/// `relative` with " (n)" added to its file stem, for the first n ≥ 2 whose
+/// case-folded form isn't `taken`.
+fn case_collision_rename(relative: &Path, taken: impl Fn(&str) -> bool) -> PathBuf {
+    let stem = relative.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = relative
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2u32..)
+        .map(|n| relative.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| !taken(&case_key(p)))
+        .unwrap_or_else(|| relative.to_path_buf())
+}
+
+/// Where an import should write `dest` under `mode`, or `None` to skip it. An
+/// existing entry is detected with `symlink_metadata`, so a symlink counts as
+/// present rather than being resolved to whatever it points at.
+///
+/// `Rename` reserves the name it picks by creating it empty with `create_new`,
+/// so a file that appears there before the copy lands is never overwritten;
+/// the caller removes the placeholder if the copy fails.
+fn conflict_dest(dest: PathBuf, mode: ImportConflictMode) -> Option<PathBuf> {
+    if mode == ImportConflictMode::Overwrite || std::fs::symlink_metadata(&dest).is_err() {
+        return Some(dest);
+    }
+    if mode == ImportConflictMode::Skip {
+        return None;
+    }
+    let stem = dest.file_stem().unwrap_or_default().to_os_string();
+    let ext = dest.extension().map(|e| e.to_os_string());
+    for n in 1u32.. {
+        let mut name = stem.clone();
+        name.push(format!(" ({})", n));
+        if let Some(ext) = &ext {
+            name.push(".");
+            name.push(ext);
         }
-        if let Err(e) = safe_create_dir(&dest_dir, workspace) {
-            errors.push(format!("dir {}: {}", rel_dir.display(), e));
+        let candidate = dest.with_file_name(name);
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            // Anything else (e.g. a read-only directory) is left for the copy
+            // to report.
+            _ => return Some(candidate),
         }
     }
+    None
+}
 
-    // Phase 3: Done
-    if !errors.is_empty() {
-        eprintln!(
-            "[commands] Import completed with {} errors out of {} files",
-            errors.len(),
-            total_files
-        );
-    }
-
-    let _ = app.emit(
-        "folder-import-progress",
-        ImportProgress {
-            import_id: import_id.to_string(),
-            processed: files_processed,
-            total: total_files,
-            current_file: String::new(),
-            phase: "done".to_string(),
-        },
-    );
+/// Check each `expected` (relative destination path, source path) pair and
+/// report every destination that is missing, not a regular file (it is
+/// `symlink_metadata`'d, so a link swapped in doesn't pass), or not the size of
+/// its source as it is now.
+fn verify_copied_files(root: &Path, expected: &[(PathBuf, &Path)]) -> Vec<String> {
+    expected
+        .iter()
+        .filter_map(|(rel, source)| {
+            let problem = match std::fs::symlink_metadata(root.join(rel)) {
+                Err(_) => "missing from destination".to_string(),
+                Ok(dest) if !dest.is_file() => "destination is not a regular file".to_string(),
+                Ok(dest) => match std::fs::symlink_metadata(source) {
+                    Ok(src) if src.len() == dest.len() => return None,
+                    Ok(src) => format!(
+                        "size mismatch (source has {} bytes, destination {})",
+                        src.len(),
+                        dest.len()
+                    ),
+                    Err(e) => format!("cannot read source: {}", e),
+                },
+            };
+            Some(format!("{}: verify: {}", path_label(rel), problem))
+        })
+        .collect()
+}
 
-    Ok(ImportResult {
-        import_id: import_id.to_string(),
-        files_copied,
-        bytes_copied,
-        dest_path: dest_root.display().to_string(),
-        errors,
-    })
+/// Whether `meta`'s mtime is older than `cutoff` (unix seconds). An unreadable
+/// mtime counts as modified: importing too much beats silently dropping a file.
+fn modified_before(meta: &std::fs::Metadata, cutoff: u64) -> bool {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .is_some_and(|d| d.as_secs() < cutoff)
 }
 
 /// Simple timestamp without pulling in chrono crate.
@@ -616,18 +3223,383 @@ pub fn get_app_version(app: tauri::AppHandle) -> String {
     app.package_info().version.to_string()
 }
 
-/// Read this boot's startup log (`<app_data>/startup.log`) — the teed workerd / D1
-/// output plus the chosen ports — so the loading screen can show WHY the backend
-/// failed to start (a Finder-launched .app has no console). Empty string if none.
+/// Read this boot's startup log (`<app_data>/startup.log`) — the teed workerd / D1
+/// output plus the chosen ports — so the loading screen can show WHY the backend
+/// failed to start (a Finder-launched .app has no console). Empty string if none.
+#[tauri::command]
+pub fn read_startup_log(app: tauri::AppHandle) -> String {
+    use tauri::Manager;
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|d| d.join("startup.log"))
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Clone)]
+pub struct CustomVmImage {
+    pub path: String,
+    pub format: crate::vm::image::ImageFormat,
+    /// Non-fatal compatibility concerns (e.g. architecture hint mismatch).
+    pub warnings: Vec<String>,
+}
+
+/// Use a locally-built VM image on the next VM start instead of the bundled or
+/// published one. Validates the file first; returns its sniffed format and any
+/// compatibility warnings. `ORCABOT_VM_IMAGE`, if set, still takes precedence.
+#[tauri::command]
+pub fn set_custom_vm_image(app: tauri::AppHandle, path: String) -> Result<CustomVmImage, String> {
+    use tauri::Manager;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let path = PathBuf::from(path)
+        .canonicalize()
+        .map_err(|e| format!("cannot resolve image path: {e}"))?;
+    let (format, warnings) =
+        crate::vm::image::validate_custom_image(&path).map_err(|e| e.to_string())?;
+    for w in &warnings {
+        eprintln!("[vm-image] custom image {}: {}", path.display(), w);
+    }
+    crate::vm::image::set_custom_image(&data_dir, &path).map_err(|e| e.to_string())?;
+    eprintln!("[vm-image] custom image set to {} (next VM start)", path.display());
+    Ok(CustomVmImage {
+        path: path.display().to_string(),
+        format,
+        warnings,
+    })
+}
+
+/// Choose how Linux QEMU runs the guest: `kvm` (refuse to start without
+/// usable KVM), `tcg` (software emulation, e.g. for compatibility testing) or
+/// `auto` (KVM when available). Saved in settings; applies from the next VM
+/// start. Other backends ignore it.
+#[tauri::command]
+pub fn set_qemu_accel(app: tauri::AppHandle, mode: String) -> Result<(), String> {
+    use tauri::Manager;
+    let accel = crate::vm::QemuAccel::parse(&mode).map_err(|e| e.to_string())?;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    crate::settings::update(&data_dir, |s| s.qemu_accel = Some(accel))
+        .map_err(|e| format!("failed to save settings: {e}"))?;
+    eprintln!("[vm] QEMU accel set to {} (next VM start)", accel.as_str());
+    Ok(())
+}
+
+/// Set the QEMU vCPU layout as `(sockets, cores, threads)`; the VM gets their
+/// product as its vCPU count. `None` goes back to a flat count. Saved in
+/// settings; applies from the next VM start. VZ and WSL ignore it, and
+/// `ORCABOT_VM_CPU_TOPOLOGY` overrides it.
+#[tauri::command]
+pub fn set_vm_cpu_topology(
+    app: tauri::AppHandle,
+    topology: Option<(u32, u32, u32)>,
+) -> Result<(), String> {
+    use tauri::Manager;
+    if let Some((sockets, cores, threads)) = topology {
+        crate::vm::config::cpu_topology_total(sockets, cores, threads).ok_or_else(|| {
+            format!("invalid CPU topology {sockets}x{cores}x{threads} (sockets x cores x threads)")
+        })?;
+    }
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    crate::settings::update(&data_dir, |s| s.cpu_topology = topology)
+        .map_err(|e| format!("failed to save settings: {e}"))?;
+    match topology {
+        Some((sockets, cores, threads)) => {
+            eprintln!("[vm] CPU topology set to {sockets}x{cores}x{threads} (next VM start)")
+        }
+        None => eprintln!("[vm] CPU topology cleared (next VM start)"),
+    }
+    Ok(())
+}
+
+/// Choose the address the frontend and control plane listen on: "127.0.0.1"
+/// (this machine only) or "0.0.0.0" (every network interface); `None` goes
+/// back to 127.0.0.1. Saved in settings; applies at the next launch. Returns
+/// the warning to show for 0.0.0.0, which that launch also refuses unless dev
+/// auth is off and the service tokens are set.
+#[tauri::command]
+pub fn set_bind_address(
+    app: tauri::AppHandle,
+    address: Option<String>,
+) -> Result<Option<String>, String> {
+    use tauri::Manager;
+    let addr = match address.as_deref().map(str::trim) {
+        Some(value) => {
+            let addr: std::net::Ipv4Addr = value
+                .parse()
+                .map_err(|_| format!("{value:?} is not an IPv4 address"))?;
+            crate::settings::check_bind_address(addr)?;
+            Some(addr)
+        }
+        None => None,
+    };
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    crate::settings::update(&data_dir, |s| s.bind_address = addr)
+        .map_err(|e| format!("failed to save settings: {e}"))?;
+    let shown = addr.unwrap_or(std::net::Ipv4Addr::LOCALHOST);
+    eprintln!("[settings] bind_address set to {} (next launch)", shown);
+    Ok(shown.is_unspecified().then(|| {
+        "The frontend and control plane will be reachable from every device on this \
+         network. The next launch stays on 127.0.0.1 unless dev auth is off and \
+         SANDBOX_INTERNAL_TOKEN and INTERNAL_API_TOKEN are set."
+            .to_string()
+    }))
+}
+
+/// Revert `set_custom_vm_image`; the next VM start uses the normal image.
+#[tauri::command]
+pub fn clear_custom_vm_image(app: tauri::AppHandle) -> Result<(), String> {
+    use tauri::Manager;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    crate::vm::image::clear_custom_image(&data_dir).map_err(|e| e.to_string())?;
+    eprintln!("[vm-image] custom image cleared");
+    Ok(())
+}
+
+/// Check a custom kernel, initrd and disk image together before trying to boot
+/// them: headers, formats, and that their architectures agree with each other
+/// and this host where that can be read. Nothing is changed; the report lists
+/// per-file and cross-file problems, and `ok` says whether any were fatal.
+#[tauri::command]
+pub async fn validate_boot_set(
+    kernel: String,
+    initrd: String,
+    image: String,
+) -> Result<crate::vm::image::BootSetReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        crate::vm::image::validate_boot_set(Path::new(&kernel), Path::new(&initrd), Path::new(&image))
+    })
+    .await
+    .map_err(|e| format!("Boot set check failed: {}", e))
+}
+
+/// Which compressions, image formats and boot modes this build can use with the
+/// tooling installed right now (e.g. qcow2 conversion needs qemu-img), so the
+/// custom-image UI can reject a file before the user tries to boot it.
+#[tauri::command]
+pub async fn get_supported_formats() -> Result<crate::vm::image::SupportedFormats, String> {
+    tauri::async_runtime::spawn_blocking(crate::vm::image::supported_formats)
+        .await
+        .map_err(|e| format!("format probe failed: {e}"))
+}
+
+#[derive(Serialize, Clone)]
+pub struct ConvertedVmImage {
+    pub path: String,
+    pub format: crate::vm::image::ImageFormat,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Clone)]
+struct VmImageConvertProgress {
+    dst: String,
+    percent: f64,
+}
+
+/// Why `path` must not be overwritten, if something may be reading it: the
+/// custom image setting, `ORCABOT_VM_IMAGE`, or a staged image (what a running
+/// VM boots from).
+fn vm_image_in_use(app: &tauri::AppHandle, path: &Path) -> Option<&'static str> {
+    use tauri::Manager;
+    let path = path.canonicalize().ok()?;
+    let same = |p: &Path| p.canonicalize().is_ok_and(|p| p == path);
+    let data_dir = app.path().app_data_dir().ok();
+    if data_dir
+        .as_deref()
+        .and_then(crate::vm::image::custom_image)
+        .is_some_and(|p| same(&p))
+    {
+        return Some("it is the custom VM image");
+    }
+    if std::env::var_os("ORCABOT_VM_IMAGE").is_some_and(|p| same(Path::new(&p))) {
+        return Some("it is ORCABOT_VM_IMAGE");
+    }
+    let staged_dirs = [
+        app.path().app_cache_dir().ok().map(|d| d.join("vm")),
+        data_dir.map(|d| d.join("vm")),
+    ];
+    staged_dirs
+        .iter()
+        .flatten()
+        .filter_map(|d| d.canonicalize().ok())
+        .any(|d| path.starts_with(d))
+        .then_some("it is a staged VM image")
+}
+
+/// Convert a VM disk image between raw and qcow2 (`format`) with
+/// `qemu-img convert`, emitting `vm-image-convert-progress`. Refuses a `dst`
+/// that may be in use (see `vm_image_in_use`) or that is `src` itself, and
+/// checks the destination has room first.
+#[tauri::command]
+pub async fn convert_vm_image(
+    app: tauri::AppHandle,
+    src: String,
+    dst: String,
+    format: String,
+) -> Result<ConvertedVmImage, String> {
+    use crate::vm::image::ImageFormat;
+    let format = match format.as_str() {
+        "raw" => ImageFormat::Raw,
+        "qcow2" => ImageFormat::Qcow2,
+        other => return Err(format!("unsupported target format {other:?} (want raw or qcow2)")),
+    };
+    let src = PathBuf::from(src)
+        .canonicalize()
+        .map_err(|e| format!("cannot resolve source image: {e}"))?;
+    let dst = PathBuf::from(dst);
+    if dst.canonicalize().is_ok_and(|d| d == src) {
+        return Err("source and destination are the same file".into());
+    }
+    if let Some(why) = vm_image_in_use(&app, &dst) {
+        return Err(format!("refusing to overwrite {}: {}", dst.display(), why));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let dst_label = dst.display().to_string();
+        let emit = |percent: f64| {
+            let _ = app.emit(
+                "vm-image-convert-progress",
+                VmImageConvertProgress {
+                    dst: dst_label.clone(),
+                    percent,
+                },
+            );
+        };
+        let bytes = crate::vm::image::convert_image(&src, &dst, format, &emit)
+            .map_err(|e| e.to_string())?;
+        eprintln!("[vm-image] converted {} -> {} ({} bytes)", src.display(), dst_label, bytes);
+        Ok(ConvertedVmImage {
+            path: dst_label,
+            format,
+            bytes,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Read one persisted desktop setting (a `settings::Settings` field name) as JSON.
+#[tauri::command]
+pub fn get_setting(app: tauri::AppHandle, key: String) -> Result<serde_json::Value, String> {
+    use tauri::Manager;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    crate::settings::get_value(&crate::settings::load(&data_dir), &key)
+}
+
+/// Persist one desktop setting. The value must have the field's JSON type;
+/// unknown keys are rejected, and so are `workspace_dir`, `custom_vm_image`
+/// and `bind_address`, which go through their own commands. Most settings
+/// apply on the next VM/app start.
+#[tauri::command]
+pub fn set_setting(
+    app: tauri::AppHandle,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    use tauri::Manager;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    crate::settings::update(&data_dir, |s| crate::settings::set_value(s, &key, value))
+        .map_err(|e| format!("failed to save settings: {e}"))??;
+    eprintln!("[settings] {} updated", key);
+    Ok(())
+}
+
+#[derive(Serialize, Clone)]
+pub struct DevFlags {
+    /// Control-plane `DEV_AUTH_ENABLED`.
+    pub dev_auth: bool,
+    /// Frontend `NEXT_PUBLIC_DEV_MODE_ENABLED`.
+    pub dev_mode: bool,
+    /// Services given a flag that weren't running: their saved value applies
+    /// on their next start.
+    pub pending: Vec<String>,
+}
+
+/// Turn dev-auth (control plane) and dev mode (frontend) on or off without
+/// restarting the app: only the workerd whose flag was passed is restarted
+/// with the new value, and the choice is saved to settings once it applied
+/// (or, for a service that isn't running, to apply on its next start).
+/// Returns the flags in effect.
+#[tauri::command]
+pub async fn set_dev_flags(
+    app: tauri::AppHandle,
+    dev_auth: Option<bool>,
+    dev_mode: Option<bool>,
+) -> Result<DevFlags, String> {
+    use tauri::Manager;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let services = app.try_state::<std::sync::Arc<crate::DesktopServices>>();
+        // The services, if `role` is among those running.
+        let live = |role: crate::ServiceRole| {
+            services
+                .as_ref()
+                .filter(|services| services.running_services().contains(&role.label()))
+        };
+        let targets = [
+            (dev_auth, crate::ServiceRole::Workerd, "DEV_AUTH_ENABLED"),
+            (dev_mode, crate::ServiceRole::WorkerdFrontend, "NEXT_PUBLIC_DEV_MODE_ENABLED"),
+        ];
+        let mut pending = Vec::new();
+        // What to save: a flag is only kept once its service took it.
+        let mut saved = [None, None];
+        let mut result = Ok(());
+        for (i, (flag, role, key)) in targets.into_iter().enumerate() {
+            let Some(flag) = flag else { continue };
+            match live(role) {
+                None => pending.push(role.label().to_string()),
+                Some(services) => {
+                    if services.service_env(role, key).as_deref() != Some(&flag.to_string()) {
+                        if let Err(e) = services.restart_with_env(role, key, &flag.to_string()) {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                }
+            }
+            saved[i] = Some(flag);
+        }
+        let settings = crate::settings::update(&data_dir, |s| {
+            s.dev_auth = saved[0].or(s.dev_auth);
+            s.dev_mode = saved[1].or(s.dev_mode);
+            s.clone()
+        })
+        .map_err(|e| format!("failed to save settings: {e}"))?;
+        result?;
+        if !pending.is_empty() {
+            eprintln!("[settings] dev flags apply on the next start of {}", pending.join(", "));
+        }
+        // A running service reports its own environment; one that isn't, the
+        // value it will start with.
+        let current = |role, key, setting: Option<bool>| match live(role) {
+            Some(services) => services.service_env(role, key).is_none_or(|v| v == "true"),
+            None => setting.unwrap_or(true),
+        };
+        Ok(DevFlags {
+            dev_auth: current(crate::ServiceRole::Workerd, "DEV_AUTH_ENABLED", settings.dev_auth),
+            dev_mode: current(
+                crate::ServiceRole::WorkerdFrontend,
+                "NEXT_PUBLIC_DEV_MODE_ENABLED",
+                settings.dev_mode,
+            ),
+            pending,
+        })
+    })
+    .await
+    .map_err(|e| format!("set_dev_flags task failed: {e}"))?
+}
+
+/// Manual recovery for a broken control-plane database: on the next start the
+/// DB is moved aside (kept under `d1/corrupt-<time>/`) and d1-shim starts on a
+/// fresh one. Restarts the app right away, so it only returns on failure.
 #[tauri::command]
-pub fn read_startup_log(app: tauri::AppHandle) -> String {
+pub fn reset_controlplane_db(app: tauri::AppHandle) -> Result<(), String> {
     use tauri::Manager;
-    app.path()
-        .app_data_dir()
-        .ok()
-        .map(|d| d.join("startup.log"))
-        .and_then(|p| std::fs::read_to_string(p).ok())
-        .unwrap_or_default()
+    let d1_dir = app.path().app_data_dir().map_err(|e| e.to_string())?.join("d1");
+    std::fs::create_dir_all(&d1_dir).map_err(|e| e.to_string())?;
+    std::fs::write(d1_dir.join(crate::CONTROLPLANE_DB_RESET_MARKER), b"")
+        .map_err(|e| format!("failed to request a database reset: {e}"))?;
+    eprintln!("[d1] control-plane database reset requested; restarting");
+    app.restart();
 }
 
 #[derive(Serialize, Clone)]
@@ -1634,12 +4606,7 @@ pub async fn download_cloud_workspace(
     if subdir.is_empty() || subdir.contains('/') || subdir.contains("..") {
         return Err("invalid workspace subdir".into());
     }
-    let ws_root = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?
-        .join("workspace")
-        .join(&subdir);
+    let ws_root = app.state::<WorkspaceState>().available_path()?.join(&subdir);
     std::fs::create_dir_all(&ws_root).map_err(|e| format!("create workspace dir: {e}"))?;
     let ws_canon = ws_root
         .canonicalize()
@@ -1754,62 +4721,968 @@ pub async fn download_cloud_workspace(
                 }
             }
         }
-        eprintln!("[cloud-dl] done: written={written} skipped={skipped}");
-        Ok(WorkspaceDownloadResult { written, skipped, had_workspace: true })
+        eprintln!("[cloud-dl] done: written={written} skipped={skipped}");
+        Ok(WorkspaceDownloadResult { written, skipped, had_workspace: true })
+    })
+    .await
+    .map_err(|e| format!("workspace download task failed: {e}"))?
+}
+
+/// Return the per-boot surface token. The host frontend sends it as the
+/// `X-Orcabot-Surface` header so the control plane knows the request is from the
+/// trusted GUI (not a process inside the sandbox VM spoofing dev-auth).
+#[tauri::command]
+pub fn get_surface_token() -> String {
+    // DIAGNOSTIC (surface-ws-diag): prove whether the webview actually invokes this
+    // IPC command. If this line never appears in headless.log after the GUI loads,
+    // the token isn't being delivered (IPC unreachable at the remote origin) and the
+    // WS-auth failure is a delivery bug, not a missing-await bug.
+    let t = crate::surface_token();
+    eprintln!(
+        "[surface-ws-diag] get_surface_token invoked by webview -> returning token len={}",
+        t.len()
+    );
+    t.to_string()
+}
+
+#[derive(Serialize, Clone)]
+pub struct LocalCredentials {
+    pub internal_api_token: String,
+    pub sandbox_internal_token: String,
+    pub controlplane_url: String,
+    pub frontend_url: String,
+    pub sandbox_url: String,
+}
+
+/// Whether `url` is the app's own UI: the bundled loading screen (`tauri://`,
+/// or `http://tauri.localhost` on Windows) or the host frontend on the port it
+/// bound this boot. Any other localhost origin the capability's `remote.urls`
+/// happens to admit (e.g. a dev server on another port) is not trusted.
+fn is_app_origin(url: &tauri::Url) -> bool {
+    match (url.scheme(), url.host_str()) {
+        ("tauri", _) => true,
+        ("http" | "https", Some("tauri.localhost")) => true,
+        ("http", Some("localhost" | "127.0.0.1")) => {
+            url.port_or_known_default() == Some(get_ports().frontend)
+        }
+        _ => false,
+    }
+}
+
+/// Return the tokens and service URLs the running stack was started with, so a
+/// companion tool driven from the app's UI can talk to the control plane and
+/// sandbox without guessing the dev defaults. Only answered for the `main`
+/// window at an app origin (see `is_app_origin`); every call is logged.
+#[tauri::command]
+pub fn get_local_credentials(webview: tauri::Webview) -> Result<LocalCredentials, String> {
+    let origin = webview
+        .url()
+        .map(|u| u.origin().ascii_serialization())
+        .unwrap_or_else(|_| "<unknown>".to_string());
+    let trusted = webview.label() == "main" && webview.url().is_ok_and(|u| is_app_origin(&u));
+    if !trusted {
+        eprintln!(
+            "[credentials] DENIED get_local_credentials for webview '{}' at {}",
+            webview.label(),
+            origin
+        );
+        return Err("local credentials are only available to the app's own window".into());
+    }
+    eprintln!(
+        "[credentials] get_local_credentials granted to webview '{}' at {}",
+        webview.label(),
+        origin
+    );
+
+    let ports = get_ports();
+    Ok(LocalCredentials {
+        internal_api_token: crate::internal_api_token(),
+        sandbox_internal_token: crate::sandbox_internal_token(),
+        controlplane_url: format!("http://localhost:{}", ports.controlplane),
+        frontend_url: format!("http://localhost:{}", ports.frontend),
+        sandbox_url: std::env::var("SANDBOX_URL")
+            .unwrap_or_else(|_| format!("http://127.0.0.1:{}", ports.sandbox)),
+    })
+}
+
+#[derive(Serialize, Clone)]
+pub struct ServicePorts {
+    pub controlplane: u16,
+    pub frontend: u16,
+    pub sandbox: u16,
+    pub d1: u16,
+    /// Address the frontend, control plane and sandbox listen on this boot
+    /// (`bind_address`); "0.0.0.0" means they are reachable from the network,
+    /// which the UI should warn about.
+    pub bind_address: String,
+}
+
+fn port_from_env(var: &str, default: u16) -> u16 {
+    std::env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Return the ports the stack actually bound to this boot. The defaults (8787 /
+/// 8788 / …) may have been busy, in which case `main.rs` picked free ports and
+/// exported them via env. The loading screen reads this to build the redirect
+/// (and to hand the control-plane port to the frontend via `?cp=`, since the
+/// frontend bakes `:8787` at build time and can't otherwise learn it).
+#[tauri::command]
+pub fn get_ports() -> ServicePorts {
+    ServicePorts {
+        controlplane: port_from_env("CONTROLPLANE_PORT", 8787),
+        frontend: port_from_env("FRONTEND_PORT", 8788),
+        sandbox: port_from_env("SANDBOX_PORT", 8080),
+        // D1_SHIM_ADDR is a host:port; extract the port.
+        d1: std::env::var("D1_SHIM_ADDR")
+            .ok()
+            .and_then(|a| a.rsplit(':').next().and_then(|s| s.trim().parse().ok()))
+            .unwrap_or(9001),
+        bind_address: std::env::var("ORCABOT_BIND_ADDRESS")
+            .unwrap_or_else(|_| "127.0.0.1".to_string()),
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct SandboxStatus {
+    /// Hypervisor running the VM ("Virtualization.framework", "QEMU (HVF
+    /// fallback)", ...); the platform's default backend while none is running.
+    pub backend: &'static str,
+    pub running: bool,
+    /// The backend's lifecycle state (`{"state": "paused"}`, `{"state":
+    /// "failed", "error": ...}`, ...); `stopped` while no VM is registered.
+    pub vm_state: crate::vm::VmState,
+    pub sandbox_url: Option<String>,
+    /// Host↔guest forwards as actually configured (host ports may be dynamic).
+    pub port_mappings: Vec<crate::vm::PortMapping>,
+    /// Frozen by `suspend_all` (the VM is paused, not stopped).
+    pub suspended: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct VmConfigExport {
+    /// Hypervisor running the guest ("Virtualization.framework", "QEMU/KVM", ...).
+    pub backend: &'static str,
+    pub app_version: String,
+    /// The effective config, secret env values redacted.
+    pub config: crate::vm::VMConfig,
+    /// Forwards as actually set up, which can differ from `config` when a host
+    /// port was reallocated.
+    pub port_mappings: Vec<crate::vm::PortMapping>,
+}
+
+/// The config the running sandbox VM was started with — resolved paths,
+/// CPU/memory, ports, workspace share, env with secrets redacted — plus the
+/// backend, for attaching to bug reports and reproducing a setup. `None` when
+/// no VM is running.
+#[tauri::command]
+pub fn get_vm_config(app: tauri::AppHandle) -> Option<VmConfigExport> {
+    use tauri::Manager;
+    let services = app.try_state::<std::sync::Arc<crate::DesktopServices>>()?;
+    let (backend, port_mappings) = {
+        let guard = services.sandbox_vm.lock().ok()?;
+        let vm = guard.as_ref()?;
+        (vm.backend_name(), vm.port_mappings())
+    };
+    let config = services.vm_config.lock().ok()?.clone()?;
+    Some(VmConfigExport {
+        backend,
+        app_version: app.package_info().version.to_string(),
+        config,
+        port_mappings,
+    })
+}
+
+/// CPU, memory and uptime of the sandbox VM process, for a live readout the
+/// frontend polls every few seconds. Takes about a quarter second on Linux and
+/// half a second on macOS (CPU is sampled over that window, without holding the
+/// VM lock); errors while the VM isn't running or on backends that can't
+/// measure it (WSL).
+#[tauri::command]
+pub async fn get_vm_metrics(app: tauri::AppHandle) -> Result<crate::vm::VmMetrics, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let services = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .ok_or_else(|| "Desktop services are not running".to_string())?;
+        // Only the pid is read under the lock; sampling sleeps, and the lock
+        // gates every other VM command.
+        let pid = {
+            let guard = services
+                .sandbox_vm
+                .lock()
+                .map_err(|_| "sandbox VM state unavailable".to_string())?;
+            let vm = guard.as_ref().ok_or_else(|| "Sandbox VM is not running".to_string())?;
+            vm.metrics_pid().map_err(|e| e.to_string())?
+        };
+        crate::vm::metrics::sample(pid).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("metrics task failed: {e}"))?
+}
+
+/// Current state of the sandbox VM. Not running (and no mappings) while it is
+/// still booting, since the VM is only registered once healthy.
+#[tauri::command]
+pub fn get_sandbox_status(app: tauri::AppHandle) -> SandboxStatus {
+    use tauri::Manager;
+    let mut status = SandboxStatus {
+        backend: crate::vm::vm_backend_name(),
+        running: false,
+        vm_state: crate::vm::VmState::Stopped,
+        sandbox_url: None,
+        port_mappings: Vec::new(),
+        suspended: false,
+    };
+    let Some(services) = app.try_state::<std::sync::Arc<crate::DesktopServices>>() else {
+        return status;
+    };
+    status.suspended = services
+        .suspended
+        .load(std::sync::atomic::Ordering::SeqCst);
+    if let Ok(guard) = services.sandbox_vm.lock() {
+        if let Some(vm) = guard.as_ref() {
+            status.backend = vm.backend_name();
+            status.running = vm.is_running();
+            status.vm_state = vm.state();
+            status.sandbox_url = vm.sandbox_url();
+            status.port_mappings = vm.port_mappings();
+        }
+    }
+    status
+}
+
+/// Freeze the whole stack to save CPU and battery while keeping its state in
+/// memory: the sandbox VM is paused and the control plane, frontend and D1 shim
+/// are SIGSTOPped. Unlike the idle shutdown nothing restarts afterwards.
+/// Emits `services-suspended` (true). Unix only; fails on Windows.
+#[tauri::command]
+pub async fn suspend_all(app: tauri::AppHandle) -> Result<(), String> {
+    set_suspended(app, true).await
+}
+
+/// Undo `suspend_all`. Emits `services-suspended` (false, or still true when
+/// the VM wouldn't resume; retry then). The guest clock is behind by the time
+/// spent paused until the clock watcher resyncs it.
+#[tauri::command]
+pub async fn resume_all(app: tauri::AppHandle) -> Result<(), String> {
+    set_suspended(app, false).await
+}
+
+async fn set_suspended(app: tauri::AppHandle, suspend: bool) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let services = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .ok_or_else(|| "Desktop services are not running".to_string())?;
+        let result = if suspend {
+            services.suspend_all()
+        } else {
+            services.resume_all()
+        };
+        let suspended = services
+            .suspended
+            .load(std::sync::atomic::Ordering::SeqCst);
+        let _ = app.emit("services-suspended", suspended);
+        result
+    })
+    .await
+    .map_err(|e| format!("Suspend task failed: {}", e))?
+}
+
+/// Tell the backend the sandbox is in use, resetting the idle-shutdown timer
+/// (`ORCABOT_SANDBOX_IDLE_MINS`). Traffic to the sandbox and exec calls already
+/// count; call it to wake the VM ahead of work that needs it: if the VM was
+/// stopped for idleness it boots again, and this returns true so the caller can
+/// show a "starting sandbox" state until it is healthy.
+#[tauri::command]
+pub fn note_sandbox_activity(app: tauri::AppHandle) -> bool {
+    use tauri::Manager;
+    match app.try_state::<std::sync::Arc<crate::DesktopServices>>() {
+        Some(services) => services.note_sandbox_activity(&app),
+        None => false,
+    }
+}
+
+/// Stage the sandbox VM's resources now (downloading and verifying the image on
+/// first run) without booting it, e.g. during onboarding, so the first
+/// `start_sandbox_vm` is quick. Emits `vm-prewarm-progress` ({done, total}
+/// bytes of the download). Safe to call again; `force` restages from scratch.
+#[tauri::command]
+pub async fn prewarm_resources(app: tauri::AppHandle, force: Option<bool>) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let services = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .ok_or_else(|| "Desktop services are not running".to_string())?;
+        services
+            .prewarm_vm(&app, force.unwrap_or(false))
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("prewarm task failed: {e}"))?
+}
+
+/// Download the VM disk image from `url` (plain or gzipped), verify it against
+/// `expected_sha256` and stage it for the next VM start, for installers that
+/// don't bundle it. Interrupted transfers are retried with range requests, and
+/// a cancelled or failed download resumes from its partial file on the next
+/// call. An image other than the manifest's must pass `validate_custom_image`
+/// before it becomes the custom image. Emits `vm-image-fetch-progress` ({done,
+/// total} bytes). Returns the staged path.
+#[tauri::command]
+pub async fn fetch_and_stage_image(
+    app: tauri::AppHandle,
+    url: String,
+    expected_sha256: String,
+) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let services = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .ok_or_else(|| "Desktop services are not running".to_string())?;
+        services
+            .fetch_and_stage_image(&app, &url, &expected_sha256)
+            .map(|path| path.display().to_string())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("image fetch task failed: {e}"))?
+}
+
+/// Stop an in-flight `fetch_and_stage_image`, which then fails with
+/// "cancelled". The partial download is kept for the next call to resume.
+#[tauri::command]
+pub fn cancel_image_fetch(app: tauri::AppHandle) {
+    use tauri::Manager;
+    if let Some(services) = app.try_state::<std::sync::Arc<crate::DesktopServices>>() {
+        services
+            .image_fetch_cancel
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// Restart the running sandbox VM with the config it booted with, e.g. when the
+/// guest is wedged, and wait until it is healthy again. Errors if it isn't
+/// running (or is still booting); a VM that fails to come back is stopped.
+#[tauri::command]
+pub async fn restart_sandbox_vm(app: tauri::AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let services = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .ok_or_else(|| "Desktop services are not running".to_string())?;
+        services.restart_sandbox_vm(&app).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("restart task failed: {e}"))?
+}
+
+/// Time sequential and small-file IO on the shared workspace from inside the
+/// guest, reporting which share (virtiofs, 9p, ...) backs it. Takes a few
+/// seconds; errors while the VM is not running.
+#[tauri::command]
+pub async fn benchmark_workspace_io(
+    app: tauri::AppHandle,
+) -> Result<crate::vm::bench::IoBenchmark, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let services = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .ok_or_else(|| "Desktop services are not running".to_string())?;
+        let channel = services
+            .user_exec_channel()
+            .ok_or_else(|| "Sandbox VM is not running".to_string())?;
+        let guest_path = services
+            .vm_config
+            .lock()
+            .ok()
+            .and_then(|config| config.as_ref()?.env.get("WORKSPACE_BASE").cloned())
+            .unwrap_or_else(|| "/workspace".to_string());
+        crate::vm::bench::run(&channel, &guest_path).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("benchmark task failed: {e}"))?
+}
+
+/// Processes running in the sandbox guest, busiest first, for spotting one
+/// that pegs the CPU. Each is flagged `protected` if `kill_guest_process`
+/// would refuse it without `force`. Errors while the VM is not running.
+#[tauri::command]
+pub async fn list_guest_processes(
+    app: tauri::AppHandle,
+) -> Result<Vec<crate::vm::processes::GuestProcess>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let channel = guest_exec_channel(&app)?;
+        crate::vm::processes::list(&channel).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("process list task failed: {e}"))?
+}
+
+/// Stop a guest process: SIGTERM, then SIGKILL after a few seconds. Init,
+/// kernel threads and the sandbox server are refused unless `force`, since
+/// killing them takes the sandbox down with them.
+#[tauri::command]
+pub async fn kill_guest_process(
+    app: tauri::AppHandle,
+    pid: u32,
+    force: Option<bool>,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let channel = guest_exec_channel(&app)?;
+        crate::vm::processes::kill(&channel, pid, force.unwrap_or(false))
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("kill task failed: {e}"))?
+}
+
+/// The running VM's exec channel, cloned out so guest work runs without
+/// holding the `sandbox_vm` lock.
+fn guest_exec_channel(app: &tauri::AppHandle) -> Result<crate::vm::ExecChannel, String> {
+    use tauri::Manager;
+    let services = app
+        .try_state::<std::sync::Arc<crate::DesktopServices>>()
+        .ok_or_else(|| "Desktop services are not running".to_string())?;
+    services
+        .user_exec_channel()
+        .ok_or_else(|| "Sandbox VM is not running".to_string())
+}
+
+/// Kernel, OS release and sandbox server revision of the running guest, for
+/// support. Errors while the VM is not running (or still booting).
+#[tauri::command]
+pub async fn get_guest_info(app: tauri::AppHandle) -> Result<crate::vm::GuestInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        app.try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .and_then(|services| services.guest_info())
+            .ok_or_else(|| "Sandbox VM is not running".to_string())
+    })
+    .await
+    .map_err(|e| format!("guest info task failed: {e}"))?
+}
+
+/// The resource root the app resolved (and which candidate it came from), plus
+/// which bundled artifacts exist under it. A single diagnostic for packaging and
+/// "sandbox disabled" reports.
+#[tauri::command]
+pub fn get_resource_info(app: tauri::AppHandle) -> Result<crate::ResourceInfo, String> {
+    use tauri::Manager;
+    Ok(crate::resource_info(app.path().resource_dir().ok()))
+}
+
+/// Installed WSL distros, with the ones Orcabot installed flagged, for
+/// diagnosing a distro name conflict. Windows only.
+#[tauri::command]
+pub async fn get_wsl_distros() -> Result<Vec<crate::vm::WslDistro>, String> {
+    #[cfg(target_os = "windows")]
+    {
+        tauri::async_runtime::spawn_blocking(|| {
+            crate::vm::windows::wsl_distros().map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| format!("WSL distro list task failed: {e}"))?
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        Err("WSL is only available on Windows".into())
+    }
+}
+
+/// Duration of each startup phase so far (services, then the VM), in completion
+/// order. Also streamed live as `startup-phase` events.
+#[tauri::command]
+pub fn get_startup_timings(app: tauri::AppHandle) -> Vec<crate::StartupPhase> {
+    use tauri::Manager;
+    app.try_state::<std::sync::Arc<crate::DesktopServices>>()
+        .and_then(|services| services.startup_timings.lock().ok().map(|t| t.clone()))
+        .unwrap_or_default()
+}
+
+/// App data dir categories `get_data_dir_usage` reports and `prune_data_dir`
+/// clears, except `workspace` (see `UNPRUNABLE_CATEGORIES`).
+const DATA_DIR_CATEGORIES: [&str; 6] = ["vm", "d1", "durable_objects", "workspace", "logs", "bin"];
+
+/// Categories `get_data_dir_usage` sizes but `prune_data_dir` refuses, with the
+/// command that clears them instead. Emptying the workspace goes through
+/// `clear_workspace`, whose confirm token keeps a stray call from deleting it.
+const UNPRUNABLE_CATEGORIES: [(&str, &str); 1] =
+    [("workspace", "prepare_clear_workspace and clear_workspace")];
+
+#[derive(Serialize, Clone)]
+pub struct DataDirCategory {
+    pub name: String,
+    pub paths: Vec<String>,
+    pub bytes: u64,
+    /// Why it can't be pruned right now (e.g. "the sandbox VM is running").
+    pub in_use: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct DataDirUsage {
+    pub data_dir: String,
+    pub categories: Vec<DataDirCategory>,
+    /// Everything under the data dir plus the VM cache dir, categorized or not.
+    pub total_bytes: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PruneDataDirResult {
+    pub pruned: Vec<String>,
+    pub bytes_freed: u64,
+    /// Entries that could not be removed: "path: error".
+    pub errors: Vec<String>,
+}
+
+/// What makes up a category. The staged VM image and runtime live in the cache
+/// dir (`vm_cache_dir`), plus whatever is left of the pre-migration
+/// `<data_dir>/vm`; `logs` is the `*.log` files at the top of the data dir.
+fn data_dir_category_paths(name: &str, data_dir: &Path, vm_cache_dir: Option<&Path>) -> Vec<PathBuf> {
+    match name {
+        "vm" => vm_cache_dir
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain([data_dir.join("vm")])
+            .collect(),
+        "logs" => std::fs::read_dir(data_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "log"))
+            .collect(),
+        "workspace" => vec![crate::workspace::workspace_dir(data_dir)],
+        _ => vec![data_dir.join(name)],
+    }
+}
+
+/// Why `name` can't be pruned now, if it can't. `start_held`: the caller holds
+/// `vm_start` itself, so only a running VM counts.
+fn data_dir_category_in_use(
+    name: &str,
+    services: Option<&crate::DesktopServices>,
+    workspace: &WorkspaceState,
+    start_held: bool,
+) -> Option<String> {
+    let vm_busy = || {
+        let services = services?;
+        if !start_held && services.vm_start.try_lock().is_err() {
+            return Some("the sandbox VM is starting".to_string());
+        }
+        let running = services.sandbox_vm.lock().map_or(true, |vm| vm.is_some());
+        running.then(|| "the sandbox VM is running".to_string())
+    };
+    let moving = || {
+        if workspace.relocating.load(std::sync::atomic::Ordering::SeqCst) {
+            Some("the workspace is being moved".to_string())
+        } else if workspace.clearing.load(std::sync::atomic::Ordering::SeqCst) {
+            Some("the workspace is being cleared".to_string())
+        } else {
+            None
+        }
+    };
+    match name {
+        // A move stops the VM and boots it again once the files are across.
+        "vm" => vm_busy().or_else(moving),
+        "workspace" => vm_busy().or_else(moving).or_else(|| {
+            let importing = workspace.active_transfers();
+            (importing > 0).then(|| format!("{} import(s) or export(s) are in progress", importing))
+        }),
+        "d1" | "durable_objects" | "bin" => {
+            let running = services.map(|s| s.running_services()).unwrap_or_default();
+            (!running.is_empty()).then(|| format!("{} running", running.join(", ")))
+        }
+        _ => None, // logs are truncated, not removed
+    }
+}
+
+/// Empty `path` and return the bytes freed: a file is truncated (a running
+/// service may still be appending to it), a directory loses its contents but
+/// stays, since the services expect it to exist.
+fn clear_data_path(path: &Path, errors: &mut Vec<String>) -> u64 {
+    let before = tree_size(path);
+    let mut fail = |p: &Path, e: std::io::Error| errors.push(format!("{}: {}", p.display(), e));
+    if path.is_file() {
+        if let Err(e) = std::fs::OpenOptions::new().write(true).open(path).and_then(|f| f.set_len(0)) {
+            fail(path, e);
+        }
+    } else if path.is_dir() {
+        match std::fs::read_dir(path) {
+            Ok(entries) => {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let p = entry.path();
+                    let removed = match entry.file_type() {
+                        Ok(t) if t.is_dir() => std::fs::remove_dir_all(&p),
+                        _ => std::fs::remove_file(&p),
+                    };
+                    if let Err(e) = removed {
+                        fail(&p, e);
+                    }
+                }
+            }
+            Err(e) => fail(path, e),
+        }
+    }
+    before.saturating_sub(tree_size(path))
+}
+
+/// Size of each app data dir category (see `DATA_DIR_CATEGORIES`), and whether
+/// `prune_data_dir` could clear it right now.
+#[tauri::command]
+pub async fn get_data_dir_usage(app: tauri::AppHandle) -> Result<DataDirUsage, String> {
+    use tauri::Manager;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let vm_cache_dir = app.path().app_cache_dir().ok().map(|d| d.join("vm"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let services = app.try_state::<std::sync::Arc<crate::DesktopServices>>();
+        let state = app.state::<WorkspaceState>();
+        let categories = DATA_DIR_CATEGORIES
+            .iter()
+            .map(|&name| {
+                let paths = data_dir_category_paths(name, &data_dir, vm_cache_dir.as_deref());
+                DataDirCategory {
+                    name: name.to_string(),
+                    bytes: paths.iter().map(|p| tree_size(p)).sum(),
+                    paths: paths.iter().map(|p| p.to_string_lossy().into_owned()).collect(),
+                    in_use: data_dir_category_in_use(name, services.as_deref().map(|s| &**s), &state, false),
+                }
+            })
+            .collect();
+        let cache_outside = vm_cache_dir.filter(|c| !c.starts_with(&data_dir));
+        Ok(DataDirUsage {
+            total_bytes: tree_size(&data_dir) + cache_outside.as_deref().map_or(0, tree_size),
+            data_dir: data_dir.to_string_lossy().into_owned(),
+            categories,
+        })
+    })
+    .await
+    .map_err(|e| format!("data dir usage task failed: {e}"))?
+}
+
+/// Clear the given data dir `categories` (names from `get_data_dir_usage`) to
+/// reclaim space. Refuses the whole request, before touching anything, if any
+/// category is still in use by a running service or the VM. `vm`, `bin` and
+/// `logs` are regenerated on the next start; `d1` and `durable_objects` hold
+/// user data and are gone for good. `workspace` is refused: it is cleared with
+/// `clear_workspace`, which asks for confirmation.
+#[tauri::command]
+pub async fn prune_data_dir(
+    app: tauri::AppHandle,
+    categories: Vec<String>,
+) -> Result<PruneDataDirResult, String> {
+    use tauri::Manager;
+    if categories.is_empty() {
+        return Err("No categories to prune".to_string());
+    }
+    if let Some(bad) = categories.iter().find(|c| !DATA_DIR_CATEGORIES.contains(&c.as_str())) {
+        return Err(format!(
+            "Unknown data dir category {:?} (expected one of {})",
+            bad,
+            DATA_DIR_CATEGORIES.join(", ")
+        ));
+    }
+    for (name, instead) in UNPRUNABLE_CATEGORIES {
+        if categories.iter().any(|c| c == name) {
+            return Err(format!("{name} can't be pruned; clear it with {instead}"));
+        }
+    }
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let vm_cache_dir = app.path().app_cache_dir().ok().map(|d| d.join("vm"));
+    tauri::async_runtime::spawn_blocking(move || {
+        let services = app.try_state::<std::sync::Arc<crate::DesktopServices>>();
+        let services = services.as_deref().map(|s| &**s);
+        let state = app.state::<WorkspaceState>();
+        // Held throughout, so neither an idle wake nor the watchdog boots the VM
+        // into a half-deleted image or workspace.
+        let _starting = match services.map(|s| s.vm_start.try_lock()) {
+            Some(Err(_)) => return Err("Cannot prune while the sandbox VM is starting".to_string()),
+            starting => starting,
+        };
+        let busy: Vec<String> = categories
+            .iter()
+            .filter_map(|c| {
+                data_dir_category_in_use(c, services, &state, true).map(|r| format!("{c} ({r})"))
+            })
+            .collect();
+        if !busy.is_empty() {
+            return Err(format!("Cannot prune while in use: {}", busy.join("; ")));
+        }
+
+        let mut result = PruneDataDirResult {
+            pruned: Vec::new(),
+            bytes_freed: 0,
+            errors: Vec::new(),
+        };
+        for name in &categories {
+            for path in data_dir_category_paths(name, &data_dir, vm_cache_dir.as_deref()) {
+                result.bytes_freed += clear_data_path(&path, &mut result.errors);
+            }
+            result.pruned.push(name.clone());
+        }
+        eprintln!(
+            "[commands] pruned {} from the data dir ({} bytes freed, {} errors)",
+            result.pruned.join(", "),
+            result.bytes_freed,
+            result.errors.len()
+        );
+        Ok(result)
     })
     .await
-    .map_err(|e| format!("workspace download task failed: {e}"))?
+    .map_err(|e| format!("prune task failed: {e}"))?
 }
 
-/// Return the per-boot surface token. The host frontend sends it as the
-/// `X-Orcabot-Surface` header so the control plane knows the request is from the
-/// trusted GUI (not a process inside the sandbox VM spoofing dev-auth).
+/// A guest path `put_sandbox_file` may write: absolute, a file name (no trailing
+/// slash), no `.`/`..` segments, and no NUL or newline.
+fn validate_guest_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') || path.ends_with('/') {
+        return Err(format!("guest path must be an absolute file path: {path:?}"));
+    }
+    if path.contains(['\0', '\n', '\r']) {
+        return Err("guest path contains a control character".to_string());
+    }
+    if path.split('/').any(|seg| seg == "." || seg == "..") {
+        return Err(format!("guest path must not contain . or .. segments: {path:?}"));
+    }
+    Ok(())
+}
+
+/// Copy a host file into the running sandbox VM at `guest_path` (absolute),
+/// replacing any file there. Returns the bytes written. Meant for one-off
+/// injections (configs, scripts) without going through the workspace mount;
+/// capped at `MAX_WRITE_FILE_BYTES`. The file is written as root, or as the
+/// guest user when `guest_uid` is set outside WSL, so system paths then fail.
 #[tauri::command]
-pub fn get_surface_token() -> String {
-    // DIAGNOSTIC (surface-ws-diag): prove whether the webview actually invokes this
-    // IPC command. If this line never appears in headless.log after the GUI loads,
-    // the token isn't being delivered (IPC unreachable at the remote origin) and the
-    // WS-auth failure is a delivery bug, not a missing-await bug.
-    let t = crate::surface_token();
-    eprintln!(
-        "[surface-ws-diag] get_surface_token invoked by webview -> returning token len={}",
-        t.len()
-    );
-    t.to_string()
+pub async fn put_sandbox_file(
+    app: tauri::AppHandle,
+    host_path: String,
+    guest_path: String,
+) -> Result<u64, String> {
+    validate_guest_path(&guest_path)?;
+    let meta = std::fs::metadata(&host_path)
+        .map_err(|e| format!("cannot read {host_path}: {e}"))?;
+    if !meta.is_file() {
+        return Err(format!("{host_path} is not a regular file"));
+    }
+    let max = crate::vm::exec::MAX_WRITE_FILE_BYTES;
+    if meta.len() > max {
+        return Err(format!(
+            "{host_path} is {} bytes; put_sandbox_file is limited to {max} bytes",
+            meta.len()
+        ));
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let channel = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .and_then(|services| services.user_exec_channel())
+            .ok_or_else(|| "Sandbox VM is not running".to_string())?;
+        let bytes = std::fs::read(&host_path).map_err(|e| format!("cannot read {host_path}: {e}"))?;
+        channel
+            .write_file(&guest_path, &bytes, 0o644)
+            .map_err(|e| e.to_string())?;
+        eprintln!("[vm] wrote {} bytes to guest {}", bytes.len(), guest_path);
+        Ok(bytes.len() as u64)
+    })
+    .await
+    .map_err(|e| format!("put_sandbox_file task failed: {e}"))?
+}
+
+/// Run `argv` (no shell) as root in the running sandbox VM and stream each
+/// stdout/stderr line to `on_output` as it's produced, so the UI can show live
+/// output of long-running commands. With `guest_uid` set (outside WSL) the
+/// command runs as that user instead. With an `exec_id`, `cancel_sandbox_exec`
+/// stops it, even while it prints nothing. Resolves with the exit code. There is no
+/// timeout; once `on_output` can no longer be delivered to (the webview dropped
+/// it), the guest command is killed and this returns an error.
+#[tauri::command]
+pub async fn sandbox_exec_streaming(
+    app: tauri::AppHandle,
+    argv: Vec<String>,
+    on_output: tauri::ipc::Channel<crate::vm::ExecLine>,
+    exec_id: Option<String>,
+) -> Result<i32, String> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    if argv.first().is_none_or(|a| a.is_empty()) {
+        return Err("argv must name a command".to_string());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        use tauri::Manager;
+        let services = app
+            .try_state::<std::sync::Arc<crate::DesktopServices>>()
+            .ok_or_else(|| "Sandbox VM is not running".to_string())?;
+        let channel = services
+            .user_exec_channel()
+            .ok_or_else(|| "Sandbox VM is not running".to_string())?;
+        let cancel = std::sync::Arc::new(AtomicBool::new(false));
+        if let Some(id) = &exec_id {
+            let mut cancels = services.exec_cancels.lock().map_err(|e| e.to_string())?;
+            if cancels.contains_key(id) {
+                return Err(format!("exec {id} is already running"));
+            }
+            cancels.insert(id.clone(), std::sync::Arc::clone(&cancel));
+        }
+        let mut closed = false;
+        let code = channel.exec_streaming(
+            &argv,
+            &mut |line| {
+                closed = on_output.send(line).is_err();
+                !closed
+            },
+            &|| cancel.load(Ordering::SeqCst),
+        );
+        if let Some(id) = &exec_id {
+            if let Ok(mut cancels) = services.exec_cancels.lock() {
+                cancels.remove(id);
+            }
+        }
+        match code.map_err(|e| e.to_string())? {
+            Some(code) => Ok(code),
+            None => {
+                let why = if closed { "output channel closed" } else { "cancel_sandbox_exec" };
+                eprintln!("[vm] streaming exec of {:?} cancelled: {}", argv[0], why);
+                Err(format!("cancelled: {why}"))
+            }
+        }
+    })
+    .await
+    .map_err(|e| format!("sandbox_exec_streaming task failed: {e}"))?
+}
+
+/// Stop the `sandbox_exec_streaming` run started with `exec_id` (within
+/// a quarter second, output or not); it then rejects with "cancelled".
+#[tauri::command]
+pub fn cancel_sandbox_exec(app: tauri::AppHandle, exec_id: String) -> Result<(), String> {
+    use tauri::Manager;
+    let services = app
+        .try_state::<std::sync::Arc<crate::DesktopServices>>()
+        .ok_or_else(|| "Desktop services are not running".to_string())?;
+    let cancels = services.exec_cancels.lock().map_err(|e| e.to_string())?;
+    let cancel = cancels
+        .get(&exec_id)
+        .ok_or_else(|| format!("No exec {} in progress", exec_id))?;
+    cancel.store(true, std::sync::atomic::Ordering::SeqCst);
+    Ok(())
 }
 
 #[derive(Serialize, Clone)]
-pub struct ServicePorts {
-    pub controlplane: u16,
-    pub frontend: u16,
-    pub sandbox: u16,
-    pub d1: u16,
+pub struct ServicesReady {
+    pub ready: bool,
+    /// Services still not answering when the timeout expired ("d1-shim", "workerd").
+    pub failed: Vec<String>,
 }
 
-fn port_from_env(var: &str, default: u16) -> u16 {
-    std::env::var(var)
-        .ok()
-        .and_then(|v| v.trim().parse().ok())
-        .unwrap_or(default)
+/// Upper bound on a single readiness wait, so a bogus `timeout_ms` can't park a
+/// blocking thread forever.
+const MAX_READY_WAIT_MS: u64 = 5 * 60 * 1000;
+
+/// Block until the d1-shim and control-plane workerd pass their health probes, or
+/// `timeout_ms` elapses. The frontend calls this behind its splash so its first API
+/// calls don't race a control plane that's still coming up. Returns which services
+/// (if any) failed to come up in time.
+#[tauri::command]
+pub async fn wait_for_services_ready(timeout_ms: u64) -> Result<ServicesReady, String> {
+    let timeout = std::time::Duration::from_millis(timeout_ms.min(MAX_READY_WAIT_MS));
+    tauri::async_runtime::spawn_blocking(move || {
+        let ports = get_ports();
+        let mut pending = vec![
+            ("d1-shim", ports.d1.to_string()),
+            ("workerd", ports.controlplane.to_string()),
+        ];
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            pending.retain(|(_, port)| !crate::probe_http(port));
+            if pending.is_empty() || std::time::Instant::now() >= deadline {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(250));
+        }
+        ServicesReady {
+            ready: pending.is_empty(),
+            failed: pending.into_iter().map(|(name, _)| name.to_string()).collect(),
+        }
+    })
+    .await
+    .map_err(|e| format!("readiness task failed: {e}"))
 }
 
-/// Return the ports the stack actually bound to this boot. The defaults (8787 /
-/// 8788 / …) may have been busy, in which case `main.rs` picked free ports and
-/// exported them via env. The loading screen reads this to build the redirect
-/// (and to hand the control-plane port to the frontend via `?cp=`, since the
-/// frontend bakes `:8787` at build time and can't otherwise learn it).
+#[derive(Serialize, Clone)]
+pub struct ServiceHealth {
+    /// "d1-shim", "workerd", "frontend" or "sandbox".
+    pub name: String,
+    pub healthy: bool,
+    /// How long the probe took, whether or not it passed.
+    pub latency_ms: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AggregateHealth {
+    /// Every service below is healthy.
+    pub overall: bool,
+    pub services: Vec<ServiceHealth>,
+}
+
+/// Probe the d1-shim, control-plane workerd, frontend workerd and sandbox VM at
+/// once and report each one's health and probe latency, so a full-stack status
+/// indicator costs the UI one call. The probes run in parallel with the same
+/// helpers as the startup waits, each bounded by their 2s read timeout. A VM
+/// that isn't running (still booting, idle-stopped) counts as unhealthy.
 #[tauri::command]
-pub fn get_ports() -> ServicePorts {
-    ServicePorts {
-        controlplane: port_from_env("CONTROLPLANE_PORT", 8787),
-        frontend: port_from_env("FRONTEND_PORT", 8788),
-        sandbox: port_from_env("SANDBOX_PORT", 8080),
-        // D1_SHIM_ADDR is a host:port; extract the port.
-        d1: std::env::var("D1_SHIM_ADDR")
-            .ok()
-            .and_then(|a| a.rsplit(':').next().and_then(|s| s.trim().parse().ok()))
-            .unwrap_or(9001),
-    }
+pub async fn aggregate_health(app: tauri::AppHandle) -> Result<AggregateHealth, String> {
+    use tauri::Manager;
+    let vm_config = app
+        .try_state::<std::sync::Arc<crate::DesktopServices>>()
+        .and_then(|services| services.vm_config.lock().ok()?.clone());
+    let sandbox_url = get_sandbox_status(app).sandbox_url;
+    tauri::async_runtime::spawn_blocking(move || {
+        let ports = get_ports();
+        let sandbox_addr = sandbox_url.map(|url| {
+            url.trim_start_matches("http://")
+                .trim_end_matches('/')
+                .to_string()
+        });
+        let timed = |name: &str, probe: &dyn Fn() -> bool| {
+            let started = std::time::Instant::now();
+            let healthy = probe();
+            ServiceHealth {
+                name: name.to_string(),
+                healthy,
+                latency_ms: started.elapsed().as_millis() as u64,
+            }
+        };
+        let services: Vec<ServiceHealth> = std::thread::scope(|scope| {
+            let probes = [
+                scope.spawn(|| timed("d1-shim", &|| crate::probe_http(&ports.d1.to_string()))),
+                scope.spawn(|| {
+                    timed("workerd", &|| crate::probe_http(&ports.controlplane.to_string()))
+                }),
+                scope.spawn(|| {
+                    timed("frontend", &|| crate::probe_http(&ports.frontend.to_string()))
+                }),
+                scope.spawn(|| {
+                    timed("sandbox", &|| {
+                        sandbox_addr
+                            .as_deref()
+                            .is_some_and(|addr| {
+                                crate::vm::health::probe_sandbox_health(addr, vm_config.as_ref())
+                            })
+                    })
+                }),
+            ];
+            probes
+                .into_iter()
+                .filter_map(|probe| probe.join().ok())
+                .collect()
+        });
+        AggregateHealth {
+            overall: services.len() == 4 && services.iter().all(|s| s.healthy),
+            services,
+        }
+    })
+    .await
+    .map_err(|e| format!("health task failed: {e}"))
 }
 
 /// Open an http(s) URL in the OS default browser. OAuth connect flows use this
@@ -1842,10 +5715,7 @@ pub fn open_url(url: String) -> Result<(), String> {
 pub async fn reveal_workspace(
     state: tauri::State<'_, WorkspaceState>,
 ) -> Result<(), String> {
-    let path = state.workspace_path.clone();
-    if path.as_os_str().is_empty() || !path.exists() {
-        return Err("workspace directory is not available".into());
-    }
+    let path = state.available_path()?;
     #[cfg(target_os = "macos")]
     let mut cmd = std::process::Command::new("open");
     #[cfg(target_os = "linux")]
@@ -1923,4 +5793,617 @@ mod tests {
         assert_eq!(b64url(&[0x00]), "AA");
         assert_eq!(b64url(&[0xff, 0xff]), "__8");
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_reported_with_raw_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(raw_path(Path::new("docs/100% café.txt")), None);
+        assert_eq!(path_label(Path::new("docs/a.txt")), "docs/a.txt");
+
+        let latin1 = Path::new(std::ffi::OsStr::from_bytes(b"docs/caf\xe9 50%.txt"));
+        assert_eq!(raw_path(latin1).as_deref(), Some("docs/caf%E9 50%25.txt"));
+        assert_eq!(
+            path_label(latin1),
+            "docs/caf\u{FFFD} 50%.txt [raw: docs/caf%E9 50%25.txt]"
+        );
+    }
+
+    #[test]
+    fn validate_guest_path_rejects_relative_and_traversal() {
+        assert!(validate_guest_path("/etc/orcabot/extra.conf").is_ok());
+        assert!(validate_guest_path("etc/passwd").is_err());
+        assert!(validate_guest_path("/tmp/").is_err());
+        assert!(validate_guest_path("/tmp/../etc/shadow").is_err());
+        assert!(validate_guest_path("/tmp/a\nb").is_err());
+    }
+
+    #[test]
+    fn case_collision_rename_skips_taken_names() {
+        let taken: std::collections::HashSet<String> = ["docs/readme.md", "docs/readme (2).md"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            case_collision_rename(Path::new("Docs/README.md"), |k| taken.contains(k)),
+            PathBuf::from("Docs/README (3).md")
+        );
+        assert_eq!(
+            case_collision_rename(Path::new("Makefile"), |k| taken.contains(k)),
+            PathBuf::from("Makefile (2)")
+        );
+    }
+
+    #[test]
+    fn case_collision_renames_follow_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["readme.md", "README.md", "Readme.md", "other.txt"] {
+            std::fs::write(dir.path().join(name), name).unwrap();
+        }
+        let options = ImportOptions {
+            rename_case_collisions: true,
+            ..Default::default()
+        };
+        // The destination already holds other.txt under another case, and the
+        // first rename's name too.
+        let dest = tempfile::tempdir().unwrap();
+        for name in ["OTHER.txt", "Other (2).TXT"] {
+            std::fs::write(dest.path().join(name), name).unwrap();
+        }
+        let scan = scan_import_source(dir.path(), Some(dest.path()), &options, || ());
+        let renames: Vec<(&str, &str)> = scan
+            .case_renames
+            .iter()
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+            .collect();
+        assert_eq!(
+            renames,
+            [
+                ("Readme.md", "Readme (2).md"),
+                ("other.txt", "other (3).txt"),
+                ("readme.md", "readme (3).md")
+            ]
+        );
+        assert_eq!(scan.entries.len(), 4);
+        assert!(scan.collisions.contains(
+            &"other.txt (destination has OTHER.txt) -> other (3).txt".to_string()
+        ));
+
+        let defaults = ImportOptions::default();
+        let scan = scan_import_source(dir.path(), Some(dest.path()), &defaults, || ());
+        assert_eq!(scan.entries.len(), 1);
+        assert!(scan.collisions.contains(&"other.txt (destination has OTHER.txt)".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn safe_copy_file_replaces_atomically_and_rejects_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.txt");
+        let dest = dir.path().join("dest.txt");
+        std::fs::write(&src, b"new contents").unwrap();
+        std::fs::write(&dest, b"old").unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dest, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        assert_eq!(safe_copy_file(&src, &dest, false, None).unwrap(), 12);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"new contents");
+        // The replaced file's mode is kept.
+        let mode = std::fs::metadata(&dest).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        // A name at NAME_MAX still copies: the temp name doesn't grow from it.
+        let long = dir.path().join("n".repeat(255));
+        assert_eq!(safe_copy_file(&src, &long, false, None).unwrap(), 12);
+
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        std::fs::write(&target, b"untouched").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        assert!(safe_copy_file(&src, &link, false, None).is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"untouched");
+
+        // Nor is a source that is a link, so one swapped in after a scan can't
+        // carry out the file it points at.
+        let err = safe_copy_file(&link, &dir.path().join("out.txt"), false, None).unwrap_err();
+        assert!(err.contains("symlink"), "{err}");
+        assert!(!dir.path().join("out.txt").exists());
+
+        // No temp files left behind either way.
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().contains(".orcabot-tmp-"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn trash_or_remove_moves_entries_and_leaves_symlink_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("workspace");
+        let outside = dir.path().join("outside.txt");
+        std::fs::create_dir_all(workspace.join("project/src")).unwrap();
+        std::fs::write(workspace.join("project/src/main.rs"), b"fn main() {}").unwrap();
+        std::fs::write(&outside, b"keep me").unwrap();
+        std::os::unix::fs::symlink(&outside, workspace.join("link")).unwrap();
+        assert_eq!(tree_size(&workspace.join("project")), 12);
+
+        let batch = new_trash_batch(&dir.path().join("trash")).unwrap();
+        assert!(trash_or_remove(&workspace.join("project"), Some(&batch)).unwrap());
+        assert!(batch.join("project/src/main.rs").exists());
+        // Without a trash the entry is deleted, and a symlink goes on its own.
+        assert!(!trash_or_remove(&workspace.join("link"), None).unwrap());
+        assert_eq!(std::fs::read_dir(&workspace).unwrap().count(), 0);
+        assert_eq!(std::fs::read(&outside).unwrap(), b"keep me");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_checks_each_destination_against_its_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("src"), dir.path().join("dest"));
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::create_dir_all(&dest).unwrap();
+        for name in ["ok", "short", "gone", "link"] {
+            std::fs::write(src.join(name), b"12345").unwrap();
+        }
+        std::fs::write(dest.join("ok"), b"12345").unwrap();
+        std::fs::write(dest.join("short"), b"12").unwrap();
+        std::os::unix::fs::symlink(src.join("link"), dest.join("link")).unwrap();
+
+        let names = ["ok", "short", "gone", "link"];
+        let sources: Vec<PathBuf> = names.iter().map(|name| src.join(name)).collect();
+        let expected: Vec<(PathBuf, &Path)> = names
+            .iter()
+            .zip(&sources)
+            .map(|(name, source)| (name.into(), source.as_path()))
+            .collect();
+        let problems = verify_copied_files(&dest, &expected);
+        assert_eq!(
+            problems,
+            [
+                "short: verify: size mismatch (source has 5 bytes, destination 2)",
+                "gone: verify: missing from destination",
+                "link: verify: destination is not a regular file",
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_flags_a_large_import_at_either_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"12345").unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), b"12345").unwrap();
+        let is_large = |small_import| {
+            let options = ImportOptions {
+                small_import,
+                ..Default::default()
+            };
+            let mut large = false;
+            scan_import_source(dir.path(), None, &options, || large = true);
+            large
+        };
+        assert!(!is_large(Some((3, 100))));
+        assert!(is_large(Some((2, 100))));
+        assert!(is_large(Some((3, 10))));
+        assert!(is_large(None));
+    }
+
+    #[test]
+    fn preview_tree_nests_excludes_and_caps() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("proj");
+        std::fs::create_dir_all(src.join("src/util")).unwrap();
+        std::fs::create_dir_all(src.join("node_modules/left-pad")).unwrap();
+        std::fs::create_dir_all(src.join("empty")).unwrap();
+        std::fs::write(src.join("src/util/a.rs"), b"12345").unwrap();
+        std::fs::write(src.join("README.md"), b"hi").unwrap();
+        std::fs::write(src.join("debug.log"), b"x").unwrap();
+        std::fs::write(src.join("node_modules/left-pad/index.js"), b"pad").unwrap();
+        let workspace = dir.path().join("ws");
+        std::fs::create_dir_all(&workspace).unwrap();
+
+        let options = ImportOptions {
+            ignore: compile_ignore_patterns(&["node_modules/".into(), "*.log".into()]).unwrap(),
+            ..Default::default()
+        };
+        let dest_root = workspace.join("imports/proj");
+        let preview = preview_tree(&src, &dest_root, &workspace, &options, 100);
+        assert_eq!((preview.files, preview.dirs, preview.bytes), (2, 3, 7));
+        assert_eq!(preview.excluded, 2);
+        assert!(!preview.truncated);
+        let mut names: Vec<&str> = preview.root.children.iter().map(|c| c.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["README.md", "empty", "src"]);
+        let src_node = preview.root.children.iter().find(|c| c.name == "src").unwrap();
+        assert_eq!(src_node.children[0].name, "util");
+        assert_eq!(src_node.children[0].children[0].bytes, Some(5));
+        assert!(!workspace.join("imports").exists(), "preview must not write");
+        assert_eq!(std::fs::read_dir(&workspace).unwrap().count(), 0, "no case probe either");
+
+        let capped = preview_tree(&src, &dest_root, &workspace, &options, 3);
+        assert!(capped.truncated);
+        assert_eq!(capped.files, 2, "totals cover the whole import");
+        fn count(n: &ImportTreeNode) -> usize {
+            1 + n.children.iter().map(count).sum::<usize>()
+        }
+        assert!(count(&capped.root) <= 3);
+
+        assert!(compile_ignore_patterns(&["[".into()]).is_err());
+        assert!(compile_ignore_patterns(&[" ".into()]).unwrap().is_none());
+    }
+
+    #[test]
+    fn clear_data_path_keeps_dirs_and_truncates_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let d1 = dir.path().join("d1");
+        std::fs::create_dir_all(d1.join("corrupt-1")).unwrap();
+        std::fs::write(d1.join("controlplane.sqlite"), b"12345").unwrap();
+        std::fs::write(d1.join("corrupt-1/old.sqlite"), b"123").unwrap();
+        std::fs::write(dir.path().join("startup.log"), b"1234567").unwrap();
+        std::fs::write(dir.path().join("surface-token"), b"tok").unwrap();
+
+        let logs = data_dir_category_paths("logs", dir.path(), None);
+        assert_eq!(logs, [dir.path().join("startup.log")]);
+
+        let mut errors = Vec::new();
+        assert_eq!(clear_data_path(&d1, &mut errors), 8);
+        assert_eq!(clear_data_path(&logs[0], &mut errors), 7);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert!(d1.is_dir() && std::fs::read_dir(&d1).unwrap().next().is_none());
+        assert_eq!(std::fs::metadata(&logs[0]).unwrap().len(), 0);
+        assert!(dir.path().join("surface-token").exists());
+    }
+
+    #[test]
+    fn moving_or_clearing_the_workspace_holds_off_a_prune() {
+        use std::sync::atomic::Ordering;
+        let state = WorkspaceState::new(PathBuf::from("/ws"));
+        assert_eq!(data_dir_category_in_use("vm", None, &state, false), None);
+        state.relocating.store(true, Ordering::SeqCst);
+        assert!(data_dir_category_in_use("vm", None, &state, false).is_some());
+        state.relocating.store(false, Ordering::SeqCst);
+        state.clearing.store(true, Ordering::SeqCst);
+        let why = data_dir_category_in_use("workspace", None, &state, false);
+        assert_eq!(why.as_deref(), Some("the workspace is being cleared"));
+        assert_eq!(data_dir_category_in_use("logs", None, &state, false), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files_are_skipped_and_empty_files_copied() {
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("pipe");
+        let path = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+
+        // The scan sees it as special, not a file to copy.
+        let kind = std::fs::symlink_metadata(&fifo).unwrap().file_type();
+        assert_eq!(special_file_kind(&kind), Some("fifo"));
+
+        // And a direct copy fails fast instead of blocking on the read.
+        let err = safe_copy_file(&fifo, &dir.path().join("copy"), false, None).unwrap_err();
+        assert!(err.contains("fifo"), "{err}");
+        assert!(!dir.path().join("copy").exists());
+
+        let empty = dir.path().join("empty");
+        std::fs::write(&empty, b"").unwrap();
+        assert_eq!(special_file_kind(&std::fs::metadata(&empty).unwrap().file_type()), None);
+        let dest = dir.path().join("empty-copy");
+        assert_eq!(safe_copy_file(&empty, &dest, true, None).unwrap(), 0);
+        assert_eq!(std::fs::metadata(&dest).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn dir_times_are_copied_after_the_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir_all(src.join("a/b")).unwrap();
+        let dest = dir.path().join("dest");
+        std::fs::create_dir_all(dest.join("a/b")).unwrap();
+        std::fs::write(dest.join("a/b/file.txt"), b"x").unwrap();
+
+        let old = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        for rel in ["", "a", "a/b"] {
+            filetime::set_file_times(src.join(rel), old, old).unwrap();
+        }
+        let mut errors = ImportErrors::default();
+        let entries = [PathBuf::from("a"), PathBuf::from("a/b"), PathBuf::from("gone")];
+        apply_dir_times(&src, &dest, &entries, &mut errors);
+        let (errors, _) = errors.finish();
+        assert!(errors.is_empty(), "{errors:?}");
+        for rel in ["", "a", "a/b"] {
+            let meta = std::fs::metadata(dest.join(rel)).unwrap();
+            assert_eq!(filetime::FileTime::from_last_modification_time(&meta), old, "{rel}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forced_cancel_leaves_destination_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("big.bin");
+        std::fs::write(&src, vec![7u8; COPY_CHUNK as usize + 1]).unwrap();
+        let dest = dir.path().join("copy.bin");
+        std::fs::write(&dest, b"previous").unwrap();
+
+        // A soft cancel doesn't interrupt a copy in progress.
+        let cancel = ImportCancel::default();
+        cancel.request(false);
+        assert!(cancel.requested() && !cancel.forced());
+        assert_eq!(
+            safe_copy_file(&src, &dest, false, Some(&cancel)).unwrap(),
+            COPY_CHUNK + 1
+        );
+
+        std::fs::write(&dest, b"previous").unwrap();
+        cancel.request(true);
+        cancel.request(false);
+        assert!(cancel.forced());
+        assert!(safe_copy_file(&src, &dest, false, Some(&cancel)).is_err());
+        assert_eq!(std::fs::read(&dest).unwrap(), b"previous");
+        let leftovers = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .contains(".orcabot-tmp-")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn import_errors_are_grouped_sorted_and_capped() {
+        let mut failed = FailedParents::default();
+        failed.record(Path::new("bad"), "Permission denied".to_string());
+        assert!(failed.absorb(Path::new("bad/b.txt")));
+        assert!(failed.absorb(Path::new("bad/deeper/c.txt")));
+        assert!(!failed.absorb(Path::new("good/d.txt")));
+
+        let mut errors = ImportErrors::default();
+        errors.extend(["z: oops".to_string(), "z: oops".to_string()]);
+        failed.into_errors(&mut errors);
+        let (errors, total) = errors.finish();
+        assert_eq!(total, 2);
+        assert_eq!(
+            errors,
+            vec!["dir bad: Permission denied (3 files not copied)", "z: oops"]
+        );
+
+        // Capped as they come in, keeping the first in sort order.
+        let mut many = ImportErrors::default();
+        many.extend((0..MAX_IMPORT_ERRORS + 5).rev().map(|i| format!("{i:05}")));
+        assert_eq!(many.kept.len(), MAX_IMPORT_ERRORS);
+        let (many, total) = many.finish();
+        assert_eq!(total, (MAX_IMPORT_ERRORS + 5) as u64);
+        assert_eq!(many.len(), MAX_IMPORT_ERRORS);
+        assert_eq!(many[0], "00000");
+
+        // The directory recorded is the one that couldn't be created, not the
+        // file's parent.
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(root.join("blocker"), b"x").unwrap();
+        let (failed_dir, _) =
+            safe_create_parent_dirs(&root.join("blocker/a/b/file.txt"), &root).unwrap_err();
+        assert_eq!(failed_dir, root.join("blocker/a"));
+    }
+
+    #[test]
+    fn new_dir_parents_covers_every_new_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let deepest = dir.path().join("a/b/c");
+        let top = topmost_missing_dir(&deepest).unwrap().to_path_buf();
+        assert_eq!(top, dir.path().join("a"));
+        let parents: Vec<&Path> = new_dir_parents(&top, &deepest).collect();
+        assert_eq!(
+            parents,
+            [dir.path().join("a/b"), dir.path().join("a"), dir.path().to_path_buf()]
+        );
+        std::fs::create_dir_all(&deepest).unwrap();
+        assert_eq!(topmost_missing_dir(&deepest), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn export_copies_the_tree_skips_links_and_stops_on_cancel() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("project");
+        std::fs::create_dir_all(source.join("sub")).unwrap();
+        std::fs::create_dir_all(source.join("empty")).unwrap();
+        std::fs::write(source.join("a.txt"), b"aaa").unwrap();
+        std::fs::write(source.join("b.txt"), b"bb").unwrap();
+        std::fs::write(source.join("sub/c.txt"), b"c").unwrap();
+        let secret = dir.path().join("secret");
+        std::fs::write(&secret, b"host file").unwrap();
+        std::os::unix::fs::symlink(&secret, source.join("link")).unwrap();
+        let out = dir.path().join("out");
+        std::fs::create_dir(&out).unwrap();
+
+        let phases = std::cell::RefCell::new(Vec::new());
+        let emit = |p: ExportProgress| phases.borrow_mut().push(p.phase);
+        let cancel = ImportCancel::default();
+        let result = do_export(&emit, &source, &out.join("project"), "export-1", &cancel).unwrap();
+        assert_eq!((result.files_copied, result.bytes_copied), (3, 6));
+        assert_eq!(result.symlinks_skipped, 1);
+        assert!(result.errors.is_empty() && !result.cancelled, "{:?}", result.errors);
+        assert_eq!(std::fs::read(out.join("project/sub/c.txt")).unwrap(), b"c");
+        assert!(out.join("project/empty").is_dir());
+        assert!(std::fs::symlink_metadata(out.join("project/link")).is_err());
+        assert_eq!(phases.borrow().last().map(String::as_str), Some(PHASE_DONE));
+
+        // A file swapped for a symlink after the scan isn't followed: once
+        // a.txt is copied, b.txt becomes a link to a file outside the tree.
+        let emit = |p: ExportProgress| {
+            if p.phase == PHASE_COPYING && p.processed == 1 {
+                std::fs::remove_file(source.join("b.txt")).unwrap();
+                std::os::unix::fs::symlink(&secret, source.join("b.txt")).unwrap();
+            }
+        };
+        let result = do_export(&emit, &source, &out.join("swap"), "export-2", &cancel).unwrap();
+        assert_eq!(result.files_copied, 2);
+        assert!(result.errors[0].starts_with("b.txt: "), "{:?}", result.errors);
+        assert!(!out.join("swap/b.txt").exists());
+
+        // A cancel stops before the next file and reports what was copied.
+        let emit = |p: ExportProgress| {
+            if p.phase == PHASE_COPYING {
+                cancel.request(false);
+            }
+        };
+        let result = do_export(&emit, &source, &out.join("cancel"), "export-3", &cancel).unwrap();
+        assert!(result.cancelled);
+        assert_eq!(result.files_copied, 1);
+        assert!(out.join("cancel/a.txt").exists() && !out.join("cancel/sub").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn export_paths_stay_out_of_the_workspace_and_its_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("workspace");
+        let out = dir.path().join("out");
+        std::fs::create_dir_all(workspace.join("project/src")).unwrap();
+        std::fs::create_dir_all(&out).unwrap();
+        std::fs::write(workspace.join("project/src/main.rs"), b"fn main() {}").unwrap();
+        std::os::unix::fs::symlink(dir.path(), workspace.join("escape")).unwrap();
+        let out_str = out.to_str().unwrap();
+
+        let (source, dest) = export_paths(&workspace, "project", out_str).unwrap();
+        assert_eq!(source, workspace.join("project"));
+        assert_eq!(dest, out.canonicalize().unwrap().join("project"));
+
+        assert!(export_paths(&workspace, "../out", out_str).is_err());
+        assert!(export_paths(&workspace, "escape/out", out_str).is_err());
+        assert!(export_paths(&workspace, "missing", out_str).is_err());
+        assert!(export_paths(&workspace, "project", "out").is_err());
+        // Into the workspace, or over a directory holding it.
+        let inside = workspace.join("project/src");
+        assert!(export_paths(&workspace, "project", inside.to_str().unwrap()).is_err());
+        let parent = dir.path().to_str().unwrap();
+        assert!(export_paths(&workspace, "", parent).is_err());
+
+        // Directories are created without going through a link in the
+        // destination tree.
+        std::fs::create_dir_all(out.join("project")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), out.join("project/src")).unwrap();
+        assert!(create_export_dir(&out, Path::new("project/src/nested")).is_err());
+        assert!(!dir.path().join("nested").exists());
+        create_export_dir(&out, Path::new("project/docs/api")).unwrap();
+        assert!(out.join("project/docs/api").is_dir());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn conflict_modes_overwrite_skip_or_rename_existing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("notes.txt");
+        let fresh = dir.path().join("fresh.txt");
+        std::fs::write(&dest, b"edited in the workspace").unwrap();
+
+        // Nothing there: every mode writes to the original path.
+        use ImportConflictMode::{Overwrite, Rename, Skip};
+        for mode in [Overwrite, Skip, Rename] {
+            assert_eq!(conflict_dest(fresh.clone(), mode), Some(fresh.clone()));
+        }
+
+        assert_eq!(conflict_dest(dest.clone(), ImportConflictMode::Overwrite), Some(dest.clone()));
+        assert_eq!(conflict_dest(dest.clone(), ImportConflictMode::Skip), None);
+        assert_eq!(
+            conflict_dest(dest.clone(), ImportConflictMode::Rename),
+            Some(dir.path().join("notes (1).txt"))
+        );
+        // The picked name is reserved, so the next conflict moves on.
+        assert_eq!(std::fs::metadata(dir.path().join("notes (1).txt")).unwrap().len(), 0);
+        assert_eq!(
+            conflict_dest(dest.clone(), ImportConflictMode::Rename),
+            Some(dir.path().join("notes (2).txt"))
+        );
+
+        // A dangling symlink still counts as an existing entry, and the name
+        // without an extension gets no trailing dot.
+        let link = dir.path().join("Makefile");
+        std::os::unix::fs::symlink(dir.path().join("missing"), &link).unwrap();
+        assert_eq!(conflict_dest(link.clone(), ImportConflictMode::Skip), None);
+        assert_eq!(
+            conflict_dest(link, ImportConflictMode::Rename),
+            Some(dir.path().join("Makefile (1)"))
+        );
+
+        let mode: ImportConflictMode = serde_json::from_str("\"rename\"").unwrap();
+        assert_eq!(mode, ImportConflictMode::Rename);
+    }
+
+    #[test]
+    fn eta_follows_byte_throughput() {
+        let secs = std::time::Duration::from_secs;
+        assert_eq!(eta_secs(secs(10), 0, 1000), None);
+        assert_eq!(eta_secs(std::time::Duration::from_millis(500), 500, 1000), None);
+        // 250 bytes in 10s: 750 left at 25 B/s.
+        assert_eq!(eta_secs(secs(10), 250, 1000), Some(30));
+        assert_eq!(eta_secs(secs(3), 1000, 1000), Some(0));
+        // Files grew since the scan: never negative.
+        assert_eq!(eta_secs(secs(3), 1200, 1000), Some(0));
+    }
+
+    #[test]
+    fn ignored_directories_are_pruned_from_the_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path();
+        std::fs::create_dir_all(src.join(".git/objects")).unwrap();
+        std::fs::create_dir_all(src.join("web/node_modules/react")).unwrap();
+        std::fs::write(src.join(".git/objects/ab"), b"x").unwrap();
+        std::fs::write(src.join("web/node_modules/react/index.js"), b"x").unwrap();
+        std::fs::write(src.join("web/node_modules/react/package.json"), b"x").unwrap();
+        std::fs::write(src.join("web/app.js"), b"x").unwrap();
+        std::fs::write(src.join("web/build"), b"a file, not the build dir").unwrap();
+        std::fs::write(src.join(".gitignore"), b"x").unwrap();
+
+        let patterns = ["# deps".into(), "node_modules".into(), ".git/".into(), "build/".into()];
+        let options = ImportOptions {
+            ignore: compile_ignore_patterns(&patterns).unwrap(),
+            ..Default::default()
+        };
+        let scan = scan_import_source(src, None, &options, || ());
+        let mut files: Vec<_> = scan.entries.iter().map(|(_, rel, _)| rel.clone()).collect();
+        files.sort();
+        // `build/` only matches directories, so the file named `build` stays.
+        assert_eq!(
+            files,
+            [PathBuf::from(".gitignore"), PathBuf::from("web/app.js"), PathBuf::from("web/build")]
+        );
+        assert_eq!(scan.dir_entries, [PathBuf::from("web")]);
+        // The pruned directories count once each, not per file inside.
+        assert_eq!(scan.ignored, 2);
+
+        let err = compile_ignore_patterns(&["*.log".into(), "!keep.log".into()]).err().unwrap();
+        assert!(err.contains("not supported"), "{err}");
+    }
+
+    #[test]
+    fn transfer_ids_are_unique_within_a_millisecond() {
+        let ids: std::collections::HashSet<String> = (0..100).map(|_| transfer_id()).collect();
+        assert_eq!(ids.len(), 100);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn case_probe_for_previews_only_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Readme"), b"").unwrap();
+        std::fs::write(dir.path().join("42"), b"").unwrap();
+        assert!(!probe_case_insensitive(dir.path()));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+        // Both cases listed: nothing to learn from that pair, so the default.
+        let both = tempfile::tempdir().unwrap();
+        std::fs::write(both.path().join("a"), b"").unwrap();
+        std::fs::write(both.path().join("A"), b"").unwrap();
+        assert!(!probe_case_insensitive(both.path()));
+    }
 }
@@ -1,11 +1,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-// REVISION: main-v13-vm-cache-dir
-const MODULE_REVISION: &str = "main-v13-vm-cache-dir";
+// REVISION: main-v76-d1-quick-check
+const MODULE_REVISION: &str = "main-v76-d1-quick-check";
+
+#[macro_use]
+mod teardown;
 
 mod commands;
+mod settings;
 mod vm;
+mod workspace;
 
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
@@ -26,6 +32,18 @@ fn pid_file_path(data_dir: &Path) -> PathBuf {
   data_dir.join("desktop-services.pid")
 }
 
+/// PIDs left alone by an `ORCABOT_NO_CLEANUP=1` launch. Kept apart from the live
+/// PID file (which this run overwrites) so the next normal launch still reaps them.
+fn skipped_pid_file_path(data_dir: &Path) -> PathBuf {
+  data_dir.join("desktop-services.pid.skipped")
+}
+
+/// `ORCABOT_NO_CLEANUP=1`: leave a previous run's processes alive for post-mortem
+/// debugging instead of killing them at startup.
+fn no_cleanup() -> bool {
+  std::env::var("ORCABOT_NO_CLEANUP").is_ok_and(|v| v == "1")
+}
+
 /// Set once the user accepts an auto-update ("Update & restart"). The whole stack is
 /// about to be torn down and relaunched, so we must NOT keep spinning up heavy
 /// processes (especially the sandbox VM boot / image download) during the ~minutes
@@ -162,6 +180,156 @@ fn passthrough_env(workerd_env: &mut Vec<(&'static str, String)>, key: &'static
   }
 }
 
+/// Optional host env vars passed through to the control-plane workerd (OAuth
+/// client IDs/secrets, Resend, ...). Users who want these features set the env
+/// vars before launching the app; unset = feature degrades gracefully. Adding a
+/// new optional var here should be paired with a binding in
+/// workerd.desktop.capnp. Drift check: `node desktop/scripts/check-drift.mjs`.
+const CONTROLPLANE_PASSTHROUGH_ENV: &[&str] = &[
+  "GOOGLE_CLIENT_ID",
+  "GOOGLE_CLIENT_SECRET",
+  "GOOGLE_API_KEY",
+  "GITHUB_CLIENT_ID",
+  "GITHUB_CLIENT_SECRET",
+  "MICROSOFT_CLIENT_ID",
+  "MICROSOFT_CLIENT_SECRET",
+  "ONEDRIVE_CLIENT_ID",
+  "ONEDRIVE_CLIENT_SECRET",
+  "BOX_CLIENT_ID",
+  "BOX_CLIENT_SECRET",
+  "TWITTER_CLIENT_ID",
+  "TWITTER_CLIENT_SECRET",
+  "DISCORD_CLIENT_ID",
+  "DISCORD_CLIENT_SECRET",
+  "SLACK_CLIENT_ID",
+  "SLACK_CLIENT_SECRET",
+  "RESEND_API_KEY",
+  "EGRESS_PROXY_ENABLED",
+];
+
+/// Settings for the host services (d1-shim, frontend workerd, control-plane
+/// workerd): ports, tokens, URLs and flags, resolved once with the defaults
+/// applied. Build it after port allocation, which exports the chosen ports.
+struct ServicesConfig {
+  controlplane_port: u16,
+  frontend_port: u16,
+  /// `host:port` the d1-shim listens on.
+  d1_addr: String,
+  d1_sqlite_path: PathBuf,
+  d1_shim_debug: Option<String>,
+  sandbox_url: String,
+  sandbox_internal_token: String,
+  internal_api_token: String,
+  dev_auth_enabled: String,
+  dev_mode_enabled: String,
+  surface_token: String,
+  secrets_encryption_key: String,
+  oauth_redirect_base: String,
+  email_from: String,
+  allowed_origins: String,
+  frontend_url: String,
+  /// Set members of `CONTROLPLANE_PASSTHROUGH_ENV`.
+  passthrough: Vec<(&'static str, String)>,
+}
+
+/// Env for each spawned service, from [`ServicesConfig::to_spawn_env`].
+struct SpawnEnv {
+  d1_shim: Vec<(&'static str, String)>,
+  frontend: Vec<(&'static str, String)>,
+  controlplane: Vec<(&'static str, String)>,
+}
+
+impl ServicesConfig {
+  /// Read the environment, with the defaults the desktop stack has always used.
+  /// The D1 database path and secrets key come from the data dir, not the env;
+  /// the dev flags fall back to `settings` before their defaults.
+  fn from_env(
+    d1_sqlite_path: PathBuf,
+    secrets_encryption_key: String,
+    settings: &settings::Settings,
+  ) -> Self {
+    let env_or = |key: &str, default: &str| std::env::var(key).unwrap_or_else(|_| default.to_string());
+    let flag = |key: &str, setting: Option<bool>| {
+      std::env::var(key).unwrap_or_else(|_| setting.unwrap_or(true).to_string())
+    };
+    let port = |key: &str, default: u16| {
+      std::env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+    };
+    let controlplane_port = port("CONTROLPLANE_PORT", 8787);
+    let frontend_port = port("FRONTEND_PORT", 8788);
+    let frontend_origin = format!("http://localhost:{}", frontend_port);
+    let mut passthrough = Vec::new();
+    for key in CONTROLPLANE_PASSTHROUGH_ENV {
+      passthrough_env(&mut passthrough, key);
+    }
+    Self {
+      controlplane_port,
+      frontend_port,
+      d1_addr: env_or("D1_SHIM_ADDR", "127.0.0.1:9001"),
+      d1_sqlite_path,
+      d1_shim_debug: std::env::var("D1_SHIM_DEBUG").ok(),
+      sandbox_url: env_or("SANDBOX_URL", "http://127.0.0.1:8080"),
+      sandbox_internal_token: sandbox_internal_token(),
+      internal_api_token: internal_api_token(),
+      dev_auth_enabled: flag("DEV_AUTH_ENABLED", settings.dev_auth),
+      dev_mode_enabled: flag("NEXT_PUBLIC_DEV_MODE_ENABLED", settings.dev_mode),
+      surface_token: surface_token().to_string(),
+      secrets_encryption_key,
+      oauth_redirect_base: env_or(
+        "OAUTH_REDIRECT_BASE",
+        &format!("http://localhost:{}", controlplane_port),
+      ),
+      email_from: env_or("EMAIL_FROM", "OrcaBot Desktop <noreply@localhost>"),
+      // Default allowed origins / frontend URL: the local frontend. A LAN origin
+      // is never added on its own; with `bind_address` 0.0.0.0 the user lists it.
+      allowed_origins: env_or("ALLOWED_ORIGINS", &frontend_origin),
+      frontend_url: env_or("FRONTEND_URL", &frontend_origin),
+      passthrough,
+    }
+  }
+
+  fn to_spawn_env(&self) -> SpawnEnv {
+    let d1_shim = vec![
+      ("D1_SQLITE_PATH", self.d1_sqlite_path.display().to_string()),
+      ("D1_SHIM_ADDR", self.d1_addr.clone()),
+      ("D1_SHIM_DEBUG", self.d1_shim_debug.clone().unwrap_or_default()),
+    ];
+    let frontend = vec![
+      ("NEXT_PUBLIC_API_URL", format!("http://localhost:{}", self.controlplane_port)),
+      ("NEXT_PUBLIC_SITE_URL", format!("http://localhost:{}", self.frontend_port)),
+      ("NEXT_PUBLIC_DEV_MODE_ENABLED", self.dev_mode_enabled.clone()),
+      ("NEXT_PUBLIC_DESKTOP_MODE", "true".to_string()),
+    ];
+    let mut controlplane = vec![
+      ("D1_HTTP_URL", "http://d1-shim".to_string()),
+      ("SANDBOX_URL", self.sandbox_url.clone()),
+      ("SANDBOX_INTERNAL_TOKEN", self.sandbox_internal_token.clone()),
+      ("INTERNAL_API_TOKEN", self.internal_api_token.clone()),
+      ("DEV_AUTH_ENABLED", self.dev_auth_enabled.clone()),
+      // Gates dev-auth to the host frontend (which sends X-Orcabot-Surface with
+      // this value); the sandbox VM never gets it, so it can't spoof user auth.
+      ("SURFACE_TOKEN", self.surface_token.clone()),
+      ("SECRETS_ENCRYPTION_KEY", self.secrets_encryption_key.clone()),
+      ("OAUTH_REDIRECT_BASE", self.oauth_redirect_base.clone()),
+      ("EMAIL_FROM", self.email_from.clone()),
+      ("ALLOWED_ORIGINS", self.allowed_origins.clone()),
+      ("FRONTEND_URL", self.frontend_url.clone()),
+    ];
+    if let Some(ref value) = self.d1_shim_debug {
+      controlplane.push(("D1_SHIM_DEBUG", value.clone()));
+    }
+    controlplane.extend(self.passthrough.iter().cloned());
+    SpawnEnv {
+      d1_shim,
+      frontend,
+      controlplane,
+    }
+  }
+}
+
 /// First free TCP port at/after `preferred` on loopback, skipping `used`. Falls
 /// back to `preferred` if nothing is free in range (the later bind then fails
 /// loudly). Used so the app boots even when a default port is occupied (e.g. a
@@ -198,12 +366,15 @@ fn pick_free_port(preferred: u16, used: &[u16]) -> u16 {
 }
 
 /// Ensure `var` holds a usable port. If the user set it explicitly, honor it
-/// verbatim (their override). Otherwise pick a free port near `preferred`,
-/// avoiding `used`, and store it. Returns the chosen port.
+/// verbatim (their override) — except 0, which can't be a fixed forward or
+/// listen port. Otherwise pick a free port near `preferred`, avoiding `used`,
+/// and store it. Returns the chosen port.
 fn ensure_port_env(var: &str, preferred: u16, used: &[u16]) -> u16 {
   if let Ok(v) = std::env::var(var) {
-    if let Ok(p) = v.trim().parse::<u16>() {
-      return p;
+    match v.trim().parse::<u16>() {
+      Ok(0) => eprintln!("[ports] {}=0 is not a usable port; allocating one instead", var),
+      Ok(p) => return p,
+      Err(_) => {}
     }
   }
   let port = pick_free_port(preferred, used);
@@ -261,6 +432,23 @@ pub fn surface_token() -> &'static str {
   })
 }
 
+/// Built-in dev defaults of the two service tokens. Anyone can send them, so a
+/// stack using them must stay on loopback (`network_bind_refusal`).
+const DEFAULT_SANDBOX_INTERNAL_TOKEN: &str = "dev-sandbox-token";
+const DEFAULT_INTERNAL_API_TOKEN: &str = "dev-internal-token";
+
+/// Effective control-plane → sandbox token (env override, else the dev default).
+/// Shared by the workerd env, the VM env and `get_local_credentials`.
+pub(crate) fn sandbox_internal_token() -> String {
+  std::env::var("SANDBOX_INTERNAL_TOKEN")
+    .unwrap_or_else(|_| DEFAULT_SANDBOX_INTERNAL_TOKEN.to_string())
+}
+
+/// Effective sandbox → control-plane token (env override, else the dev default).
+pub(crate) fn internal_api_token() -> String {
+  std::env::var("INTERNAL_API_TOKEN").unwrap_or_else(|_| DEFAULT_INTERNAL_API_TOKEN.to_string())
+}
+
 /// Persist the surface token to a host-only file (0600) so trusted host clients
 /// that use dev-auth — the `orcabot` CLI and scripts — can read it and send the
 /// X-Orcabot-Surface header. The app-data dir is NOT shared into the sandbox VM
@@ -276,10 +464,99 @@ fn write_surface_token_file(data_dir: &std::path::Path) {
   }
 }
 
-/// Kill any processes listed in a stale PID file from a previous run.
+/// Marker (in the D1 dir) left by `reset_controlplane_db`: the next start moves
+/// the control-plane DB aside before d1-shim opens it.
+const CONTROLPLANE_DB_RESET_MARKER: &str = "reset-requested";
+
+/// Run d1-shim's `--integrity-check` on the control-plane DB: `PRAGMA
+/// quick_check` unless `full` (the whole `integrity_check`, which also compares
+/// every index against its table and can take much longer). Some(detail) only
+/// when it reports corruption; a check that can't run (old binary, locked file,
+/// timeout) is logged and treated as healthy so startup goes on as before.
+fn controlplane_db_corruption(d1_shim_bin: &Path, db: &Path, full: bool) -> Option<String> {
+  if !db.exists() {
+    return None;
+  }
+  let mut command = Command::new(d1_shim_bin);
+  command.arg("--integrity-check");
+  if !full {
+    command.arg("--quick");
+  }
+  let mut child = match command
+    .env("D1_SQLITE_PATH", db)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+  {
+    Ok(c) => c,
+    Err(e) => {
+      eprintln!("[d1] integrity check skipped: {e}");
+      return None;
+    }
+  };
+  let started = std::time::Instant::now();
+  let status = loop {
+    match child.try_wait() {
+      Ok(Some(status)) => break status,
+      Ok(None) if started.elapsed() < Duration::from_secs(60) => {
+        std::thread::sleep(Duration::from_millis(100));
+      }
+      _ => {
+        eprintln!("[d1] integrity check did not finish; skipping it");
+        let _ = child.kill();
+        let _ = child.wait();
+        return None;
+      }
+    }
+  };
+  let mut output = String::new();
+  if let Some(mut out) = child.stdout.take() {
+    let _ = out.read_to_string(&mut output);
+  }
+  let detail = output.lines().take(5).collect::<Vec<_>>().join("; ");
+  match status.code() {
+    Some(0) => None,
+    Some(2) => Some(detail),
+    _ => {
+      eprintln!("[d1] integrity check could not run ({status}): {detail}");
+      None
+    }
+  }
+}
+
+/// Move the control-plane DB and its WAL/SHM files into `<d1>/corrupt-<unix secs>/`
+/// so d1-shim starts on a fresh database. Returns the backup dir.
+fn quarantine_controlplane_db(db: &Path) -> std::io::Result<PathBuf> {
+  let secs = SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default();
+  let backup_dir = db
+    .parent()
+    .unwrap_or(Path::new("."))
+    .join(format!("corrupt-{secs}"));
+  fs::create_dir_all(&backup_dir)?;
+  let name = db.file_name().unwrap_or_default().to_string_lossy().to_string();
+  for suffix in ["", "-wal", "-shm"] {
+    let file = db.with_file_name(format!("{name}{suffix}"));
+    if file.exists() {
+      fs::rename(&file, backup_dir.join(format!("{name}{suffix}")))?;
+    }
+  }
+  Ok(backup_dir)
+}
+
+/// Kill any processes listed in a stale PID file from a previous run, including
+/// ones an `ORCABOT_NO_CLEANUP=1` launch left behind.
 fn cleanup_stale_processes(data_dir: &Path) {
-  let pid_path = pid_file_path(data_dir);
-  let contents = match std::fs::read_to_string(&pid_path) {
+  for pid_path in [pid_file_path(data_dir), skipped_pid_file_path(data_dir)] {
+    cleanup_pid_file(&pid_path, data_dir);
+  }
+}
+
+fn cleanup_pid_file(pid_path: &Path, data_dir: &Path) {
+  let contents = match std::fs::read_to_string(pid_path) {
     Ok(c) => c,
     Err(_) => return, // No PID file — nothing to clean up
   };
@@ -305,10 +582,123 @@ fn cleanup_stale_processes(data_dir: &Path) {
           None => eprintln!("[cleanup] Skipping PID {pid} — could not verify its identity"),
         }
       }
+      // No signals on Windows: identify by command line, then end the whole
+      // tree (a stale `wsl.exe` sandbox child or a service) with taskkill.
+      #[cfg(windows)]
+      {
+        match proc_command(pid) {
+          Some(cmd) if is_orcabot_process(&cmd, data_dir) => {
+            eprintln!("[cleanup] Killing stale Orcabot process {pid}");
+            let _ = std::process::Command::new("taskkill")
+              .args(["/PID", &pid.to_string(), "/T", "/F"])
+              .output();
+          }
+          Some(_) => eprintln!("[cleanup] Skipping PID {pid} — not an Orcabot process (PID reused?)"),
+          None => {} // not running
+        }
+      }
+    }
+  }
+
+  let _ = std::fs::remove_file(pid_path);
+}
+
+/// Startup path for `ORCABOT_NO_CLEANUP=1`: move the previous run's PIDs into the
+/// skipped file (appending, so repeated debug launches accumulate) and warn.
+fn skip_stale_cleanup(data_dir: &Path) {
+  let pid_path = pid_file_path(data_dir);
+  let stale = std::fs::read_to_string(&pid_path).unwrap_or_default();
+  let pids: Vec<&str> = stale.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+  eprintln!(
+    "[cleanup] WARNING: ORCABOT_NO_CLEANUP=1 — skipping stale-process cleanup; orphaned \
+     services from a previous run may still be running and holding ports"
+  );
+  if pids.is_empty() {
+    return;
+  }
+  eprintln!("[cleanup] Leaving previous PIDs alive: {}", pids.join(", "));
+  let skipped_path = skipped_pid_file_path(data_dir);
+  let mut all = std::fs::read_to_string(&skipped_path).unwrap_or_default();
+  for pid in pids {
+    all.push_str(pid);
+    all.push('\n');
+  }
+  if std::fs::write(&skipped_path, all).is_ok() {
+    let _ = std::fs::remove_file(&pid_path);
+  }
+}
+
+/// Executables we ship and spawn from the data or resource dir. The orphan scan
+/// only ever considers these (and `orphan_vm_process`), so e.g. a user's
+/// `orcabot` CLI session living in the same resource dir is never touched.
+#[cfg(unix)]
+const ORPHAN_EXE_NAMES: &[&str] = &["workerd", "d1-shim", "vz-helper", "virtiofsd"];
+
+/// Whether `command` is a QEMU or virtiofsd the app started, by the markers it
+/// put on their command lines (`vm::QEMU_NAME`, `vm::VIRTIOFS_SOCKET_PREFIX`).
+/// Both usually run from the system's binaries, outside any of our dirs.
+#[cfg(unix)]
+fn orphan_vm_process(exe_name: &str, command: &str) -> bool {
+  let args: Vec<&str> = command.split_whitespace().collect();
+  if exe_name.starts_with("qemu-system-") {
+    return args.windows(2).any(|pair| pair == ["-name", vm::QEMU_NAME]);
+  }
+  exe_name == "virtiofsd" && args.iter().any(|arg| arg.contains(vm::VIRTIOFS_SOCKET_PREFIX))
+}
+
+/// Opt-in (`ORCABOT_ORPHAN_SCAN=1`) safety net for when the PID file is gone but
+/// our services survived (e.g. the app data dir was cleared after a crash): kill
+/// every process whose executable lives under one of `roots` and is one of ours
+/// by name (`ORPHAN_EXE_NAMES`), and every QEMU or virtiofsd whose command line
+/// carries the app's markers (`orphan_vm_process`), wherever it is installed.
+/// More aggressive than `cleanup_stale_processes` — a second running
+/// instance's services match too — hence the flag.
+#[cfg(unix)]
+fn kill_orphans_by_exe(roots: &[&Path]) {
+  let out = match std::process::Command::new("ps")
+    .args(["-axo", "pid=,command="])
+    .output()
+  {
+    Ok(o) if o.status.success() => o,
+    _ => {
+      eprintln!("[cleanup] Orphan scan skipped — could not list processes");
+      return;
+    }
+  };
+  let me = std::process::id() as i32;
+  for line in String::from_utf8_lossy(&out.stdout).lines() {
+    let mut parts = line.trim().splitn(2, char::is_whitespace);
+    let pid = match parts.next().and_then(|p| p.parse::<i32>().ok()) {
+      Some(pid) if pid != me => pid,
+      _ => continue,
+    };
+    let command = parts.next().unwrap_or("");
+    let Some(exe) = proc_exe(pid, command) else {
+      continue;
+    };
+    let name = exe.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let ours = (ORPHAN_EXE_NAMES.contains(&name) && roots.iter().any(|r| exe.starts_with(r)))
+      || orphan_vm_process(name, command);
+    if ours {
+      eprintln!("[cleanup] Killing orphaned {} (PID {pid})", exe.display());
+      unsafe { libc::kill(pid, libc::SIGTERM) };
+      std::thread::sleep(Duration::from_millis(500));
+      unsafe { libc::kill(pid, libc::SIGKILL) };
     }
   }
+}
 
-  let _ = std::fs::remove_file(&pid_path);
+/// Executable path of `pid`: `/proc/<pid>/exe` on Linux (immune to argv
+/// rewriting), else argv[0] from its `ps` command line. We always spawn by
+/// absolute path, so argv[0] is the full path for our own children.
+#[cfg(unix)]
+fn proc_exe(pid: i32, command: &str) -> Option<PathBuf> {
+  #[cfg(target_os = "linux")]
+  if let Ok(exe) = std::fs::read_link(format!("/proc/{pid}/exe")) {
+    return Some(exe);
+  }
+  let _ = pid;
+  command.split_whitespace().next().map(PathBuf::from).filter(|p| p.is_absolute())
 }
 
 /// The full command line of a running PID (via `ps`), or None if unreadable/gone.
@@ -329,11 +719,28 @@ fn proc_command(pid: i32) -> Option<String> {
   }
 }
 
+/// The full command line of a running PID (via `Win32_Process`), or None if
+/// unreadable/gone.
+#[cfg(windows)]
+fn proc_command(pid: i32) -> Option<String> {
+  let out = std::process::Command::new("powershell")
+    .args([
+      "-NoProfile",
+      "-NonInteractive",
+      "-Command",
+      &format!("(Get-CimInstance Win32_Process -Filter \"ProcessId={pid}\").CommandLine"),
+    ])
+    .output()
+    .ok()?;
+  let cmd = String::from_utf8_lossy(&out.stdout).trim().to_string();
+  (out.status.success() && !cmd.is_empty()).then_some(cmd)
+}
+
 /// Whether a command line looks like one of Orcabot's own children. Our workerd,
-/// d1-shim, and vz-helper run from the data dir, and the headless backend runs
-/// from the com.orcabot bundle — install-specific markers, so a recycled PID
-/// running an unrelated program is not matched.
-#[cfg(unix)]
+/// d1-shim, and vz-helper run from the data dir, the headless backend runs
+/// from the com.orcabot bundle, and the WSL backend's `wsl.exe` runs
+/// orcabot-server — install-specific markers, so a recycled PID running an
+/// unrelated program is not matched.
 fn is_orcabot_process(cmd: &str, data_dir: &Path) -> bool {
   let dd = data_dir.to_string_lossy();
   (!dd.is_empty() && cmd.contains(dd.as_ref()))
@@ -341,14 +748,15 @@ fn is_orcabot_process(cmd: &str, data_dir: &Path) -> bool {
     || cmd.contains("orcabot-desktop")
     || cmd.contains("d1-shim")
     || cmd.contains("vz-helper")
+    || cmd.contains("orcabot-server")
 }
 
 /// Write all tracked child PIDs to the PID file.
-fn write_pid_file(data_dir: &Path, children: &[Child], vm_pid: Option<u32>) {
+fn write_pid_file(data_dir: &Path, children: &[ServiceChild], vm_pid: Option<u32>) {
   let pid_path = pid_file_path(data_dir);
   let mut pids = Vec::new();
-  for child in children {
-    pids.push(child.id().to_string());
+  for service in children {
+    pids.push(service.child.id().to_string());
   }
   if let Some(pid) = vm_pid {
     pids.push(pid.to_string());
@@ -356,10 +764,284 @@ fn write_pid_file(data_dir: &Path, children: &[Child], vm_pid: Option<u32>) {
   let _ = std::fs::write(&pid_path, pids.join("\n"));
 }
 
+/// Which host service a spawned child is. Drives per-service shutdown policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum ServiceRole {
+  D1Shim,
+  WorkerdFrontend,
+  Workerd,
+}
+
+impl ServiceRole {
+  /// Label used for log prefixes (`[d1-shim] ...`) and the startup log.
+  fn label(self) -> &'static str {
+    match self {
+      ServiceRole::D1Shim => "d1-shim",
+      ServiceRole::WorkerdFrontend => "workerd-frontend",
+      ServiceRole::Workerd => "workerd",
+    }
+  }
+
+  /// Per-role override of the shutdown grace period.
+  fn grace_env(self) -> &'static str {
+    match self {
+      ServiceRole::D1Shim => "SHUTDOWN_GRACE_SECS_D1_SHIM",
+      ServiceRole::WorkerdFrontend => "SHUTDOWN_GRACE_SECS_WORKERD_FRONTEND",
+      ServiceRole::Workerd => "SHUTDOWN_GRACE_SECS_WORKERD",
+    }
+  }
+
+  /// Built-in grace when neither env var is set. d1-shim gets longer so SQLite
+  /// can checkpoint its WAL; the workerds hold no durable state we'd lose by
+  /// killing them, so a wedged one shouldn't hold up quit.
+  fn default_grace(self) -> Duration {
+    match self {
+      ServiceRole::D1Shim => Duration::from_secs(5),
+      ServiceRole::WorkerdFrontend => Duration::from_secs(1),
+      ServiceRole::Workerd => Duration::from_secs(2),
+    }
+  }
+
+  /// How long to wait after the stop signal before SIGKILL:
+  /// `SHUTDOWN_GRACE_SECS_<ROLE>`, else `SHUTDOWN_GRACE_SECS`, else the role default.
+  fn shutdown_grace(self) -> Duration {
+    [self.grace_env(), "SHUTDOWN_GRACE_SECS"]
+      .iter()
+      .find_map(|var| std::env::var(var).ok().and_then(|v| v.trim().parse::<f64>().ok()))
+      .filter(|secs| secs.is_finite() && *secs >= 0.0)
+      .map(Duration::from_secs_f64)
+      .unwrap_or_else(|| self.default_grace())
+  }
+}
+
+/// Signal sent to children to request a graceful stop (`SHUTDOWN_SIGNAL`:
+/// TERM, INT, HUP or QUIT; default TERM). SIGKILL follows after the grace period.
+#[cfg(unix)]
+fn shutdown_signal() -> libc::c_int {
+  match std::env::var("SHUTDOWN_SIGNAL")
+    .unwrap_or_default()
+    .trim()
+    .trim_start_matches("SIG")
+    .to_ascii_uppercase()
+    .as_str()
+  {
+    "INT" => libc::SIGINT,
+    "HUP" => libc::SIGHUP,
+    "QUIT" => libc::SIGQUIT,
+    _ => libc::SIGTERM,
+  }
+}
+
+/// How a host service was launched, kept so `watch_services` can respawn it.
+#[derive(Clone)]
+struct ServiceSpec {
+  binary: PathBuf,
+  args: Vec<OsString>,
+  envs: Vec<(String, String)>,
+}
+
+/// A spawned host service and the role it plays.
+struct ServiceChild {
+  role: ServiceRole,
+  child: Child,
+  spec: ServiceSpec,
+}
+
+/// How often `watch_services` checks for exited services.
+const SERVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Pause after each service restart before its restart slot is released, so a
+/// burst of crashes comes back one after another rather than all at once.
+const SERVICE_RESTART_STAGGER: Duration = Duration::from_secs(2);
+
+/// Counting semaphore bounding concurrent service restarts. The limit is read
+/// per acquire (`settings.json` `max_concurrent_restarts`), so changing the
+/// setting applies to the next restart.
+#[derive(Default)]
+struct RestartSlots {
+  active: Mutex<u32>,
+  freed: std::sync::Condvar,
+}
+
+impl RestartSlots {
+  fn acquire(&self, limit: u32) {
+    let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+    while *active >= limit.max(1) {
+      active = self.freed.wait(active).unwrap_or_else(|e| e.into_inner());
+    }
+    *active += 1;
+  }
+
+  fn release(&self) {
+    let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+    *active = active.saturating_sub(1);
+    self.freed.notify_one();
+  }
+}
+
+/// Sandbox VM idle tracking for `ORCABOT_SANDBOX_IDLE_MINS`.
+/// When the sandbox was last used is `DesktopServices::sandbox_activity`.
+struct SandboxIdle {
+  /// The idle watcher stopped the VM; the next activity boots it again.
+  stopped: bool,
+  /// `(data_dir, vm_dir, resource_root)` of the first boot, reused to restart.
+  boot_dirs: Option<(PathBuf, PathBuf, PathBuf)>,
+}
+
+/// One finished startup phase. Kept for `get_startup_timings`, mirrored to
+/// `<data_dir>/startup-timings.json` (shown by `orcabot status`), and emitted as
+/// `startup-phase` so the loading screen can show where a slow launch is stuck.
+#[derive(Clone, serde::Serialize)]
+struct StartupPhase {
+  /// "staging" | "db_check" | "d1_shim_start" | "frontend_health" |
+  /// "workerd_health" | "vm_staging" | "vm_boot" | "vm_health" | "vm_prewarm"
+  phase: String,
+  /// When the phase began, in ms since the app launched.
+  started_ms: u64,
+  duration_ms: u64,
+  ok: bool,
+}
+
+fn startup_timings_path(data_dir: &Path) -> PathBuf {
+  data_dir.join("startup-timings.json")
+}
+
 struct DesktopServices {
-  children: Mutex<Vec<Child>>,
+  children: Mutex<Vec<ServiceChild>>,
   sandbox_vm: Mutex<Option<Box<dyn VirtualMachine>>>,
+  /// Held by `start_sandbox_vm` from its "is a VM already up?" check until the
+  /// new VM is stored, so concurrent starts can't each boot one. A separate lock
+  /// because holding `sandbox_vm` through a boot would block status queries and
+  /// shutdown for up to the 120s health wait.
+  vm_start: Mutex<()>,
   data_dir: Mutex<Option<PathBuf>>,
+  sandbox_idle: Mutex<SandboxIdle>,
+  /// Last sandbox use: traffic through the sandbox relay, user exec calls and
+  /// `note_sandbox_activity`. Read by `watch_sandbox_idle`.
+  sandbox_activity: Arc<vm::relay::Activity>,
+  /// `get_guest_info` result, keyed by the VM process it was read from.
+  guest_info: Mutex<Option<(Option<u32>, vm::GuestInfo)>>,
+  /// Config the current (or last) sandbox VM was started with, for
+  /// `get_vm_config`. Only meaningful while `sandbox_vm` holds a VM.
+  vm_config: Mutex<Option<VMConfig>>,
+  /// Reference point for `StartupPhase::started_ms`.
+  launched: std::time::Instant,
+  /// Phases in completion order; a VM restart (idle wake) appends its own.
+  startup_timings: Mutex<Vec<StartupPhase>>,
+  /// Set by `shutdown`, so `watch_services` doesn't restart what it stops.
+  shutting_down: std::sync::atomic::AtomicBool,
+  service_restarts: RestartSlots,
+  /// Restarts whose spawn failed, for `watch_services` to count against the
+  /// service's backoff and retry (the service is no longer in `children`).
+  failed_restarts: Mutex<Vec<(ServiceRole, ServiceSpec, String)>>,
+  /// Set by `suspend_all` while the stack is frozen; the watchers skip their
+  /// checks so a stopped service or paused VM isn't taken for a dead one.
+  suspended: std::sync::atomic::AtomicBool,
+  /// Set by `cancel_image_fetch` to stop an in-flight `fetch_and_stage_image`.
+  image_fetch_cancel: std::sync::atomic::AtomicBool,
+  /// Held for a whole `fetch_and_stage_image`, so two fetches don't share a
+  /// partial file. Only the final rename takes `vm_start`.
+  image_fetch: Mutex<()>,
+  /// Cancel flags of `sandbox_exec_streaming` runs started with an `exec_id`,
+  /// set by `cancel_sandbox_exec`.
+  exec_cancels: Mutex<std::collections::HashMap<String, Arc<std::sync::atomic::AtomicBool>>>,
+}
+
+/// How often `watch_vm_exit` checks the VM process. `is_running` is a lock
+/// read where a reaper thread holds the process; WSL asks `tasklist`.
+const VM_EXIT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Idle window after which the sandbox VM is stopped to save power
+/// (`ORCABOT_SANDBOX_IDLE_MINS`; unset or 0 = never).
+fn sandbox_idle_window() -> Option<Duration> {
+  std::env::var("ORCABOT_SANDBOX_IDLE_MINS")
+    .ok()
+    .and_then(|v| v.trim().parse::<f64>().ok())
+    .filter(|mins| mins.is_finite() && *mins > 0.0)
+    .map(|mins| Duration::from_secs_f64(mins * 60.0))
+}
+
+/// Stage the VM resources (kernel, initrd, vz-helper, and the disk image, which
+/// is downloaded and verified on first use) into `vm_dir`. Shared by VM start and
+/// `prewarm_resources`; callers hold `DesktopServices::vm_start` so two stagings
+/// never write the same files. `progress` follows a download, `staging` a local
+/// image being copied or decompressed (see [`staging_progress`]).
+fn stage_vm(
+  data_dir: &Path,
+  vm_dir: &Path,
+  resource_root: &Path,
+  progress: &dyn Fn(u64, u64),
+  staging: vm::image::StageProgress,
+) -> Result<vm::image::VMResourcePaths, vm::VMError> {
+  // One-time migration: the VM image + staged runtime binaries used to live under
+  // the app-data dir. They're large (~1GB) and fully regenerable, so they now live
+  // in the cache dir instead (Caches is the OS-purgeable bucket, and this keeps the
+  // image out of the precious Application Support tree). The old dir is reclaimed
+  // only after staging confirms a valid image below — never on mere dir existence.
+  let old_vm_dir = data_dir.join("vm");
+  migrate_vm_dir(&old_vm_dir, vm_dir);
+
+  // Check if VM resources exist
+  let mut vm_resource_paths = vm::image::VMResourcePaths::from_resource_root(resource_root);
+  // A tester-selected image (set_custom_vm_image) stands in for the bundled one.
+  // ensure_vm_image still checks ORCABOT_VM_IMAGE first, so the env var wins.
+  if let Some(custom) = vm::image::custom_image(data_dir) {
+    if custom.exists() {
+      eprintln!("[vm-image] using custom image {}", custom.display());
+      vm_resource_paths.set_image(custom);
+    } else {
+      eprintln!(
+        "[vm-image] custom image {} not found — using the default image",
+        custom.display()
+      );
+    }
+  }
+
+  match vm::image::stage_vm_resources(&vm_resource_paths, vm_dir, progress, Some(staging)) {
+    Ok(paths) => {
+      // Staging confirmed a valid image in the cache dir, so it's now safe to
+      // reclaim any leftover pre-migration VM dir. Gating on staging success —
+      // not directory existence — means a failed migration never deletes the
+      // last working image.
+      reclaim_old_vm_dir(&old_vm_dir, vm_dir);
+      Ok(paths)
+    }
+    Err(cache_err) if old_vm_dir.as_path() != vm_dir && old_vm_dir.exists() => {
+      // The cache dir is full/unwritable, but the preserved pre-migration image
+      // is still intact — boot from it so the sandbox still works instead of
+      // failing every launch. Leave `old_vm_dir` in place; a later launch with a
+      // writable cache migrates it over and reclaims it.
+      eprintln!(
+        "[vm] cache staging failed ({cache_err}); falling back to preserved VM dir {}",
+        old_vm_dir.display()
+      );
+      vm::image::stage_vm_resources(&vm_resource_paths, &old_vm_dir, progress, Some(staging))
+    }
+    Err(e) => Err(e),
+  }
+}
+
+/// Least time between two `vm-staging-progress` events; staging reports every
+/// 64KB, far more often than a progress bar needs.
+const STAGING_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Emits `vm-staging-progress` `{done, total}` (bytes; `total` is null while
+/// decompressing) as a local VM image is staged, so the several seconds a
+/// multi-GB copy takes don't look like a hang. Throttled to one event per
+/// `STAGING_PROGRESS_INTERVAL`, plus the last chunk of a copy.
+fn staging_progress(app: &tauri::AppHandle) -> impl Fn(u64, Option<u64>) + '_ {
+  use tauri::Emitter;
+  let last = std::cell::Cell::new(None::<std::time::Instant>);
+  move |done, total| {
+    let recent = last.get().is_some_and(|t| t.elapsed() < STAGING_PROGRESS_INTERVAL);
+    if !recent || Some(done) == total {
+      last.set(Some(std::time::Instant::now()));
+      let _ = app.emit(
+        "vm-staging-progress",
+        serde_json::json!({ "done": done, "total": total }),
+      );
+    }
+  }
 }
 
 /// Relocate the staged VM dir from its old (app-data) location to the new (cache)
@@ -443,7 +1125,24 @@ impl DesktopServices {
     Self {
       children: Mutex::new(Vec::new()),
       sandbox_vm: Mutex::new(None),
+      vm_start: Mutex::new(()),
       data_dir: Mutex::new(None),
+      sandbox_idle: Mutex::new(SandboxIdle {
+        stopped: false,
+        boot_dirs: None,
+      }),
+      sandbox_activity: Default::default(),
+      guest_info: Mutex::new(None),
+      vm_config: Mutex::new(None),
+      launched: std::time::Instant::now(),
+      startup_timings: Mutex::new(Vec::new()),
+      shutting_down: Default::default(),
+      service_restarts: Default::default(),
+      failed_restarts: Mutex::new(Vec::new()),
+      suspended: Default::default(),
+      image_fetch_cancel: Default::default(),
+      image_fetch: Mutex::new(()),
+      exec_cancels: Default::default(),
     }
   }
 
@@ -486,14 +1185,25 @@ impl DesktopServices {
       }
     };
 
-    // Kill any orphaned processes from a previous crash/force-quit
-    cleanup_stale_processes(&data_dir);
+    // Kill any orphaned processes from a previous crash/force-quit, unless
+    // they're being kept for debugging (which also overrides the orphan scan).
+    if no_cleanup() {
+      skip_stale_cleanup(&data_dir);
+    } else {
+      cleanup_stale_processes(&data_dir);
+    }
+    if !no_cleanup() && std::env::var("ORCABOT_ORPHAN_SCAN").is_ok_and(|v| v == "1") {
+      #[cfg(unix)]
+      kill_orphans_by_exe(&[data_dir.as_path(), resource_root.as_path()]);
+    }
 
     // Store data_dir for PID file writes
     if let Ok(mut dd) = self.data_dir.lock() {
       *dd = Some(data_dir.clone());
     }
+    teardown::set_log_dir(&data_dir);
 
+    let phase_start = std::time::Instant::now();
     let bin_dir = data_dir.join("bin");
     if let Err(err) = std::fs::create_dir_all(&bin_dir) {
       eprintln!("Failed to create bin dir: {}", err);
@@ -542,6 +1252,7 @@ impl DesktopServices {
     }
 
     let d1_db = d1_dir.join("controlplane.sqlite");
+    self.record_phase(app.handle(), "staging", phase_start, true);
 
     // Pick free ports BEFORE anything binds, so a stray process on a default
     // port (e.g. `wrangler dev` on 8787) doesn't stop the app from starting.
@@ -574,6 +1285,15 @@ impl DesktopServices {
         format!("http://127.0.0.1:{}", sandbox_host_port),
       );
     }
+    // With an idle shutdown, the sandbox relay takes SANDBOX_PORT and the VM's own
+    // forward moves behind it to this loopback port (`start_sandbox_relay`).
+    if sandbox_idle_window().is_some() {
+      ensure_port_env(
+        "ORCABOT_SANDBOX_VM_PORT",
+        18080,
+        &[cp_port, fe_port, d1_port, sandbox_host_port],
+      );
+    }
 
     // Start the per-boot startup log with the chosen ports — the first thing to check
     // when startup fails (was a default busy? did allocation move a service?).
@@ -582,6 +1302,28 @@ impl DesktopServices {
       cp_port, fe_port, d1_port, sandbox_host_port
     ));
 
+    let (bind_address, refusal) = resolve_bind_address(&data_dir);
+    if let Some(refusal) = refusal {
+      eprintln!("{refusal}");
+      self.append_startup_log(&refusal);
+    }
+    if bind_address.is_unspecified() {
+      let warning = format!(
+        "[security] WARNING: bind_address is {} — the frontend (:{}) and control plane \
+         (:{}) are reachable from every device on this network. Use it only on a network \
+         you trust, and set it back to 127.0.0.1 when done. The sandbox (:{}) stays on \
+         127.0.0.1, and the control plane only accepts browsers from ALLOWED_ORIGINS ({}): \
+         add the address other devices use.",
+        bind_address,
+        fe_port,
+        cp_port,
+        sandbox_host_port,
+        std::env::var("ALLOWED_ORIGINS").unwrap_or_else(|_| format!("http://localhost:{fe_port}"))
+      );
+      eprintln!("{warning}");
+      self.append_startup_log(&warning);
+    }
+
     if cp_port != 8787 || fe_port != 8788 || d1_port != 9001 || sandbox_host_port != 8080 {
       eprintln!(
         "[ports] a default port was busy — using control-plane={} frontend={} d1-shim={} sandbox={}",
@@ -593,24 +1335,62 @@ impl DesktopServices {
     // the hardcoded defaults) connects to this stack correctly.
     write_ports_file(&data_dir, cp_port, fe_port, sandbox_host_port, d1_port);
 
-    let d1_addr = std::env::var("D1_SHIM_ADDR").unwrap_or_else(|_| "127.0.0.1:9001".to_string());
-    let d1_shim_debug = std::env::var("D1_SHIM_DEBUG").ok();
+    // Encryption key for stored user_secrets. Generated on first launch and
+    // persisted in data_dir; losing the file makes existing stored secrets
+    // unreadable, which is by design (same property as cloud deployments).
+    let secrets_key = match ensure_secrets_encryption_key(&data_dir) {
+      Ok(k) => k,
+      Err(err) => {
+        eprintln!("[secrets] FATAL: could not load/generate encryption key: {err}");
+        // Continue with empty key — secrets routes will return 500, but app loads.
+        String::new()
+      }
+    };
 
-    self.spawn_binary(
-      &d1_shim_bin,
-      "d1-shim",
-      &[],
-      &[
-        ("D1_SQLITE_PATH", d1_db.display().to_string()),
-        ("D1_SHIM_ADDR", d1_addr.clone()),
-        ("D1_SHIM_DEBUG", d1_shim_debug.clone().unwrap_or_default()),
-      ],
-    );
+    // A hard kill can leave the control-plane DB corrupt, and d1-shim then fails
+    // every query. Quick-check it before d1-shim opens it; on corruption (or a
+    // manual reset_controlplane_db) keep the old files as a backup and start
+    // fresh. A requested reset runs the full check, so its log says whether the
+    // old DB was actually damaged.
+    let phase_start = std::time::Instant::now();
+    let reset_marker = d1_dir.join(CONTROLPLANE_DB_RESET_MARKER);
+    let reset_reason = if reset_marker.exists() {
+      Some(match controlplane_db_corruption(&d1_shim_bin, &d1_db, true) {
+        Some(detail) => format!("reset requested; integrity check failed: {detail}"),
+        None => "reset requested; integrity check found no corruption".to_string(),
+      })
+    } else {
+      controlplane_db_corruption(&d1_shim_bin, &d1_db, false)
+        .map(|detail| format!("quick check failed: {detail}"))
+    };
+    if let Some(reason) = reset_reason {
+      match quarantine_controlplane_db(&d1_db) {
+        Ok(backup) => {
+          let msg = format!(
+            "[d1] WARNING: control-plane database reset ({reason}). The old database was moved to {}; \
+             local dashboards, sessions and stored secrets start empty.",
+            backup.display()
+          );
+          eprintln!("{msg}");
+          self.append_startup_log(&msg);
+        }
+        Err(err) => eprintln!("[d1] could not move the control-plane database aside: {err}"),
+      }
+      let _ = std::fs::remove_file(&reset_marker);
+    }
+    self.record_phase(app.handle(), "db_check", phase_start, true);
 
-    // Start frontend workerd (serves the Next.js app)
-    let frontend_port =
-      std::env::var("FRONTEND_PORT").unwrap_or_else(|_| "8788".to_string());
+    let services_config = ServicesConfig::from_env(d1_db, secrets_key, &settings::load(&data_dir));
+    let spawn_env = services_config.to_spawn_env();
+    let controlplane_port = services_config.controlplane_port.to_string();
+    let frontend_port = services_config.frontend_port.to_string();
 
+    let phase_start = std::time::Instant::now();
+    // A failed spawn is logged to startup.log, which the loading screen shows.
+    let _ = self.spawn_binary(&d1_shim_bin, ServiceRole::D1Shim, &[], &spawn_env.d1_shim);
+    self.record_phase(app.handle(), "d1_shim_start", phase_start, true);
+
+    // Start frontend workerd (serves the Next.js app)
     if workerd_frontend_config.exists() && frontend_assets_dir.exists() {
       eprintln!(
         "Frontend assets dir: {}",
@@ -621,31 +1401,29 @@ impl DesktopServices {
         workerd_frontend_config.display()
       );
       eprintln!("Starting frontend workerd on port {}...", frontend_port);
-      self.spawn_binary(
+      let phase_start = std::time::Instant::now();
+      let _ = self.spawn_binary(
         &workerd_bin,
-        "workerd-frontend",
+        ServiceRole::WorkerdFrontend,
         &[
-          "serve",
-          "--experimental",
-          "--import-path",
-          workerd_import.to_str().unwrap_or_default(),
-          "--import-path",
-          workerd_import_root.to_str().unwrap_or_default(),
-          "--directory-path",
-          &format!("assets-dir={}", frontend_assets_dir.display()),
-          "--socket-addr",
-          &format!("http=127.0.0.1:{}", frontend_port),
-          workerd_frontend_config.to_str().unwrap_or_default(),
-        ],
-        &[
-          ("NEXT_PUBLIC_API_URL", format!("http://localhost:{}", std::env::var("CONTROLPLANE_PORT").unwrap_or_else(|_| "8787".to_string()))),
-          ("NEXT_PUBLIC_SITE_URL", format!("http://localhost:{}", frontend_port)),
-          ("NEXT_PUBLIC_DEV_MODE_ENABLED", "true".to_string()),
-          ("NEXT_PUBLIC_DESKTOP_MODE", "true".to_string()),
+          OsStr::new("serve"),
+          OsStr::new("--experimental"),
+          OsStr::new("--import-path"),
+          workerd_import.as_os_str(),
+          OsStr::new("--import-path"),
+          workerd_import_root.as_os_str(),
+          // workerd splits `name=path` at the first '=', so the path needs no escaping.
+          OsStr::new("--directory-path"),
+          &named_path("assets-dir", &frontend_assets_dir),
+          OsStr::new("--socket-addr"),
+          OsStr::new(&format!("http={}:{}", bind_address, frontend_port)),
+          workerd_frontend_config.as_os_str(),
         ],
+        &spawn_env.frontend,
       );
 
-      wait_for_health(&frontend_port);
+      let healthy = wait_for_frontend(&frontend_port);
+      self.record_phase(app.handle(), "frontend_health", phase_start, healthy);
       eprintln!("Frontend workerd running at http://localhost:{}", frontend_port);
     } else {
       eprintln!(
@@ -655,122 +1433,40 @@ impl DesktopServices {
       );
     }
 
-    let controlplane_port =
-      std::env::var("CONTROLPLANE_PORT").unwrap_or_else(|_| "8787".to_string());
-    let sandbox_url =
-      std::env::var("SANDBOX_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string());
-    let sandbox_internal_token =
-      std::env::var("SANDBOX_INTERNAL_TOKEN").unwrap_or_else(|_| "dev-sandbox-token".to_string());
-    let internal_api_token =
-      std::env::var("INTERNAL_API_TOKEN").unwrap_or_else(|_| "dev-internal-token".to_string());
-    let dev_auth_enabled =
-      std::env::var("DEV_AUTH_ENABLED").unwrap_or_else(|_| "true".to_string());
-
-    // Encryption key for stored user_secrets. Generated on first launch and
-    // persisted in data_dir; losing the file makes existing stored secrets
-    // unreadable, which is by design (same property as cloud deployments).
-    let secrets_key = match ensure_secrets_encryption_key(&data_dir) {
-      Ok(k) => k,
-      Err(err) => {
-        eprintln!("[secrets] FATAL: could not load/generate encryption key: {err}");
-        // Continue with empty key — secrets routes will return 500, but app loads.
-        String::new()
-      }
-    };
-
     // Host-only token file for trusted dev-auth clients (the CLI / scripts).
     write_surface_token_file(&data_dir);
 
-    let mut workerd_env = vec![
-      ("D1_HTTP_URL", "http://d1-shim".to_string()),
-      ("SANDBOX_URL", sandbox_url),
-      ("SANDBOX_INTERNAL_TOKEN", sandbox_internal_token),
-      ("INTERNAL_API_TOKEN", internal_api_token.clone()),
-      ("DEV_AUTH_ENABLED", dev_auth_enabled),
-      // Gates dev-auth to the host frontend (which sends X-Orcabot-Surface with
-      // this value); the sandbox VM never gets it, so it can't spoof user auth.
-      ("SURFACE_TOKEN", surface_token().to_string()),
-      ("SECRETS_ENCRYPTION_KEY", secrets_key),
-      ("OAUTH_REDIRECT_BASE", std::env::var("OAUTH_REDIRECT_BASE")
-        .unwrap_or_else(|_| format!("http://localhost:{}", controlplane_port))),
-      ("EMAIL_FROM", std::env::var("EMAIL_FROM")
-        .unwrap_or_else(|_| "OrcaBot Desktop <noreply@localhost>".to_string())),
-    ];
-
-    if let Ok(value) = std::env::var("ALLOWED_ORIGINS") {
-      workerd_env.push(("ALLOWED_ORIGINS", value));
-    } else {
-      // Default allowed origins includes the local frontend
-      workerd_env.push(("ALLOWED_ORIGINS", format!("http://localhost:{}", frontend_port)));
-    }
-    if let Ok(value) = std::env::var("FRONTEND_URL") {
-      workerd_env.push(("FRONTEND_URL", value));
-    } else {
-      // Default frontend URL is the local frontend
-      workerd_env.push(("FRONTEND_URL", format!("http://localhost:{}", frontend_port)));
-    }
-    if let Some(value) = d1_shim_debug {
-      workerd_env.push(("D1_SHIM_DEBUG", value));
-    }
-
-    // Optional pass-through for OAuth client IDs/secrets, Resend, etc. Users
-    // who want these features set the env vars before launching the app;
-    // unset = feature degrades gracefully. Adding a new optional var here
-    // should be paired with a binding in workerd.desktop.capnp.
-    // Drift check: `node desktop/scripts/check-drift.mjs`.
-    for key in &[
-      "GOOGLE_CLIENT_ID",
-      "GOOGLE_CLIENT_SECRET",
-      "GOOGLE_API_KEY",
-      "GITHUB_CLIENT_ID",
-      "GITHUB_CLIENT_SECRET",
-      "MICROSOFT_CLIENT_ID",
-      "MICROSOFT_CLIENT_SECRET",
-      "ONEDRIVE_CLIENT_ID",
-      "ONEDRIVE_CLIENT_SECRET",
-      "BOX_CLIENT_ID",
-      "BOX_CLIENT_SECRET",
-      "TWITTER_CLIENT_ID",
-      "TWITTER_CLIENT_SECRET",
-      "DISCORD_CLIENT_ID",
-      "DISCORD_CLIENT_SECRET",
-      "SLACK_CLIENT_ID",
-      "SLACK_CLIENT_SECRET",
-      "RESEND_API_KEY",
-      "EGRESS_PROXY_ENABLED",
-    ] {
-      passthrough_env(&mut workerd_env, *key);
-    }
-
-    self.spawn_binary(
+    let phase_start = std::time::Instant::now();
+    let _ = self.spawn_binary(
       &workerd_bin,
-      "workerd",
+      ServiceRole::Workerd,
       &[
-        "serve",
-        "--experimental",
-        "--import-path",
-        workerd_import.to_str().unwrap_or_default(),
-        "--import-path",
-        workerd_import_root.to_str().unwrap_or_default(),
-        "--socket-addr",
-        &format!("http=127.0.0.1:{}", controlplane_port),
+        OsStr::new("serve"),
+        OsStr::new("--experimental"),
+        OsStr::new("--import-path"),
+        workerd_import.as_os_str(),
+        OsStr::new("--import-path"),
+        workerd_import_root.as_os_str(),
+        OsStr::new("--socket-addr"),
+        OsStr::new(&format!("http={}:{}", bind_address, controlplane_port)),
         // The d1-shim external service is hardcoded to 127.0.0.1:9001 in the
         // capnp; override it at launch so a dynamically-chosen shim port works.
-        "--external-addr",
-        &format!("d1-shim={}", d1_addr),
-        "--directory-path",
-        &format!("do-storage={}", do_storage_dir.display()),
-        workerd_config.to_str().unwrap_or_default(),
+        OsStr::new("--external-addr"),
+        OsStr::new(&format!("d1-shim={}", services_config.d1_addr)),
+        OsStr::new("--directory-path"),
+        &named_path("do-storage", &do_storage_dir),
+        workerd_config.as_os_str(),
       ],
-      &workerd_env,
+      &spawn_env.controlplane,
     );
 
-    wait_for_health(&controlplane_port);
+    let healthy = wait_for_health(&controlplane_port);
+    self.record_phase(app.handle(), "workerd_health", phase_start, healthy);
 
     // Apply the D1 schema on every launch (idempotent CREATE TABLE IF NOT EXISTS).
     // Without this, schema changes shipped in an app update never reach an existing
     // user's DB — the worker only runs init on a brand-new DB's first /health.
-    apply_schema(&controlplane_port, &internal_api_token);
+    apply_schema(&controlplane_port, &services_config.internal_api_token);
 
     // Write PID file so next launch can clean up orphans if we crash
     if let Ok(children) = self.children.lock() {
@@ -781,8 +1477,140 @@ impl DesktopServices {
     // to avoid blocking the window from appearing.
   }
 
+  /// Stage the VM resources without booting (`prewarm_resources`), so a later
+  /// start skips the download. Idempotent: staging skips whatever is already
+  /// current. `force` first drops what's staged, re-copying local resources and
+  /// re-downloading (and re-verifying) the image; refused while the VM runs.
+  fn prewarm_vm(&self, app: &tauri::AppHandle, force: bool) -> Result<(), vm::VMError> {
+    let boot_dirs = self.sandbox_idle.lock().ok().and_then(|idle| idle.boot_dirs.clone());
+    let Some((data_dir, vm_dir, resource_root)) = boot_dirs else {
+      return Err(vm::VMError::StartFailed(
+        "sandbox VM is unavailable (no resources or data dir)".into(),
+      ));
+    };
+    let _starting = self.vm_start.lock().unwrap_or_else(|e| e.into_inner());
+    if force {
+      let running = self
+        .sandbox_vm
+        .lock()
+        .ok()
+        .is_some_and(|vm| vm.as_ref().is_some_and(|vm| vm.is_running()));
+      if running {
+        return Err(vm::VMError::StartFailed(
+          "can't force a restage while the sandbox VM is running".into(),
+        ));
+      }
+      vm::image::clear_staged(&vm_dir)?;
+    }
+
+    use tauri::Emitter;
+    let last_pct = std::cell::Cell::new(-1i64);
+    let progress = |done: u64, total: u64| {
+      let pct = if total > 0 { (done.saturating_mul(100) / total) as i64 } else { 0 };
+      if pct != last_pct.get() {
+        last_pct.set(pct);
+        let _ = app.emit(
+          "vm-prewarm-progress",
+          serde_json::json!({ "done": done, "total": total }),
+        );
+      }
+    };
+    let started = std::time::Instant::now();
+    let staging = staging_progress(app);
+    let result = stage_vm(&data_dir, &vm_dir, &resource_root, &progress, &staging);
+    self.record_phase(app, "vm_prewarm", started, result.is_ok());
+    result.map(|_| ())
+  }
+
+  /// Download a VM image from `url`, verify it against `sha256` and stage it for
+  /// the next VM start (see `vm::image::fetch_image` for retries and resume).
+  /// The manifest's own image is staged where `ensure_vm_image` adopts it;
+  /// any other becomes the custom image once `validate_custom_image` accepts
+  /// it. Emits `vm-image-fetch-progress`.
+  ///
+  /// The download goes to a temp file under `fetched/` without holding
+  /// `vm_start`, so a VM start isn't blocked behind it; the lock is taken only
+  /// to rename the image into place (and record it as the custom image).
+  fn fetch_and_stage_image(
+    &self,
+    app: &tauri::AppHandle,
+    url: &str,
+    sha256: &str,
+  ) -> Result<PathBuf, vm::VMError> {
+    let boot_dirs = self.sandbox_idle.lock().ok().and_then(|idle| idle.boot_dirs.clone());
+    let Some((data_dir, vm_dir, _)) = boot_dirs else {
+      return Err(vm::VMError::StartFailed(
+        "sandbox VM is unavailable (no resources or data dir)".into(),
+      ));
+    };
+    let _fetching = self.image_fetch.lock().unwrap_or_else(|e| e.into_inner());
+    self.image_fetch_cancel.store(false, std::sync::atomic::Ordering::SeqCst);
+
+    let sha256 = sha256.trim().to_lowercase();
+    let manifest = vm::image::vm_image_manifest();
+    let is_manifest_image = sha256 == manifest.sha256.to_lowercase();
+    // Outside vm_dir's top level, which staging prunes.
+    let fetched = vm_dir.join("fetched");
+    let name = format!("sandbox-{}.img", sha256.get(..16).unwrap_or(&sha256));
+    let download = fetched.join(format!("{name}.download"));
+    let dest = if is_manifest_image {
+      vm_dir.join(format!("sandbox-{}.img", manifest.version))
+    } else {
+      fetched.join(name)
+    };
+
+    use tauri::Emitter;
+    let last_pct = std::cell::Cell::new(-1i64);
+    let progress = |done: u64, total: u64| {
+      let pct = if total > 0 { (done.saturating_mul(100) / total) as i64 } else { 0 };
+      if pct != last_pct.get() {
+        last_pct.set(pct);
+        let _ = app.emit(
+          "vm-image-fetch-progress",
+          serde_json::json!({ "done": done, "total": total }),
+        );
+      }
+    };
+    let started = std::time::Instant::now();
+    let result = vm::image::fetch_image(
+      url,
+      &sha256,
+      &download,
+      &progress,
+      &self.image_fetch_cancel,
+    )
+    .and_then(|()| {
+      if !is_manifest_image {
+        let (_, warnings) = vm::image::validate_custom_image(&download)?;
+        for warning in warnings {
+          eprintln!("[vm-image] {}: {}", url, warning);
+        }
+      }
+      Ok(())
+    });
+    self.record_phase(app, "vm_image_fetch", started, result.is_ok());
+    if let Err(e) = result {
+      // A rejected image is verified and complete; refetching won't change it.
+      if !matches!(e, vm::VMError::Download(_)) {
+        let _ = std::fs::remove_file(&download);
+      }
+      return Err(e);
+    }
+
+    // Staging reads and writes the same files, and a start would race the
+    // rename.
+    let _starting = self.vm_start.lock().unwrap_or_else(|e| e.into_inner());
+    std::fs::rename(&download, &dest)?;
+    if !is_manifest_image {
+      vm::image::set_custom_image(&data_dir, &dest)?;
+    }
+    eprintln!("[vm-image] fetched {} -> {} (next VM start)", url, dest.display());
+    Ok(dest)
+  }
+
   fn start_sandbox_vm(
     &self,
+    app: &tauri::AppHandle,
     data_dir: &Path,
     vm_dir: &Path,
     resource_root: &Path,
@@ -794,22 +1622,28 @@ impl DesktopServices {
       return Ok(());
     }
 
-    // One-time migration: the VM image + staged runtime binaries used to live under
-    // the app-data dir. They're large (~1GB) and fully regenerable, so they now live
-    // in the cache dir instead (Caches is the OS-purgeable bucket, and this keeps the
-    // image out of the precious Application Support tree). The old dir is reclaimed
-    // only after staging confirms a valid image below — never on mere dir existence.
-    let old_vm_dir = data_dir.join("vm");
-    migrate_vm_dir(&old_vm_dir, vm_dir);
-
-    // Check if VM resources exist
-    let vm_resource_paths = vm::image::VMResourcePaths::from_resource_root(resource_root);
+    // One start at a time. A VM that's already up is left alone; one whose
+    // process has died is stopped (reaping it and its helpers) before booting
+    // a replacement, so nothing is orphaned.
+    let starting = self.vm_start.lock().unwrap_or_else(|e| e.into_inner());
+    if let Ok(mut vm_lock) = self.sandbox_vm.lock() {
+      if let Some(existing) = vm_lock.as_mut() {
+        if existing.is_running() {
+          eprintln!("[vm] sandbox VM already running — not starting another");
+          return Ok(());
+        }
+        eprintln!("[vm] previous sandbox VM is no longer running — stopping it before restart");
+        let _ = existing.stop();
+        *vm_lock = None;
+      }
+    }
 
     eprintln!("Starting sandbox VM ({})...", vm::vm_backend_name());
 
     // Stage VM resources. The disk image isn't bundled (it would bloat every
     // auto-update); ensure_vm_image downloads + verifies it on first use, or
     // adopts an image an earlier install already staged. Log download progress.
+    let phase_start = std::time::Instant::now();
     let last_pct = std::cell::Cell::new(-1i64);
     let progress = |done: u64, total: u64| {
       if total > 0 {
@@ -823,43 +1657,36 @@ impl DesktopServices {
         }
       }
     };
-    let staged_paths = match vm::image::stage_vm_resources(&vm_resource_paths, vm_dir, &progress) {
-      Ok(paths) => {
-        // Staging confirmed a valid image in the cache dir, so it's now safe to
-        // reclaim any leftover pre-migration VM dir. Gating on staging success —
-        // not directory existence — means a failed migration never deletes the
-        // last working image.
-        reclaim_old_vm_dir(&old_vm_dir, vm_dir);
-        paths
-      }
-      Err(cache_err) if old_vm_dir.as_path() != vm_dir && old_vm_dir.exists() => {
-        // The cache dir is full/unwritable, but the preserved pre-migration image
-        // is still intact — boot from it so the sandbox still works instead of
-        // failing every launch. Leave `old_vm_dir` in place; a later launch with a
-        // writable cache migrates it over and reclaims it.
-        eprintln!(
-          "[vm] cache staging failed ({cache_err}); falling back to preserved VM dir {}",
-          old_vm_dir.display()
-        );
-        vm::image::stage_vm_resources(&vm_resource_paths, &old_vm_dir, &progress)?
+    let staging = staging_progress(app);
+    let staged_paths = match stage_vm(data_dir, vm_dir, resource_root, &progress, &staging) {
+      Ok(paths) => paths,
+      Err(e) => {
+        self.record_phase(app, "vm_staging", phase_start, false);
+        return Err(e);
       }
-      Err(e) => return Err(e),
     };
+    self.record_phase(app, "vm_staging", phase_start, true);
 
-    // Create workspace directory
-    let workspace_dir = data_dir.join("workspace");
-    std::fs::create_dir_all(&workspace_dir)?;
+    // Create workspace directory. One on a drive that isn't connected fails the
+    // start instead of being recreated (empty) on the boot disk.
+    let workspace_dir = workspace::workspace_dir(data_dir);
+    workspace::prepare(&workspace_dir).map_err(|e| vm::VMError::MountFailed(e.to_string()))?;
 
     // Build VM configuration. This is the HOST-side sandbox port (the host→guest
     // forward listens here); it may be dynamic. The guest sandbox always binds
-    // 8080 (baked default), which is the guest side of the forward.
-    let sandbox_host_port: u16 = std::env::var("SANDBOX_PORT")
+    // 8080 (baked default), which is the guest side of the forward. Behind the
+    // sandbox relay it is the relay's private loopback target instead.
+    let relayed_port: Option<u16> = std::env::var("ORCABOT_SANDBOX_VM_PORT")
       .ok()
-      .and_then(|s| s.parse().ok())
-      .unwrap_or(8080);
+      .and_then(|s| s.parse().ok());
+    let sandbox_host_port: u16 = relayed_port.unwrap_or_else(|| {
+      std::env::var("SANDBOX_PORT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(8080)
+    });
 
-    let sandbox_internal_token =
-      std::env::var("SANDBOX_INTERNAL_TOKEN").unwrap_or_else(|_| "dev-sandbox-token".to_string());
+    let sandbox_internal_token = sandbox_internal_token();
 
     let allowed_origins =
       std::env::var("ALLOWED_ORIGINS").unwrap_or_else(|_| "http://localhost:8788".to_string());
@@ -875,8 +1702,7 @@ impl DesktopServices {
         // CONTROLPLANE_URL explicitly when the controlplane lives elsewhere.
         vm::host_loopback_url(&port)
       });
-    let internal_api_token =
-      std::env::var("INTERNAL_API_TOKEN").unwrap_or_else(|_| "dev-internal-token".to_string());
+    let internal_api_token = internal_api_token();
 
     // Host-side control-plane port for the guest→host reverse bridge. Matches the
     // port the control-plane workerd actually bound to (possibly dynamic). The
@@ -920,26 +1746,159 @@ impl DesktopServices {
       config = config.with_vz_helper(vz_helper);
     }
 
-    // Default kernel command line; VZ virtio console shows up as hvc0 on macOS.
+    // Default kernel command line. No `console=`: the backend adds the one for the
+    // device it actually boots with (VZ virtio console → hvc0, QEMU serial →
+    // ttyS0), which on macOS isn't known until VZ-vs-QEMU fallback is decided.
     // net.ifnames=0 biosdevname=0: force legacy interface naming so the virtio NIC
     // is `eth0` instead of `enp0s1`. This is paired with the DHCP bring-up in the
     // VM's minimal init (vm/scripts/build-images.sh MININIT): the macOS direct-boot
     // path runs that init, NOT OpenRC, so it leases an address on eth0 itself.
     // Without both halves the guest has no IP/DNS/route → no internet (npm hangs).
     let cmdline = if cfg!(target_os = "macos") {
-      "console=hvc0 earlycon=virtio_console keep_bootcon root=/dev/vda rw net.ifnames=0 biosdevname=0 loglevel=7 ignore_loglevel rdinit=/init"
+      "keep_bootcon root=/dev/vda rw net.ifnames=0 biosdevname=0 loglevel=7 ignore_loglevel rdinit=/init"
     } else {
-      "console=ttyS0 root=/dev/vda rw net.ifnames=0 biosdevname=0 quiet"
+      "root=/dev/vda rw net.ifnames=0 biosdevname=0 quiet"
     };
     config = config.with_cmdline(cmdline);
 
+    // Corporate networks: resolve through specific DNS servers instead of the
+    // backend's default forwarder (comma-separated IPs).
+    if let Ok(servers) = std::env::var("ORCABOT_VM_DNS") {
+      for server in servers.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if server.parse::<std::net::IpAddr>().is_ok() {
+          config = config.with_dns(server);
+        } else {
+          eprintln!("[vm] ignoring ORCABOT_VM_DNS entry {server:?} (not an IP address)");
+        }
+      }
+    }
+
+    let saved = settings::load(data_dir);
+
+    // QEMU CPU layout as SOCKETSxCORESxTHREADS (e.g. 1x2x2), from the env var or
+    // else `set_vm_cpu_topology`; also sets the vCPU count to the product.
+    let env_topology = std::env::var("ORCABOT_VM_CPU_TOPOLOGY").ok().and_then(|topology| {
+      let parts: Vec<u32> = topology
+        .split('x')
+        .filter_map(|p| p.trim().parse().ok())
+        .collect();
+      match parts[..] {
+        [sockets, cores, threads] if !parts.contains(&0) => Some((sockets, cores, threads)),
+        _ => {
+          eprintln!(
+            "[vm] ignoring ORCABOT_VM_CPU_TOPOLOGY={topology:?} (want SOCKETSxCORESxTHREADS)"
+          );
+          None
+        }
+      }
+    });
+    if let Some((sockets, cores, threads)) = env_topology.or(saved.cpu_topology) {
+      config = config.with_cpu_topology(sockets, cores, threads);
+      eprintln!(
+        "[vm] CPU topology {}x{}x{} ({} vCPUs)",
+        sockets, cores, threads, config.cpus
+      );
+    }
+
+    // Run the guest server as the host user (or explicit IDs) so workspace files
+    // line up: ORCABOT_GUEST_IDS=host | UID:GID.
+    if let Ok(ids) = std::env::var("ORCABOT_GUEST_IDS") {
+      #[cfg(unix)]
+      let host_ids = Some(unsafe { (libc::getuid(), libc::getgid()) });
+      #[cfg(not(unix))]
+      let host_ids: Option<(u32, u32)> = None;
+      let parsed = if ids.trim() == "host" {
+        host_ids
+      } else {
+        ids
+          .split_once(':')
+          .and_then(|(u, g)| Some((u.trim().parse().ok()?, g.trim().parse().ok()?)))
+      };
+      match parsed {
+        Some((uid, gid)) => {
+          config = config.with_guest_ids(uid, gid);
+          eprintln!("[vm] sandbox server runs as guest uid {} gid {}", uid, gid);
+        }
+        None => eprintln!("[vm] ignoring ORCABOT_GUEST_IDS={ids:?} (want \"host\" or UID:GID)"),
+      }
+    }
+
+    // Remote QEMU hosts: the guest mounts the workspace from a network export
+    // (nfs:HOST:/PATH or smb://HOST/SHARE) instead of the local share.
+    if let Ok(share) = std::env::var("ORCABOT_WORKSPACE_SHARE") {
+      match vm::WorkspaceShare::parse(&share) {
+        Ok(share) => config = config.with_workspace_share(share),
+        Err(e) => eprintln!("[vm] ignoring ORCABOT_WORKSPACE_SHARE: {}", e),
+      }
+    }
+
+    // Guest RNG is on by default; ORCABOT_VM_RNG=0 leaves the device out.
+    if std::env::var("ORCABOT_VM_RNG").is_ok_and(|v| v == "0") {
+      config = config.with_rng(false);
+      eprintln!("[vm] guest RNG device disabled (ORCABOT_VM_RNG=0)");
+    }
+
+    // Linux QEMU acceleration: auto, or kvm/tcg forced via `set_qemu_accel`.
+    config = config.with_qemu_accel(saved.qemu_accel.unwrap_or_default());
+
+    // A sandbox image whose server answers health somewhere other than /health.
+    if let Some(path) = std::env::var("ORCABOT_VM_HEALTH_PATH").ok().filter(|p| !p.trim().is_empty()) {
+      config = config.with_health_path(path.trim());
+    }
+    match std::env::var("ORCABOT_VM_HEALTH_STATUS").map(|s| s.trim().parse::<u16>()) {
+      Ok(Ok(status)) => config = config.with_health_status(status),
+      Ok(Err(e)) => eprintln!("[vm] ignoring ORCABOT_VM_HEALTH_STATUS: {e}"),
+      Err(_) => {}
+    }
+
+    // Extra readiness gate: a guest shell command that must exit 0 after health.
+    if let Some(command) = std::env::var("ORCABOT_VM_READY_CMD").ok().filter(|c| !c.trim().is_empty()) {
+      config = config.with_readiness_command(command);
+    }
+
+    // One-time guest setup (extra packages, CA certs, ...), run after health.
+    if let Some(script) = std::env::var_os("ORCABOT_PROVISION_SCRIPT").filter(|v| !v.is_empty()) {
+      config = config.with_provision_script(PathBuf::from(script));
+    }
+
     // Create and start VM — unless an update was accepted while we were staging.
     if is_updating() {
       eprintln!("[vm] app update accepted — not booting sandbox VM");
       return Ok(());
     }
     let mut vm = create_platform_vm();
-    vm.start(&config)?;
+    let phase_start = std::time::Instant::now();
+    let started = vm.start(&config);
+    self.record_phase(app, "vm_boot", phase_start, started.is_ok());
+    started?;
+    let backend = format!("[vm] sandbox VM started on {}", vm.backend_name());
+    eprintln!("{backend}");
+    self.append_startup_log(&backend);
+    // Follow the boot on the console until health: `vm-boot-phase` per phase,
+    // and again if one stalls.
+    let boot_watch = vm.console_log().map(|log| {
+      let app = app.clone();
+      vm::boot_phase::BootPhaseWatch::start(log, vm::boot_phase::markers_from_env(), phase_start, move |event| {
+        use tauri::Emitter;
+        if event.stalled {
+          eprintln!("[vm] boot stalled in phase {} ({}ms)", event.phase, event.elapsed_ms);
+        } else {
+          eprintln!("[vm] boot phase {} ({}ms)", event.phase, event.elapsed_ms);
+        }
+        let _ = app.emit("vm-boot-phase", event);
+      })
+    });
+    // Record the VM process (the QEMU, vz-helper or wsl.exe child) right away, not
+    // only once it's healthy: a crash during the health wait would otherwise
+    // leave it running with nothing for the next launch's stale cleanup to kill.
+    let vm_pid = vm.pid();
+    if let Ok(dd) = self.data_dir.lock() {
+      if let Some(ref data_dir) = *dd {
+        if let Ok(children) = self.children.lock() {
+          write_pid_file(data_dir, &children, vm_pid);
+        }
+      }
+    }
     // If the update landed during boot, stop the VM we just started rather than
     // waiting 120s for health only to tear it down on relaunch.
     if is_updating() {
@@ -950,39 +1909,99 @@ impl DesktopServices {
 
     // Wait for sandbox to be healthy
     eprintln!("Waiting for sandbox VM to become healthy...");
-    vm.wait_for_health(Duration::from_secs(120))?;
+    let phase_start = std::time::Instant::now();
+    let health_timeout = Duration::from_secs(120);
+    let healthy = vm.wait_for_health(health_timeout);
+    self.record_phase(app, "vm_health", phase_start, healthy.is_ok());
+    let last_boot_phase = boot_watch.and_then(|watch| watch.finish());
+    if healthy.is_err() {
+      let msg = match last_boot_phase {
+        Some(phase) => format!("[vm] boot got as far as the {phase} phase (guest console)"),
+        None => "[vm] no boot phase seen on the guest console".to_string(),
+      };
+      eprintln!("{msg}");
+      self.append_startup_log(&msg);
+    }
+    healthy?;
+
+    // App-specific gate on top of the HTTP probe, within the same time budget.
+    if let Some(ref command) = config.readiness_command {
+      let ready_start = std::time::Instant::now();
+      let ready = match vm.exec_channel() {
+        Some(channel) => vm::exec::wait_for_readiness(&channel, command, phase_start + health_timeout),
+        None => Err(vm::VMError::StartFailed(
+          "readiness command set, but this backend has no guest exec channel".to_string(),
+        )),
+      };
+      self.record_phase(app, "vm_readiness", ready_start, ready.is_ok());
+      if let Err(ref e) = ready {
+        self.append_startup_log(&format!("[vm] {}", e));
+      }
+      ready?;
+    }
 
     if let Some(url) = vm.sandbox_url() {
       eprintln!("Sandbox VM running at {}", url);
     }
 
-    let vm_pid = vm.pid();
+    let exec_channel = vm.exec_channel();
+    if let Ok(mut stored) = self.vm_config.lock() {
+      *stored = Some(config.clone());
+    }
 
-    // Store VM instance
+    // Store VM instance. `vm_start` makes a leftover here unexpected, but never
+    // drop one without stopping it.
     if let Ok(mut vm_lock) = self.sandbox_vm.lock() {
-      *vm_lock = Some(vm);
+      if let Some(mut old) = vm_lock.replace(vm) {
+        eprintln!("[vm] replacing a sandbox VM that was still stored — stopping it");
+        let _ = old.stop();
+      }
     }
-
-    // Re-write PID file with VM process included
-    if let Ok(dd) = self.data_dir.lock() {
-      if let Some(ref data_dir) = *dd {
-        if let Ok(children) = self.children.lock() {
-          write_pid_file(data_dir, &children, vm_pid);
+    drop(starting);
+
+    // Provision outside the sandbox_vm lock: a script may run for minutes. A
+    // failure leaves the VM up (the sandbox itself is healthy) and is reported
+    // via the `vm-provision` event.
+    if let Some(ref script) = config.provision_script {
+      use tauri::Emitter;
+      let emit = |event: vm::provision::ProvisionEvent| {
+        let _ = app.emit("vm-provision", event);
+      };
+      match exec_channel {
+        Some(channel) => {
+          if let Err(e) = vm::provision::run_once(&channel, script, &emit) {
+            eprintln!("[provision] {}", e);
+          }
         }
+        None => eprintln!(
+          "[provision] no guest exec channel on this backend; skipping {}",
+          script.display()
+        ),
       }
     }
 
     Ok(())
   }
 
-  fn spawn_binary(&self, binary_path: &Path, label: &str, args: &[&str], envs: &[(&str, String)]) {
+  /// Start `binary_path` as the `role` service and add it to `children`. A
+  /// failure is logged (and written to startup.log) as well as returned.
+  fn spawn_binary(
+    &self,
+    binary_path: &Path,
+    role: ServiceRole,
+    args: &[&OsStr],
+    envs: &[(&str, String)],
+  ) -> Result<(), String> {
+    let label = role.label();
     if !binary_path.exists() {
-      eprintln!(
-        "Desktop service binary not found for {}: {}",
-        label,
-        binary_path.display()
-      );
-      return;
+      let err = format!("binary not found: {}", binary_path.display());
+      eprintln!("Desktop service binary not found for {}: {}", label, binary_path.display());
+      return Err(err);
+    }
+    if let Err(err) = check_executable_arch(binary_path) {
+      eprintln!("Failed to start {}: {}", label, err);
+      self.append_startup_log(&format!("[{}] FAILED TO START: {}", label, err));
+      return Err(err.to_string());
     }
 
     let mut command = Command::new(binary_path);
@@ -1006,13 +2025,20 @@ impl DesktopServices {
         if let Some(err) = child.stderr.take() {
           tee_child_stream(err, label.to_string(), log_path, true);
         }
+        let spec = ServiceSpec {
+          binary: binary_path.to_path_buf(),
+          args: args.iter().map(|a| a.to_os_string()).collect(),
+          envs: envs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
+        };
         if let Ok(mut children) = self.children.lock() {
-          children.push(child);
+          children.push(ServiceChild { role, child, spec });
         }
+        Ok(())
       }
       Err(err) => {
         eprintln!("Failed to start {}: {}", label, err);
         self.append_startup_log(&format!("[{}] FAILED TO START: {}", label, err));
+        Err(err.to_string())
       }
     }
   }
@@ -1044,6 +2070,684 @@ impl DesktopServices {
     }
   }
 
+  /// Record a finished startup phase that began at `started`: logged, kept for
+  /// `get_startup_timings`, persisted for `orcabot status`, and emitted as
+  /// `startup-phase`.
+  fn record_phase(
+    &self,
+    app: &tauri::AppHandle,
+    phase: &str,
+    started: std::time::Instant,
+    ok: bool,
+  ) {
+    use tauri::Emitter;
+    let entry = StartupPhase {
+      phase: phase.to_string(),
+      started_ms: started.saturating_duration_since(self.launched).as_millis() as u64,
+      duration_ms: started.elapsed().as_millis() as u64,
+      ok,
+    };
+    let line = format!(
+      "[timing] {} {}ms{}",
+      entry.phase,
+      entry.duration_ms,
+      if ok { "" } else { " (failed)" }
+    );
+    eprintln!("{line}");
+    self.append_startup_log(&line);
+    let snapshot = match self.startup_timings.lock() {
+      Ok(mut timings) => {
+        timings.push(entry.clone());
+        timings.clone()
+      }
+      Err(_) => Vec::new(),
+    };
+    if let Some(data_dir) = self.data_dir.lock().ok().and_then(|dd| dd.clone()) {
+      if let Ok(json) = serde_json::to_vec_pretty(&snapshot) {
+        let _ = std::fs::write(startup_timings_path(&data_dir), json);
+      }
+    }
+    let _ = app.emit("startup-phase", entry);
+  }
+
+  /// Labels of the service processes (d1-shim, workerd, ...) still alive.
+  fn running_services(&self) -> Vec<&'static str> {
+    match self.children.lock() {
+      Ok(mut children) => children
+        .iter_mut()
+        .filter_map(|service| matches!(service.child.try_wait(), Ok(None)).then_some(service.role.label()))
+        .collect(),
+      Err(_) => Vec::new(),
+    }
+  }
+
+  /// Exec channel of the running sandbox VM, taken without holding the lock
+  /// afterwards (guest commands can be slow). `None` when no VM is up.
+  fn sandbox_exec_channel(&self) -> Option<vm::ExecChannel> {
+    let guard = self.sandbox_vm.lock().ok()?;
+    guard.as_ref()?.exec_channel()
+  }
+
+  /// [`Self::sandbox_exec_channel`] for a user's exec call (`sandbox_exec_streaming`,
+  /// `put_sandbox_file`, the process list, ...), which counts as sandbox use.
+  fn user_exec_channel(&self) -> Option<vm::ExecChannel> {
+    self.sandbox_activity.note();
+    self.sandbox_exec_channel()
+  }
+
+  /// Kernel / OS / sandbox-server versions of the running guest. Cached for the
+  /// life of the VM process (a restart re-queries); `None` when no VM is up.
+  fn guest_info(&self) -> Option<vm::GuestInfo> {
+    let (pid, channel, url) = {
+      let guard = self.sandbox_vm.lock().ok()?;
+      let vm = guard.as_ref()?;
+      (vm.pid(), vm.exec_channel(), vm.sandbox_url())
+    };
+    if let Ok(cache) = self.guest_info.lock() {
+      if let Some((_, info)) = cache.as_ref().filter(|(p, _)| *p == pid) {
+        return Some(info.clone());
+      }
+    }
+    // Query without holding any lock: exec round-trips can take seconds.
+    let info = vm::GuestInfo::query(channel.as_ref(), url.as_deref());
+    // Only cache a complete answer, so a transient exec failure is retried.
+    if info.kernel.is_some() && info.server_revision.is_some() {
+      if let Ok(mut cache) = self.guest_info.lock() {
+        *cache = Some((pid, info.clone()));
+      }
+    }
+    Some(info)
+  }
+
+  /// Record sandbox use (a connection through the sandbox relay, or the
+  /// `note_sandbox_activity` command). If the idle watcher stopped the VM, boot
+  /// it again in the background; returns true then.
+  fn note_sandbox_activity(self: &Arc<Self>, app: &tauri::AppHandle) -> bool {
+    self.sandbox_activity.note();
+    let boot_dirs = {
+      let Ok(mut idle) = self.sandbox_idle.lock() else {
+        return false;
+      };
+      // A suspended stack can't boot a VM; `resume_all` has to come first.
+      if !idle.stopped || self.suspended.load(std::sync::atomic::Ordering::SeqCst) {
+        return false;
+      }
+      idle.stopped = false;
+      idle.boot_dirs.clone()
+    };
+    let Some((data_dir, vm_dir, resource_root)) = boot_dirs else {
+      return false;
+    };
+    eprintln!("[vm] sandbox activity after idle stop — restarting sandbox VM");
+    let services = Arc::clone(self);
+    let app = app.clone();
+    std::thread::spawn(move || {
+      if let Err(err) = services.start_sandbox_vm(&app, &data_dir, &vm_dir, &resource_root) {
+        eprintln!("[vm] restart after idle stop failed: {}", err);
+        if let Ok(mut idle) = services.sandbox_idle.lock() {
+          idle.stopped = true; // let the next activity retry
+        }
+      }
+    });
+    true
+  }
+
+  /// Put the sandbox relay (`vm::relay`) on the public sandbox port in front of
+  /// the VM's forward, so `watch_sandbox_idle` sees the control plane's and the
+  /// CLI's traffic, and a connection wakes an idle-stopped VM. Only with an idle
+  /// window (`ORCABOT_SANDBOX_VM_PORT` set); call before the first VM boot. If
+  /// the port can't be taken, the VM binds it itself as it would without one.
+  fn start_sandbox_relay(self: &Arc<Self>, app: &tauri::AppHandle) {
+    let port = |key: &str| std::env::var(key).ok().and_then(|v| v.trim().parse::<u16>().ok());
+    let (Some(public), Some(vm_port)) = (port("SANDBOX_PORT"), port("ORCABOT_SANDBOX_VM_PORT"))
+    else {
+      return;
+    };
+    let listener = match std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, public)) {
+      Ok(listener) => listener,
+      Err(err) => {
+        eprintln!(
+          "[vm] sandbox relay could not listen on :{}: {} — idle tracking only sees exec calls",
+          public, err
+        );
+        std::env::remove_var("ORCABOT_SANDBOX_VM_PORT");
+        return;
+      }
+    };
+    eprintln!("[vm] sandbox relay :{} -> 127.0.0.1:{}", public, vm_port);
+    let services = Arc::clone(self);
+    let app = app.clone();
+    vm::relay::spawn(
+      listener,
+      (std::net::Ipv4Addr::LOCALHOST, vm_port).into(),
+      Arc::clone(&self.sandbox_activity),
+      move || {
+        services.note_sandbox_activity(&app);
+      },
+    );
+  }
+
+  /// Boot the sandbox VM in the background with the directories it first
+  /// started from, unless the idle watcher has it stopped (activity wakes it).
+  /// `relocate_workspace` uses this after stopping the VM to move the workspace.
+  /// Returns whether a boot was started.
+  fn boot_sandbox_vm(self: &Arc<Self>, app: &tauri::AppHandle) -> bool {
+    let boot_dirs = match self.sandbox_idle.lock() {
+      Ok(idle) if !idle.stopped => idle.boot_dirs.clone(),
+      _ => None,
+    };
+    let Some((data_dir, vm_dir, resource_root)) = boot_dirs else {
+      return false;
+    };
+    let services = Arc::clone(self);
+    let app = app.clone();
+    std::thread::spawn(move || {
+      if let Err(err) = services.start_sandbox_vm(&app, &data_dir, &vm_dir, &resource_root) {
+        eprintln!("[vm] sandbox VM boot failed: {}", err);
+      }
+    });
+    true
+  }
+
+  /// Compare the guest clock with the host's every `policy.interval`, emitting
+  /// `guest-clock-skew` (and, with auto-sync, resetting the guest clock) when it
+  /// is off by more than the threshold. Runs for the life of the app; a stopped
+  /// VM is simply skipped.
+  fn watch_guest_clock(&self, app: &tauri::AppHandle, mut policy: vm::clock::SkewPolicy) {
+    use tauri::Emitter;
+    let auto_sync = policy.auto_sync;
+    loop {
+      std::thread::sleep(policy.interval);
+      if is_updating() {
+        return;
+      }
+      if self.suspended.load(std::sync::atomic::Ordering::SeqCst) {
+        continue;
+      }
+      let Some(channel) = self.sandbox_exec_channel() else {
+        continue;
+      };
+      // `date -s` needs root; with guest_uid set debug-exec doesn't have it.
+      let root_exec = self
+        .vm_config
+        .lock()
+        .ok()
+        .and_then(|c| c.as_ref().map(|c| c.exec_runs_as_root()))
+        .unwrap_or(true);
+      if auto_sync && policy.auto_sync != root_exec {
+        policy.auto_sync = root_exec;
+        if !root_exec {
+          eprintln!(
+            "[vm] ORCABOT_CLOCK_AUTOSYNC is off while guest_uid is set (debug-exec is not \
+             root); skew is only reported"
+          );
+        }
+      }
+      match vm::clock::check(&channel, &policy) {
+        Ok(Some(skew)) => {
+          eprintln!(
+            "[vm] guest clock is {:+.1}s off the host (threshold {}s){}",
+            skew.skew_secs,
+            skew.threshold_secs,
+            if skew.synced { " — synced to host time" } else { "" }
+          );
+          let _ = app.emit("guest-clock-skew", skew);
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("[vm] clock skew check failed: {}", e),
+      }
+    }
+  }
+
+  /// Stop the sandbox VM once it has seen no activity for `window`, emitting
+  /// `sandbox-idle-stopped`. Runs for the life of the app.
+  fn watch_sandbox_idle(&self, app: &tauri::AppHandle, window: Duration) {
+    use tauri::Emitter;
+    let tick = (window / 4).clamp(Duration::from_secs(5), Duration::from_secs(60));
+    loop {
+      std::thread::sleep(tick);
+      if is_updating() {
+        return;
+      }
+      if self.suspended.load(std::sync::atomic::Ordering::SeqCst) {
+        continue;
+      }
+      let idle_for = match self.sandbox_idle.lock() {
+        Ok(idle) if !idle.stopped => self.sandbox_activity.idle_for(),
+        _ => continue,
+      };
+      if idle_for < window {
+        continue;
+      }
+      let stopped = match self.sandbox_vm.lock() {
+        Ok(mut vm_lock) => match vm_lock.take() {
+          Some(mut vm) => {
+            eprintln!("[vm] sandbox idle for {}s — stopping VM", idle_for.as_secs());
+            let _ = vm.stop();
+            true
+          }
+          None => false, // still booting (or never started)
+        },
+        Err(_) => false,
+      };
+      if stopped {
+        if let Ok(mut idle) = self.sandbox_idle.lock() {
+          idle.stopped = true;
+        }
+        let _ = app.emit("sandbox-idle-stopped", idle_for.as_secs());
+      }
+    }
+  }
+
+  /// Restart the sandbox VM when its process dies or `/health` stops answering,
+  /// backing off between restarts (see `vm::watchdog`). After
+  /// `policy.max_attempts` restarts without a stable period it emits
+  /// `sandbox-restart-giving-up` with the last error and stops watching. An
+  /// idle-stopped or still-booting VM is skipped.
+  fn watch_sandbox_health(&self, app: &tauri::AppHandle, policy: vm::watchdog::WatchdogPolicy) {
+    use tauri::Emitter;
+    let mut backoff = vm::watchdog::RestartBackoff::default();
+    let mut misses = 0;
+    let mut last_error = String::new();
+    let mut restart_failed = false;
+    loop {
+      std::thread::sleep(policy.interval);
+      if is_updating() {
+        return;
+      }
+      if self.sandbox_idle.lock().map_or(true, |idle| idle.stopped)
+        || self.suspended.load(std::sync::atomic::Ordering::SeqCst)
+      {
+        misses = 0;
+        continue;
+      }
+      // A start in progress holds `vm_start`; leave that VM to its own health wait.
+      let probe = match (self.vm_start.try_lock(), self.sandbox_vm.lock()) {
+        (Ok(_start), Ok(vm_lock)) => vm_lock.as_ref().map(|vm| (vm.is_running(), vm.sandbox_url())),
+        _ => continue,
+      };
+      let (running, url) = match probe {
+        Some(probe) => {
+          restart_failed = false;
+          probe
+        }
+        // Our last restart failed and left no VM: keep counting it as down.
+        None if restart_failed => (false, None),
+        None => continue,
+      };
+      let healthy = running
+        && url
+          .as_deref()
+          .map(|u| u.trim_start_matches("http://").trim_end_matches('/'))
+          .is_some_and(|addr| {
+            let config = self.vm_config.lock().ok().and_then(|c| c.clone());
+            vm::health::probe_sandbox_health(addr, config.as_ref())
+          });
+      if healthy {
+        misses = 0;
+        backoff.healthy(std::time::Instant::now(), &policy);
+        continue;
+      }
+      backoff.unhealthy();
+      misses += 1;
+      if running && misses < policy.failures_before_restart {
+        continue;
+      }
+      misses = 0;
+      if !restart_failed {
+        last_error = if running {
+          "sandbox health endpoint not responding".to_string()
+        } else {
+          "sandbox VM process exited".to_string()
+        };
+      }
+
+      let Some(delay) = backoff.next_restart(&policy) else {
+        eprintln!(
+          "[vm] giving up on the sandbox VM after {} restarts: {}",
+          backoff.attempts(),
+          last_error
+        );
+        let _ = app.emit(
+          "sandbox-restart-giving-up",
+          serde_json::json!({ "attempts": backoff.attempts(), "error": last_error }),
+        );
+        return;
+      };
+      eprintln!(
+        "[vm] {} — restarting in {}s (attempt {}/{})",
+        last_error,
+        delay.as_secs(),
+        backoff.attempts(),
+        policy.max_attempts
+      );
+      std::thread::sleep(delay);
+      if is_updating() {
+        return;
+      }
+      let boot_dirs = match self.sandbox_idle.lock() {
+        Ok(idle) if !idle.stopped => idle.boot_dirs.clone(),
+        _ => continue, // stopped on purpose while we waited
+      };
+      let Some((data_dir, vm_dir, resource_root)) = boot_dirs else {
+        return;
+      };
+      if let Ok(mut vm_lock) = self.sandbox_vm.lock() {
+        if let Some(mut vm) = vm_lock.take() {
+          let _ = vm.stop();
+        }
+      }
+      restart_failed = match self.start_sandbox_vm(app, &data_dir, &vm_dir, &resource_root) {
+        Ok(()) => false,
+        Err(e) => {
+          eprintln!("[vm] watchdog restart failed: {}", e);
+          last_error = e.to_string();
+          true
+        }
+      };
+    }
+  }
+
+  /// Emit `sandbox-vm-exited` as soon as the stored VM's process dies on its
+  /// own, so the UI stops treating the sandbox as up while the watchdog (which
+  /// probes far less often) gets round to restarting it. The payload carries
+  /// the exit code or signal where the backend reaps its process (QEMU, VZ).
+  /// A VM stopped on purpose is dropped from `sandbox_vm` first, so it's never
+  /// reported.
+  fn watch_vm_exit(&self, app: &tauri::AppHandle) {
+    use tauri::Emitter;
+    let mut reported: Option<u32> = None;
+    loop {
+      std::thread::sleep(VM_EXIT_POLL_INTERVAL);
+      if is_updating() {
+        return;
+      }
+      let exited = match self.sandbox_vm.lock() {
+        // Stopped in place (update, shutdown) is not an exit to report.
+        Ok(vm_lock) => vm_lock
+          .as_ref()
+          .filter(|vm| vm.state() != vm::VmState::Stopped && !vm.is_running())
+          .and_then(|vm| Some((vm.pid()?, vm.exit_status(), vm.backend_name()))),
+        Err(_) => continue,
+      };
+      let Some((pid, exit, backend)) = exited else {
+        continue;
+      };
+      if reported == Some(pid) {
+        continue;
+      }
+      reported = Some(pid);
+      let description = exit
+        .as_ref()
+        .map_or("exit status unknown", |e| e.description.as_str());
+      eprintln!("[vm] sandbox VM process {} exited unexpectedly: {}", pid, description);
+      let _ = app.emit(
+        "sandbox-vm-exited",
+        serde_json::json!({
+          "pid": pid,
+          "backend": backend,
+          "code": exit.as_ref().and_then(|e| e.code),
+          "signal": exit.as_ref().and_then(|e| e.signal),
+          "status": description,
+        }),
+      );
+    }
+  }
+
+  /// Respawn host services (d1-shim, workerd) that exit on their own, backing
+  /// off per service (`vm::watchdog::RestartBackoff`) and giving up on one after
+  /// five restarts without a stable period, with a `service-restart-giving-up`
+  /// event. Each restart runs on its own thread but holds a `service_restarts`
+  /// slot (at most `max_concurrent_restarts` at once) through the spawn and a
+  /// short stagger, so services that crash together (say, after the disk
+  /// briefly filled) come back in turn instead of thrashing.
+  fn watch_services(self: &Arc<Self>, app: &tauri::AppHandle) {
+    use std::sync::atomic::Ordering;
+    use tauri::Emitter;
+    let policy = vm::watchdog::WatchdogPolicy {
+      interval: SERVICE_POLL_INTERVAL,
+      failures_before_restart: 1,
+      base_delay: Duration::from_secs(1),
+      max_delay: Duration::from_secs(60),
+      max_attempts: 5,
+      stable_after: Duration::from_secs(5 * 60),
+    };
+    let mut backoff: std::collections::HashMap<ServiceRole, vm::watchdog::RestartBackoff> =
+      Default::default();
+    loop {
+      std::thread::sleep(policy.interval);
+      if is_updating() {
+        return;
+      }
+      if self.suspended.load(Ordering::SeqCst) {
+        continue;
+      }
+      let (exited, running) = {
+        let Ok(mut children) = self.children.lock() else {
+          return;
+        };
+        if self.shutting_down.load(Ordering::SeqCst) {
+          return;
+        }
+        let mut exited = Vec::new();
+        children.retain_mut(|service| match service.child.try_wait() {
+          Ok(Some(status)) => {
+            exited.push((service.role, service.spec.clone(), format!("exited ({status})")));
+            false
+          }
+          _ => true,
+        });
+        if let Ok(mut failed) = self.failed_restarts.lock() {
+          exited.extend(failed.drain(..).map(|(role, spec, err)| {
+            (role, spec, format!("failed to restart ({err})"))
+          }));
+        }
+        let running: Vec<ServiceRole> = children.iter().map(|service| service.role).collect();
+        (exited, running)
+      };
+      let now = std::time::Instant::now();
+      for role in running {
+        backoff.entry(role).or_default().healthy(now, &policy);
+      }
+      for (role, spec, why) in exited {
+        let label = role.label();
+        let service_backoff = backoff.entry(role).or_default();
+        service_backoff.unhealthy();
+        let Some(delay) = service_backoff.next_restart(&policy) else {
+          let msg = format!(
+            "[{}] {}; giving up after {} restarts",
+            label,
+            why,
+            service_backoff.attempts()
+          );
+          eprintln!("{msg}");
+          self.append_startup_log(&msg);
+          let _ = app.emit(
+            "service-restart-giving-up",
+            serde_json::json!({ "service": label, "attempts": service_backoff.attempts() }),
+          );
+          continue;
+        };
+        let msg = format!(
+          "[{}] {}; restarting in {}s (attempt {}/{})",
+          label,
+          why,
+          delay.as_secs(),
+          service_backoff.attempts(),
+          policy.max_attempts
+        );
+        eprintln!("{msg}");
+        self.append_startup_log(&msg);
+        let services = Arc::clone(self);
+        std::thread::spawn(move || {
+          let _ = services.restart_service(role, spec, delay);
+        });
+      }
+    }
+  }
+
+  /// Respawn one exited service after `delay`, holding a `service_restarts`
+  /// slot through the spawn and the stagger after it. A failed spawn is queued
+  /// in `failed_restarts`, so `watch_services` retries it with backoff (and
+  /// eventually gives up) as it would a crash, and returned.
+  fn restart_service(
+    &self,
+    role: ServiceRole,
+    spec: ServiceSpec,
+    delay: Duration,
+  ) -> Result<(), String> {
+    use std::sync::atomic::Ordering;
+    std::thread::sleep(delay);
+    let limit = self
+      .data_dir
+      .lock()
+      .ok()
+      .and_then(|dd| dd.clone())
+      .and_then(|dd| settings::load(&dd).max_concurrent_restarts)
+      .unwrap_or(1);
+    self.service_restarts.acquire(limit);
+    let mut result = Ok(());
+    if !is_updating() && !self.shutting_down.load(Ordering::SeqCst) {
+      let args: Vec<&OsStr> = spec.args.iter().map(OsString::as_os_str).collect();
+      let envs: Vec<(&str, String)> =
+        spec.envs.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+      result = self.spawn_binary(&spec.binary, role, &args, &envs);
+      if let Err(err) = &result {
+        if let Ok(mut failed) = self.failed_restarts.lock() {
+          failed.push((role, spec.clone(), err.clone()));
+        }
+      } else if self.shutting_down.load(Ordering::SeqCst) {
+        // Shutdown began while we were spawning and may have missed this child.
+        if let Ok(mut children) = self.children.lock() {
+          for service in children.iter_mut().filter(|service| service.role == role) {
+            let _ = service.child.kill();
+            let _ = service.child.wait();
+          }
+        }
+      } else {
+        self.refresh_pid_file();
+        std::thread::sleep(SERVICE_RESTART_STAGGER);
+      }
+    }
+    self.service_restarts.release();
+    result
+  }
+
+  /// Restart the running `role` service with `key=value` in its environment
+  /// (`set_dev_flags`), through the same path as a crash restart. The old child
+  /// is taken out of `children` before it's killed, so `watch_services` doesn't
+  /// count it as a crash; a respawn that fails is retried there with backoff.
+  fn restart_with_env(&self, role: ServiceRole, key: &str, value: &str) -> Result<(), String> {
+    use std::sync::atomic::Ordering;
+    if self.suspended.load(Ordering::SeqCst) {
+      return Err("services are suspended; resume them first".to_string());
+    }
+    let mut service = {
+      let mut children = self.children.lock().map_err(|_| "service list unavailable".to_string())?;
+      let index = children
+        .iter()
+        .position(|service| service.role == role)
+        .ok_or_else(|| format!("{} is not running", role.label()))?;
+      children.remove(index)
+    };
+    let _ = service.child.kill();
+    let _ = service.child.wait();
+    let mut spec = service.spec;
+    match spec.envs.iter_mut().find(|(k, _)| k == key) {
+      Some(entry) => entry.1 = value.to_string(),
+      None => spec.envs.push((key.to_string(), value.to_string())),
+    }
+    let msg = format!("[{}] restarting with {}={}", role.label(), key, value);
+    eprintln!("{msg}");
+    self.append_startup_log(&msg);
+    self
+      .restart_service(role, spec, Duration::ZERO)
+      .map_err(|err| format!("{} failed to restart: {} (retrying)", role.label(), err))
+  }
+
+  /// Value of `key` in the running `role` service's environment.
+  fn service_env(&self, role: ServiceRole, key: &str) -> Option<String> {
+    let children = self.children.lock().ok()?;
+    let service = children.iter().find(|service| service.role == role)?;
+    service.spec.envs.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+  }
+
+  /// Cycle the running sandbox VM with the config it booted with
+  /// (`restart_sandbox_vm`), via `VirtualMachine::restart`, then wait for health
+  /// and the readiness gate as a fresh start would. The VM is held outside
+  /// `sandbox_vm` meanwhile, so status and the watchdog see it as down; one that
+  /// fails to come back is stopped and dropped.
+  fn restart_sandbox_vm(&self, app: &tauri::AppHandle) -> Result<(), vm::VMError> {
+    use std::sync::atomic::Ordering;
+    if self.suspended.load(Ordering::SeqCst) {
+      return Err(vm::VMError::StartFailed(
+        "services are suspended; resume them first".into(),
+      ));
+    }
+    let Ok(_starting) = self.vm_start.try_lock() else {
+      return Err(vm::VMError::StartFailed("sandbox VM is already starting".into()));
+    };
+    let config = self.vm_config.lock().ok().and_then(|config| config.clone());
+    let taken = self.sandbox_vm.lock().ok().and_then(|mut vm| vm.take());
+    let (Some(config), Some(mut vm)) = (config, taken) else {
+      return Err(vm::VMError::StartFailed("sandbox VM is not running".into()));
+    };
+
+    let msg = format!("[vm] restarting sandbox VM ({})", vm.backend_name());
+    eprintln!("{msg}");
+    self.append_startup_log(&msg);
+    let phase_start = std::time::Instant::now();
+    let health_timeout = Duration::from_secs(120);
+    let mut result = vm.restart(&config);
+    // The process (and its PID) changed even if health fails below.
+    self.refresh_pid_file();
+    if result.is_ok() {
+      result = vm.wait_for_health(health_timeout);
+    }
+    if result.is_ok() {
+      if let Some(ref command) = config.readiness_command {
+        result = match vm.exec_channel() {
+          Some(channel) => vm::exec::wait_for_readiness(&channel, command, phase_start + health_timeout),
+          None => Err(vm::VMError::StartFailed(
+            "readiness command set, but this backend has no guest exec channel".to_string(),
+          )),
+        };
+      }
+    }
+    self.record_phase(app, "vm_restart", phase_start, result.is_ok());
+    if let Err(ref e) = result {
+      self.append_startup_log(&format!("[vm] restart failed: {}", e));
+      let _ = vm.stop();
+      self.refresh_pid_file();
+      return result;
+    }
+
+    if let Ok(mut vm_lock) = self.sandbox_vm.lock() {
+      if let Some(mut old) = vm_lock.replace(vm) {
+        let _ = old.stop();
+      }
+    }
+    self.refresh_pid_file();
+    self.sandbox_activity.note();
+    Ok(())
+  }
+
+  /// Rewrite the PID file from the current children and sandbox VM.
+  fn refresh_pid_file(&self) {
+    let vm_pid = self
+      .sandbox_vm
+      .lock()
+      .ok()
+      .and_then(|vm| vm.as_ref().and_then(|vm| vm.pid()));
+    if let Ok(dd) = self.data_dir.lock() {
+      if let Some(ref data_dir) = *dd {
+        if let Ok(children) = self.children.lock() {
+          write_pid_file(data_dir, &children, vm_pid);
+        }
+      }
+    }
+  }
+
   /// Stop ONLY the sandbox VM (leave workerd/frontend running). Used when the user
   /// accepts an update: the heavy VM shouldn't keep running/booting during the
   /// download, but the frontend must stay up so the update-progress bar keeps working.
@@ -1057,53 +2761,198 @@ impl DesktopServices {
     }
   }
 
-  fn shutdown(&self) {
-    // Stop sandbox VM first
+  /// Freeze the whole stack in place (`suspend_all`): pause the sandbox VM and
+  /// SIGSTOP the host services, keeping all their state in memory. Idempotent.
+  #[cfg(unix)]
+  fn suspend_all(&self) -> Result<(), String> {
+    use std::sync::atomic::Ordering;
+    // A VM that finished booting after this would come up unpaused.
+    let _start = self
+      .vm_start
+      .try_lock()
+      .map_err(|_| "The sandbox VM is starting; try again once it is up".to_string())?;
+    if self.shutting_down.load(Ordering::SeqCst) {
+      return Err("Orcabot is shutting down".to_string());
+    }
+    if self.suspended.swap(true, Ordering::SeqCst) {
+      return Ok(());
+    }
     if let Ok(mut vm_lock) = self.sandbox_vm.lock() {
-      if let Some(ref mut vm) = *vm_lock {
-        eprintln!("Stopping sandbox VM...");
-        let _ = vm.stop();
+      if let Some(vm) = vm_lock.as_mut().filter(|vm| vm.is_running()) {
+        if let Err(e) = vm.pause() {
+          self.suspended.store(false, Ordering::SeqCst);
+          return Err(format!("Could not pause the sandbox VM: {}", e));
+        }
       }
     }
+    self.signal_services(libc::SIGSTOP);
+    eprintln!("[main] suspended: sandbox VM paused, host services stopped");
+    Ok(())
+  }
+
+  /// Windows has no SIGSTOP, and a WSL distro can't be paused without
+  /// terminating it, so there is nothing to freeze the stack with.
+  #[cfg(not(unix))]
+  fn suspend_all(&self) -> Result<(), String> {
+    Err("Suspending the services is not supported on Windows".to_string())
+  }
+
+  /// Undo `suspend_all`: resume the VM, SIGCONT the services, and restart the
+  /// idle timer so the time spent suspended doesn't count as idle. A VM that
+  /// won't resume leaves everything suspended (services still stopped, the
+  /// watchdogs still off), so the call can simply be retried.
+  #[cfg(unix)]
+  fn resume_all(&self) -> Result<(), String> {
+    use std::sync::atomic::Ordering;
+    if !self.suspended.load(Ordering::SeqCst) {
+      return Ok(());
+    }
+    if let Ok(mut vm_lock) = self.sandbox_vm.lock() {
+      if let Some(vm) = vm_lock.as_mut().filter(|vm| vm.is_running()) {
+        vm.resume().map_err(|e| format!("Could not resume the sandbox VM: {}", e))?;
+      }
+    }
+    self.signal_services(libc::SIGCONT);
+    self.sandbox_activity.note();
+    self.suspended.store(false, Ordering::SeqCst);
+    eprintln!("[main] resumed: sandbox VM resumed, host services continued");
+    Ok(())
+  }
+
+  #[cfg(not(unix))]
+  fn resume_all(&self) -> Result<(), String> {
+    Ok(())
+  }
+
+  #[cfg(unix)]
+  fn signal_services(&self, sig: libc::c_int) {
+    if let Ok(children) = self.children.lock() {
+      for service in children.iter() {
+        unsafe { libc::kill(service.child.id() as i32, sig) };
+      }
+    }
+  }
+
+  fn shutdown(&self) {
+    teardown::begin("DesktopServices::shutdown");
+    let started = std::time::Instant::now();
+    self.shutting_down.store(true, std::sync::atomic::Ordering::SeqCst);
+    // Stop sandbox VM first
+    match self.sandbox_vm.lock() {
+      Ok(mut vm_lock) => match *vm_lock {
+        Some(ref mut vm) => {
+          eprintln!("Stopping sandbox VM...");
+          teardown_log!("stopping sandbox VM ({}, pid {:?})", vm.backend_name(), vm.pid());
+          match vm.stop() {
+            Ok(()) => teardown_log!("sandbox VM stopped"),
+            Err(e) => teardown_log!("sandbox VM stop failed: {}", e),
+          }
+        }
+        None => teardown_log!("no sandbox VM to stop"),
+      },
+      Err(_) => teardown_log!("sandbox VM lock poisoned; VM not stopped"),
+    }
 
-    // Stop child processes: SIGTERM first for graceful shutdown, then SIGKILL
+    // Stop child processes: signal all at once for a graceful shutdown, then
+    // SIGKILL each one that outlives its role's grace period. Returns as soon as
+    // everything has exited, so a clean quit doesn't pay the full grace.
     if let Ok(mut children) = self.children.lock() {
-      // Send SIGTERM to all children
-      for child in children.iter() {
-        #[cfg(unix)]
-        unsafe { libc::kill(child.id() as i32, libc::SIGTERM) };
-      }
-      // Wait briefly for graceful exit
-      std::thread::sleep(Duration::from_secs(2));
-      // Force kill any survivors
-      for child in children.iter_mut() {
-        let _ = child.kill();
-        let _ = child.wait();
+      #[cfg(unix)]
+      {
+        let sig = shutdown_signal();
+        let suspended = self.suspended.load(std::sync::atomic::Ordering::SeqCst);
+        for service in children.iter() {
+          let pid = service.child.id();
+          if unsafe { libc::kill(pid as i32, sig) } == 0 {
+            teardown_log!("signalled {} (pid {}) with {}", service.role.label(), pid, sig);
+          } else {
+            teardown_log!(
+              "signalling {} (pid {}): {}",
+              service.role.label(),
+              pid,
+              std::io::Error::last_os_error()
+            );
+          }
+          if suspended {
+            // A stopped process only acts on the signal once it is continued.
+            unsafe { libc::kill(service.child.id() as i32, libc::SIGCONT) };
+          }
+        }
+      }
+      let children_started = std::time::Instant::now();
+      let mut pending: Vec<(&mut ServiceChild, Duration)> = children
+        .iter_mut()
+        .map(|service| {
+          // No stop signal off Unix, so there's nothing to wait for.
+          let grace = if cfg!(unix) { service.role.shutdown_grace() } else { Duration::ZERO };
+          (service, grace)
+        })
+        .collect();
+      while !pending.is_empty() {
+        pending.retain_mut(|(service, grace)| {
+          if let Ok(Some(status)) = service.child.try_wait() {
+            teardown_log!(
+              "{} (pid {}) exited: {}",
+              service.role.label(),
+              service.child.id(),
+              status
+            );
+            return false;
+          }
+          if children_started.elapsed() >= *grace {
+            eprintln!(
+              "[shutdown] {} still running after {:.1}s grace; killing",
+              service.role.label(),
+              grace.as_secs_f64()
+            );
+            vm::kill_logged(service.role.label(), &mut service.child);
+            return false;
+          }
+          true
+        });
+        if !pending.is_empty() {
+          std::thread::sleep(Duration::from_millis(100));
+        }
       }
     }
 
     // Remove PID + ports files since we've cleaned up
     if let Ok(dd) = self.data_dir.lock() {
       if let Some(ref data_dir) = *dd {
-        let _ = std::fs::remove_file(pid_file_path(data_dir));
-        let _ = std::fs::remove_file(ports_file_path(data_dir));
+        for path in [pid_file_path(data_dir), ports_file_path(data_dir)] {
+          if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+              teardown_log!("removing {}: {}", path.display(), e);
+            }
+          }
+        }
       }
     }
+    teardown_log!("shutdown finished in {:.2}s", started.elapsed().as_secs_f64());
   }
 }
 
 impl Drop for DesktopServices {
   fn drop(&mut self) {
+    teardown_log!("DesktopServices dropped");
     self.shutdown();
   }
 }
 
 fn resolve_resource_root(app: &tauri::App) -> Option<PathBuf> {
+  let candidates = resource_root_candidates(app.path().resource_dir().ok());
+  candidates
+    .into_iter()
+    .find(|(_, path)| resource_layout_valid(path))
+    .map(|(_, path)| path)
+}
+
+/// Resource-root candidates in resolution order, tagged with where each came
+/// from ("env", "resource-dir", "dev"). The first with a valid layout wins.
+fn resource_root_candidates(resource_dir: Option<PathBuf>) -> Vec<(&'static str, PathBuf)> {
+  let mut candidates = Vec::new();
   if let Ok(root) = std::env::var("ORCABOT_DESKTOP_ROOT") {
-    let root_path = PathBuf::from(root);
-    if resource_layout_valid(&root_path) {
-      return Some(root_path);
-    }
+    candidates.push(("env", PathBuf::from(root)));
   }
 
   // Installed .app: bundled resources live at Contents/Resources/resources/
@@ -1112,20 +2961,88 @@ fn resolve_resource_root(app: &tauri::App) -> Option<PathBuf> {
   // first, then the dir itself. Without this, an installed app only resolved via
   // the compile-time dev path below, so it worked on the build machine but had
   // no resources (and skipped autostart) on any other Mac.
-  if let Ok(resource_dir) = app.path().resource_dir() {
-    for candidate in [resource_dir.join("resources"), resource_dir] {
-      if resource_layout_valid(&candidate) {
-        return Some(candidate);
+  if let Some(resource_dir) = resource_dir {
+    candidates.push(("resource-dir", resource_dir.join("resources")));
+    candidates.push(("resource-dir", resource_dir));
+  }
+
+  candidates.push(("dev", PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources")));
+  candidates
+}
+
+#[derive(serde::Serialize)]
+struct ResourceCandidate {
+  source: &'static str,
+  path: String,
+  valid: bool,
+}
+
+/// Which resource root `resolve_resource_root` picks and what's in it, for
+/// diagnosing "resources not found" / "sandbox disabled" without the logs.
+#[derive(serde::Serialize)]
+struct ResourceInfo {
+  root: Option<String>,
+  /// "env" | "resource-dir" | "dev"; None when no candidate is valid.
+  source: Option<&'static str>,
+  candidates: Vec<ResourceCandidate>,
+  /// Artifact name -> exists under `root`. Empty when no root was found.
+  artifacts: std::collections::BTreeMap<&'static str, bool>,
+  /// Why each VM resource was (or wasn't) re-staged this run; empty until the
+  /// first staging.
+  staging: Vec<vm::image::StagingDecision>,
+  /// Why the last VM start skipped the preferred backend (macOS: the app is
+  /// signed without the virtualization entitlement, so QEMU was used).
+  backend_warning: Option<String>,
+}
+
+fn resource_info(resource_dir: Option<PathBuf>) -> ResourceInfo {
+  let candidates = resource_root_candidates(resource_dir);
+  let chosen = candidates
+    .iter()
+    .find(|(_, path)| resource_layout_valid(path))
+    .cloned();
+
+  let mut artifacts = std::collections::BTreeMap::new();
+  if let Some((_, ref root)) = chosen {
+    for (name, rel) in [
+      ("workerd", "workerd/workerd"),
+      ("d1-shim", "d1-shim/d1-shim"),
+      ("workerd-config", "workerd/config/workerd.desktop.capnp"),
+      ("workerd-frontend-config", "workerd/config/workerd.frontend.capnp"),
+      ("frontend-assets", "frontend/assets"),
+    ] {
+      artifacts.insert(name, root.join(rel).exists());
+    }
+    // Platform-specific VM artifacts. The image may legitimately be missing
+    // from the bundle when it's downloaded on demand.
+    let vm_paths = vm::image::VMResourcePaths::from_resource_root(root);
+    artifacts.insert("vm-image", vm_paths.image.exists());
+    for (name, path) in [
+      ("kernel", &vm_paths.kernel),
+      ("initrd", &vm_paths.initrd),
+      ("vz-helper", &vm_paths.vz_helper),
+    ] {
+      if let Some(path) = path {
+        artifacts.insert(name, path.exists());
       }
     }
   }
 
-  let dev_resource_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
-  if resource_layout_valid(&dev_resource_dir) {
-    return Some(dev_resource_dir);
+  ResourceInfo {
+    root: chosen.as_ref().map(|(_, p)| p.display().to_string()),
+    source: chosen.map(|(source, _)| source),
+    candidates: candidates
+      .into_iter()
+      .map(|(source, path)| ResourceCandidate {
+        source,
+        valid: resource_layout_valid(&path),
+        path: path.display().to_string(),
+      })
+      .collect(),
+    artifacts,
+    staging: vm::image::last_staging_decisions(),
+    backend_warning: vm::backend_warning(),
   }
-
-  None
 }
 
 fn resource_layout_valid(root: &Path) -> bool {
@@ -1165,48 +3082,239 @@ fn stage_executable(src: &Path, dest: &Path) -> std::io::Result<PathBuf> {
   Ok(dest.to_path_buf())
 }
 
-fn wait_for_health(port: &str) {
-  let addr = format!("127.0.0.1:{}", port);
-  for _ in 0..10 {
-    if let Ok(mut stream) = std::net::TcpStream::connect(&addr) {
-      let _ = stream.set_read_timeout(Some(Duration::from_secs(2)));
-      let _ = stream.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
-      let mut buf = [0u8; 128];
-      let n = stream.read(&mut buf).unwrap_or(0);
-      // Ready only on a real HTTP response. We accept ANY status (the d1-shim and
-      // frontend workerd legitimately 404 on /health) but require the "HTTP/"
-      // status line, so a stray non-HTTP listener on the port isn't mistaken for
-      // a healthy service.
-      if String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/") {
-        return;
+/// CPU architectures (`std::env::consts::ARCH` names) a native executable is
+/// built for, from its ELF, Mach-O (every slice of a universal binary) or PE
+/// header. Empty for anything unrecognised.
+fn executable_arches(head: &[u8]) -> Vec<&'static str> {
+  let u32_at = |at: usize, big_endian: bool| {
+    let bytes: [u8; 4] = head.get(at..at + 4)?.try_into().ok()?;
+    Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+  };
+  let mach_cpu = |cpu: u32| match cpu {
+    0x0100_0007 => Some("x86_64"),
+    0x0100_000c => Some("aarch64"),
+    7 => Some("x86"),
+    12 => Some("arm"),
+    _ => None,
+  };
+  if let Some(arch) = vm::image::elf_arch(head) {
+    return vec![arch];
+  }
+  match u32_at(0, false) {
+    // Thin Mach-O (MH_MAGIC / MH_MAGIC_64), little-endian on every Apple CPU.
+    Some(0xfeed_face | 0xfeed_facf) => {
+      return u32_at(4, false).and_then(mach_cpu).into_iter().collect();
+    }
+    // "MZ": PE, whose COFF machine field follows the signature at e_lfanew.
+    Some(magic) if magic & 0xffff == 0x5a4d => {
+      let Some(pe) = u32_at(0x3c, false).map(|o| o as usize) else {
+        return Vec::new();
+      };
+      if head.get(pe..pe + 4) != Some(b"PE\0\0".as_slice()) {
+        return Vec::new();
       }
+      let machine = head.get(pe + 4..pe + 6).map(|m| u16::from_le_bytes([m[0], m[1]]));
+      return match machine {
+        Some(0x8664) => vec!["x86_64"],
+        Some(0xaa64) => vec!["aarch64"],
+        Some(0x014c) => vec!["x86"],
+        _ => Vec::new(),
+      };
+    }
+    _ => {}
+  }
+  // Universal binary (FAT_MAGIC / FAT_MAGIC_64): big-endian table of slices.
+  // Java class files share 0xcafebabe; their "count" is a version far above
+  // any real number of slices.
+  let stride = match u32_at(0, true) {
+    Some(0xcafe_babe) => 20,
+    Some(0xcafe_babf) => 32,
+    _ => return Vec::new(),
+  };
+  let count = u32_at(4, true).unwrap_or(0) as usize;
+  if count > 16 {
+    return Vec::new();
+  }
+  (0..count)
+    .filter_map(|i| u32_at(8 + i * stride, true).and_then(mach_cpu))
+    .collect()
+}
+
+/// Whether the OS runs a binary built for `arches` on a `host` CPU anyway:
+/// x86_64 under Rosetta or on Windows on Arm, and 32-bit x86 on any 64-bit
+/// Windows (WOW64).
+fn runs_translated(host: &str, arches: &[&str], windows: bool, rosetta: bool) -> bool {
+  (host == "aarch64" && arches.contains(&"x86_64") && (windows || rosetta))
+    || (windows && arches.contains(&"x86"))
+}
+
+/// Refuse to spawn a service binary built for another CPU. Without this a
+/// mis-packaged x86_64 `workerd` on Apple Silicon (without Rosetta) or an
+/// aarch64 one on an x86_64 Linux box fails with a bare "Bad CPU type" /
+/// "exec format error". Binaries the OS translates (`runs_translated`) are
+/// allowed, with a warning since they may run slower.
+fn check_executable_arch(path: &Path) -> Result<(), String> {
+  let host = std::env::consts::ARCH;
+  let Ok(head) = vm::image::read_head(path, 4096) else {
+    return Ok(()); // Let the spawn report why it can't be read.
+  };
+  let arches = executable_arches(&head);
+  if arches.is_empty() || arches.contains(&host) {
+    return Ok(());
+  }
+  let built = arches.join("/");
+  let rosetta = cfg!(target_os = "macos")
+    && Path::new("/Library/Apple/usr/libexec/oah/libRosettaRuntime").exists();
+  if runs_translated(host, &arches, cfg!(windows), rosetta) {
+    eprintln!(
+      "[main] {} is built for {} and will run translated (Rosetta/WOW64) on this {} machine",
+      path.display(),
+      built,
+      host
+    );
+    return Ok(());
+  }
+  Err(format!(
+    "{} is built for {} but this machine is {}; the app was packaged with the wrong \
+     binary (rebuild or reinstall the {} build)",
+    path.display(),
+    built,
+    host,
+    host
+  ))
+}
+
+/// This boot's listen address for the frontend and control plane:
+/// `ORCABOT_BIND_ADDRESS`, else the `bind_address` setting, else 127.0.0.1. The
+/// d1-shim and the sandbox port always stay on loopback. 0.0.0.0 falls back to
+/// 127.0.0.1, with the reason returned, unless `network_bind_refusal` allows it.
+/// Exported back into `ORCABOT_BIND_ADDRESS` so `get_ports` uses the same value.
+fn resolve_bind_address(data_dir: &Path) -> (std::net::Ipv4Addr, Option<String>) {
+  let from_env = std::env::var("ORCABOT_BIND_ADDRESS").ok().and_then(|value| {
+    match value.trim().parse() {
+      Ok(addr) if settings::check_bind_address(addr).is_ok() => Some(addr),
+      _ => {
+        eprintln!("[main] ignoring ORCABOT_BIND_ADDRESS={value:?}: expected 127.0.0.1 or 0.0.0.0");
+        None
+      }
+    }
+  });
+  let settings = settings::load(data_dir);
+  let mut addr = from_env.unwrap_or_else(|| settings::bind_address(&settings));
+  let mut refusal = None;
+  if !addr.is_loopback() {
+    // As `ServicesConfig::from_env` resolves it for the control plane.
+    let dev_auth = std::env::var("DEV_AUTH_ENABLED")
+      .unwrap_or_else(|_| settings.dev_auth.unwrap_or(true).to_string());
+    refusal = network_bind_refusal(&dev_auth, &sandbox_internal_token(), &internal_api_token())
+      .map(|reason| format!("[security] bind_address {addr} refused, using 127.0.0.1: {reason}"));
+    if refusal.is_some() {
+      addr = std::net::Ipv4Addr::LOCALHOST;
+    }
+  }
+  std::env::set_var("ORCABOT_BIND_ADDRESS", addr.to_string());
+  (addr, refusal)
+}
+
+/// Why the stack may not listen beyond loopback, if it may not: with dev auth
+/// on, anyone on the network is signed in, and the built-in service tokens let
+/// them act as the sandbox or the control plane.
+fn network_bind_refusal(
+  dev_auth: &str,
+  sandbox_token: &str,
+  internal_token: &str,
+) -> Option<String> {
+  if dev_auth.trim() == "true" {
+    return Some("dev auth is on (set DEV_AUTH_ENABLED=false or turn it off in settings)".into());
+  }
+  if sandbox_token == DEFAULT_SANDBOX_INTERNAL_TOKEN
+    || internal_token == DEFAULT_INTERNAL_API_TOKEN
+  {
+    return Some(
+      "SANDBOX_INTERNAL_TOKEN and INTERNAL_API_TOKEN must be set to non-default values".into(),
+    );
+  }
+  None
+}
+
+/// `name=path` for workerd's `--directory-path`, built as an OsString so a path
+/// that isn't valid UTF-8 reaches workerd intact instead of lossily converted.
+fn named_path(name: &str, path: &Path) -> OsString {
+  let mut arg = OsString::from(name);
+  arg.push("=");
+  arg.push(path);
+  arg
+}
+
+fn wait_for_health(port: &str) -> bool {
+  for _ in 0..10 {
+    if probe_http(port) {
+      return true;
     }
     std::thread::sleep(Duration::from_millis(500));
   }
+  false
+}
+
+/// How long `wait_for_frontend` keeps asking for the app shell before falling
+/// back to the plain port check.
+const FRONTEND_READY_ATTEMPTS: u32 = 40;
+
+/// Wait until the frontend workerd serves the app itself: `GET /` must answer
+/// 200, not just any HTTP status — workerd answers on the port (404/503) before
+/// the assets worker can serve the page, and a webview that loads then shows a
+/// blank page. Retries for ~10s; if the root never comes back 200 but the port
+/// speaks HTTP, logs it and reports ready anyway so a frontend that answers `/`
+/// differently (e.g. a redirect) doesn't hold up startup.
+fn wait_for_frontend(port: &str) -> bool {
+  let mut last_status = None;
+  for _ in 0..FRONTEND_READY_ATTEMPTS {
+    match probe_http_path(port, "/") {
+      Some(200) => return true,
+      Some(status) => last_status = Some(status),
+      None => {}
+    }
+    std::thread::sleep(Duration::from_millis(250));
+  }
+  if probe_http(port) {
+    eprintln!(
+      "[frontend] GET / never returned 200 (last: {}); continuing since the port answers HTTP",
+      last_status.map_or_else(|| "no response".to_string(), |c| c.to_string())
+    );
+    return true;
+  }
+  false
+}
+
+/// Status code of `GET <path>` against a loopback service, or `None` if nothing
+/// HTTP answered.
+fn probe_http_path(port: &str, path: &str) -> Option<u16> {
+  vm::health::http_status(&format!("127.0.0.1:{}", port), path, Duration::from_secs(2))
+}
+
+/// One readiness probe of a loopback service: GET /health and require a real HTTP
+/// status line. Shared by the startup waits and the `wait_for_services_ready`
+/// command.
+pub(crate) fn probe_http(port: &str) -> bool {
+  // Ready on any status (the d1-shim and frontend workerd legitimately 404 on
+  // /health), but only once a complete HTTP response head with a parseable
+  // status line arrives, so a stray non-HTTP listener on the port isn't
+  // mistaken for a healthy service.
+  probe_http_path(port, "/health").is_some()
 }
 
 /// POST /init-db to apply the D1 schema (idempotent). Best-effort: logs and
 /// continues on failure so a transient hiccup never blocks app startup.
 fn apply_schema(port: &str, internal_token: &str) {
+  let req = format!(
+    "POST /init-db HTTP/1.1\r\nHost: localhost\r\nX-Internal-Token: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    internal_token
+  );
   let addr = format!("127.0.0.1:{}", port);
-  match std::net::TcpStream::connect(&addr) {
-    Ok(mut stream) => {
-      let req = format!(
-        "POST /init-db HTTP/1.1\r\nHost: localhost\r\nX-Internal-Token: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
-        internal_token
-      );
-      let _ = stream.write_all(req.as_bytes());
-      let mut buf = [0u8; 256];
-      let n = stream.read(&mut buf).unwrap_or(0);
-      let head = String::from_utf8_lossy(&buf[..n]);
-      let status = head.lines().next().unwrap_or("");
-      if status.contains(" 200") {
-        eprintln!("[schema] applied via /init-db");
-      } else {
-        eprintln!("[schema] /init-db unexpected response: {status}");
-      }
-    }
-    Err(err) => eprintln!("[schema] could not reach control plane for /init-db: {err}"),
+  match vm::health::request_status(&addr, &req, Duration::from_secs(10)) {
+    Some(200) => eprintln!("[schema] applied via /init-db"),
+    Some(status) => eprintln!("[schema] /init-db unexpected response: {status}"),
+    None => eprintln!("[schema] no HTTP response from the control plane for /init-db"),
   }
 }
 
@@ -1230,12 +3338,58 @@ fn main() {
     .invoke_handler(tauri::generate_handler![
       commands::get_workspace_path,
       commands::import_folder,
+      commands::export_folder,
+      commands::cancel_export,
+      commands::active_exports,
       commands::switch_to_cli,
       commands::quit_app,
       commands::get_surface_token,
+      commands::get_local_credentials,
+      commands::set_custom_vm_image,
+      commands::clear_custom_vm_image,
+      commands::set_qemu_accel,
+      commands::set_vm_cpu_topology,
+      commands::validate_boot_set,
+      commands::get_supported_formats,
+      commands::convert_vm_image,
+      commands::active_imports,
+      commands::cancel_import,
+      commands::preview_import_tree,
       commands::open_url,
       commands::reveal_workspace,
+      commands::relocate_workspace,
       commands::get_ports,
+      commands::get_sandbox_status,
+      commands::get_vm_config,
+      commands::get_vm_metrics,
+      commands::note_sandbox_activity,
+      commands::suspend_all,
+      commands::resume_all,
+      commands::prewarm_resources,
+      commands::fetch_and_stage_image,
+      commands::cancel_image_fetch,
+      commands::restart_sandbox_vm,
+      commands::benchmark_workspace_io,
+      commands::kill_guest_process,
+      commands::list_guest_processes,
+      commands::get_guest_info,
+      commands::get_resource_info,
+      commands::get_wsl_distros,
+      commands::get_setting,
+      commands::set_setting,
+      commands::set_bind_address,
+      commands::set_dev_flags,
+      commands::reset_controlplane_db,
+      commands::put_sandbox_file,
+      commands::sandbox_exec_streaming,
+      commands::cancel_sandbox_exec,
+      commands::prepare_clear_workspace,
+      commands::clear_workspace,
+      commands::get_startup_timings,
+      commands::get_data_dir_usage,
+      commands::prune_data_dir,
+      commands::wait_for_services_ready,
+      commands::aggregate_health,
       commands::get_app_version,
       commands::read_startup_log,
       commands::verify_orcabot_account,
@@ -1257,9 +3411,42 @@ fn main() {
         std::process::exit(0);
       });
 
+      // SIGTERM/SIGHUP/SIGQUIT: same clean shutdown as a quit. signal-hook's
+      // handler only writes to a self-pipe; this thread asks the run loop to exit,
+      // and RunEvent::Exit runs `shutdown()` on the main thread. A second signal
+      // while that's in progress (a wedged main thread) tears down from here.
+      #[cfg(unix)]
+      {
+        use signal_hook::consts::{SIGHUP, SIGQUIT, SIGTERM};
+        let handle = app.handle().clone();
+        let signal_services = Arc::clone(&services);
+        if let Ok(mut signals) = signal_hook::iterator::Signals::new([SIGTERM, SIGHUP, SIGQUIT]) {
+          std::thread::spawn(move || {
+            let mut exiting = false;
+            for sig in signals.forever() {
+              if exiting {
+                eprintln!("[shutdown] signal {} again; shutting down now", sig);
+                signal_services.shutdown();
+                std::process::exit(1);
+              }
+              exiting = true;
+              eprintln!("[shutdown] signal {}; exiting", sig);
+              handle.exit(0);
+            }
+          });
+        }
+      }
+
       // Start core services (d1-shim, workerd) — blocks until healthy (~5-10s)
       services.start(app);
 
+      // Respawn services that crash (ORCABOT_SERVICE_RESTART=0 turns this off).
+      if !std::env::var("ORCABOT_SERVICE_RESTART").is_ok_and(|v| v.trim() == "0") {
+        let supervisor = Arc::clone(&services);
+        let handle = app.handle().clone();
+        std::thread::spawn(move || supervisor.watch_services(&handle));
+      }
+
       // NOTE: we deliberately do NOT clear the webview's browsing data here. An
       // earlier attempt used clear_all_browsing_data() to bust a *suspected* stale
       // frontend cache, but it also wiped cookies/localStorage — including the
@@ -1272,14 +3459,14 @@ fn main() {
       // Register workspace state for Tauri commands
       let data_dir = app.path().app_data_dir().ok();
       if let Some(ref dd) = data_dir {
-        let workspace_path = dd.join("workspace");
-        let _ = std::fs::create_dir_all(&workspace_path);
-        app.manage(WorkspaceState { workspace_path });
+        let workspace_path = workspace::workspace_dir(dd);
+        if let Err(e) = workspace::prepare(&workspace_path) {
+          eprintln!("[main] workspace {}: {}", workspace_path.display(), e);
+        }
+        app.manage(WorkspaceState::new(workspace_path));
       } else {
         // Fallback: manage with empty path (commands will return errors)
-        app.manage(WorkspaceState {
-          workspace_path: PathBuf::new(),
-        });
+        app.manage(WorkspaceState::new(PathBuf::new()));
       }
 
       // Start sandbox VM in a background thread so the window appears immediately
@@ -1298,10 +3485,36 @@ fn main() {
       };
       if let (Some(rr), Some(dd)) = (resource_root, data_dir) {
         let vm_services = Arc::clone(&services);
+        let vm_app = app.handle().clone();
+        if let Ok(mut idle) = services.sandbox_idle.lock() {
+          idle.boot_dirs = Some((dd.clone(), vm_dir.clone(), rr.clone()));
+        }
+        services.start_sandbox_relay(app.handle());
         std::thread::spawn(move || {
-          if let Err(err) = vm_services.start_sandbox_vm(&dd, &vm_dir, &rr) {
+          if let Err(err) = vm_services.start_sandbox_vm(&vm_app, &dd, &vm_dir, &rr) {
             eprintln!("Failed to start sandbox VM: {}", err);
             eprintln!("Sandbox features will be unavailable.");
+            return;
+          }
+          if let Some(policy) = vm::clock::SkewPolicy::from_env() {
+            let clock_services = Arc::clone(&vm_services);
+            let clock_app = vm_app.clone();
+            std::thread::spawn(move || clock_services.watch_guest_clock(&clock_app, policy));
+          }
+          {
+            let exit_services = Arc::clone(&vm_services);
+            let exit_app = vm_app.clone();
+            std::thread::spawn(move || exit_services.watch_vm_exit(&exit_app));
+          }
+          if let Some(policy) = vm::watchdog::WatchdogPolicy::from_env() {
+            let watchdog_services = Arc::clone(&vm_services);
+            let watchdog_app = vm_app.clone();
+            std::thread::spawn(move || watchdog_services.watch_sandbox_health(&watchdog_app, policy));
+          }
+          if let Some(window) = sandbox_idle_window() {
+            eprintln!("[vm] idle shutdown after {}s without sandbox activity", window.as_secs());
+            vm_services.sandbox_activity.note();
+            vm_services.watch_sandbox_idle(&vm_app, window);
           }
         });
       }
@@ -1473,3 +3686,128 @@ fn main() {
     }
   });
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(unix)]
+  #[test]
+  fn orphan_scan_knows_our_qemu_and_virtiofsd_by_their_markers() {
+    let ours = format!("/usr/bin/qemu-system-x86_64 -name {} -machine q35", vm::QEMU_NAME);
+    assert!(orphan_vm_process("qemu-system-x86_64", &ours));
+    assert!(!orphan_vm_process("qemu-system-x86_64", "/usr/bin/qemu-system-x86_64 -name win11"));
+    let fs = format!(
+      "/usr/libexec/virtiofsd --socket-path=/run/user/1000/orcabot/{}42.sock",
+      vm::VIRTIOFS_SOCKET_PREFIX
+    );
+    assert!(orphan_vm_process("virtiofsd", &fs));
+    assert!(!orphan_vm_process("virtiofsd", "/usr/libexec/virtiofsd --socket-path=/tmp/vm.sock"));
+    // Anything else goes by where its executable lives, not its arguments.
+    assert!(!orphan_vm_process("workerd", &ours));
+  }
+
+  #[test]
+  fn executable_arches_reads_elf_macho_and_pe_headers() {
+    let elf = |class_endian: &[u8], machine: [u8; 2]| {
+      let mut head = b"\x7fELF".to_vec();
+      head.extend(class_endian);
+      head.resize(18, 0);
+      head.extend(machine);
+      head.resize(64, 0);
+      head
+    };
+    assert_eq!(executable_arches(&elf(&[2, 1], [0x3e, 0])), ["x86_64"]);
+    assert_eq!(executable_arches(&elf(&[2, 2], [0, 0xb7])), ["aarch64"]);
+    assert!(executable_arches(&elf(&[2, 1], [0x28, 0])).is_empty()); // 32-bit ARM
+    assert!(executable_arches(b"\x7fELF").is_empty()); // truncated
+
+    // Thin Mach-O: magic, then cputype, little-endian.
+    let mut thin = 0xfeed_facf_u32.to_le_bytes().to_vec();
+    thin.extend(0x0100_000c_u32.to_le_bytes());
+    assert_eq!(executable_arches(&thin), ["aarch64"]);
+    // Universal: big-endian count, then 20-byte slices led by cputype.
+    let mut fat = 0xcafe_babe_u32.to_be_bytes().to_vec();
+    fat.extend(2u32.to_be_bytes());
+    for cpu in [0x0100_0007_u32, 0x0100_000c] {
+      fat.extend(cpu.to_be_bytes());
+      fat.extend([0u8; 16]);
+    }
+    assert_eq!(executable_arches(&fat), ["x86_64", "aarch64"]);
+    // A Java class file: same magic, a "count" that is really its version.
+    let mut class = 0xcafe_babe_u32.to_be_bytes().to_vec();
+    class.extend(0x0000_0041_u32.to_be_bytes());
+    assert!(executable_arches(&class).is_empty());
+
+    // PE: "MZ", e_lfanew at 0x3c, then "PE\0\0" and the COFF machine.
+    let pe = |machine: u16| {
+      let mut head = b"MZ".to_vec();
+      head.resize(0x3c, 0);
+      head.extend(0x80u32.to_le_bytes());
+      head.resize(0x80, 0);
+      head.extend(b"PE\0\0");
+      head.extend(machine.to_le_bytes());
+      head
+    };
+    assert_eq!(executable_arches(&pe(0x8664)), ["x86_64"]);
+    assert_eq!(executable_arches(&pe(0xaa64)), ["aarch64"]);
+    assert_eq!(executable_arches(&pe(0x014c)), ["x86"]);
+    let mut dos_only = pe(0x8664);
+    dos_only[0x80] = b'X';
+    assert!(executable_arches(&dos_only).is_empty());
+
+    // Only what the OS actually translates passes.
+    assert!(runs_translated("x86_64", &["x86"], true, false)); // WOW64
+    assert!(runs_translated("aarch64", &["x86"], true, false));
+    assert!(!runs_translated("x86_64", &["x86"], false, false));
+    assert!(runs_translated("aarch64", &["x86_64"], false, true)); // Rosetta
+    assert!(!runs_translated("aarch64", &["x86_64"], false, false));
+    assert!(!runs_translated("x86_64", &["aarch64"], true, false));
+  }
+
+  fn value<'a>(env: &'a [(&'static str, String)], key: &str) -> Option<&'a str> {
+    env.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str())
+  }
+
+  #[test]
+  fn spawn_env_follows_settings_and_passes_optional_keys() {
+    // The only test that touches these variables.
+    for key in ["DEV_AUTH_ENABLED", "NEXT_PUBLIC_DEV_MODE_ENABLED", "D1_SHIM_DEBUG"] {
+      std::env::remove_var(key);
+    }
+    std::env::set_var("RESEND_API_KEY", "re_test");
+    let settings = settings::Settings {
+      dev_auth: Some(false),
+      dev_mode: None,
+      ..Default::default()
+    };
+    let mut config =
+      ServicesConfig::from_env(PathBuf::from("/data/d1.sqlite"), "key".into(), &settings);
+    std::env::remove_var("RESEND_API_KEY");
+
+    let env = config.to_spawn_env();
+    // Unset env: the dev flags come from settings, defaulting to on.
+    assert_eq!(value(&env.controlplane, "DEV_AUTH_ENABLED"), Some("false"));
+    assert_eq!(value(&env.frontend, "NEXT_PUBLIC_DEV_MODE_ENABLED"), Some("true"));
+    // D1_SHIM_DEBUG reaches the control plane only when set.
+    assert_eq!(value(&env.controlplane, "D1_SHIM_DEBUG"), None);
+    assert_eq!(value(&env.d1_shim, "D1_SHIM_DEBUG"), Some(""));
+    assert_eq!(value(&env.controlplane, "RESEND_API_KEY"), Some("re_test"));
+    assert_eq!(value(&env.d1_shim, "D1_SQLITE_PATH"), Some("/data/d1.sqlite"));
+
+    config.d1_shim_debug = Some("1".into());
+    let env = config.to_spawn_env();
+    assert_eq!(value(&env.controlplane, "D1_SHIM_DEBUG"), Some("1"));
+    assert_eq!(value(&env.d1_shim, "D1_SHIM_DEBUG"), Some("1"));
+  }
+
+
+  #[test]
+  fn network_bind_needs_dev_auth_off_and_real_tokens() {
+    let refused = |dev_auth, sandbox, internal| network_bind_refusal(dev_auth, sandbox, internal);
+    assert!(refused("true", "s3cret-a", "s3cret-b").is_some());
+    assert!(refused("false", DEFAULT_SANDBOX_INTERNAL_TOKEN, "s3cret-b").is_some());
+    assert!(refused("false", "s3cret-a", DEFAULT_INTERNAL_API_TOKEN).is_some());
+    assert!(refused("false", "s3cret-a", "s3cret-b").is_none());
+  }
+}
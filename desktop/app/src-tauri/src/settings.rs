@@ -0,0 +1,263 @@
+//! Persisted desktop settings, in one versioned file: `<data_dir>/settings.json`.
+//!
+//! Writes go to a sibling temp file that is fsynced and renamed over the real
+//! one, so a crash mid-save leaves the previous settings intact. Older layouts
+//! are migrated on load (`migrate`), and fields written by a newer app version
+//! are carried through a save untouched instead of being dropped.
+
+// REVISION: settings-v7-dedicated-keys
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Current schema version. Bump it and extend `migrate` when a field changes
+/// meaning or moves; plain new fields just need `#[serde(default)]`.
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Serializes load-modify-save cycles within this process.
+static SETTINGS_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Schema version the file was written with (0 = no file yet).
+    pub version: u32,
+    /// Local VM image used in place of the bundled/published one
+    /// (`set_custom_vm_image`). `ORCABOT_VM_IMAGE` still wins over it.
+    pub custom_vm_image: Option<PathBuf>,
+    /// Workspace location when it isn't `<data_dir>/workspace`
+    /// (`relocate_workspace`).
+    pub workspace_dir: Option<PathBuf>,
+    /// Most crashed host services (d1-shim, workerd) restarted at once; unset
+    /// means 1, so simultaneous crashes recover one at a time.
+    pub max_concurrent_restarts: Option<u32>,
+    /// Address the frontend and control plane listen on (`set_bind_address`,
+    /// see [`bind_address`]); unset means 127.0.0.1. Applied at the next launch.
+    pub bind_address: Option<std::net::Ipv4Addr>,
+    /// Control-plane `DEV_AUTH_ENABLED` (`set_dev_flags`); unset means on. The
+    /// env var still wins at launch.
+    pub dev_auth: Option<bool>,
+    /// Frontend `NEXT_PUBLIC_DEV_MODE_ENABLED` (`set_dev_flags`); unset means
+    /// on. The env var still wins at launch.
+    pub dev_mode: Option<bool>,
+    /// Linux QEMU acceleration (`set_qemu_accel`); unset means auto. Applied at
+    /// the next VM start.
+    pub qemu_accel: Option<crate::vm::QemuAccel>,
+    /// QEMU vCPU layout as (sockets, cores, threads) (`set_vm_cpu_topology`);
+    /// unset means a flat vCPU count. `ORCABOT_VM_CPU_TOPOLOGY` still wins.
+    /// Applied at the next VM start.
+    pub cpu_topology: Option<(u32, u32, u32)>,
+    /// Fields this version doesn't know about, kept so a downgrade followed by
+    /// a save doesn't erase a newer version's settings.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+fn settings_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("settings.json")
+}
+
+/// Pre-settings.json location of the custom image path (a bare text file).
+fn legacy_custom_image_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("custom-vm-image")
+}
+
+/// Load settings, migrating older layouts. A missing file yields defaults; an
+/// unparseable one is set aside as `settings.json.corrupt` and also yields
+/// defaults, so one bad write can't wedge startup.
+pub fn load(data_dir: &Path) -> Settings {
+    let path = settings_path(data_dir);
+    let mut settings = match std::fs::read(&path) {
+        Ok(bytes) => match serde_json::from_slice::<Settings>(&bytes) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!(
+                    "[settings] {} is unreadable ({}); moving it aside and using defaults",
+                    path.display(),
+                    e
+                );
+                let _ = std::fs::rename(&path, data_dir.join("settings.json.corrupt"));
+                Settings::default()
+            }
+        },
+        Err(_) => Settings::default(),
+    };
+    migrate(data_dir, &mut settings);
+    settings
+}
+
+/// Bring `settings` up to `SETTINGS_VERSION`. Files from a newer version are left
+/// as they are.
+fn migrate(data_dir: &Path, settings: &mut Settings) {
+    if settings.version < 1 {
+        // v0 -> v1: the custom image path lived in its own file.
+        if settings.custom_vm_image.is_none() {
+            settings.custom_vm_image = std::fs::read_to_string(legacy_custom_image_path(data_dir))
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from);
+        }
+    }
+    settings.version = settings.version.max(SETTINGS_VERSION);
+}
+
+/// Atomically replace `settings.json` with `settings`.
+pub fn save(data_dir: &Path, settings: &Settings) -> std::io::Result<()> {
+    std::fs::create_dir_all(data_dir)?;
+    let path = settings_path(data_dir);
+    let tmp = data_dir.join(format!(".settings.json.tmp-{}", std::process::id()));
+    let json = serde_json::to_vec_pretty(settings)?;
+    let result = std::fs::File::create(&tmp)
+        .and_then(|mut f| {
+            f.write_all(&json)?;
+            f.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&tmp, &path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result?;
+    // Once the migrated value is safely in settings.json the old file can go.
+    let _ = std::fs::remove_file(legacy_custom_image_path(data_dir));
+    Ok(())
+}
+
+/// Load, apply `f`, and save, holding the process-wide settings lock throughout
+/// so concurrent updates don't overwrite each other.
+pub fn update<T>(data_dir: &Path, f: impl FnOnce(&mut Settings) -> T) -> std::io::Result<T> {
+    let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut settings = load(data_dir);
+    let out = f(&mut settings);
+    save(data_dir, &settings)?;
+    Ok(out)
+}
+
+/// One setting as JSON, by its field name (e.g. "custom_vm_image").
+pub fn get_value(settings: &Settings, key: &str) -> Result<serde_json::Value, String> {
+    match serde_json::to_value(settings).map_err(|e| e.to_string())? {
+        serde_json::Value::Object(map) => map
+            .get(key)
+            .cloned()
+            .ok_or_else(|| format!("unknown setting: {key}")),
+        _ => Err("settings did not serialize to an object".to_string()),
+    }
+}
+
+/// Check a `bind_address`. Only loopback and the IPv4 wildcard work: the app
+/// reaches its own services (health checks, the VM's route to the control
+/// plane) on 127.0.0.1, which a single-interface address would cut off.
+pub fn check_bind_address(addr: std::net::Ipv4Addr) -> Result<(), String> {
+    if addr == std::net::Ipv4Addr::LOCALHOST || addr.is_unspecified() {
+        return Ok(());
+    }
+    Err(format!(
+        "bind_address {addr} is not supported: use 127.0.0.1 (this machine only) \
+         or 0.0.0.0 (every network interface)"
+    ))
+}
+
+/// The checked `bind_address`, 127.0.0.1 when unset or unusable.
+pub fn bind_address(settings: &Settings) -> std::net::Ipv4Addr {
+    match settings.bind_address {
+        Some(addr) => match check_bind_address(addr) {
+            Ok(()) => addr,
+            Err(e) => {
+                eprintln!("[settings] {e}; using 127.0.0.1");
+                std::net::Ipv4Addr::LOCALHOST
+            }
+        },
+        None => std::net::Ipv4Addr::LOCALHOST,
+    }
+}
+
+/// Settings whose command does more than store the value (moving the
+/// workspace, validating an image, warning about the network), with that
+/// command. `set_value` refuses them.
+const DEDICATED_SETTINGS: &[(&str, &str)] = &[
+    ("workspace_dir", "relocate_workspace"),
+    ("custom_vm_image", "set_custom_vm_image or clear_custom_vm_image"),
+    ("bind_address", "set_bind_address"),
+    ("cpu_topology", "set_vm_cpu_topology"),
+];
+
+/// Set one known setting from JSON, type-checked against the `Settings` field.
+pub fn set_value(settings: &mut Settings, key: &str, value: serde_json::Value) -> Result<(), String> {
+    if key == "version" {
+        return Err("the settings version is not writable".to_string());
+    }
+    if let Some((_, command)) = DEDICATED_SETTINGS.iter().find(|(name, _)| *name == key) {
+        return Err(format!("{key} can't be set directly; use {command}"));
+    }
+    let serde_json::Value::Object(mut map) =
+        serde_json::to_value(&*settings).map_err(|e| e.to_string())?
+    else {
+        return Err("settings did not serialize to an object".to_string());
+    };
+    if !map.contains_key(key) || settings.extra.contains_key(key) {
+        return Err(format!("unknown setting: {key}"));
+    }
+    map.insert(key.to_string(), value);
+    *settings = serde_json::from_value(serde_json::Value::Object(map))
+        .map_err(|e| format!("invalid value for {key}: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_legacy_custom_image_and_keeps_unknown_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(legacy_custom_image_path(dir.path()), "/images/dev.img\n").unwrap();
+
+        let mut settings = load(dir.path());
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.custom_vm_image, Some(PathBuf::from("/images/dev.img")));
+
+        settings
+            .extra
+            .insert("from_the_future".to_string(), serde_json::json!(42));
+        save(dir.path(), &settings).unwrap();
+        assert!(!legacy_custom_image_path(dir.path()).exists());
+        assert_eq!(load(dir.path()), settings);
+    }
+
+    #[test]
+    fn set_value_is_type_checked() {
+        let mut settings = Settings::default();
+        set_value(&mut settings, "max_concurrent_restarts", serde_json::json!(3)).unwrap();
+        assert_eq!(
+            get_value(&settings, "max_concurrent_restarts").unwrap(),
+            serde_json::json!(3)
+        );
+        let wrong_type = serde_json::json!("x");
+        assert!(set_value(&mut settings, "max_concurrent_restarts", wrong_type).is_err());
+        assert!(set_value(&mut settings, "version", serde_json::json!(9)).is_err());
+        assert!(set_value(&mut settings, "nope", serde_json::json!(true)).is_err());
+        assert_eq!(settings.max_concurrent_restarts, Some(3));
+
+        assert!(check_bind_address(std::net::Ipv4Addr::UNSPECIFIED).is_ok());
+        assert!(check_bind_address(std::net::Ipv4Addr::new(192, 168, 1, 5)).is_err());
+        settings.bind_address = Some(std::net::Ipv4Addr::new(192, 168, 1, 5));
+        assert_eq!(bind_address(&settings), std::net::Ipv4Addr::LOCALHOST);
+    }
+
+    #[test]
+    fn set_value_refuses_settings_with_their_own_command() {
+        let mut settings = Settings::default();
+        for (key, value) in [
+            ("workspace_dir", serde_json::json!("/elsewhere")),
+            ("custom_vm_image", serde_json::json!("/a.img")),
+            ("bind_address", serde_json::json!("0.0.0.0")),
+            ("cpu_topology", serde_json::json!([0, 2, 2])),
+        ] {
+            let err = set_value(&mut settings, key, value).unwrap_err();
+            assert!(err.contains("use "), "{key}: {err}");
+        }
+        assert_eq!(settings, Settings::default());
+    }
+}
@@ -0,0 +1,86 @@
+//! Teardown logging, so "the app closed — was the VM stopped?" can be answered
+//! after the fact.
+//!
+//! `DesktopServices::shutdown`, the VM backends' `stop` and the `Drop` impls
+//! log what they tear down (PIDs, signals, errors) with [`teardown_log!`]. Lines
+//! go to stderr and to `<data_dir>/shutdown.log`, which the first shutdown of a
+//! run truncates so it always describes the latest exit. This runs while the
+//! process is exiting, so writes are best-effort and never panic (`eprintln!`
+//! does if stderr is already closed), and the file is opened per line rather
+//! than held by anything that might already be dropped.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+static LOG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+static BEGUN: AtomicBool = AtomicBool::new(false);
+static STARTED: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Log teardown lines to `<data_dir>/shutdown.log` from now on.
+pub fn set_log_dir(data_dir: &Path) {
+    if let Ok(mut path) = LOG_PATH.lock() {
+        *path = Some(data_dir.join("shutdown.log"));
+    }
+}
+
+/// Mark the start of a shutdown. The first call in a process truncates the
+/// log; later ones (the exit handler, then `Drop`) only note that they ran.
+pub fn begin(caller: &str) {
+    if BEGUN.swap(true, Ordering::SeqCst) {
+        log(format_args!("{caller}: shutdown already under way"));
+        return;
+    }
+    if let Ok(mut started) = STARTED.lock() {
+        *started = Some(Instant::now());
+    }
+    let unix_secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if let Some(path) = log_path() {
+        let _ = std::fs::write(
+            path,
+            format!(
+                "=== Orcabot desktop shutdown (pid {}, unix time {}) ===\n",
+                std::process::id(),
+                unix_secs
+            ),
+        );
+    }
+    log(format_args!("{caller}: shutdown started"));
+}
+
+/// Write one line, prefixed with the time since [`begin`] once it has run.
+pub fn log(args: std::fmt::Arguments) {
+    let elapsed = STARTED
+        .lock()
+        .ok()
+        .and_then(|started| *started)
+        .map(|t| format!("+{:.3}s ", t.elapsed().as_secs_f64()))
+        .unwrap_or_default();
+    let line = format!("[teardown] {elapsed}{args}\n");
+    let _ = std::io::stderr().write_all(line.as_bytes());
+    if let Some(path) = log_path() {
+        if let Ok(mut file) = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+fn log_path() -> Option<PathBuf> {
+    LOG_PATH.lock().ok()?.clone()
+}
+
+/// `format!`-style teardown log line; see the module docs.
+macro_rules! teardown_log {
+    ($($arg:tt)*) => {
+        $crate::teardown::log(format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,174 @@
+//! Workspace IO benchmark.
+//!
+//! How fast the guest sees the shared workspace depends on the share backend
+//! (virtiofs is far quicker than 9p for small files). This runs a short
+//! sequential and small-file benchmark against it over the exec channel, timing
+//! each step on the host and subtracting the channel's own round-trip, so the
+//! numbers are comparable across backends.
+
+use super::exec::shell_quote;
+use super::{ExecChannel, VMError};
+use std::time::{Duration, Instant};
+
+const SEQUENTIAL_MIB: u64 = 64;
+const SMALL_FILES: u32 = 200;
+const SMALL_FILE_BYTES: u64 = 4096;
+const STEP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Result of [`run`]. Latencies are per file; throughputs in MiB/s.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IoBenchmark {
+    /// Filesystem type of the guest mount holding the workspace ("virtiofs",
+    /// "9p", "nfs4", ...), from /proc/mounts.
+    pub share: Option<String>,
+    pub guest_path: String,
+    pub sequential_bytes: u64,
+    pub sequential_write_mib_s: f64,
+    pub sequential_read_mib_s: f64,
+    pub small_files: u32,
+    pub small_file_bytes: u64,
+    pub small_file_create_ms: f64,
+    pub small_file_read_ms: f64,
+    pub small_file_delete_ms: f64,
+    /// Round-trip of an empty guest command, already subtracted from the above.
+    pub exec_overhead_ms: f64,
+}
+
+/// Benchmark the workspace mounted at `guest_path`, in a scratch directory that
+/// is removed afterwards (also on failure). Takes a few seconds on virtiofs,
+/// longer on 9p.
+pub fn run(channel: &ExecChannel, guest_path: &str) -> Result<IoBenchmark, VMError> {
+    let mounts = step(channel, "cat /proc/mounts")?.1;
+    let share = share_fstype(&mounts, guest_path);
+    let overhead = (0..3)
+        .map(|_| step(channel, "true").map(|(elapsed, _)| elapsed))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .min()
+        .unwrap_or_default();
+
+    let dir = format!(
+        "{}/.orcabot-io-bench-{}",
+        guest_path.trim_end_matches('/'),
+        std::process::id()
+    );
+    let q = shell_quote(&dir);
+    step(channel, &format!("rm -rf {q} && mkdir -p {q}/small"))?;
+    let timed = run_steps(channel, &q);
+    let _ = channel.exec(&format!("rm -rf {q}"), STEP_TIMEOUT);
+    let [write, read, create, read_small, delete] = timed?;
+
+    let ms = |d: Duration| d.saturating_sub(overhead).as_secs_f64() * 1000.0;
+    let mib_s = |d: Duration| SEQUENTIAL_MIB as f64 / (ms(d) / 1000.0).max(0.001);
+    let per_file = |d: Duration| ms(d) / SMALL_FILES as f64;
+    Ok(IoBenchmark {
+        share,
+        guest_path: guest_path.to_string(),
+        sequential_bytes: SEQUENTIAL_MIB * 1024 * 1024,
+        sequential_write_mib_s: mib_s(write),
+        sequential_read_mib_s: mib_s(read),
+        small_files: SMALL_FILES,
+        small_file_bytes: SMALL_FILE_BYTES,
+        small_file_create_ms: per_file(create),
+        small_file_read_ms: per_file(read_small),
+        small_file_delete_ms: per_file(delete),
+        exec_overhead_ms: overhead.as_secs_f64() * 1000.0,
+    })
+}
+
+/// The timed steps, in `dir` (already shell-quoted). Guest caches are dropped
+/// before each read so it hits the share rather than guest memory.
+fn run_steps(channel: &ExecChannel, dir: &str) -> Result<[Duration; 5], VMError> {
+    let drop_caches = "sync; echo 3 > /proc/sys/vm/drop_caches 2>/dev/null; true";
+    let write = step(
+        channel,
+        &format!(
+            "dd if=/dev/zero of={dir}/seq bs=1M count={SEQUENTIAL_MIB} conv=fsync 2>/dev/null"
+        ),
+    )?
+    .0;
+    step(channel, drop_caches)?;
+    let read = step(
+        channel,
+        &format!("dd if={dir}/seq of=/dev/null bs=1M 2>/dev/null"),
+    )?
+    .0;
+    let create = step(
+        channel,
+        &format!(
+            "i=0; while [ $i -lt {SMALL_FILES} ]; do \
+             head -c {SMALL_FILE_BYTES} /dev/zero > {dir}/small/$i || exit 1; i=$((i+1)); done"
+        ),
+    )?
+    .0;
+    step(channel, drop_caches)?;
+    let read_small = step(channel, &format!("cat {dir}/small/* > /dev/null"))?.0;
+    let delete = step(channel, &format!("rm -rf {dir}/small"))?.0;
+    Ok([write, read, create, read_small, delete])
+}
+
+/// Run `cmd`, failing on a non-zero exit; returns its wall time and stdout.
+fn step(channel: &ExecChannel, cmd: &str) -> Result<(Duration, String), VMError> {
+    let started = Instant::now();
+    let out = channel.exec(cmd, STEP_TIMEOUT)?;
+    let elapsed = started.elapsed();
+    if out.exit_code != 0 || out.timed_out {
+        return Err(VMError::CommandFailed(format!(
+            "benchmark step `{}` failed ({}{}): {}",
+            cmd,
+            out.exit_code,
+            if out.timed_out { ", timed out" } else { "" },
+            out.stderr.trim()
+        )));
+    }
+    Ok((elapsed, out.stdout))
+}
+
+/// Filesystem type of the mount containing `path`: the longest mount point that
+/// is `path` or one of its ancestors.
+fn share_fstype(mounts: &str, path: &str) -> Option<String> {
+    let path = path.trim_end_matches('/');
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, point, fstype) = (fields.next()?, fields.next()?, fields.next()?);
+            Some((point.replace("\\040", " "), fstype))
+        })
+        .filter(|(point, _)| {
+            let point = point.trim_end_matches('/');
+            path == point
+                || path
+                    .strip_prefix(point)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .max_by_key(|(point, _)| point.len())
+        .map(|(_, fstype)| fstype.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_fstype_picks_the_innermost_mount() {
+        let mounts = "/dev/vda / ext4 rw 0 0\n\
+                      workspace /workspace virtiofs rw 0 0\n\
+                      drvfs /mnt/c 9p rw 0 0\n\
+                      tmpfs /workspace-tmp tmpfs rw 0 0\n";
+        assert_eq!(
+            share_fstype(mounts, "/workspace").as_deref(),
+            Some("virtiofs")
+        );
+        assert_eq!(
+            share_fstype(mounts, "/workspace/").as_deref(),
+            Some("virtiofs")
+        );
+        assert_eq!(
+            share_fstype(mounts, "/mnt/c/Users/me/ws").as_deref(),
+            Some("9p")
+        );
+        assert_eq!(share_fstype(mounts, "/workspacex").as_deref(), Some("ext4"));
+        assert_eq!(share_fstype("", "/workspace"), None);
+    }
+}
@@ -0,0 +1,284 @@
+//! Boot phases read off the guest's serial console.
+//!
+//! While the VM boots, the console log is tailed and matched against an ordered
+//! list of markers (bootloader, kernel, init, network, sandbox server). Each
+//! phase is reported once, as it is first seen, and a phase the console sits in
+//! for too long is reported again as stalled, so a slow or failed boot says
+//! where it stopped rather than only that health timed out. The markers can be
+//! replaced with `ORCABOT_VM_BOOT_MARKERS`.
+
+use regex::Regex;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// No new phase for this long → the current one is reported as stalled.
+pub const STALL_AFTER: Duration = Duration::from_secs(30);
+
+/// Phase name and the console line pattern that marks its start.
+const DEFAULT_MARKERS: &[(&str, &str)] = &[
+    // QEMU's firmware; direct kernel boot on VZ has no bootloader output.
+    ("bootloader", r"SeaBIOS|Booting from ROM|EFI stub:"),
+    ("kernel", r"Linux version \d"),
+    ("init", r"Run \S+ as init process"),
+    ("network", r"udhcpc: lease of|DHCPACK|bound to \d+\.\d+\.\d+\.\d+"),
+    ("server", r"Starting server on :\d+"),
+];
+
+pub struct BootMarker {
+    pub phase: String,
+    pattern: Regex,
+}
+
+#[derive(serde::Deserialize)]
+struct MarkerSpec {
+    phase: String,
+    pattern: String,
+}
+
+/// A phase reached (or stalled in), sent to the frontend as `vm-boot-phase`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BootPhaseEvent {
+    pub phase: String,
+    /// Position among the markers, from 0, and how many there are.
+    pub index: usize,
+    pub total: usize,
+    /// Since the VM process started.
+    pub elapsed_ms: u64,
+    /// The console line that matched (trimmed, at most 200 characters); for a
+    /// stall, the line that started the phase.
+    pub line: String,
+    /// No later phase has been seen for [`STALL_AFTER`].
+    pub stalled: bool,
+}
+
+pub fn default_markers() -> Vec<BootMarker> {
+    DEFAULT_MARKERS
+        .iter()
+        .map(|(phase, pattern)| BootMarker {
+            phase: phase.to_string(),
+            pattern: Regex::new(pattern).expect("default boot marker"),
+        })
+        .collect()
+}
+
+/// Parse `[{"phase": "...", "pattern": "<regex>"}, ...]`, in boot order.
+pub fn parse_markers(json: &str) -> Result<Vec<BootMarker>, String> {
+    let specs: Vec<MarkerSpec> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if specs.is_empty() {
+        return Err("no markers".to_string());
+    }
+    specs
+        .into_iter()
+        .map(|spec| {
+            let pattern =
+                Regex::new(&spec.pattern).map_err(|e| format!("phase {:?}: {}", spec.phase, e))?;
+            Ok(BootMarker {
+                phase: spec.phase,
+                pattern,
+            })
+        })
+        .collect()
+}
+
+/// `ORCABOT_VM_BOOT_MARKERS` (see [`parse_markers`]) or the defaults.
+pub fn markers_from_env() -> Vec<BootMarker> {
+    match std::env::var("ORCABOT_VM_BOOT_MARKERS") {
+        Ok(json) if !json.trim().is_empty() => parse_markers(&json).unwrap_or_else(|e| {
+            eprintln!("[vm] ignoring ORCABOT_VM_BOOT_MARKERS ({e}); using the default markers");
+            default_markers()
+        }),
+        _ => default_markers(),
+    }
+}
+
+/// Matches console lines against the markers in order. A later marker may match
+/// before an earlier one (a phase without console output is skipped), but never
+/// the other way round, so stray output can't move the boot backwards.
+pub struct BootPhaseTracker {
+    markers: Vec<BootMarker>,
+    next: usize,
+    started: Instant,
+    /// The last phase seen: its index, when, its line, and whether its stall
+    /// was already reported.
+    current: Option<(usize, Instant, String, bool)>,
+}
+
+impl BootPhaseTracker {
+    pub fn new(markers: Vec<BootMarker>, started: Instant) -> Self {
+        Self {
+            markers,
+            next: 0,
+            started,
+            current: None,
+        }
+    }
+
+    /// The newest phase `line` reaches, if any.
+    pub fn feed(&mut self, line: &str) -> Option<BootPhaseEvent> {
+        let offset = self.markers[self.next..]
+            .iter()
+            .position(|m| m.pattern.is_match(line))?;
+        let index = self.next + offset;
+        self.next = index + 1;
+        let line: String = line.trim().chars().take(200).collect();
+        self.current = Some((index, Instant::now(), line.clone(), false));
+        Some(self.event(index, line, false))
+    }
+
+    /// The current phase, once, after [`STALL_AFTER`] (`stall_after` in tests)
+    /// without a later one. Nothing stalls after the last marker.
+    pub fn check_stall(&mut self, stall_after: Duration) -> Option<BootPhaseEvent> {
+        let total = self.markers.len();
+        let (index, since, line, reported) = self.current.as_mut()?;
+        if *reported || *index + 1 == total || since.elapsed() < stall_after {
+            return None;
+        }
+        *reported = true;
+        let (index, line) = (*index, line.clone());
+        Some(self.event(index, line, true))
+    }
+
+    /// The last phase seen, for error messages.
+    pub fn current_phase(&self) -> Option<&str> {
+        let (index, ..) = self.current.as_ref()?;
+        Some(&self.markers[*index].phase)
+    }
+
+    fn event(&self, index: usize, line: String, stalled: bool) -> BootPhaseEvent {
+        BootPhaseEvent {
+            phase: self.markers[index].phase.clone(),
+            index,
+            total: self.markers.len(),
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            line,
+            stalled,
+        }
+    }
+}
+
+/// Tails a console log on a background thread until dropped.
+pub struct BootPhaseWatch {
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<Option<String>>>,
+}
+
+impl BootPhaseWatch {
+    /// Start tailing `console_log` from its beginning (backends start a fresh
+    /// log per boot), calling `on_event` for every phase and stall.
+    pub fn start(
+        console_log: PathBuf,
+        markers: Vec<BootMarker>,
+        started: Instant,
+        mut on_event: impl FnMut(BootPhaseEvent) + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            let mut tracker = BootPhaseTracker::new(markers, started);
+            let mut offset = 0u64;
+            let mut partial = Vec::new();
+            while !flag.load(Ordering::SeqCst) {
+                for line in read_new_lines(&console_log, &mut offset, &mut partial) {
+                    if let Some(event) = tracker.feed(&line) {
+                        on_event(event);
+                    }
+                }
+                if let Some(event) = tracker.check_stall(STALL_AFTER) {
+                    on_event(event);
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            tracker.current_phase().map(str::to_string)
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop tailing; returns the last phase seen.
+    pub fn finish(mut self) -> Option<String> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.take()?.join().ok().flatten()
+    }
+}
+
+impl Drop for BootPhaseWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Complete lines appended to `path` since `offset`, keeping an unterminated
+/// tail in `partial`. A file that shrank was recreated: start over.
+fn read_new_lines(path: &std::path::Path, offset: &mut u64, partial: &mut Vec<u8>) -> Vec<String> {
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    if len < *offset {
+        *offset = 0;
+        partial.clear();
+    }
+    let mut buf = Vec::new();
+    if file.seek(SeekFrom::Start(*offset)).is_err() || file.read_to_end(&mut buf).is_err() {
+        return Vec::new();
+    }
+    *offset += buf.len() as u64;
+    partial.extend_from_slice(&buf);
+    let Some(end) = partial.iter().rposition(|&b| b == b'\n') else {
+        return Vec::new();
+    };
+    let complete: Vec<u8> = partial.drain(..=end).collect();
+    String::from_utf8_lossy(&complete)
+        .lines()
+        .map(|l| l.trim_end_matches('\r').to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_advance_in_order_and_stall_once() {
+        let mut tracker = BootPhaseTracker::new(default_markers(), Instant::now());
+        assert!(tracker.feed("random noise").is_none());
+        // VZ direct boot: no bootloader output, so the kernel comes first.
+        let kernel = tracker
+            .feed("[    0.000000] Linux version 6.1.0 (gcc 12)")
+            .unwrap();
+        assert_eq!((kernel.phase.as_str(), kernel.index), ("kernel", 1));
+        // An earlier phase's marker can't move the boot backwards.
+        assert!(tracker.feed("SeaBIOS (version 1.16)").is_none());
+        assert_eq!(tracker.feed("Run /init as init process").unwrap().phase, "init");
+
+        let stall = tracker.check_stall(Duration::ZERO).unwrap();
+        assert!(stall.stalled);
+        assert_eq!(stall.phase, "init");
+        assert!(tracker.check_stall(Duration::ZERO).is_none());
+
+        let server = tracker.feed("2026/01/01 Starting server on :8080").unwrap();
+        assert_eq!(server.index + 1, server.total);
+        assert!(tracker.check_stall(Duration::ZERO).is_none());
+        assert_eq!(tracker.current_phase(), Some("server"));
+    }
+
+    #[test]
+    fn tail_keeps_partial_lines_and_restarts_on_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("console.log");
+        let (mut offset, mut partial) = (0, Vec::new());
+        std::fs::write(&log, "one\r\ntw").unwrap();
+        assert_eq!(read_new_lines(&log, &mut offset, &mut partial), ["one"]);
+        std::fs::write(&log, "one\r\ntwo\nthr").unwrap();
+        assert_eq!(read_new_lines(&log, &mut offset, &mut partial), ["two"]);
+        std::fs::write(&log, "new\n").unwrap();
+        assert_eq!(read_new_lines(&log, &mut offset, &mut partial), ["new"]);
+    }
+}
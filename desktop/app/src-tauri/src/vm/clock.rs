@@ -0,0 +1,118 @@
+//! Host/guest clock skew.
+//!
+//! A guest clock that drifts (slowly, or in one jump after the host sleeps)
+//! breaks TLS validity checks and token expiry inside the sandbox. The host
+//! reads the guest clock over the exec channel, compares it with its own, and
+//! can set the guest clock back to host time.
+
+use super::{ExecChannel, VMError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A skew measurement (or the warning emitted for one), sent to the frontend as
+/// `guest-clock-skew`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClockSkew {
+    /// Guest time minus host time; positive = guest ahead.
+    pub skew_secs: f64,
+    pub threshold_secs: f64,
+    /// Whether the guest clock was reset to host time afterwards.
+    pub synced: bool,
+}
+
+/// Skew checks configured from the environment: `ORCABOT_CLOCK_SKEW_SECS`
+/// (warning threshold, default 5; 0 disables the checks) and
+/// `ORCABOT_CLOCK_AUTOSYNC=1` (reset the guest clock when over the threshold).
+#[derive(Debug, Clone, Copy)]
+pub struct SkewPolicy {
+    pub threshold: Duration,
+    pub auto_sync: bool,
+    pub interval: Duration,
+}
+
+impl SkewPolicy {
+    pub fn from_env() -> Option<Self> {
+        let default = Duration::from_secs(5);
+        // try_from_secs_f64 refuses NaN, negative and out-of-range values,
+        // which from_secs_f64 would panic on.
+        let threshold = match std::env::var("ORCABOT_CLOCK_SKEW_SECS") {
+            Ok(v) => v
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                .unwrap_or_else(|| {
+                    eprintln!("[vm] ignoring ORCABOT_CLOCK_SKEW_SECS={v:?} (want seconds)");
+                    default
+                }),
+            Err(_) => default,
+        };
+        if threshold.is_zero() {
+            return None;
+        }
+        Some(Self {
+            threshold,
+            auto_sync: std::env::var("ORCABOT_CLOCK_AUTOSYNC").is_ok_and(|v| v == "1"),
+            interval: Duration::from_secs(5 * 60),
+        })
+    }
+}
+
+fn host_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Guest time minus host time, in seconds. The host reference is the midpoint
+/// of the exec round-trip, so channel latency mostly cancels out.
+pub fn measure_skew(channel: &ExecChannel) -> Result<f64, VMError> {
+    let before = host_now();
+    let out = channel.exec("date +%s.%N", EXEC_TIMEOUT)?;
+    let after = host_now();
+    // busybox `date` prints a literal "%N"; fall back to whole seconds.
+    let stamp = out.stdout.trim().trim_end_matches(".%N").trim_end_matches("N");
+    let guest: f64 = stamp.parse().map_err(|_| {
+        VMError::GuestExec(format!("unexpected guest date output: {:?}", out.stdout.trim()))
+    })?;
+    Ok(guest - (before + after) / 2.0)
+}
+
+/// Set the guest clock to host time (whole seconds, which is well inside any
+/// useful threshold).
+pub fn sync_to_host(channel: &ExecChannel) -> Result<(), VMError> {
+    let now = host_now().round() as u64;
+    let out = channel.exec(&format!("date -u -s @{} >/dev/null", now), EXEC_TIMEOUT)?;
+    if out.exit_code != 0 {
+        return Err(VMError::GuestExec(format!(
+            "setting the guest clock failed ({}): {}",
+            out.exit_code,
+            out.stderr.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Measure once and, past `policy.threshold`, optionally sync. Returns the
+/// measurement only when it is over the threshold.
+pub fn check(channel: &ExecChannel, policy: &SkewPolicy) -> Result<Option<ClockSkew>, VMError> {
+    let skew = measure_skew(channel)?;
+    if skew.abs() < policy.threshold.as_secs_f64() {
+        return Ok(None);
+    }
+    let synced = policy.auto_sync
+        && match sync_to_host(channel) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("[vm] clock sync failed: {}", e);
+                false
+            }
+        };
+    Ok(Some(ClockSkew {
+        skew_secs: skew,
+        threshold_secs: policy.threshold.as_secs_f64(),
+        synced,
+    }))
+}
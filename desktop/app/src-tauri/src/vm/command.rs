@@ -0,0 +1,239 @@
+//! Running host tools (`which`, `codesign`, `wsl`, `sw_vers`, ...) with a
+//! timeout and captured output.
+//!
+//! The backends used to call `Command::output()` inline, which blocks forever
+//! on a wedged tool and reports failures without saying which command failed.
+//! [`run_command`] bounds the wait and puts the command line and its stderr in
+//! the error.
+
+use super::VMError;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Timeout for quick probes (`which`, `kill -0`, `sw_vers`, ...), which should
+/// answer in milliseconds.
+pub const TOOL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Output of a finished (or timed-out) host command.
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    /// Exit code; `None` when the process was killed by a signal or the timeout.
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// `bin arg1 arg2`, for error messages.
+fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|a| a.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
+
+/// A job object holding a spawned tool, so a timeout can end everything it
+/// started too. Windows has no process groups to signal; this is the
+/// equivalent.
+#[cfg(windows)]
+struct Job(Handle);
+
+#[cfg(windows)]
+type Handle = *mut std::ffi::c_void;
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn CreateJobObjectW(attributes: *const std::ffi::c_void, name: *const u16) -> Handle;
+    fn AssignProcessToJobObject(job: Handle, process: Handle) -> i32;
+    fn TerminateJobObject(job: Handle, exit_code: u32) -> i32;
+    fn CloseHandle(handle: Handle) -> i32;
+}
+
+#[cfg(windows)]
+impl Job {
+    /// A new job holding `child`, or `None` if that fails (a timeout then
+    /// kills `child` alone). Processes it starts from here on join the job;
+    /// any it started before being assigned don't.
+    fn assign(child: &std::process::Child) -> Option<Job> {
+        use std::os::windows::io::AsRawHandle;
+        // SAFETY: an unnamed job with default security; `child`'s handle stays
+        // open while it's borrowed.
+        unsafe {
+            let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if handle.is_null() {
+                return None;
+            }
+            let job = Job(handle);
+            (AssignProcessToJobObject(job.0, child.as_raw_handle()) != 0).then_some(job)
+        }
+    }
+
+    fn terminate(&self) {
+        // SAFETY: `self.0` is a job handle we own.
+        unsafe { TerminateJobObject(self.0, 1) };
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Job {
+    fn drop(&mut self) {
+        // SAFETY: closed once, here. Without KILL_ON_JOB_CLOSE this leaves the
+        // processes in the job running.
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Run `cmd` to completion or until `timeout`, capturing stdout and stderr. On
+/// timeout the tool is killed along with whatever it started: it runs in its
+/// own process group (a job object on Windows), so a helper it forked can't
+/// keep running, or hold the output pipes open, after it's gone. Only a
+/// failure to spawn is an error, as `VMError::Io` with the original
+/// `ErrorKind` (so callers can still tell `NotFound`) and the command line in
+/// the message; a non-zero exit is left to the caller.
+pub fn capture(cmd: &mut Command, timeout: Duration) -> Result<CommandOutput, VMError> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", command_line(cmd), e)))?;
+    #[cfg(windows)]
+    let job = Job::assign(&child);
+
+    // Drain both pipes on their own threads so a chatty tool can't fill one and
+    // block before it exits.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let (status, timed_out) = loop {
+        if let Some(status) = child.try_wait()? {
+            break (Some(status), false);
+        }
+        if Instant::now() >= deadline {
+            // The group's id is the child's pid (`process_group(0)`).
+            #[cfg(unix)]
+            if let Ok(pgid) = libc::pid_t::try_from(child.id()) {
+                // SAFETY: kill has no memory-safety preconditions.
+                unsafe { libc::kill(-pgid, libc::SIGKILL) };
+            }
+            #[cfg(windows)]
+            if let Some(job) = &job {
+                job.terminate();
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+            break (None, true);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    Ok(CommandOutput {
+        code: status.and_then(|s| s.code()),
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+        timed_out,
+    })
+}
+
+/// Run `bin args...` with a timeout. `Err(CommandFailed)` naming the command
+/// line (with its stderr, or stdout when stderr is empty) if it times out or
+/// exits non-zero; spawn failures as in [`capture`].
+pub fn run_command<S: AsRef<OsStr>>(
+    bin: &str,
+    args: &[S],
+    timeout: Duration,
+) -> Result<CommandOutput, VMError> {
+    let mut cmd = Command::new(bin);
+    cmd.args(args);
+    let output = capture(&mut cmd, timeout)?;
+    if output.timed_out {
+        return Err(VMError::CommandFailed(format!(
+            "`{}` timed out after {}s",
+            command_line(&cmd),
+            timeout.as_secs()
+        )));
+    }
+    if !output.success() {
+        let status = output
+            .code
+            .map(|c| format!("exit code {}", c))
+            .unwrap_or_else(|| "killed by a signal".to_string());
+        // Some tools (wsl.exe among them) report errors on stdout.
+        let detail = match output.stderr.trim() {
+            "" => output.stdout.trim(),
+            stderr => stderr,
+        };
+        return Err(VMError::CommandFailed(format!(
+            "`{}` failed ({}){}{}",
+            command_line(&cmd),
+            status,
+            if detail.is_empty() { "" } else { ": " },
+            detail
+        )));
+    }
+    Ok(output)
+}
+
+/// Whether `binary` is on PATH (`which`).
+#[cfg(unix)]
+pub fn on_path(binary: &str) -> bool {
+    run_command("which", &[binary], TOOL_TIMEOUT).is_ok()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_command_reports_command_line_stderr_and_timeout() {
+        let out = run_command("sh", &["-c", "echo hi"], TOOL_TIMEOUT).unwrap();
+        assert_eq!(out.stdout, "hi\n");
+
+        let err = run_command("sh", &["-c", "echo oops >&2; exit 3"], TOOL_TIMEOUT)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`sh -c echo oops >&2; exit 3`"), "{err}");
+        assert!(err.contains("exit code 3") && err.ends_with("oops"), "{err}");
+
+        let err = run_command("sleep", &["5"], Duration::from_millis(200)).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+
+        // A timeout takes the tool's children with it: otherwise this
+        // background sleep would hold stdout open and capture wouldn't return.
+        let started = Instant::now();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 5 & wait"]);
+        let out = capture(&mut cmd, Duration::from_millis(200)).unwrap();
+        assert!(out.timed_out);
+        let elapsed = started.elapsed();
+        assert!(elapsed < Duration::from_secs(4), "{elapsed:?}");
+
+        match run_command::<&str>("orcabot-no-such-tool", &[], TOOL_TIMEOUT) {
+            Err(VMError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            other => panic!("expected NotFound, got {other:?}"),
+        }
+    }
+}
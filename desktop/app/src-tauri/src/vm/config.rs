@@ -1,8 +1,11 @@
+use super::VMError;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-/// Configuration for starting a virtual machine.
-#[derive(Debug, Clone)]
+/// Configuration for starting a virtual machine. Serializes with secret-looking
+/// `env` values redacted (see [`is_secret_env`]), so the JSON is safe to attach
+/// to a bug report.
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct VMConfig {
     /// Path to the VM image (disk image, rootfs tarball, or kernel depending on platform)
     pub image_path: PathBuf,
@@ -13,6 +16,11 @@ pub struct VMConfig {
     /// Number of vCPUs (minimum 2 recommended)
     pub cpus: u32,
 
+    /// Optional `(sockets, cores, threads)` layout of `cpus` for the QEMU
+    /// backends (`-smp sockets=,cores=,threads=`). The product must equal `cpus`.
+    /// `None` = a flat count. VZ and WSL can't express a topology and ignore it.
+    pub cpu_topology: Option<(u32, u32, u32)>,
+
     /// Memory in bytes (minimum 2GB recommended)
     pub memory_bytes: u64,
 
@@ -25,6 +33,7 @@ pub struct VMConfig {
     pub controlplane_host_port: u16,
 
     /// Environment variables to pass to sandbox process inside VM
+    #[serde(serialize_with = "serialize_redacted_env")]
     pub env: HashMap<String, String>,
 
     /// Optional path to kernel image (required for macOS Virtualization.framework)
@@ -33,11 +42,197 @@ pub struct VMConfig {
     /// Optional path to initrd image (required for macOS Virtualization.framework)
     pub initrd_path: Option<PathBuf>,
 
-    /// Optional kernel command line arguments
+    /// Optional kernel command line arguments. Leave out `console=`: each backend
+    /// adds the console matching the device it actually boots with (see
+    /// [`VMConfig::cmdline`]); an explicit `console=` here overrides that.
     pub kernel_cmdline: Option<String>,
 
     /// Optional path to vz-helper binary (macOS Virtualization.framework)
     pub vz_helper_path: Option<PathBuf>,
+
+    /// Upstream DNS servers (IP addresses) the guest should resolve through, for
+    /// networks where the default resolver can't see internal hostnames. Empty =
+    /// the backend default: QEMU SLIRP's built-in forwarder (host resolver) or the
+    /// VZ NAT's DHCP-provided server. Delivered as `orcabot.dns=` on the kernel
+    /// cmdline (see [`VMConfig::cmdline`]), which the guest init writes to
+    /// resolv.conf after its DHCP lease. QEMU's own `-netdev user,dns=` can't do
+    /// this: it only moves the built-in forwarder's guest-visible address. WSL
+    /// ignores it (the distro uses WSL's generated resolv.conf).
+    pub dns_servers: Vec<String>,
+
+    /// Optional host shell script run once as root in the guest after the first
+    /// healthy boot of a disk (see `vm::provision`). Not read by the backends;
+    /// the caller runs it over the VM's exec channel.
+    pub provision_script: Option<PathBuf>,
+
+    /// Optional shell command run in the guest once the health check passes
+    /// (e.g. waiting on an app migration); the VM only counts as up when it
+    /// exits 0. Retried until the health timeout runs out. Not read by the
+    /// backends; the caller runs it over the VM's exec channel.
+    pub readiness_command: Option<String>,
+
+    /// UID/GID the guest init runs the sandbox server as (usually the host
+    /// user's), so files it writes into the shared workspace aren't root-owned
+    /// and imported files are owned by the server inside the guest. `None` =
+    /// root. Delivered as `orcabot.uid=`/`orcabot.gid=` on the kernel cmdline
+    /// (the init drops privileges with `setpriv`); WSL wraps the server command
+    /// instead. On VZ and QEMU, guest commands over debug-exec then run as this
+    /// user too, so nothing that needs root can use the exec channel: `validate`
+    /// refuses a `provision_script` and the clock watcher doesn't sync (see
+    /// [`VMConfig::exec_runs_as_root`]).
+    pub guest_uid: Option<u32>,
+    pub guest_gid: Option<u32>,
+
+    /// Attach a virtio RNG so the guest kernel's entropy pool is seeded at boot
+    /// (without one, early TLS handshakes stall waiting on `/dev/random`). QEMU
+    /// feeds it from the host's `/dev/urandom`; VZ's entropy device from the
+    /// macOS kernel CSPRNG. WSL ignores it (the WSL kernel has its own source).
+    pub enable_rng: bool,
+
+    /// How the guest gets the workspace: the backend's local share by default,
+    /// or a network export for a VM on a remote QEMU host (see [`WorkspaceShare`]).
+    pub workspace_share: WorkspaceShare,
+
+    /// CPU acceleration for Linux QEMU (see [`QemuAccel`]). Other backends
+    /// ignore it.
+    pub qemu_accel: QemuAccel,
+
+    /// Path the sandbox health probe GETs (the boot wait, the watchdog and
+    /// `aggregate_health`). Defaults to [`DEFAULT_HEALTH_PATH`].
+    pub health_path: String,
+
+    /// Status the health probe must answer with; `None` = any 2xx.
+    pub health_expect_status: Option<u16>,
+}
+
+/// Where the guest sandbox server answers its health check.
+pub const DEFAULT_HEALTH_PATH: &str = "/health";
+
+/// How Linux QEMU runs guest code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QemuAccel {
+    /// KVM when `/dev/kvm` exists, else TCG with a warning.
+    #[default]
+    Auto,
+    /// KVM or refuse to start: TCG is too slow to be worth booting by accident.
+    Kvm,
+    /// Software emulation even where KVM works, for compatibility testing.
+    Tcg,
+}
+
+impl QemuAccel {
+    /// Parse `auto`, `kvm` or `tcg` (`set_qemu_accel`).
+    pub fn parse(s: &str) -> Result<Self, VMError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(QemuAccel::Auto),
+            "kvm" => Ok(QemuAccel::Kvm),
+            "tcg" => Ok(QemuAccel::Tcg),
+            other => Err(VMError::InvalidConfig(format!(
+                "QEMU accel mode {:?}: want auto, kvm or tcg",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QemuAccel::Auto => "auto",
+            QemuAccel::Kvm => "kvm",
+            QemuAccel::Tcg => "tcg",
+        }
+    }
+}
+
+/// File-sharing protocol of a [`WorkspaceShare::Network`] export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkShareProtocol {
+    Nfs,
+    Smb,
+}
+
+impl NetworkShareProtocol {
+    /// Name used on the kernel cmdline and in `ORCABOT_WORKSPACE_SHARE`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NetworkShareProtocol::Nfs => "nfs",
+            NetworkShareProtocol::Smb => "smb",
+        }
+    }
+}
+
+/// Where the guest's `/workspace` comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkspaceShare {
+    /// The backend's own share of `workspace_path`: virtiofs (or 9p) on QEMU,
+    /// virtio-fs on VZ, the `/mnt` drive on WSL.
+    #[default]
+    Local,
+    /// The guest mounts `export` over the network once it has a DHCP lease: NFS
+    /// as `host:/path`, SMB as `//host/share` (guest access, no credentials).
+    /// Exporting the workspace is the host's own NFS/SMB server configuration;
+    /// the app doesn't set it up. Linux QEMU only. Delivered as
+    /// `orcabot.share=<protocol>:<export>` on the kernel cmdline.
+    Network {
+        protocol: NetworkShareProtocol,
+        export: String,
+    },
+}
+
+impl WorkspaceShare {
+    /// Parse `nfs:host:/path` or `smb://host/share` (`ORCABOT_WORKSPACE_SHARE`).
+    pub fn parse(s: &str) -> Result<Self, VMError> {
+        let s = s.trim();
+        let share = if let Some(export) = s.strip_prefix("nfs:") {
+            WorkspaceShare::Network {
+                protocol: NetworkShareProtocol::Nfs,
+                export: export.to_string(),
+            }
+        } else if let Some(rest) = s.strip_prefix("smb:") {
+            WorkspaceShare::Network {
+                protocol: NetworkShareProtocol::Smb,
+                export: rest.to_string(),
+            }
+        } else {
+            return Err(VMError::InvalidConfig(format!(
+                "workspace share {:?}: want nfs:HOST:/PATH or smb://HOST/SHARE",
+                s
+            )));
+        };
+        share.check()?;
+        Ok(share)
+    }
+
+    /// Reject an export the guest init couldn't mount, or that could smuggle
+    /// extra kernel arguments (whitespace) or mount options (commas) in.
+    fn check(&self) -> Result<(), VMError> {
+        let WorkspaceShare::Network { protocol, export } = self else {
+            return Ok(());
+        };
+        let well_formed = match protocol {
+            NetworkShareProtocol::Nfs => export
+                .split_once(":/")
+                .is_some_and(|(host, _)| !host.is_empty() && !host.contains('/')),
+            NetworkShareProtocol::Smb => export
+                .strip_prefix("//")
+                .and_then(|rest| rest.split_once('/'))
+                .is_some_and(|(host, share)| !host.is_empty() && !share.is_empty()),
+        };
+        if !well_formed || export.chars().any(|c| c.is_whitespace() || c == ',' || c == '"') {
+            return Err(VMError::InvalidConfig(format!(
+                "{} export {:?}: want {}",
+                protocol.as_str(),
+                export,
+                match protocol {
+                    NetworkShareProtocol::Nfs => "HOST:/PATH",
+                    NetworkShareProtocol::Smb => "//HOST/SHARE",
+                }
+            )));
+        }
+        Ok(())
+    }
 }
 
 impl VMConfig {
@@ -47,6 +242,7 @@ impl VMConfig {
             image_path,
             workspace_path,
             cpus: 2,
+            cpu_topology: None,
             memory_bytes: 2 * 1024 * 1024 * 1024, // 2GB
             sandbox_port: 8080,
             controlplane_host_port: 8787,
@@ -55,6 +251,16 @@ impl VMConfig {
             initrd_path: None,
             kernel_cmdline: None,
             vz_helper_path: None,
+            dns_servers: Vec::new(),
+            provision_script: None,
+            readiness_command: None,
+            guest_uid: None,
+            guest_gid: None,
+            enable_rng: true,
+            workspace_share: WorkspaceShare::Local,
+            qemu_accel: QemuAccel::Auto,
+            health_path: DEFAULT_HEALTH_PATH.to_string(),
+            health_expect_status: None,
         }
     }
 
@@ -64,13 +270,40 @@ impl VMConfig {
         self
     }
 
+    /// Set the CPU topology; `cpus` becomes sockets × cores × threads.
+    pub fn with_cpu_topology(mut self, sockets: u32, cores: u32, threads: u32) -> Self {
+        self.cpu_topology = Some((sockets, cores, threads));
+        self.cpus = sockets.saturating_mul(cores).saturating_mul(threads);
+        self
+    }
+
+    /// QEMU `-smp` value: the flat count, or the count plus the topology when one
+    /// is set. Errors if a topology has a zero entry or doesn't multiply to `cpus`.
+    pub fn qemu_smp(&self) -> Result<String, VMError> {
+        let Some((sockets, cores, threads)) = self.cpu_topology else {
+            return Ok(self.cpus.to_string());
+        };
+        if cpu_topology_total(sockets, cores, threads) != Some(self.cpus) {
+            return Err(VMError::InvalidConfig(format!(
+                "CPU topology {}x{}x{} (sockets x cores x threads) doesn't match {} vCPUs",
+                sockets, cores, threads, self.cpus
+            )));
+        }
+        Ok(format!(
+            "{},sockets={},cores={},threads={}",
+            self.cpus, sockets, cores, threads
+        ))
+    }
+
     /// Set memory in bytes.
     pub fn with_memory(mut self, bytes: u64) -> Self {
         self.memory_bytes = bytes;
         self
     }
 
-    /// Set the sandbox port.
+    /// Set the host port the sandbox is forwarded to. Must be non-zero (it's a
+    /// fixed forward, not "any free port"); ports below 1024 are privileged on
+    /// Linux and [`VMConfig::validate`] warns about them.
     pub fn with_port(mut self, port: u16) -> Self {
         self.sandbox_port = port;
         self
@@ -112,18 +345,269 @@ impl VMConfig {
         self
     }
 
+    /// Add an upstream DNS server for the guest (see `dns_servers`).
+    pub fn with_dns(mut self, server: impl Into<String>) -> Self {
+        self.dns_servers.push(server.into());
+        self
+    }
+
+    /// Set the one-time guest provisioning script.
+    pub fn with_provision_script(mut self, path: PathBuf) -> Self {
+        self.provision_script = Some(path);
+        self
+    }
+
+    /// Set the post-health readiness command (see `readiness_command`).
+    pub fn with_readiness_command(mut self, command: impl Into<String>) -> Self {
+        self.readiness_command = Some(command.into());
+        self
+    }
+
+    /// Run the guest sandbox server as `uid`/`gid` (see `guest_uid`).
+    pub fn with_guest_ids(mut self, uid: u32, gid: u32) -> Self {
+        self.guest_uid = Some(uid);
+        self.guest_gid = Some(gid);
+        self
+    }
+
+    /// Attach (or leave out) the guest RNG device (see `enable_rng`).
+    pub fn with_rng(mut self, enabled: bool) -> Self {
+        self.enable_rng = enabled;
+        self
+    }
+
+    /// Set the Linux QEMU acceleration mode (see [`QemuAccel`]).
+    pub fn with_qemu_accel(mut self, accel: QemuAccel) -> Self {
+        self.qemu_accel = accel;
+        self
+    }
+
+    /// Probe `path` instead of [`DEFAULT_HEALTH_PATH`] for sandbox health.
+    pub fn with_health_path(mut self, path: impl Into<String>) -> Self {
+        self.health_path = path.into();
+        self
+    }
+
+    /// Require health probes to answer exactly `status` rather than any 2xx.
+    pub fn with_health_status(mut self, status: u16) -> Self {
+        self.health_expect_status = Some(status);
+        self
+    }
+
+    /// Whether a health probe answering `status` means the sandbox is up.
+    pub fn health_status_ok(&self, status: u16) -> bool {
+        match self.health_expect_status {
+            Some(expected) => status == expected,
+            None => (200..300).contains(&status),
+        }
+    }
+
+    /// Mount the workspace from a network export (see [`WorkspaceShare`]).
+    pub fn with_workspace_share(mut self, share: WorkspaceShare) -> Self {
+        self.workspace_share = share;
+        self
+    }
+
+    /// Err(`UnsupportedPlatform`) for a network workspace share, from backends
+    /// that only do their local share.
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    pub fn require_local_share(&self, backend: &str) -> Result<(), VMError> {
+        match self.workspace_share {
+            WorkspaceShare::Local => Ok(()),
+            WorkspaceShare::Network { protocol, .. } => Err(VMError::UnsupportedPlatform(format!(
+                "{} workspace shares are only supported on Linux QEMU, not {}",
+                protocol.as_str().to_uppercase(),
+                backend
+            ))),
+        }
+    }
+
+    /// QEMU arguments for the virtio RNG, backed by the host's `/dev/urandom`
+    /// (QEMU's default backend reads `/dev/random`, which can block on older
+    /// hosts). Empty when `enable_rng` is off.
+    pub fn qemu_rng_args(&self) -> &'static [&'static str] {
+        if self.enable_rng {
+            &[
+                "-object",
+                "rng-random,id=rng0,filename=/dev/urandom",
+                "-device",
+                "virtio-rng-pci,rng=rng0",
+            ]
+        } else {
+            &[]
+        }
+    }
+
+    /// Kernel command line as passed to the guest by a backend whose console
+    /// device is described by `console` (e.g. `console=ttyS0`): that console,
+    /// then `kernel_cmdline`, then `orcabot.dns=` when DNS servers are set,
+    /// `orcabot.uid=`/`orcabot.gid=` when guest IDs are, and `orcabot.share=` for
+    /// a network workspace share. DNS entries that aren't IP addresses (and
+    /// malformed share exports) are dropped so nothing can smuggle extra kernel
+    /// arguments in.
+    pub fn cmdline(&self, console: &str) -> Option<String> {
+        let dns: Vec<&str> = self
+            .dns_servers
+            .iter()
+            .map(|s| s.trim())
+            .filter(|s| s.parse::<std::net::IpAddr>().is_ok())
+            .collect();
+        let base = self.kernel_cmdline.as_ref()?;
+        let mut cmdline = if base.split_whitespace().any(|a| a.starts_with("console=")) {
+            base.clone()
+        } else {
+            format!("{} {}", console, base)
+        };
+        if !dns.is_empty() {
+            cmdline.push_str(&format!(" orcabot.dns={}", dns.join(",")));
+        }
+        if let Some(uid) = self.guest_uid {
+            cmdline.push_str(&format!(" orcabot.uid={}", uid));
+            cmdline.push_str(&format!(" orcabot.gid={}", self.guest_gid.unwrap_or(uid)));
+        }
+        if let WorkspaceShare::Network { protocol, export } = &self.workspace_share {
+            if self.workspace_share.check().is_ok() {
+                cmdline.push_str(&format!(" orcabot.share={}:{}", protocol.as_str(), export));
+            }
+        }
+        Some(cmdline)
+    }
+
+    /// Reject a config that can't describe a VM — chiefly one built with
+    /// `Default`, whose empty paths would otherwise surface as a baffling
+    /// `ImageNotFound("")`. Backends call this first in `start()`.
+    pub fn validate(&self) -> Result<(), VMError> {
+        let problem = if self.image_path.as_os_str().is_empty() {
+            "image_path is empty"
+        } else if self.workspace_path.as_os_str().is_empty() {
+            "workspace_path is empty"
+        } else if self.cpus == 0 {
+            "cpus is 0"
+        } else if self.memory_bytes == 0 {
+            "memory_bytes is 0"
+        } else if self.sandbox_port == 0 {
+            "sandbox_port is 0 (the forward needs a fixed host port)"
+        } else if !self.health_path.starts_with('/')
+            || self.health_path.chars().any(|c| c.is_whitespace() || c.is_control())
+        {
+            // It goes into the request line verbatim.
+            "health_path must start with / and contain no whitespace"
+        } else if self
+            .health_expect_status
+            .is_some_and(|s| !(100..=599).contains(&s))
+        {
+            "health_expect_status is not an HTTP status (100-599)"
+        } else if self.provision_script.is_some() && !self.exec_runs_as_root() {
+            // It writes /var/lib/orcabot and usually installs packages.
+            "provision_script needs root in the guest, but guest_uid makes debug-exec run as \
+             that user; unset one of them"
+        } else {
+            if let Some(hint) = privileged_port_hint(self.sandbox_port) {
+                eprintln!(
+                    "[vm] WARNING: sandbox_port {} is a privileged port; the forward may fail to bind. {}",
+                    self.sandbox_port, hint
+                );
+            }
+            return self.workspace_share.check();
+        };
+        Err(VMError::InvalidConfig(problem.to_string()))
+    }
+
+    /// Whether the exec channel runs guest commands as root. `/debug/exec` runs
+    /// inside the sandbox server, so it has `guest_uid` when that is set; WSL's
+    /// channel always passes `wsl.exe -u root`.
+    pub fn exec_runs_as_root(&self) -> bool {
+        self.guest_uid.is_none() || cfg!(target_os = "windows")
+    }
+
     /// Memory in megabytes (convenience method).
     pub fn memory_mb(&self) -> u64 {
         self.memory_bytes / (1024 * 1024)
     }
 }
 
+/// How to get a privileged host `port` bound, or `None` when it isn't one. Linux
+/// honours `net.ipv4.ip_unprivileged_port_start` (1024 unless lowered); macOS
+/// (since 10.14) and Windows let any user bind low ports on these addresses.
+fn privileged_port_hint(port: u16) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        let start = std::fs::read_to_string("/proc/sys/net/ipv4/ip_unprivileged_port_start")
+            .ok()
+            .and_then(|s| s.trim().parse::<u32>().ok())
+            .unwrap_or(1024);
+        (u32::from(port) < start).then(|| {
+            "Grant QEMU CAP_NET_BIND_SERVICE (setcap cap_net_bind_service=+ep <qemu binary>) \
+             or use a port of 1024 or above."
+                .to_string()
+        })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = port;
+        None
+    }
+}
+
+/// vCPUs a (sockets, cores, threads) layout adds up to; `None` if an entry is
+/// zero or the product overflows.
+pub fn cpu_topology_total(sockets: u32, cores: u32, threads: u32) -> Option<u32> {
+    if sockets == 0 || cores == 0 || threads == 0 {
+        return None;
+    }
+    sockets.checked_mul(cores)?.checked_mul(threads)
+}
+
+/// Whether an env var's value is a credential, judged by its name (e.g.
+/// `SANDBOX_INTERNAL_TOKEN`, `INTERNAL_API_TOKEN`).
+pub fn is_secret_env(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    ["TOKEN", "SECRET", "PASSWORD", "KEY", "CREDENTIAL"]
+        .iter()
+        .any(|marker| name.contains(marker))
+}
+
+/// `env` sorted by name, with [`is_secret_env`] values replaced by `<redacted>`.
+fn serialize_redacted_env<S: serde::Serializer>(
+    env: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::Serialize;
+    env.iter()
+        .map(|(k, v)| {
+            let v = if is_secret_env(k) { "<redacted>" } else { v.as_str() };
+            (k.as_str(), v)
+        })
+        .collect::<std::collections::BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+/// `path` as UTF-8, for arguments that embed it in a larger string. Errs
+/// instead of passing on a lossy conversion the tool would then fail on with a
+/// confusing message.
+pub fn utf8_path(path: &Path) -> Result<&str, VMError> {
+    path.to_str().ok_or_else(|| {
+        VMError::InvalidConfig(format!("path is not valid UTF-8: {}", path.display()))
+    })
+}
+
+/// `path` as the value of a QEMU `key=value,...` option (`-drive file=`,
+/// `-fsdev path=`, `-chardev path=`/`logfile=`). QEMU splits those on commas
+/// and reads `,,` as a literal comma; spaces and `=` are fine as they are, since
+/// the whole option is a single argument.
+pub fn qemu_opt_path(path: &Path) -> Result<String, VMError> {
+    Ok(utf8_path(path)?.replace(',', ",,"))
+}
+
+/// Empty paths: only useful as a base for struct-update syntax. `start()` rejects
+/// it via [`VMConfig::validate`] until `image_path` and `workspace_path` are set.
 impl Default for VMConfig {
     fn default() -> Self {
         Self {
             image_path: PathBuf::new(),
             workspace_path: PathBuf::new(),
             cpus: 2,
+            cpu_topology: None,
             memory_bytes: 2 * 1024 * 1024 * 1024,
             sandbox_port: 8080,
             controlplane_host_port: 8787,
@@ -132,6 +616,61 @@ impl Default for VMConfig {
             initrd_path: None,
             kernel_cmdline: None,
             vz_helper_path: None,
+            dns_servers: Vec::new(),
+            provision_script: None,
+            readiness_command: None,
+            guest_uid: None,
+            guest_gid: None,
+            enable_rng: true,
+            workspace_share: WorkspaceShare::Local,
+            qemu_accel: QemuAccel::Auto,
+            health_path: DEFAULT_HEALTH_PATH.to_string(),
+            health_expect_status: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialized_config_redacts_secret_env() {
+        let config = VMConfig::new("/img".into(), "/ws".into())
+            .with_env("SANDBOX_INTERNAL_TOKEN", "s3cret")
+            .with_env("WORKSPACE_BASE", "/workspace");
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["env"]["SANDBOX_INTERNAL_TOKEN"], "<redacted>");
+        assert_eq!(json["env"]["WORKSPACE_BASE"], "/workspace");
+        assert!(!json.to_string().contains("s3cret"));
+        assert_eq!(json["workspace_share"], "local");
+    }
+
+    #[test]
+    fn provision_script_needs_a_root_exec_channel() {
+        let config = VMConfig::new("/img".into(), "/ws".into())
+            .with_provision_script("/setup.sh".into());
+        assert!(config.exec_runs_as_root() && config.validate().is_ok());
+        let config = config.with_guest_ids(501, 20);
+        assert_eq!(config.validate().is_ok(), cfg!(target_os = "windows"));
+    }
+
+    #[test]
+    fn health_probe_defaults_to_any_2xx_on_health() {
+        let config = VMConfig::new("/img".into(), "/ws".into());
+        assert_eq!(config.health_path, "/health");
+        assert!(config.health_status_ok(200) && config.health_status_ok(204));
+        assert!(!config.health_status_ok(503));
+
+        let config = config.with_health_path("/healthz").with_health_status(204);
+        assert!(config.validate().is_ok());
+        assert!(config.health_status_ok(204) && !config.health_status_ok(200));
+        for bad in [
+            config.clone().with_health_path("healthz"),
+            config.clone().with_health_path("/health HTTP/1.0\r\nX: y"),
+            config.clone().with_health_status(42),
+        ] {
+            assert!(matches!(bad.validate(), Err(VMError::InvalidConfig(_))));
         }
     }
 }
@@ -1,4 +1,4 @@
-// REVISION: vm-image-ondemand-v1
+// REVISION: vm-error-v8-guest-exec-and-provision
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -8,10 +8,29 @@ pub enum VMError {
     StartFailed(String),
     StopFailed(String),
     HealthTimeout(Duration),
+    /// The VM process died before the sandbox became healthy (e.g. a kernel
+    /// panic), with its exit status.
+    VmExited(String),
     MountFailed(String),
     UnsupportedPlatform(String),
+    /// The `VMConfig` is unusable as given (see `VMConfig::validate`).
+    InvalidConfig(String),
+    /// A host tool (`vm::command::run_command`) exited non-zero or timed out;
+    /// the message names the command line and carries its stderr.
+    CommandFailed(String),
+    /// Running a command in the guest over the exec channel failed, or it
+    /// exited non-zero where success was required.
+    GuestExec(String),
+    /// The `VMConfig::provision_script` run failed (see `vm::provision`).
+    ProvisionFailed(String),
+    /// Pausing or resuming the guest failed, or the backend can't do it.
+    PauseFailed(String),
     /// Fetching/verifying the on-demand VM image failed.
     Download(String),
+    /// A staged VM image doesn't hash to its expected SHA-256 (hex).
+    ChecksumMismatch { expected: String, actual: String },
+    /// Not enough free space to stage the VM resources (bytes).
+    InsufficientDiskSpace { needed: u64, available: u64 },
     Io(std::io::Error),
 }
 
@@ -24,11 +43,28 @@ impl std::fmt::Display for VMError {
             VMError::HealthTimeout(duration) => {
                 write!(f, "VM health check failed after {:?}", duration)
             }
+            VMError::VmExited(msg) => write!(f, "VM process exited during boot: {}", msg),
             VMError::MountFailed(msg) => write!(f, "Shared filesystem mount failed: {}", msg),
             VMError::UnsupportedPlatform(platform) => {
                 write!(f, "Platform not supported: {}", platform)
             }
+            VMError::InvalidConfig(msg) => write!(f, "Invalid VM config: {}", msg),
+            VMError::CommandFailed(msg) => write!(f, "Command failed: {}", msg),
+            VMError::GuestExec(msg) => write!(f, "Guest exec failed: {}", msg),
+            VMError::ProvisionFailed(msg) => write!(f, "Guest provisioning failed: {}", msg),
+            VMError::PauseFailed(msg) => write!(f, "Failed to pause/resume VM: {}", msg),
             VMError::Download(msg) => write!(f, "VM image download failed: {}", msg),
+            VMError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "VM image checksum mismatch: expected SHA-256 {}, got {}",
+                expected, actual
+            ),
+            VMError::InsufficientDiskSpace { needed, available } => write!(
+                f,
+                "Not enough disk space to stage the VM: need about {} MB, {} MB available",
+                needed / (1024 * 1024),
+                available / (1024 * 1024)
+            ),
             VMError::Io(err) => write!(f, "IO error: {}", err),
         }
     }
@@ -0,0 +1,521 @@
+//! Running shell commands inside the guest.
+//!
+//! The VZ and QEMU guests run the sandbox's `POST /debug/exec` endpoint (see
+//! desktop/CLAUDE.md, "Guest console + debug-exec"), authed by a per-boot token
+//! the guest prints only to its root-only console — so the host reads it from
+//! the backend's console log. WSL has no such console; `wsl.exe -d` runs the
+//! command in the distro directly. [`ExecChannel::exec_streaming`] uses the
+//! sandbox's `POST /debug/exec/stream` and a piped `wsl.exe` respectively.
+//!
+//! Commands run as root, except over debug-exec with `VMConfig::guest_uid` set:
+//! the endpoint runs inside the sandbox server, which has dropped to that user
+//! (see [`super::VMConfig::exec_runs_as_root`]).
+//!
+//! An [`ExecChannel`] is a plain owned value (no borrow of the VM), so callers can
+//! run long guest work without holding the `sandbox_vm` lock.
+
+use super::VMError;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Upper bound the sandbox enforces on a single `/debug/exec` call.
+pub const MAX_EXEC_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Largest file [`ExecChannel::write_file`] will push: it streams base64 through
+/// shell commands, which is fine for configs and scripts, not for datasets.
+pub const MAX_WRITE_FILE_BYTES: u64 = 32 * 1024 * 1024;
+
+const WRITE_FILE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often [`ExecChannel::exec_streaming`] checks for cancellation while the
+/// guest command is quiet.
+const STREAM_POLL: Duration = Duration::from_millis(250);
+
+/// Result of a guest command.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExecOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+}
+
+/// Which guest pipe an [`ExecLine`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// One line of output from [`ExecChannel::exec_streaming`], without its newline.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExecLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+/// How to reach a running guest's shell.
+#[derive(Debug, Clone)]
+pub enum ExecChannel {
+    /// The sandbox's `/debug/exec`, with the token read from `console_log`.
+    DebugExec { sandbox_url: String, console_log: PathBuf },
+    /// `wsl.exe -d <distro> -u root -- /bin/sh -c <cmd>`.
+    #[cfg(target_os = "windows")]
+    Wsl { distro: String },
+}
+
+impl ExecChannel {
+    /// Run `cmd` under `/bin/sh -c` as root (see the module docs) in the guest.
+    /// `timeout` is capped at [`MAX_EXEC_TIMEOUT`] for the HTTP channel; WSL
+    /// kills the command at it.
+    pub fn exec(&self, cmd: &str, timeout: Duration) -> Result<ExecOutput, VMError> {
+        match self {
+            ExecChannel::DebugExec {
+                sandbox_url,
+                console_log,
+            } => debug_exec(sandbox_url, console_log, cmd, timeout),
+            #[cfg(target_os = "windows")]
+            ExecChannel::Wsl { distro } => wsl_exec(distro, cmd, timeout),
+        }
+    }
+
+    /// Run `argv` (no shell) as root (see the module docs) in the guest, handing
+    /// each output line to `on_line` as it is produced. Returns the exit code
+    /// (-1 if killed by a signal), or `None` when cancelled — `on_line` returned
+    /// `false`, or `cancelled` returned `true` (it is polled every
+    /// [`STREAM_POLL`], so a command that prints nothing can still be stopped);
+    /// the guest command is killed then. There is no timeout: the command runs
+    /// until it exits or is cancelled.
+    pub fn exec_streaming(
+        &self,
+        argv: &[String],
+        on_line: &mut dyn FnMut(ExecLine) -> bool,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<Option<i32>, VMError> {
+        if argv.first().is_none_or(|a| a.is_empty()) {
+            return Err(VMError::GuestExec("empty argv".to_string()));
+        }
+        match self {
+            ExecChannel::DebugExec {
+                sandbox_url,
+                console_log,
+            } => debug_exec_streaming(sandbox_url, console_log, argv, on_line, cancelled),
+            #[cfg(target_os = "windows")]
+            ExecChannel::Wsl { distro } => wsl_exec_streaming(distro, argv, on_line, cancelled),
+        }
+    }
+
+    /// Base64 bytes per command when streaming a file in. `wsl.exe` arguments
+    /// share Windows' 32K command-line limit, so that channel gets smaller chunks.
+    fn copy_chunk(&self) -> usize {
+        match self {
+            ExecChannel::DebugExec { .. } => 64 * 1024,
+            #[cfg(target_os = "windows")]
+            ExecChannel::Wsl { .. } => 16 * 1024,
+        }
+    }
+
+    /// Write `bytes` to the absolute `guest_path` as root (see the module docs)
+    /// with `mode`, creating parent dirs. The data lands in a temp file next to
+    /// the target and is moved over it at the end, so a failed copy never leaves
+    /// a truncated file.
+    pub fn write_file(&self, guest_path: &str, bytes: &[u8], mode: u32) -> Result<(), VMError> {
+        let path = shell_quote(guest_path);
+        let tmp = shell_quote(&format!("{}.orcabot-put-{}", guest_path, std::process::id()));
+        let b64 = shell_quote(&format!("{}.orcabot-put-{}.b64", guest_path, std::process::id()));
+        self.run_checked(&format!(
+            "mkdir -p \"$(dirname {path})\" && rm -f {tmp} {b64} && : > {b64}"
+        ))?;
+        let encoded = crate::base64_encode(bytes);
+        let result = encoded
+            .as_bytes()
+            .chunks(self.copy_chunk())
+            // Base64 has no shell metacharacters, so single quotes are safe.
+            .try_for_each(|chunk| {
+                self.run_checked(&format!(
+                    "printf '%s' '{}' >> {b64}",
+                    String::from_utf8_lossy(chunk)
+                ))
+            })
+            .and_then(|()| {
+                self.run_checked(&format!(
+                    "base64 -d {b64} > {tmp} && chmod {mode:o} {tmp} && mv -f {tmp} {path}"
+                ))
+            });
+        let _ = self.exec(&format!("rm -f {tmp} {b64}"), WRITE_FILE_TIMEOUT);
+        result
+    }
+
+    fn run_checked(&self, cmd: &str) -> Result<(), VMError> {
+        let out = self.exec(cmd, WRITE_FILE_TIMEOUT)?;
+        if out.exit_code != 0 {
+            return Err(VMError::GuestExec(format!(
+                "command failed ({}): {}",
+                out.exit_code,
+                out.stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Pause between attempts of [`wait_for_readiness`].
+const READINESS_RETRY: Duration = Duration::from_secs(2);
+
+/// Run `cmd` in the guest until it exits 0, retrying until `deadline`. Each
+/// attempt gets the time left (up to [`MAX_EXEC_TIMEOUT`]); the error carries
+/// the last attempt's exit code and stderr.
+pub fn wait_for_readiness(
+    channel: &ExecChannel,
+    cmd: &str,
+    deadline: std::time::Instant,
+) -> Result<(), VMError> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        let last = match channel.exec(cmd, left.max(Duration::from_secs(1))) {
+            Ok(out) if out.exit_code == 0 => return Ok(()),
+            Ok(out) => format!("exit {}: {}", out.exit_code, out.stderr.trim()),
+            Err(e) => e.to_string(),
+        };
+        if std::time::Instant::now() + READINESS_RETRY >= deadline {
+            return Err(VMError::GuestExec(format!(
+                "readiness command {cmd:?} did not succeed after {attempts} attempt(s) (last: {last})"
+            )));
+        }
+        std::thread::sleep(READINESS_RETRY);
+    }
+}
+
+/// Single-quote `s` for `/bin/sh`.
+pub(super) fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// What's running inside the guest, for support and diagnostics. Fields the
+/// backend can't provide are `None`.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct GuestInfo {
+    /// `uname -srm`, e.g. "Linux 6.1.0-18-arm64 aarch64".
+    pub kernel: Option<String>,
+    /// `PRETTY_NAME` from /etc/os-release.
+    pub os_release: Option<String>,
+    /// The sandbox server's revision, from its `/health` response.
+    pub server_revision: Option<String>,
+}
+
+impl GuestInfo {
+    /// Query the guest: kernel and OS over `channel`, server revision over HTTP.
+    pub fn query(channel: Option<&ExecChannel>, sandbox_url: Option<&str>) -> Self {
+        let mut info = GuestInfo::default();
+        if let Some(channel) = channel {
+            let cmd = "uname -srm; sed -n 's/^PRETTY_NAME=//p' /etc/os-release | tr -d '\"'";
+            match channel.exec(cmd, Duration::from_secs(10)) {
+                Ok(out) if out.exit_code == 0 => {
+                    let mut lines = out.stdout.lines().map(str::trim).filter(|l| !l.is_empty());
+                    info.kernel = lines.next().map(str::to_string);
+                    info.os_release = lines.next().map(str::to_string);
+                }
+                Ok(out) => eprintln!("[vm] guest info: exit {}: {}", out.exit_code, out.stderr.trim()),
+                Err(e) => eprintln!("[vm] guest info: {}", e),
+            }
+        }
+        if let Some(url) = sandbox_url {
+            info.server_revision = ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .get(&format!("{}/health", url.trim_end_matches('/')))
+                .call()
+                .ok()
+                .and_then(|r| r.into_json::<serde_json::Value>().ok())
+                .and_then(|j| j.get("revision")?.as_str().map(str::to_string));
+        }
+        info
+    }
+}
+
+fn debug_exec(
+    sandbox_url: &str,
+    console_log: &Path,
+    cmd: &str,
+    timeout: Duration,
+) -> Result<ExecOutput, VMError> {
+    let token = read_debug_token(console_log).ok_or_else(|| {
+        VMError::GuestExec(format!(
+            "no debug-exec token in {} (guest not booted with ORCABOT_DEBUG_EXEC=1?)",
+            console_log.display()
+        ))
+    })?;
+    let timeout = timeout.min(MAX_EXEC_TIMEOUT);
+    let body = serde_json::json!({ "cmd": cmd, "timeout_ms": timeout.as_millis() as u64 });
+    let resp = ureq::AgentBuilder::new()
+        .timeout(timeout + Duration::from_secs(5))
+        .build()
+        .post(&format!("{}/debug/exec", sandbox_url.trim_end_matches('/')))
+        .set("X-Debug-Exec-Token", &token)
+        .send_json(body)
+        .map_err(|e| VMError::GuestExec(format!("request failed: {e}")))?;
+    let json: serde_json::Value = resp
+        .into_json()
+        .map_err(|e| VMError::GuestExec(format!("bad response: {e}")))?;
+    let text = |k: &str| json.get(k).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    Ok(ExecOutput {
+        exit_code: json.get("exit_code").and_then(|v| v.as_i64()).unwrap_or(-1) as i32,
+        stdout: text("stdout"),
+        stderr: text("stderr"),
+        timed_out: json.get("timed_out").and_then(|v| v.as_bool()).unwrap_or(false),
+    })
+}
+
+/// `/debug/exec/stream`: NDJSON output lines, then an `exit_code` record. Dropping
+/// the response closes the connection, which makes the guest kill the command.
+/// Reads time out every [`STREAM_POLL`] to check `cancelled`; a timed-out read
+/// consumes nothing, so the next one picks up where it left off.
+fn debug_exec_streaming(
+    sandbox_url: &str,
+    console_log: &Path,
+    argv: &[String],
+    on_line: &mut dyn FnMut(ExecLine) -> bool,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<Option<i32>, VMError> {
+    use std::io::BufRead;
+    let token = read_debug_token(console_log).ok_or_else(|| {
+        VMError::GuestExec(format!(
+            "no debug-exec token in {} (guest not booted with ORCABOT_DEBUG_EXEC=1?)",
+            console_log.display()
+        ))
+    })?;
+    let resp = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(5))
+        .timeout_read(STREAM_POLL)
+        .build()
+        .post(&format!("{}/debug/exec/stream", sandbox_url.trim_end_matches('/')))
+        .set("X-Debug-Exec-Token", &token)
+        .send_json(serde_json::json!({ "argv": argv }))
+        .map_err(|e| VMError::GuestExec(format!("request failed: {e}")))?;
+    let mut reader = std::io::BufReader::new(resp.into_reader());
+    let mut record = Vec::new();
+    loop {
+        if cancelled() {
+            return Ok(None);
+        }
+        match reader.read_until(b'\n', &mut record) {
+            Ok(0) if record.is_empty() => break,
+            // A whole record, or a last one without its newline.
+            Ok(_) => {}
+            Err(e) if is_poll_timeout(&e) || e.kind() == std::io::ErrorKind::Interrupted => {
+                continue
+            }
+            Err(e) => {
+                return Err(VMError::GuestExec(format!("stream broken: {e}")));
+            }
+        }
+        let json: serde_json::Value = serde_json::from_slice(&record)
+            .map_err(|e| VMError::GuestExec(format!("bad record: {e}")))?;
+        record.clear();
+        if let Some(code) = json.get("exit_code").and_then(|v| v.as_i64()) {
+            return Ok(Some(code as i32));
+        }
+        let stream = match json.get("stream").and_then(|v| v.as_str()) {
+            Some("stderr") => OutputStream::Stderr,
+            _ => OutputStream::Stdout,
+        };
+        let line = json.get("line").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        if !on_line(ExecLine { stream, line }) {
+            return Ok(None);
+        }
+    }
+    Err(VMError::GuestExec(
+        "stream ended without an exit code (guest restarted?)".to_string(),
+    ))
+}
+
+/// A read that gave up after [`STREAM_POLL`] (`WouldBlock` on Unix sockets,
+/// `TimedOut` on Windows).
+fn is_poll_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock)
+}
+
+/// Pipes `wsl.exe` directly. Killing `wsl.exe` doesn't reliably reach the Linux
+/// side, so the wrapper prints its PID first and cancellation kills it (and its
+/// children) with a second `wsl.exe` call.
+#[cfg(target_os = "windows")]
+fn wsl_exec_streaming(
+    distro: &str,
+    argv: &[String],
+    on_line: &mut dyn FnMut(ExecLine) -> bool,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<Option<i32>, VMError> {
+    use std::io::BufRead;
+    use std::process::{Command, Stdio};
+    use std::sync::mpsc;
+
+    let mut child = Command::new("wsl")
+        .args(["-d", distro, "-u", "root", "--", "/bin/sh", "-c", "echo $$; exec \"$@\"", "sh"])
+        .args(argv)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| VMError::GuestExec(format!("wsl exec: {e}")))?;
+    let (tx, rx) = mpsc::channel::<ExecLine>();
+    let pump = |pipe: Box<dyn std::io::Read + Send>, stream: OutputStream, tx: mpsc::Sender<ExecLine>| {
+        std::thread::spawn(move || {
+            let mut reader = std::io::BufReader::new(pipe);
+            let mut buf = Vec::new();
+            while matches!(reader.read_until(b'\n', &mut buf), Ok(n) if n > 0) {
+                let text = String::from_utf8_lossy(&buf);
+                let line = text.trim_end_matches(['\r', '\n']).to_string();
+                buf.clear();
+                if tx.send(ExecLine { stream, line }).is_err() {
+                    break;
+                }
+            }
+        })
+    };
+    let stdout = child.stdout.take().map(|p| Box::new(p) as Box<dyn std::io::Read + Send>);
+    let stderr = child.stderr.take().map(|p| Box::new(p) as Box<dyn std::io::Read + Send>);
+    if let Some(pipe) = stdout {
+        pump(pipe, OutputStream::Stdout, tx.clone());
+    }
+    if let Some(pipe) = stderr {
+        pump(pipe, OutputStream::Stderr, tx.clone());
+    }
+    drop(tx);
+
+    let mut guest_pid: Option<u32> = None;
+    loop {
+        let keep_going = match rx.recv_timeout(STREAM_POLL) {
+            Ok(line) if guest_pid.is_none() && line.stream == OutputStream::Stdout => {
+                guest_pid = line.line.trim().parse().ok();
+                continue;
+            }
+            Ok(line) => on_line(line) && !cancelled(),
+            Err(mpsc::RecvTimeoutError::Timeout) => !cancelled(),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        if !keep_going {
+            if let Some(pid) = guest_pid {
+                let kill = format!("pkill -KILL -P {pid}; kill -KILL {pid}");
+                let _ = super::command::capture(
+                    Command::new("wsl").args(["-d", distro, "-u", "root", "--", "/bin/sh", "-c", &kill]),
+                    Duration::from_secs(10),
+                );
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+    }
+    let status = child
+        .wait()
+        .map_err(|e| VMError::GuestExec(format!("wsl exec: {e}")))?;
+    Ok(Some(status.code().unwrap_or(-1)))
+}
+
+#[cfg(target_os = "windows")]
+fn wsl_exec(distro: &str, cmd: &str, timeout: Duration) -> Result<ExecOutput, VMError> {
+    let output = super::command::capture(
+        std::process::Command::new("wsl").args(["-d", distro, "-u", "root", "--", "/bin/sh", "-c", cmd]),
+        timeout,
+    )?;
+    Ok(ExecOutput {
+        exit_code: output.code.unwrap_or(-1),
+        stdout: output.stdout,
+        stderr: output.stderr,
+        timed_out: output.timed_out,
+    })
+}
+
+/// The latest `[debug-exec] auth token: <hex>` line in a guest console log.
+fn read_debug_token(console_log: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(console_log).ok()?;
+    let line = content
+        .lines()
+        .rev()
+        .find(|l| l.contains("debug-exec] auth token:"))?;
+    let tok: String = line
+        .rsplit("auth token:")
+        .next()?
+        .trim()
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .collect();
+    (tok.len() >= 32).then_some(tok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A `/debug/exec/stream` that prints one line and then goes quiet, and a
+    /// console log carrying its token.
+    fn quiet_guest(dir: &Path) -> (String, PathBuf, std::sync::mpsc::Receiver<()>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (closed_tx, closed) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                line.clear();
+            }
+            let mut stream = stream;
+            let record = "{\"stream\":\"stdout\",\"line\":\"started\"}\n";
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+                record.len(),
+                record
+            );
+            let _ = stream.flush();
+            // The guest kills the command when the host hangs up.
+            let mut rest = Vec::new();
+            let _ = std::io::Read::read_to_end(&mut reader, &mut rest);
+            let _ = closed_tx.send(());
+        });
+        let console_log = dir.join("console.log");
+        let token = "ab".repeat(16);
+        std::fs::write(&console_log, format!("[debug-exec] auth token: {token}\n")).unwrap();
+        (url, console_log, closed)
+    }
+
+    #[test]
+    fn a_quiet_stream_can_still_be_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, console_log, closed) = quiet_guest(dir.path());
+        let channel = ExecChannel::DebugExec {
+            sandbox_url: url,
+            console_log,
+        };
+        // Cancelled from elsewhere after the only line; no further output will
+        // come along to check it.
+        let cancel = std::sync::Arc::new(AtomicBool::new(false));
+        let canceller = std::sync::Arc::clone(&cancel);
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(600));
+            canceller.store(true, Ordering::SeqCst);
+        });
+        let mut lines = Vec::new();
+        let started = std::time::Instant::now();
+        let code = channel
+            .exec_streaming(
+                &["sleep".to_string(), "600".to_string()],
+                &mut |line| {
+                    lines.push(line.line);
+                    true
+                },
+                &|| cancel.load(Ordering::SeqCst),
+            )
+            .unwrap();
+        assert_eq!(code, None);
+        assert_eq!(lines, ["started"]);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        closed.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+//! HTTP health probes for the sandbox and the host services.
+//!
+//! A probe reads the whole response head (through the blank line that ends the
+//! headers), parses the status line and judges the status code — so a 503 whose
+//! body happens to say "ok", or a non-HTTP listener that echoes text back, is
+//! never taken for a healthy service.
+
+use super::config::DEFAULT_HEALTH_PATH;
+use super::{VMConfig, HEALTH_READ_CAP};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// How long one probe of the sandbox may take, from connect to the end of the
+/// response head.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether `GET <path>` on `addr` answers with a 2xx status within `timeout`.
+pub fn http_health_check(addr: &str, path: &str, timeout: Duration) -> bool {
+    matches!(http_status(addr, path, timeout), Some(200..=299))
+}
+
+/// One health probe of the guest sandbox forwarded to `addr`, as `config`
+/// describes it (`health_path`, `health_expect_status`); without a config,
+/// `GET /health` and any 2xx.
+pub(crate) fn probe_sandbox_health(addr: &str, config: Option<&VMConfig>) -> bool {
+    match config {
+        Some(config) => http_status(addr, &config.health_path, PROBE_TIMEOUT)
+            .is_some_and(|status| config.health_status_ok(status)),
+        None => http_health_check(addr, DEFAULT_HEALTH_PATH, PROBE_TIMEOUT),
+    }
+}
+
+/// Status code of `GET <path>` on `addr`, or `None` if nothing answered with a
+/// complete HTTP response head within `timeout`.
+pub(crate) fn http_status(addr: &str, path: &str, timeout: Duration) -> Option<u16> {
+    let req = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    request_status(addr, &req, timeout)
+}
+
+/// Send the raw HTTP/1.1 `request` to `addr` and return the status code of
+/// the response, read and checked as [`http_status`] does.
+pub(crate) fn request_status(addr: &str, request: &str, timeout: Duration) -> Option<u16> {
+    let deadline = Instant::now() + timeout;
+    let sock = addr.to_socket_addrs().ok()?.next()?;
+    let mut stream = TcpStream::connect_timeout(&sock, timeout).ok()?;
+    let _ = stream.set_write_timeout(Some(timeout));
+    stream.write_all(request.as_bytes()).ok()?;
+    parse_status_line(&read_head(&mut stream, deadline)?)
+}
+
+/// The response head up to the `\r\n\r\n` that ends it. `None` if the peer
+/// closes, the deadline passes or [`HEALTH_READ_CAP`] bytes arrive first.
+fn read_head(stream: &mut TcpStream, deadline: Instant) -> Option<String> {
+    let mut received = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while received.len() < HEALTH_READ_CAP {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())?;
+        stream.set_read_timeout(Some(remaining)).ok()?;
+        let want = chunk.len().min(HEALTH_READ_CAP - received.len());
+        match stream.read(&mut chunk[..want]) {
+            Ok(0) => return None,
+            Ok(n) => received.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => return None,
+        }
+        if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+            return Some(String::from_utf8_lossy(&received[..end]).into_owned());
+        }
+    }
+    None
+}
+
+/// The code from a `HTTP/1.1 200 OK` status line at the start of `head`.
+fn parse_status_line(head: &str) -> Option<u16> {
+    let mut parts = head.lines().next()?.split_whitespace();
+    let version = parts.next()?.strip_prefix("HTTP/")?;
+    if !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let code = parts.next()?;
+    if code.len() != 3 {
+        return None;
+    }
+    code.parse().ok().filter(|c| (100..=599).contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Answer one connection with `chunks`, pausing between them so they arrive
+    /// as separate segments, then close. Returns the address to probe.
+    fn serve(chunks: &'static [&'static [u8]]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0u8; 1];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                request.push(byte[0]);
+            }
+            for chunk in chunks {
+                let _ = stream.write_all(chunk);
+                let _ = stream.flush();
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn only_a_2xx_status_line_is_healthy() {
+        let timeout = Duration::from_secs(2);
+        let ok = serve(&[b"HTTP/1.1 200 OK\r\nContent-Length: 15\r\n\r\n{\"status\":\"ok\"}"]);
+        assert!(http_health_check(&ok, "/health", timeout));
+        let no_content = serve(&[b"HTTP/1.0 204 No Content\r\n\r\n"]);
+        assert!(http_health_check(&no_content, "/health", timeout));
+
+        let unavailable = serve(&[b"HTTP/1.1 503 Service Unavailable\r\n\r\n{\"status\":\"ok\"}"]);
+        assert_eq!(http_status(&unavailable, "/health", timeout), Some(503));
+        let unavailable = serve(&[b"HTTP/1.1 503 Service Unavailable\r\n\r\nok"]);
+        assert!(!http_health_check(&unavailable, "/health", timeout));
+
+        // Status line and headers split across segments.
+        let split = serve(&[b"HTT", b"P/1.1 2", b"00 OK\r\nX-Padding: a", b"b\r\n", b"\r\n"]);
+        assert!(http_health_check(&split, "/health", timeout));
+    }
+
+    #[test]
+    fn non_http_and_truncated_responses_are_unhealthy() {
+        let timeout = Duration::from_secs(2);
+        let ssh = serve(&[b"SSH-2.0-OpenSSH_9.6\r\n\r\n"]);
+        assert_eq!(http_status(&ssh, "/health", timeout), None);
+        let echo = serve(&[b"ok 200 OK\r\n\r\n"]);
+        assert!(!http_health_check(&echo, "/health", timeout));
+        // Closed before the headers ended.
+        let truncated = serve(&[b"HTTP/1.1 200 OK\r\nContent-Type: text"]);
+        assert!(!http_health_check(&truncated, "/health", timeout));
+
+        // Accepts but never answers: gives up at the timeout.
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = silent.local_addr().unwrap().to_string();
+        let start = Instant::now();
+        assert!(!http_health_check(&addr, "/health", Duration::from_millis(300)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        drop(silent);
+        assert!(!http_health_check(&addr, "/health", timeout));
+    }
+
+    #[test]
+    fn status_lines_parse() {
+        assert_eq!(parse_status_line("HTTP/1.1 200 OK"), Some(200));
+        assert_eq!(parse_status_line("HTTP/2 404\r\nServer: workerd"), Some(404));
+        assert_eq!(parse_status_line("HTTP/1.1 200"), Some(200));
+        assert_eq!(parse_status_line("HTTP/1.1 20 OK"), None);
+        assert_eq!(parse_status_line("HTTP/1.1 999 Nope"), None);
+        assert_eq!(parse_status_line("HTTP/x 200 OK"), None);
+        assert_eq!(parse_status_line("200 OK"), None);
+        assert_eq!(parse_status_line(""), None);
+    }
+}
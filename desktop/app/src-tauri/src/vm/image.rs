@@ -4,48 +4,88 @@
 //! per image version and verified against a SHA-256 baked into the binary. The
 //! small resources (kernel/initrd/vz-helper) are still staged from the bundle.
 //
-// REVISION: vm-image-ondemand-v2-cache-dir
+// REVISION: vm-image-ondemand-v21-chunked-io-copy-stage
 
 use super::VMError;
 use sha2::{Digest, Sha256};
 use std::fs::{self, File};
 use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
 
 /// Stage a VM image from resources to the app data directory.
 ///
-/// If the source is gzip-compressed (.gz), it will be decompressed.
+/// If the source is compressed (.gz, .zst or .xz), it will be decompressed.
 /// Uses smart caching: only extracts if source is newer or sizes differ.
-pub fn stage_image(src: &Path, dest: &Path) -> Result<PathBuf, VMError> {
-    let is_gzipped = src.extension().map_or(false, |e| e == "gz");
+/// `progress` gets `(bytes written, total)` per 64KB chunk; see [`StageProgress`].
+pub fn stage_image(
+    src: &Path,
+    dest: &Path,
+    progress: Option<StageProgress>,
+) -> Result<PathBuf, VMError> {
+    let dest_path = staged_path(src, dest);
+    stage_image_to(src, &dest_path, None, progress)?;
+    Ok(dest_path)
+}
 
-    let dest_path = if is_gzipped {
-        // Remove .gz extension for destination
+/// Where [`stage_image`] puts `src` in `dest`.
+fn staged_path(src: &Path, dest: &Path) -> PathBuf {
+    if Compression::from_path(src).is_some() {
+        // Remove the compression extension for destination
         let stem = src.file_stem().unwrap_or_default();
         dest.join(stem)
     } else {
         dest.join(src.file_name().unwrap_or_default())
-    };
-
-    stage_image_to(src, &dest_path)?;
-    Ok(dest_path)
+    }
 }
 
-/// Stage `src` to a specific destination file (decompressing if `src` is `.gz`).
-/// Mtime-cached against the source signature so re-staging an unchanged source is
-/// a no-op.
-fn stage_image_to(src: &Path, dest_path: &Path) -> Result<(), VMError> {
-    let is_gzipped = src.extension().map_or(false, |e| e == "gz");
+/// Staging progress: bytes written to the destination so far, and the total
+/// when it's known up front (a plain copy). Decompressing can't know the
+/// output size, so it passes `None`.
+pub type StageProgress<'a> = &'a dyn Fn(u64, Option<u64>);
+
+/// Stage `src` to a specific destination file (decompressing if `src` has a
+/// compression extension). Mtime-cached against the source signature so
+/// re-staging an unchanged source is a no-op. With `expected_sha256`, the
+/// staged (decompressed) file must hash to it: a truncated or corrupted source
+/// fails here with `ChecksumMismatch` instead of cryptically at boot, and the
+/// bad copy is removed so the next attempt stages afresh.
+///
+/// The copy is written to a `<dest>.tmp-<pid>` sibling and renamed into place
+/// only once it's complete (and verified), so an interrupted stage (force-quit,
+/// full disk) never leaves a truncated image at the real path for a later
+/// launch to boot.
+fn stage_image_to(
+    src: &Path,
+    dest_path: &Path,
+    expected_sha256: Option<&str>,
+    progress: Option<StageProgress>,
+) -> Result<(), VMError> {
     if needs_staging(src, dest_path)? {
         if let Some(parent) = dest_path.parent() {
             fs::create_dir_all(parent)?;
         }
-        if is_gzipped {
-            decompress_gzip(src, dest_path)?;
-        } else {
-            copy_file(src, dest_path)?;
+        // The decompressed image is several GB; stop early if the disk fills.
+        // A compressed source's size is only a lower bound for what it stages.
+        let mut watch =
+            super::runtime::FreeSpaceWatch::new(dest_path.parent().unwrap_or(Path::new(".")));
+        watch.check(fs::metadata(src)?.len()).map_err(low_space)?;
+        let tmp = TempFile(dest_path.with_extension(format!("tmp-{}", std::process::id())));
+        match Compression::from_path(src) {
+            Some(codec) => decompress(src, &tmp.0, codec, &mut watch, progress)?,
+            None => copy_file(src, &tmp.0, &mut watch, progress)?,
         }
+        if let Some(expected) = expected_sha256 {
+            let actual = file_sha256(&tmp.0)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(VMError::ChecksumMismatch {
+                    expected: expected.to_lowercase(),
+                    actual,
+                });
+            }
+        }
+        tmp.persist(dest_path)?;
         // Record the source signature so a later runtime mutation of dest (the VM
         // image boots read-write, so the guest bumps its mtime) never makes a
         // genuinely-updated source look stale and skip re-staging.
@@ -56,6 +96,75 @@ fn stage_image_to(src: &Path, dest_path: &Path) -> Result<(), VMError> {
     Ok(())
 }
 
+/// A staging temp file, removed on drop unless [`TempFile::persist`] renamed it
+/// into place.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Rename over `dest`: atomic within one directory, so `dest` is either the
+    /// old file or the complete new one.
+    fn persist(mut self, dest: &Path) -> io::Result<()> {
+        fs::rename(&self.0, dest)?;
+        self.0 = PathBuf::new();
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.0.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+}
+
+/// How a staged image is compressed, picked by its file extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// The compression `path`'s extension (`.gz`, `.zst`, `.xz`) names, if any.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            "xz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+
+    /// The [`compression_kind`] name of this compression.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+        }
+    }
+
+    /// The compression a [`compression_kind`] name stands for, if we can decode it.
+    fn from_kind(kind: &str) -> Option<Self> {
+        match kind {
+            "gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            "xz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+
+    /// A reader yielding the decompressed bytes of `reader`.
+    fn decoder<'a, R: Read + 'a>(self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(flate2::read::GzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new(reader)),
+        })
+    }
+}
 /// Stable signature of the SOURCE file (modification time + size).
 ///
 /// Uses NANOSECOND mtime, not seconds: a rebuild that lands in the same wall-clock
@@ -65,13 +174,7 @@ fn stage_image_to(src: &Path, dest_path: &Path) -> Result<(), VMError> {
 /// robust still, but hashing a multi-GB image on every launch is too slow.
 fn source_signature(src: &Path) -> Result<String, VMError> {
     let meta = fs::metadata(src)?;
-    let mtime_nanos = meta
-        .modified()
-        .ok()
-        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
-        .map(|d| d.as_nanos())
-        .unwrap_or(0);
-    Ok(format!("{}:{}", mtime_nanos, meta.len()))
+    Ok(format!("{}:{}", mtime_ns(&meta).unwrap_or(0), meta.len()))
 }
 
 /// Path of the sidecar stamp file recording the source signature at last stage.
@@ -81,6 +184,41 @@ fn stamp_path(dest: &Path) -> PathBuf {
     PathBuf::from(s)
 }
 
+/// Why `needs_staging` did or didn't re-stage a file, with the inputs it looked
+/// at. The latest one per destination is kept for `get_resource_info`, so a
+/// "booted a stale image" report shows what the cache heuristic saw.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StagingDecision {
+    pub src: String,
+    pub dest: String,
+    pub restage: bool,
+    /// "dest missing", "no stamp", "source changed" or "up to date".
+    pub reason: &'static str,
+    pub src_mtime_ns: Option<u128>,
+    pub src_size: Option<u64>,
+    /// Informational only: the guest writes the staged image, so its mtime
+    /// never takes part in the decision.
+    pub dest_mtime_ns: Option<u128>,
+    pub dest_size: Option<u64>,
+    /// Source signature (`mtime_ns:size`) recorded at the last stage.
+    pub stamp: Option<String>,
+}
+
+/// Latest [`StagingDecision`] per destination, in first-staged order.
+static STAGING_DECISIONS: std::sync::Mutex<Vec<StagingDecision>> = std::sync::Mutex::new(Vec::new());
+
+/// The staging decisions made by this process so far (latest per destination).
+pub fn last_staging_decisions() -> Vec<StagingDecision> {
+    STAGING_DECISIONS.lock().map(|d| d.clone()).unwrap_or_default()
+}
+
+fn mtime_ns(meta: &fs::Metadata) -> Option<u128> {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+}
+
 /// Check if staging is needed by comparing the source's signature against the
 /// stamp recorded at the last successful stage.
 ///
@@ -88,46 +226,121 @@ fn stamp_path(dest: &Path) -> PathBuf {
 /// is mounted read-write, so the running guest mutates the staged copy and bumps
 /// its mtime past a freshly-rebuilt source — which made the old "source newer"
 /// check skip re-staging and silently boot a stale image.
+///
+/// Every decision is recorded (see [`last_staging_decisions`]) and, with
+/// `ORCABOT_STAGING_DEBUG=1`, logged with the mtimes and sizes behind it.
 fn needs_staging(src: &Path, dest: &Path) -> Result<bool, VMError> {
-    if !dest.exists() {
-        return Ok(true);
+    let decision = staging_decision(src, dest)?;
+    if std::env::var("ORCABOT_STAGING_DEBUG").is_ok_and(|v| v == "1") {
+        eprintln!(
+            "[vm-image] staging {}: {} ({}) src mtime_ns={:?} size={:?}; dest mtime_ns={:?} size={:?}; stamp={:?}",
+            decision.dest,
+            if decision.restage { "restage" } else { "keep" },
+            decision.reason,
+            decision.src_mtime_ns,
+            decision.src_size,
+            decision.dest_mtime_ns,
+            decision.dest_size,
+            decision.stamp
+        );
     }
-    let sig = source_signature(src)?;
-    match fs::read_to_string(stamp_path(dest)) {
-        Ok(recorded) => Ok(recorded.trim() != sig),
-        Err(_) => Ok(true), // no stamp (e.g. older install) → re-stage and write one
+    let restage = decision.restage;
+    if let Ok(mut decisions) = STAGING_DECISIONS.lock() {
+        match decisions.iter_mut().find(|d| d.dest == decision.dest) {
+            Some(slot) => *slot = decision,
+            None => decisions.push(decision),
+        }
     }
+    Ok(restage)
 }
 
-/// Decompress a gzip file.
-fn decompress_gzip(src: &Path, dest: &Path) -> Result<(), VMError> {
-    let src_file = File::open(src)?;
-    let reader = BufReader::new(src_file);
+fn staging_decision(src: &Path, dest: &Path) -> Result<StagingDecision, VMError> {
+    let src_meta = fs::metadata(src).ok();
+    let dest_meta = fs::metadata(dest).ok();
+    let stamp = fs::read_to_string(stamp_path(dest))
+        .ok()
+        .map(|s| s.trim().to_string());
+    let (restage, reason) = if dest_meta.is_none() {
+        (true, "dest missing")
+    } else {
+        let sig = source_signature(src)?;
+        match stamp {
+            Some(ref recorded) if *recorded == sig => (false, "up to date"),
+            Some(_) => (true, "source changed"),
+            None => (true, "no stamp"), // e.g. older install → re-stage and write one
+        }
+    };
+    Ok(StagingDecision {
+        src: src.display().to_string(),
+        dest: dest.display().to_string(),
+        restage,
+        reason,
+        src_mtime_ns: src_meta.as_ref().and_then(mtime_ns),
+        src_size: src_meta.as_ref().map(|m| m.len()),
+        dest_mtime_ns: dest_meta.as_ref().and_then(mtime_ns),
+        dest_size: dest_meta.as_ref().map(|m| m.len()),
+        stamp,
+    })
+}
 
-    // Use flate2 for gzip decompression
-    let mut decoder = flate2::read::GzDecoder::new(reader);
+fn low_space(msg: String) -> VMError {
+    VMError::Io(io::Error::other(msg))
+}
+
+/// Decompress `src`, compressed with `codec`, into `dest`.
+fn decompress(
+    src: &Path,
+    dest: &Path,
+    codec: Compression,
+    watch: &mut super::runtime::FreeSpaceWatch,
+    progress: Option<StageProgress>,
+) -> Result<(), VMError> {
+    let src_file = File::open(src)?;
+    let mut decoder = codec.decoder(BufReader::new(src_file))?;
 
     let dest_file = File::create(dest)?;
     let mut writer = BufWriter::new(dest_file);
 
     let mut buffer = [0u8; 64 * 1024]; // 64KB buffer
+    let mut done = 0u64;
     loop {
         let bytes_read = decoder.read(&mut buffer)?;
         if bytes_read == 0 {
             break;
         }
+        // The decompressed size isn't known up front: only the spare is kept.
+        watch.check(0).map_err(low_space)?;
         writer.write_all(&buffer[..bytes_read])?;
+        done += bytes_read as u64;
+        if let Some(progress) = progress {
+            progress(done, None);
+        }
     }
 
     writer.flush()?;
     Ok(())
 }
 
-/// Sign vz-helper with virtualization entitlement on macOS.
+/// Attempts at signing vz-helper before giving up. Transient failures (locked
+/// keychain, another codesign holding the file) usually clear within a second.
+#[cfg(target_os = "macos")]
+const CODESIGN_ATTEMPTS: u32 = 3;
+
+#[cfg(target_os = "macos")]
+const CODESIGN_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Per-attempt limit; codesign can stall on a locked keychain.
 #[cfg(target_os = "macos")]
-fn sign_vz_helper(path: &Path) {
-    use std::process::Command;
+const CODESIGN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
 
+/// Sign vz-helper with virtualization entitlement on macOS.
+///
+/// Retries transient codesign failures; a missing `codesign` binary is reported
+/// straight away, since retrying can't fix it. An unsigned helper makes VZ fail
+/// at launch, so errors name the likely fix.
+#[cfg(target_os = "macos")]
+fn sign_vz_helper(path: &Path) -> Result<(), String> {
+    use std::ffi::OsStr;
     // Create temporary entitlements file
     let entitlements_content = r#"<?xml version="1.0" encoding="UTF-8"?>
 <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
@@ -142,49 +355,87 @@ fn sign_vz_helper(path: &Path) {
         .unwrap_or(Path::new("."))
         .join("vz-helper.entitlements");
 
-    if let Err(e) = fs::write(&entitlements_path, entitlements_content) {
-        eprintln!("Warning: Failed to write vz-helper entitlements: {}", e);
-        return;
-    }
-
-    // Sign the binary with entitlements (ad-hoc signing with -)
-    let result = Command::new("codesign")
-        .args([
-            "--force",
-            "--sign", "-",
-            "--entitlements", entitlements_path.to_str().unwrap_or_default(),
-            path.to_str().unwrap_or_default(),
-        ])
-        .output();
-
-    match result {
-        Ok(output) => {
-            if !output.status.success() {
-                eprintln!(
-                    "Warning: Failed to sign vz-helper: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                );
+    fs::write(&entitlements_path, entitlements_content)
+        .map_err(|e| format!("failed to write vz-helper entitlements: {}", e))?;
+
+    let mut last_error = String::new();
+    for attempt in 1..=CODESIGN_ATTEMPTS {
+        // Sign the binary with entitlements (ad-hoc signing with -)
+        let result = super::command::run_command(
+            "codesign",
+            &[
+                OsStr::new("--force"),
+                OsStr::new("--sign"), OsStr::new("-"),
+                OsStr::new("--entitlements"), entitlements_path.as_os_str(),
+                path.as_os_str(),
+            ],
+            CODESIGN_TIMEOUT,
+        );
+
+        match result {
+            Ok(_) => {
+                last_error.clear();
+                break;
+            }
+            Err(VMError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                last_error = "codesign not found; install the Xcode Command Line Tools \
+                              (`xcode-select --install`) and relaunch"
+                    .to_string();
+                break;
+            }
+            Err(e) => {
+                last_error = e.to_string();
             }
         }
-        Err(e) => {
-            eprintln!("Warning: Failed to run codesign: {}", e);
+        if attempt < CODESIGN_ATTEMPTS {
+            eprintln!(
+                "[vm] signing vz-helper failed (attempt {}/{}), retrying: {}",
+                attempt, CODESIGN_ATTEMPTS, last_error
+            );
+            std::thread::sleep(CODESIGN_RETRY_DELAY);
         }
     }
 
     // Clean up entitlements file
     let _ = fs::remove_file(&entitlements_path);
-}
 
-/// Copy a file with progress (for large VM images).
-fn copy_file(src: &Path, dest: &Path) -> Result<(), VMError> {
-    let src_file = File::open(src)?;
-    let mut reader = BufReader::new(src_file);
+    if last_error.is_empty() {
+        Ok(())
+    } else {
+        Err(last_error)
+    }
+}
 
-    let dest_file = File::create(dest)?;
-    let mut writer = BufWriter::new(dest_file);
+/// Bytes [`copy_file`] copies between free-space checks and progress reports.
+const STAGE_COPY_CHUNK: u64 = 64 * 1024 * 1024;
+
+/// Copy a file with progress (for large VM images). `io::copy` between the two
+/// files keeps the kernel fast path (copy_file_range/sendfile where the OS has
+/// them); it runs in [`STAGE_COPY_CHUNK`] pieces so free space is checked
+/// against what is left between them.
+fn copy_file(
+    src: &Path,
+    dest: &Path,
+    watch: &mut super::runtime::FreeSpaceWatch,
+    progress: Option<StageProgress>,
+) -> Result<(), VMError> {
+    let mut src_file = File::open(src)?;
+    let total = src_file.metadata().ok().map(|m| m.len());
+    let mut dest_file = File::create(dest)?;
 
-    io::copy(&mut reader, &mut writer)?;
-    writer.flush()?;
+    let mut done = 0u64;
+    loop {
+        let remaining = total.map_or(0, |total| total.saturating_sub(done));
+        watch.check(remaining).map_err(low_space)?;
+        let n = io::copy(&mut (&mut src_file).take(STAGE_COPY_CHUNK), &mut dest_file)?;
+        done += n;
+        if let Some(progress) = progress {
+            progress(done, total);
+        }
+        if n < STAGE_COPY_CHUNK {
+            break;
+        }
+    }
 
     // Preserve modification time
     if let Ok(src_meta) = fs::metadata(src) {
@@ -196,10 +447,52 @@ fn copy_file(src: &Path, dest: &Path) -> Result<(), VMError> {
     Ok(())
 }
 
+/// The checksum of the staged (decompressed) image, from `<image>.sha256`
+/// (`sha256sum` output or a bare hex digest) if that file exists. For a
+/// compressed `sandbox.img.gz` that is `sandbox.img.sha256`, named after what it
+/// describes; a `sandbox.img.gz.sha256` would be `sha256sum` of the compressed
+/// bytes, so it is logged and ignored. A file that doesn't hold a SHA-256 is
+/// logged and ignored too.
+pub fn read_sha256_file(image: &Path) -> Option<String> {
+    let path = match Compression::from_path(image) {
+        Some(_) => {
+            let compressed = sibling(image, ".sha256");
+            if compressed.exists() {
+                eprintln!(
+                    "[vm-image] ignoring {}: a compressed image is checked against its \
+                     decompressed bytes, from {}",
+                    compressed.display(),
+                    image.with_extension("sha256").display()
+                );
+            }
+            image.with_extension("sha256")
+        }
+        None => sibling(image, ".sha256"),
+    };
+    let text = fs::read_to_string(&path).ok()?;
+    let digest = text.split_whitespace().next().unwrap_or("").to_lowercase();
+    if digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Some(digest)
+    } else {
+        eprintln!("[vm-image] ignoring {}: no SHA-256 digest in it", path.display());
+        None
+    }
+}
+
+/// Lowercase hex SHA-256 of the file at `path`.
+fn file_sha256(path: &Path) -> Result<String, VMError> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+    Ok(hex_encode(hasher.finalize().as_slice()))
+}
+
 /// Paths for VM resources based on platform.
 pub struct VMResourcePaths {
     /// Path to the main VM image
     pub image: PathBuf,
+    /// SHA-256 (hex) the staged, decompressed image must have, from a
+    /// `<image>.sha256` file beside it (see [`read_sha256_file`]).
+    pub image_sha256: Option<String>,
     /// Path to kernel (macOS only)
     pub kernel: Option<PathBuf>,
     /// Path to initrd (macOS only)
@@ -212,11 +505,24 @@ impl VMResourcePaths {
     /// Resolve VM resource paths from the given resource root.
     /// Tries multiple image formats in order of preference.
     pub fn from_resource_root(root: &Path) -> Self {
+        let mut paths = Self::platform_paths(root);
+        paths.image_sha256 = read_sha256_file(&paths.image);
+        paths
+    }
+
+    /// Use `image` instead, with its own `.sha256` file (if any).
+    pub fn set_image(&mut self, image: PathBuf) {
+        self.image_sha256 = read_sha256_file(&image);
+        self.image = image;
+    }
+
+    fn platform_paths(root: &Path) -> Self {
         #[cfg(target_os = "macos")]
         {
             // macOS: prefer raw disk image for QEMU/VZ boot
             Self {
                 image: root.join("vm/sandbox.img"),
+                image_sha256: None,
                 kernel: Some(root.join("vm/vmlinuz")),
                 initrd: Some(root.join("vm/initrd.img")),
                 vz_helper: Some(root.join("vm/vz-helper")),
@@ -228,6 +534,7 @@ impl VMResourcePaths {
             // Windows: use rootfs tarball for WSL2 import
             Self {
                 image: root.join("vm/sandbox-rootfs.tar.gz"),
+                image_sha256: None,
                 kernel: None,
                 initrd: None,
                 vz_helper: None,
@@ -245,6 +552,7 @@ impl VMResourcePaths {
 
             Self {
                 image,
+                image_sha256: None,
                 kernel: Some(root.join("vm/vmlinuz")),
                 initrd: Some(root.join("vm/initrd.img")),
                 vz_helper: None,
@@ -255,6 +563,7 @@ impl VMResourcePaths {
         {
             Self {
                 image: root.join("vm/sandbox.img"),
+                image_sha256: None,
                 kernel: None,
                 initrd: None,
                 vz_helper: None,
@@ -264,11 +573,14 @@ impl VMResourcePaths {
 
 }
 
-/// Stage all VM resources to the app data directory.
+/// Stage all VM resources to the app data directory. `progress` follows an
+/// image download (see [`fetch_image`]); `staging` a local image being copied
+/// or decompressed into place (see [`StageProgress`]).
 pub fn stage_vm_resources(
     resource_paths: &VMResourcePaths,
     vm_dir: &Path,
     progress: &dyn Fn(u64, u64),
+    staging: Option<StageProgress>,
 ) -> Result<VMResourcePaths, VMError> {
     // vm_dir lives under the CACHE dir (resolved in start_sandbox_vm): the disk
     // image + staged runtime binaries are large and fully regenerable, so they
@@ -276,26 +588,43 @@ pub fn stage_vm_resources(
     let vm_dir = vm_dir.to_path_buf();
     fs::create_dir_all(&vm_dir)?;
 
+    // Refuse up front rather than with a bare ENOSPC halfway through a
+    // multi-GB copy. Only files that will actually be (re)staged count.
+    let required = pending_local_stages(resource_paths, &vm_dir)
+        .iter()
+        .filter_map(|(src, _)| fs::metadata(src).ok())
+        .map(|meta| stage_budget(&meta))
+        .sum::<u64>();
+    if required > 0 {
+        check_free_space(&vm_dir, required.saturating_add(super::runtime::min_free_bytes()))?;
+    }
+
     // The disk image is NOT bundled in the app (it would bloat every
     // auto-update), so fetch/adopt it on demand instead of staging from a
     // bundled resource.
-    let staged_image = ensure_vm_image(&resource_paths.image, &vm_dir, progress)?;
+    let staged_image = ensure_vm_image(
+        &resource_paths.image,
+        resource_paths.image_sha256.as_deref(),
+        &vm_dir,
+        progress,
+        staging,
+    )?;
 
     let staged_kernel = if let Some(ref kernel) = resource_paths.kernel {
-        Some(stage_image(kernel, &vm_dir)?)
+        Some(stage_image(kernel, &vm_dir, None)?)
     } else {
         None
     };
 
     let staged_initrd = if let Some(ref initrd) = resource_paths.initrd {
-        Some(stage_image(initrd, &vm_dir)?)
+        Some(stage_image(initrd, &vm_dir, None)?)
     } else {
         None
     };
 
     let staged_vz_helper = if let Some(ref vz_helper) = resource_paths.vz_helper {
         if vz_helper.exists() {
-            let staged = stage_image(vz_helper, &vm_dir)?;
+            let staged = stage_image(vz_helper, &vm_dir, None)?;
             // Ensure vz-helper is executable and properly signed
             #[cfg(unix)]
             {
@@ -305,10 +634,11 @@ pub fn stage_vm_resources(
                     let _ = fs::set_permissions(&staged, perms);
                 }
             }
-            // On macOS, re-sign vz-helper with virtualization entitlement
+            // On macOS, re-sign vz-helper with virtualization entitlement.
+            // Not fatal: without it VZ fails to start and we fall back to QEMU.
             #[cfg(target_os = "macos")]
-            {
-                sign_vz_helper(&staged);
+            if let Err(e) = sign_vz_helper(&staged) {
+                eprintln!("Warning: vz-helper is unsigned, Virtualization.framework will fail: {}", e);
             }
             Some(staged)
         } else {
@@ -320,12 +650,91 @@ pub fn stage_vm_resources(
 
     Ok(VMResourcePaths {
         image: staged_image,
+        image_sha256: resource_paths.image_sha256.clone(),
         kernel: staged_kernel,
         initrd: staged_initrd,
         vz_helper: staged_vz_helper,
     })
 }
 
+/// Headroom on top of a compressed source's size when budgeting for its
+/// decompressed copy (VM images are mostly filesystem slack, which compresses to
+/// nearly nothing, so this is an estimate, not a bound).
+fn stage_budget(meta: &fs::Metadata) -> u64 {
+    meta.len().saturating_add(meta.len() / 5)
+}
+
+/// Err(`InsufficientDiskSpace`) unless the filesystem holding `dir` has at least
+/// `required` bytes available. Passes (with a log line) where free space can't
+/// be queried.
+pub fn check_free_space(dir: &Path, required: u64) -> Result<(), VMError> {
+    match super::runtime::available_bytes(dir) {
+        Ok(available) if available < required => Err(VMError::InsufficientDiskSpace {
+            needed: required,
+            available,
+        }),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            eprintln!(
+                "[vm-image] can't check free space on {}: {}",
+                dir.display(),
+                e
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The local files [`stage_vm_resources`] would copy into `vm_dir` that aren't
+/// staged yet, as `(source, destination)`. A download isn't included: its size
+/// isn't known up front, and `fetch_image` watches free space as it goes.
+fn pending_local_stages(
+    resource_paths: &VMResourcePaths,
+    vm_dir: &Path,
+) -> Vec<(PathBuf, PathBuf)> {
+    // The same choice `ensure_vm_image` makes: the override, else a local image.
+    let image = match image_override().filter(|p| p.exists()) {
+        Some(src) => Some((override_image_dest(vm_dir, &src), src)),
+        None => Some(&resource_paths.image)
+            .filter(|src| src.exists())
+            .map(|src| (resource_image_dest(vm_dir, src), src.clone())),
+    }
+    .map(|(dest, src)| (src, dest));
+    let resources = [
+        &resource_paths.kernel,
+        &resource_paths.initrd,
+        &resource_paths.vz_helper,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|src| src.exists())
+    .map(|src| (src.clone(), staged_path(src, vm_dir)));
+    image
+        .into_iter()
+        .chain(resources)
+        .filter(|(src, dest)| staging_decision(src, dest).map_or(true, |d| d.restage))
+        .collect()
+}
+
+/// Forget what's staged in `vm_dir` so the next staging redoes it: drops the
+/// source stamps (local resources are copied again) and the downloaded image for
+/// the current manifest version (fetched and verified again).
+pub fn clear_staged(vm_dir: &Path) -> Result<(), VMError> {
+    let Ok(entries) = fs::read_dir(vm_dir) else {
+        return Ok(());
+    };
+    let downloaded = format!("sandbox-{}.img", vm_image_manifest().version);
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.ends_with(".stamp") || name == downloaded {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    eprintln!("[vm-image] cleared staged resources in {}", vm_dir.display());
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // VM image: fetched on demand, not bundled.
 //
@@ -373,47 +782,48 @@ const LEGACY_IMAGE_VERSION: &str = "v1";
 ///
 /// Resolution order:
 ///  0. `ORCABOT_VM_IMAGE` dev override → stage that file (named by its signature);
-///  1. a local resource image (dev build / bundled) → stage it;
+///  1. a local resource image (dev build / bundled) → stage it, checked against
+///     `resource_sha256` when given;
 ///  2. the versioned image already staged for the manifest version → use it;
 ///  3. migrate a pre-content-naming `sandbox.img` by renaming it (if it's the
 ///     required version) — a fresh path also clears any stale size cache;
 ///  4. otherwise download the gz artifact, verify its SHA-256, decompress.
 pub fn ensure_vm_image(
     resource_image: &Path,
+    resource_sha256: Option<&str>,
     vm_dir: &Path,
     progress: &dyn Fn(u64, u64),
+    staging: Option<StageProgress>,
 ) -> Result<PathBuf, VMError> {
     let manifest = vm_image_manifest();
     let vm_dir = vm_dir.to_path_buf();
     fs::create_dir_all(&vm_dir)?;
 
     // 0. Dev override: ORCABOT_VM_IMAGE forces a specific local image (raw .img or
-    //    .gz), bypassing the version check + release download. Named by the source
+    //    .gz/.zst/.xz), bypassing the version check + release download. Named by the source
     //    signature so swapping to an image of a different size lands on a fresh path.
-    if let Ok(override_path) = std::env::var("ORCABOT_VM_IMAGE") {
-        if !override_path.is_empty() {
-            let p = Path::new(&override_path);
-            if p.exists() {
-                let dest = vm_dir.join(format!("sandbox-ovr-{}.img", local_content_token(p)));
-                eprintln!(
-                    "[vm-image] ORCABOT_VM_IMAGE override: staging {} -> {}",
-                    p.display(),
-                    dest.display()
-                );
-                stage_image_to(p, &dest)?;
-                cleanup_stale_images(&vm_dir, &dest);
-                return Ok(dest);
-            }
+    if let Some(p) = image_override() {
+        if p.exists() {
+            let dest = override_image_dest(&vm_dir, &p);
             eprintln!(
-                "[vm-image] ORCABOT_VM_IMAGE set but file not found: {override_path} — ignoring"
+                "[vm-image] ORCABOT_VM_IMAGE override: staging {} -> {}",
+                p.display(),
+                dest.display()
             );
+            stage_image_to(&p, &dest, read_sha256_file(&p).as_deref(), staging)?;
+            cleanup_stale_images(&vm_dir, &dest);
+            return Ok(dest);
         }
+        eprintln!(
+            "[vm-image] ORCABOT_VM_IMAGE set but file not found: {} — ignoring",
+            p.display()
+        );
     }
 
     // 1. Dev / bundled: a local resource image is the source of truth.
     if resource_image.exists() {
-        let dest = vm_dir.join(format!("sandbox-res-{}.img", local_content_token(resource_image)));
-        stage_image_to(resource_image, &dest)?;
+        let dest = resource_image_dest(&vm_dir, resource_image);
+        stage_image_to(resource_image, &dest, resource_sha256, staging)?;
         cleanup_stale_images(&vm_dir, &dest);
         return Ok(dest);
     }
@@ -445,11 +855,657 @@ pub fn ensure_vm_image(
         "[vm-image] fetching sandbox image {} from {}",
         manifest.version, manifest.url
     );
-    download_and_stage_image(&manifest, &vm_dir, &dest, progress)?;
+    download_and_stage_image(&manifest, &dest, progress)?;
     cleanup_stale_images(&vm_dir, &dest);
     Ok(dest)
 }
 
+// ---------------------------------------------------------------------------
+// Custom image override (tester setting).
+//
+// `set_custom_vm_image` records a local image path as the `custom_vm_image`
+// setting (see `crate::settings`); the next VM start stages it in place of the
+// bundled/published image. The `ORCABOT_VM_IMAGE` env override still wins.
+// ---------------------------------------------------------------------------
+
+/// The recorded custom image, if any.
+pub fn custom_image(data_dir: &Path) -> Option<PathBuf> {
+    crate::settings::load(data_dir).custom_vm_image
+}
+
+/// Record `path` as the custom image (after `validate_custom_image`).
+pub fn set_custom_image(data_dir: &Path, path: &Path) -> Result<(), VMError> {
+    crate::settings::update(data_dir, |s| s.custom_vm_image = Some(path.to_path_buf()))?;
+    Ok(())
+}
+
+/// Forget the custom image; the next start goes back to the normal image.
+pub fn clear_custom_image(data_dir: &Path) -> Result<(), VMError> {
+    crate::settings::update(data_dir, |s| s.custom_vm_image = None)?;
+    Ok(())
+}
+
+/// On-disk format of a candidate image, sniffed from its magic bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Gzip,
+    Zstd,
+    Xz,
+    Qcow2,
+    Raw,
+    Unknown,
+}
+
+impl ImageFormat {
+    fn is_compressed(self) -> bool {
+        matches!(self, ImageFormat::Gzip | ImageFormat::Zstd | ImageFormat::Xz)
+    }
+}
+
+impl From<Compression> for ImageFormat {
+    fn from(codec: Compression) -> Self {
+        match codec {
+            Compression::Gzip => ImageFormat::Gzip,
+            Compression::Zstd => ImageFormat::Zstd,
+            Compression::Xz => ImageFormat::Xz,
+        }
+    }
+}
+
+fn sniff_image_format(path: &Path) -> Result<ImageFormat, VMError> {
+    let mut head = [0u8; 1082];
+    let mut f = File::open(path)?;
+    let mut n = 0;
+    while n < head.len() {
+        match f.read(&mut head[n..])? {
+            0 => break,
+            m => n += m,
+        }
+    }
+    let head = &head[..n];
+    Ok(if let Some(codec) = compression_kind(head).and_then(Compression::from_kind) {
+        codec.into()
+    } else if head.starts_with(b"QFI\xfb") {
+        ImageFormat::Qcow2
+    } else if head.len() >= 512 && head[510..512] == [0x55, 0xaa] // MBR / protective MBR
+        || head.len() >= 1082 && head[1080..1082] == [0x53, 0xef] // bare ext2/3/4
+    {
+        ImageFormat::Raw
+    } else {
+        ImageFormat::Unknown
+    })
+}
+
+/// Check that `path` can plausibly boot on this host. Hard errors for a missing,
+/// empty, or wrong-format file; soft warnings (returned) for things we can only
+/// guess at, like an architecture hint in the file name that doesn't match ours.
+pub fn validate_custom_image(path: &Path) -> Result<(ImageFormat, Vec<String>), VMError> {
+    let meta = fs::metadata(path).map_err(|_| VMError::ImageNotFound(path.to_path_buf()))?;
+    if !meta.is_file() || meta.len() == 0 {
+        return Err(VMError::StartFailed(format!(
+            "{} is not a non-empty regular file",
+            path.display()
+        )));
+    }
+
+    let format = sniff_image_format(path)?;
+    let mut warnings = Vec::new();
+    let named = Compression::from_path(path);
+    if named.map(ImageFormat::from) != Some(format) && (named.is_some() || format.is_compressed()) {
+        // stage_image_to decides whether to decompress by extension alone.
+        return Err(VMError::StartFailed(format!(
+            "{} is {:?} but {}; rename it so it stages correctly",
+            path.display(),
+            format,
+            match named {
+                Some(codec) => format!("has a {} extension", codec.as_str()),
+                None => "has no compression extension (.gz, .zst, .xz)".to_string(),
+            }
+        )));
+    }
+    if cfg!(target_os = "macos") && format == ImageFormat::Qcow2 {
+        return Err(VMError::StartFailed(
+            "qcow2 images aren't supported by Virtualization.framework; convert to raw".into(),
+        ));
+    }
+    if cfg!(target_os = "windows") && named.is_none() {
+        warnings.push(
+            "WSL expects a compressed rootfs tarball (.tar.gz, .tar.zst, .tar.xz)".to_string(),
+        );
+    }
+    if format == ImageFormat::Unknown {
+        warnings.push("no partition table or filesystem signature found; may not boot".into());
+    }
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let hinted = if name.contains("aarch64") || name.contains("arm64") {
+        Some("aarch64")
+    } else if name.contains("x86_64") || name.contains("amd64") {
+        Some("x86_64")
+    } else {
+        None
+    };
+    if let Some(arch) = hinted.filter(|a| *a != std::env::consts::ARCH) {
+        warnings.push(format!(
+            "file name suggests {arch} but this host is {}",
+            std::env::consts::ARCH
+        ));
+    }
+    Ok((format, warnings))
+}
+
+/// What `validate_boot_set` found out about one boot artifact.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct BootArtifactCheck {
+    pub path: String,
+    pub bytes: u64,
+    /// What the header says it is: "elf", "bzimage", "arm64-image", "pe",
+    /// "gzip", "zstd", "xz", "lz4", "bzip2", "cpio", an `ImageFormat` name for
+    /// the disk image, or "unknown".
+    pub kind: String,
+    /// Target architecture, when the headers (or, for the image, the file
+    /// name) say.
+    pub arch: Option<String>,
+    /// Problems that would stop it booting.
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// `validate_boot_set` result: each artifact plus what holds across them.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct BootSetReport {
+    pub kernel: BootArtifactCheck,
+    pub initrd: BootArtifactCheck,
+    pub image: BootArtifactCheck,
+    /// Architecture mismatches, between the artifacts or with this host.
+    pub errors: Vec<String>,
+    /// No errors anywhere in the report.
+    pub ok: bool,
+}
+
+/// First `n` bytes of `path` (fewer if it is shorter).
+pub(crate) fn read_head(path: &Path, n: usize) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(n);
+    File::open(path)?.take(n as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Architecture of an ELF file from its `e_machine` field.
+pub(crate) fn elf_arch(head: &[u8]) -> Option<&'static str> {
+    if !head.starts_with(b"\x7fELF") || head.len() < 20 {
+        return None;
+    }
+    let machine = match head[5] {
+        2 => u16::from_be_bytes([head[18], head[19]]),
+        _ => u16::from_le_bytes([head[18], head[19]]),
+    };
+    match machine {
+        0x3e => Some("x86_64"),
+        0xb7 => Some("aarch64"),
+        _ => None,
+    }
+}
+
+/// Compression (or archive) format from magic bytes.
+fn compression_kind(head: &[u8]) -> Option<&'static str> {
+    const MAGIC: [(&[u8], &str); 7] = [
+        (&[0x1f, 0x8b], "gzip"),
+        (&[0x28, 0xb5, 0x2f, 0xfd], "zstd"),
+        (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], "xz"),
+        (&[0x02, 0x21, 0x4c, 0x18], "lz4"),
+        (b"BZh", "bzip2"),
+        (b"070701", "cpio"),
+        (b"070702", "cpio"),
+    ];
+    MAGIC
+        .iter()
+        .find(|(magic, _)| head.starts_with(magic))
+        .map(|(_, kind)| *kind)
+}
+
+/// Identify a kernel: a bare ELF vmlinux, an x86 bzImage ("HdrS" at 0x202), an
+/// arm64 Image ("ARM\x64" at 0x38), or something compressed.
+fn kernel_kind(head: &[u8]) -> (&'static str, Option<&'static str>) {
+    if let Some(arch) = elf_arch(head) {
+        return ("elf", Some(arch));
+    }
+    if head.get(0x38..0x3c) == Some(b"ARM\x64") {
+        return ("arm64-image", Some("aarch64"));
+    }
+    if head.get(0x202..0x206) == Some(b"HdrS") {
+        return ("bzimage", Some("x86_64"));
+    }
+    if head.starts_with(b"\x7fELF") {
+        return ("elf", None);
+    }
+    if head.starts_with(b"MZ") {
+        return ("pe", None);
+    }
+    (compression_kind(head).unwrap_or("unknown"), None)
+}
+
+/// Architecture of the first ELF binary in a newc cpio stream (an initramfs
+/// ships busybox or an init binary near the front). Reads at most 16 MB.
+fn cpio_elf_arch(reader: impl Read) -> Option<&'static str> {
+    let mut reader = reader.take(16 * 1024 * 1024);
+    let mut header = [0u8; 110];
+    loop {
+        reader.read_exact(&mut header).ok()?;
+        if !header.starts_with(b"07070") {
+            return None;
+        }
+        let field = |i: usize| {
+            std::str::from_utf8(&header[6 + i * 8..14 + i * 8])
+                .ok()
+                .and_then(|h| u32::from_str_radix(h, 16).ok())
+        };
+        let (file_size, name_size) = (field(6)? as u64, field(11)? as u64);
+        let pad4 = |n: u64| (4 - n % 4) % 4;
+        let mut name = vec![0u8; (name_size + pad4(110 + name_size)) as usize];
+        reader.read_exact(&mut name).ok()?;
+        if name.starts_with(b"TRAILER!!!") {
+            return None;
+        }
+        let mut body = (&mut reader).take(file_size);
+        let mut head = Vec::with_capacity(20);
+        (&mut body).take(20).read_to_end(&mut head).ok()?;
+        if let Some(arch) = elf_arch(&head) {
+            return Some(arch);
+        }
+        io::copy(&mut body, &mut io::sink()).ok()?;
+        io::copy(&mut (&mut reader).take(pad4(file_size)), &mut io::sink()).ok()?;
+    }
+}
+
+/// Size and existence checks shared by every artifact; `None` when the file
+/// can't be examined further.
+fn boot_artifact(path: &Path) -> (BootArtifactCheck, Option<Vec<u8>>) {
+    let mut check = BootArtifactCheck {
+        path: path.display().to_string(),
+        kind: "unknown".to_string(),
+        ..Default::default()
+    };
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_file() && meta.len() > 0 => check.bytes = meta.len(),
+        Ok(_) => check.errors.push("not a non-empty regular file".to_string()),
+        Err(e) => check.errors.push(format!("cannot read: {e}")),
+    }
+    if !check.errors.is_empty() {
+        return (check, None);
+    }
+    match read_head(path, 4096) {
+        Ok(head) => (check, Some(head)),
+        Err(e) => {
+            check.errors.push(format!("cannot read: {e}"));
+            (check, None)
+        }
+    }
+}
+
+fn check_kernel(path: &Path) -> BootArtifactCheck {
+    let (mut check, head) = boot_artifact(path);
+    let Some(head) = head else {
+        return check;
+    };
+    let (kind, arch) = kernel_kind(&head);
+    check.kind = kind.to_string();
+    check.arch = arch.map(str::to_string);
+    match kind {
+        "unknown" | "cpio" => check.errors.push("not a Linux kernel".to_string()),
+        "gzip" | "zstd" | "xz" | "lz4" | "bzip2" if cfg!(target_os = "macos") => check
+            .errors
+            .push(format!("{kind}-compressed; Virtualization.framework needs an uncompressed kernel")),
+        "gzip" | "zstd" | "xz" | "lz4" | "bzip2" => check
+            .warnings
+            .push(format!("{kind}-compressed; its architecture can't be checked")),
+        _ if arch.is_none() => check
+            .warnings
+            .push("architecture not recognised from the kernel header".to_string()),
+        _ => {}
+    }
+    check
+}
+
+fn check_initrd(path: &Path) -> BootArtifactCheck {
+    let (mut check, head) = boot_artifact(path);
+    let Some(head) = head else {
+        return check;
+    };
+    let kind = compression_kind(&head).unwrap_or("unknown");
+    check.kind = kind.to_string();
+    let arch = match kind {
+        "cpio" => File::open(path).ok().and_then(|f| cpio_elf_arch(BufReader::new(f))),
+        "gzip" | "zstd" | "xz" => File::open(path).ok().and_then(|f| {
+            let codec = Compression::from_kind(kind)?;
+            cpio_elf_arch(codec.decoder(BufReader::new(f)).ok()?)
+        }),
+        "unknown" => {
+            check
+                .errors
+                .push("not an initramfs (no cpio or compression signature)".to_string());
+            None
+        }
+        _ => None,
+    };
+    check.arch = arch.map(str::to_string);
+    if arch.is_none() && kind != "unknown" {
+        check
+            .warnings
+            .push("no ELF binary found to tell its architecture".to_string());
+    }
+    check
+}
+
+fn check_image(path: &Path) -> BootArtifactCheck {
+    let (mut check, head) = boot_artifact(path);
+    if head.is_none() {
+        return check;
+    }
+    match validate_custom_image(path) {
+        Ok((format, warnings)) => {
+            check.kind = format!("{:?}", format).to_lowercase();
+            check.warnings = warnings;
+        }
+        Err(e) => check.errors.push(e.to_string()),
+    }
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    check.arch = if name.contains("aarch64") || name.contains("arm64") {
+        Some("aarch64".to_string())
+    } else if name.contains("x86_64") || name.contains("amd64") {
+        Some("x86_64".to_string())
+    } else {
+        None
+    };
+    check
+}
+
+/// Check a custom kernel, initrd and disk image as a set before booting them:
+/// each exists and has the right kind of header (ELF/bzImage/arm64 Image for
+/// the kernel, cpio or a compressed cpio for the initrd, a disk image format as
+/// in `validate_custom_image`), and every architecture that can be read, from
+/// the kernel header or the first ELF binary in the initrd, matches the others
+/// and this host. The image's architecture only comes from its file name, so a
+/// mismatch there is left as that artifact's warning.
+pub fn validate_boot_set(kernel: &Path, initrd: &Path, image: &Path) -> BootSetReport {
+    let kernel = check_kernel(kernel);
+    let initrd = check_initrd(initrd);
+    let image = check_image(image);
+    let host = std::env::consts::ARCH;
+    let errors: Vec<String> = [("kernel", &kernel), ("initrd", &initrd)]
+        .iter()
+        .filter_map(|(name, check)| {
+            let arch = check.arch.as_deref()?;
+            (arch != host).then(|| format!("{name} is {arch} but this host is {host}"))
+        })
+        .chain(match (kernel.arch.as_deref(), initrd.arch.as_deref()) {
+            (Some(k), Some(i)) if k != i => Some(format!("kernel is {k} but initrd is {i}")),
+            _ => None,
+        })
+        .collect();
+    let ok = errors.is_empty()
+        && [&kernel, &initrd, &image]
+            .iter()
+            .all(|check| check.errors.is_empty());
+    BootSetReport {
+        kernel,
+        initrd,
+        image,
+        errors,
+        ok,
+    }
+}
+
+/// What this build can do with custom VM artifacts on this host, from
+/// `supported_formats`. Tool-dependent entries reflect what is installed now.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct SupportedFormats {
+    /// Compressions a custom image may use; staging decompresses them (picked by
+    /// file extension: `.gz`, `.zst`, `.xz`).
+    pub image_compression: Vec<&'static str>,
+    /// Disk-image formats the VM boots from as they are. WSL imports a rootfs
+    /// tarball ("tar") instead.
+    pub image_formats: Vec<&'static str>,
+    /// Formats `convert_image` can write; empty without qemu-img.
+    pub convert_to: Vec<&'static str>,
+    /// Compressed kernels the boot path accepts besides an uncompressed one
+    /// (an x86 bzImage decompresses itself and always works under QEMU).
+    pub kernel_compression: Vec<&'static str>,
+    /// Usable ways to boot: "vz-direct-kernel", "qemu-direct-kernel",
+    /// "qemu-disk" or "wsl-import".
+    pub boot_modes: Vec<&'static str>,
+    pub qemu_img: bool,
+    /// Why something above is missing (e.g. how to install qemu-img).
+    pub notes: Vec<String>,
+}
+
+/// Probe which image, compression and boot options are usable right now, so a
+/// custom image can be checked against them before it's selected.
+pub fn supported_formats() -> SupportedFormats {
+    use super::command::on_path;
+    let qemu_img = on_path("qemu-img");
+    let qemu_system = on_path(&format!("qemu-system-{}", std::env::consts::ARCH));
+    let mut notes = Vec::new();
+    if !qemu_img && !cfg!(target_os = "windows") {
+        notes.push(
+            "qemu-img not found: no raw/qcow2 conversion (brew install qemu / apt install qemu-utils)"
+                .to_string(),
+        );
+    }
+    let mut boot_modes = Vec::new();
+    if cfg!(target_os = "windows") {
+        boot_modes.push("wsl-import");
+    } else {
+        if cfg!(target_os = "macos") {
+            boot_modes.push("vz-direct-kernel");
+        }
+        if qemu_system {
+            boot_modes.push("qemu-direct-kernel");
+            if cfg!(target_os = "linux") {
+                boot_modes.push("qemu-disk");
+            }
+        } else {
+            notes.push(format!(
+                "qemu-system-{} not found: no QEMU boot{}",
+                std::env::consts::ARCH,
+                if cfg!(target_os = "macos") { " fallback" } else { "" }
+            ));
+        }
+    }
+    let image_formats = if cfg!(target_os = "windows") {
+        vec!["tar"]
+    } else if cfg!(target_os = "linux") {
+        vec!["raw", "qcow2"]
+    } else {
+        // VZ and the macOS QEMU fallback both attach the disk as raw.
+        vec!["raw"]
+    };
+    // VZ only boots an uncompressed kernel; QEMU unpacks a gzipped arm64 Image.
+    let kernel_compression = if cfg!(target_os = "linux") && cfg!(target_arch = "aarch64") {
+        vec!["gzip"]
+    } else {
+        Vec::new()
+    };
+    SupportedFormats {
+        image_compression: vec!["gzip", "zstd", "xz"],
+        image_formats,
+        convert_to: if qemu_img { vec!["raw", "qcow2"] } else { Vec::new() },
+        kernel_compression,
+        boot_modes,
+        qemu_img,
+        notes,
+    }
+}
+
+/// Bound on `qemu-img measure`, which only reads image metadata.
+const QEMU_IMG_MEASURE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Convert `src` to `format` (raw or qcow2) at `dst` with `qemu-img convert`.
+/// Checks first (`qemu-img measure`) that the destination filesystem has room
+/// for the result plus the usual free-space floor. Writes a sibling temp file and
+/// renames it over `dst` only once complete. `progress` gets qemu-img's 0–100
+/// percentage. Returns the size of the converted file.
+pub fn convert_image(
+    src: &Path,
+    dst: &Path,
+    format: ImageFormat,
+    progress: &dyn Fn(f64),
+) -> Result<u64, VMError> {
+    let target = match format {
+        ImageFormat::Raw => "raw",
+        ImageFormat::Qcow2 => "qcow2",
+        other => {
+            return Err(VMError::InvalidConfig(format!(
+                "can't convert to {:?}; use raw or qcow2",
+                other
+            )))
+        }
+    };
+    let source = match sniff_image_format(src)? {
+        compressed @ (ImageFormat::Gzip | ImageFormat::Zstd | ImageFormat::Xz) => {
+            return Err(VMError::StartFailed(format!(
+                "{} is {:?}-compressed; decompress it before converting",
+                src.display(),
+                compressed
+            )))
+        }
+        ImageFormat::Qcow2 => Some("qcow2"),
+        ImageFormat::Raw => Some("raw"),
+        // Let qemu-img probe it.
+        ImageFormat::Unknown => None,
+    };
+    if source == Some(target) {
+        return Err(VMError::InvalidConfig(format!(
+            "{} is already {}",
+            src.display(),
+            target
+        )));
+    }
+    let source_args: Vec<&str> = source.map(|f| vec!["-f", f]).unwrap_or_default();
+    let src_arg = src.to_string_lossy();
+    let dir = dst
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mut args = vec!["measure", "--output=json", "-O", target];
+    args.extend(&source_args);
+    args.push(&src_arg);
+    let measured = super::command::run_command("qemu-img", &args, QEMU_IMG_MEASURE_TIMEOUT)
+        .map_err(qemu_img_missing)?;
+    let required = serde_json::from_str::<serde_json::Value>(&measured.stdout)
+        .ok()
+        .and_then(|j| j.get("required")?.as_u64())
+        .ok_or_else(|| {
+            VMError::CommandFailed(format!(
+                "unexpected `qemu-img measure` output: {:?}",
+                measured.stdout.trim()
+            ))
+        })?;
+    match super::runtime::available_bytes(dir) {
+        Ok(avail) if avail < required.saturating_add(super::runtime::min_free_bytes()) => {
+            return Err(VMError::StartFailed(format!(
+                "Low disk space: converting needs {} MB but only {} MB is free on {}",
+                required / (1024 * 1024),
+                avail / (1024 * 1024),
+                dir.display()
+            )));
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("[vm-image] convert: can't check free space: {}", e),
+    }
+
+    let name = dst
+        .file_name()
+        .ok_or_else(|| VMError::InvalidConfig(format!("{} is not a file path", dst.display())))?;
+    let tmp = dir.join(format!(".{}.convert-part", name.to_string_lossy()));
+    let _ = fs::remove_file(&tmp);
+    eprintln!(
+        "[vm-image] converting {} -> {} ({})",
+        src.display(),
+        dst.display(),
+        target
+    );
+    let mut child = std::process::Command::new("qemu-img")
+        .args(["convert", "-p"])
+        .args(&source_args)
+        .args(["-O", target])
+        .arg(src)
+        .arg(&tmp)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| qemu_img_missing(e.into()))?;
+    let stderr = child.stderr.take();
+    let stderr = std::thread::spawn(move || {
+        let mut s = String::new();
+        if let Some(mut pipe) = stderr {
+            let _ = pipe.read_to_string(&mut s);
+        }
+        s
+    });
+
+    // `-p` redraws "    (12.34/100%)" with a carriage return on each update.
+    if let Some(mut stdout) = child.stdout.take() {
+        let mut buf = [0u8; 256];
+        let mut line = Vec::new();
+        loop {
+            let n = match stdout.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            for &b in &buf[..n] {
+                if b == b'\r' || b == b'\n' {
+                    if let Some(pct) = parse_convert_progress(&String::from_utf8_lossy(&line)) {
+                        progress(pct);
+                    }
+                    line.clear();
+                } else {
+                    line.push(b);
+                }
+            }
+        }
+    }
+    let status = child.wait()?;
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let _ = fs::remove_file(&tmp);
+        return Err(VMError::CommandFailed(format!(
+            "`qemu-img convert` failed ({}): {}",
+            status,
+            stderr.trim()
+        )));
+    }
+    if let Err(e) = fs::rename(&tmp, dst) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(fs::metadata(dst)?.len())
+}
+
+/// Say how to get qemu-img when it isn't installed.
+fn qemu_img_missing(e: VMError) -> VMError {
+    match e {
+        VMError::Io(io) if io.kind() == io::ErrorKind::NotFound => VMError::StartFailed(
+            "qemu-img not found; install QEMU (brew install qemu / apt install qemu-utils)".into(),
+        ),
+        other => other,
+    }
+}
+
+/// The percentage from one `qemu-img convert -p` progress line, "(12.34/100%)".
+fn parse_convert_progress(line: &str) -> Option<f64> {
+    let pct: f64 = line.trim().strip_prefix('(')?.split('/').next()?.parse().ok()?;
+    Some(pct.clamp(0.0, 100.0))
+}
+
 /// Short, stable token for a local source image's content: nanosecond mtime + size
 /// (via `source_signature`), hashed. Changes whenever the source changes — including
 /// its SIZE, the property the stale VZ/macOS disk-size cache keys on. `DefaultHasher`
@@ -462,6 +1518,24 @@ fn local_content_token(src: &Path) -> String {
     format!("{:016x}", hasher.finish())
 }
 
+/// `ORCABOT_VM_IMAGE`, when set (see [`ensure_vm_image`]).
+fn image_override() -> Option<PathBuf> {
+    std::env::var_os("ORCABOT_VM_IMAGE")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Where the `ORCABOT_VM_IMAGE` image `src` is staged: named by its source
+/// signature, so swapping to an image of a different size lands on a fresh path.
+fn override_image_dest(vm_dir: &Path, src: &Path) -> PathBuf {
+    vm_dir.join(format!("sandbox-ovr-{}.img", local_content_token(src)))
+}
+
+/// Where a local resource image `src` is staged (named like the override).
+fn resource_image_dest(vm_dir: &Path, src: &Path) -> PathBuf {
+    vm_dir.join(format!("sandbox-res-{}.img", local_content_token(src)))
+}
+
 /// Remove staged sandbox images + sidecars other than `keep`, so old versions
 /// don't accumulate in the data dir. Best-effort.
 fn cleanup_stale_images(vm_dir: &Path, keep: &Path) {
@@ -480,6 +1554,8 @@ fn cleanup_stale_images(vm_dir: &Path, keep: &Path) {
             || n == "sandbox.img.stamp"
             || n == "sandbox.img.version"
             || n.ends_with(".img.gz.part")
+            || n.ends_with(".img.partial")
+            || n.contains(".tmp-") // a stage_image_to temp file left by a crash
             || (n.starts_with("sandbox-") && (n.ends_with(".img") || n.ends_with(".img.stamp")));
         if stale {
             let _ = fs::remove_file(&path);
@@ -489,62 +1565,259 @@ fn cleanup_stale_images(vm_dir: &Path, keep: &Path) {
 
 fn download_and_stage_image(
     manifest: &VmImageManifest,
-    vm_dir: &Path,
     target: &Path,
     progress: &dyn Fn(u64, u64),
 ) -> Result<(), VMError> {
-    let tmp_gz = vm_dir.join("sandbox.img.gz.part");
-
-    let resp = ureq::get(&manifest.url)
-        .call()
-        .map_err(|e| VMError::Download(format!("request failed: {e}")))?;
-    let total: u64 = resp
-        .header("Content-Length")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-
-    // Stream to disk, hashing as we go.
-    let mut reader = resp.into_reader();
-    let mut writer = BufWriter::new(File::create(&tmp_gz)?);
+    fetch_image(
+        &manifest.url,
+        &manifest.sha256,
+        target,
+        progress,
+        &AtomicBool::new(false),
+    )
+}
+
+/// Attempts at a download before giving up; each resumes where the last stopped.
+const FETCH_ATTEMPTS: u32 = 5;
+
+const FETCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A transfer that delivers nothing for this long is dropped and retried.
+const FETCH_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How one [`fetch_image`] request ended, short of a fatal error.
+enum FetchAttempt {
+    Done,
+    /// Transient (connection dropped, timeout, 5xx): try again from where the
+    /// partial file ends.
+    Retry(String),
+}
+
+/// Download `url` to `dest`, verifying it against `expected_sha256` (hex) and
+/// decompressing it if it's gzip, zstd or xz. Bytes land in `<dest>.partial` first: an
+/// interrupted transfer is retried with an HTTP range request, and a later call
+/// picks up a partial file left by an earlier one (cancelled, crashed, out of
+/// attempts). `dest` only ever appears complete and verified. Setting `cancel`
+/// stops the download, keeping the partial file for the next call.
+pub fn fetch_image(
+    url: &str,
+    expected_sha256: &str,
+    dest: &Path,
+    progress: &dyn Fn(u64, u64),
+    cancel: &AtomicBool,
+) -> Result<(), VMError> {
+    let expected = expected_sha256.trim().to_lowercase();
+    if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(VMError::InvalidConfig(format!(
+            "not a SHA-256 hex digest: {expected_sha256:?}"
+        )));
+    }
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(VMError::InvalidConfig(format!("not an http(s) URL: {url}")));
+    }
+    let dir = dest
+        .parent()
+        .ok_or_else(|| VMError::InvalidConfig(format!("no directory in {}", dest.display())))?;
+    fs::create_dir_all(dir)?;
+    let partial = sibling(dest, ".partial");
+
+    // Hash what an earlier call already fetched, so a resumed download is
+    // still verified end to end.
     let mut hasher = Sha256::new();
-    let mut buf = vec![0u8; 1 << 20];
-    let mut downloaded: u64 = 0;
+    let mut have = match File::open(&partial) {
+        Ok(file) => io::copy(&mut BufReader::new(file), &mut hasher)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    if have > 0 {
+        eprintln!("[vm-image] resuming {} at byte {}", url, have);
+    }
+
+    // ureq follows up to 5 redirects, keeping the Range header.
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(FETCH_CONNECT_TIMEOUT)
+        .timeout_read(FETCH_READ_TIMEOUT)
+        .build();
+    let mut watch = super::runtime::FreeSpaceWatch::new(dir);
+    let mut attempt = 1;
     loop {
-        let n = reader.read(&mut buf)?;
-        if n == 0 {
-            break;
+        let request = FetchRequest {
+            agent: &agent,
+            url,
+            partial: &partial,
+            cancel,
+        };
+        match request.run(&mut hasher, &mut have, progress, &mut watch)? {
+            FetchAttempt::Done => break,
+            FetchAttempt::Retry(why) if attempt < FETCH_ATTEMPTS => {
+                let delay = Duration::from_secs(1 << attempt);
+                eprintln!(
+                    "[vm-image] download interrupted at byte {} ({}); retrying in {}s",
+                    have,
+                    why,
+                    delay.as_secs()
+                );
+                sleep_unless_cancelled(delay, cancel)?;
+                attempt += 1;
+            }
+            FetchAttempt::Retry(why) => {
+                return Err(VMError::Download(format!(
+                    "{why}, after {FETCH_ATTEMPTS} attempts; {have} bytes kept for the next try"
+                )));
+            }
         }
-        hasher.update(&buf[..n]);
-        writer.write_all(&buf[..n])?;
-        downloaded += n as u64;
-        progress(downloaded, total);
     }
-    writer.flush()?;
 
-    let digest = hasher.finalize();
-    let got = hex_encode(digest.as_slice());
-    if got.to_lowercase() != manifest.sha256.to_lowercase() {
-        let _ = fs::remove_file(&tmp_gz);
+    let got = hex_encode(hasher.finalize().as_slice());
+    if got != expected {
+        let _ = fs::remove_file(&partial);
         return Err(VMError::Download(format!(
             "checksum mismatch: expected {}, got {}",
-            manifest.sha256, got
+            expected, got
         )));
     }
 
     // Verified — decompress to a temp file, then atomically rename into place.
-    // A crash / disk-full mid-decompress must NOT leave a partial sandbox.img,
-    // or a later launch could mistake it for a complete (adoptable) image.
-    let tmp_img = vm_dir.join("sandbox.img.part");
-    if let Err(e) = decompress_gzip(&tmp_gz, &tmp_img) {
-        let _ = fs::remove_file(&tmp_img);
-        let _ = fs::remove_file(&tmp_gz);
-        return Err(e);
-    }
-    fs::rename(&tmp_img, target)?;
-    let _ = fs::remove_file(&tmp_gz);
+    // A crash / disk-full mid-decompress must NOT leave a partial image, or a
+    // later launch could mistake it for a complete (adoptable) one. The verified
+    // download is kept until then, so a retry needn't fetch it again.
+    let codec = compression_kind(&read_head(&partial, 6)?).and_then(Compression::from_kind);
+    if let Some(codec) = codec {
+        let tmp = sibling(dest, ".part");
+        if let Err(e) = decompress(&partial, &tmp, codec, &mut watch, None) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        fs::rename(&tmp, dest)?;
+        let _ = fs::remove_file(&partial);
+    } else {
+        fs::rename(&partial, dest)?;
+    }
+    Ok(())
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn sleep_unless_cancelled(delay: Duration, cancel: &AtomicBool) -> Result<(), VMError> {
+    let until = std::time::Instant::now() + delay;
+    while std::time::Instant::now() < until {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(VMError::Download("cancelled".into()));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
     Ok(())
 }
 
+/// One GET of the rest of the download, appended to the partial file.
+struct FetchRequest<'a> {
+    agent: &'a ureq::Agent,
+    url: &'a str,
+    partial: &'a Path,
+    cancel: &'a AtomicBool,
+}
+
+impl FetchRequest<'_> {
+    fn run(
+        &self,
+        hasher: &mut Sha256,
+        have: &mut u64,
+        progress: &dyn Fn(u64, u64),
+        watch: &mut super::runtime::FreeSpaceWatch,
+    ) -> Result<FetchAttempt, VMError> {
+        let mut request = self.agent.get(self.url);
+        if *have > 0 {
+            request = request.set("Range", &format!("bytes={have}-"));
+        }
+        let resp = match request.call() {
+            Ok(resp) => resp,
+            // The range starts at the end: the partial file is already whole.
+            Err(ureq::Error::Status(416, _)) if *have > 0 => return Ok(FetchAttempt::Done),
+            Err(ureq::Error::Status(code, _)) if code == 429 || code >= 500 => {
+                return Ok(FetchAttempt::Retry(format!("HTTP {code}")));
+            }
+            Err(ureq::Error::Status(code, resp)) => {
+                return Err(VMError::Download(format!(
+                    "HTTP {} {}",
+                    code,
+                    resp.status_text()
+                )));
+            }
+            Err(e) => return Ok(FetchAttempt::Retry(e.to_string())),
+        };
+
+        let resumed = resp.status() == 206;
+        if resumed && resp.header("Content-Range").and_then(content_range_start) != Some(*have) {
+            // Not the range asked for; start over rather than splice it in.
+            let _ = fs::remove_file(self.partial);
+            (*have, *hasher) = (0, Sha256::new());
+            return Ok(FetchAttempt::Retry("unexpected Content-Range".into()));
+        }
+        if !resumed && *have > 0 {
+            eprintln!("[vm-image] server ignored the range request; downloading from the start");
+            (*have, *hasher) = (0, Sha256::new());
+        }
+        let total = resp
+            .header("Content-Length")
+            .and_then(|s| s.parse::<u64>().ok())
+            .map_or(0, |len| len + *have);
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(self.partial)?;
+        let mut writer = BufWriter::new(file);
+        let mut reader = resp.into_reader();
+        let mut buf = vec![0u8; 1 << 20];
+        loop {
+            if self.cancel.load(Ordering::SeqCst) {
+                writer.flush()?;
+                return Err(VMError::Download("cancelled".into()));
+            }
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    writer.flush()?;
+                    return Ok(FetchAttempt::Retry(e.to_string()));
+                }
+            };
+            watch.check(total.saturating_sub(*have)).map_err(low_space)?;
+            writer.write_all(&buf[..n])?;
+            hasher.update(&buf[..n]);
+            *have += n as u64;
+            progress(*have, total);
+        }
+        writer.flush()?;
+        if total > 0 && *have < total {
+            return Ok(FetchAttempt::Retry(format!(
+                "connection closed at byte {} of {}",
+                have, total
+            )));
+        }
+        Ok(FetchAttempt::Done)
+    }
+}
+
+/// First byte of a `Content-Range: bytes <first>-<last>/<size>` header.
+fn content_range_start(header: &str) -> Option<u64> {
+    header
+        .trim()
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
 fn hex_encode(bytes: &[u8]) -> String {
     let mut s = String::with_capacity(bytes.len() * 2);
     for b in bytes {
@@ -569,6 +1842,93 @@ mod tests {
         assert!(needs_staging(&src, &dest).unwrap());
     }
 
+    #[test]
+    fn test_interrupted_staging_keeps_the_previous_image() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("sandbox.img");
+        let dest = dir.path().join("staged.img");
+        std::fs::write(&src, b"image v1").unwrap();
+        stage_image_to(&src, &dest, None, None).unwrap();
+        assert!(!needs_staging(&src, &dest).unwrap());
+
+        // A new source that fails mid-stage (here, a corrupt gzip stream).
+        let broken = dir.path().join("sandbox.img.gz");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&b"image v2".repeat(10_000)).unwrap();
+        let mut bytes = gz.finish().unwrap();
+        bytes.truncate(bytes.len() / 2);
+        std::fs::write(&broken, bytes).unwrap();
+        assert!(stage_image_to(&broken, &dest, None, None).is_err());
+
+        // The real path still holds the complete old image, no temp file is left
+        // behind, and the failed source still counts as needing a stage.
+        assert_eq!(std::fs::read(&dest).unwrap(), b"image v1");
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().contains(".tmp-"))
+            .collect();
+        assert!(leftovers.is_empty(), "{leftovers:?}");
+        assert!(needs_staging(&broken, &dest).unwrap());
+
+        let fresh = dir.path().join("fresh.img");
+        assert!(stage_image_to(&broken, &fresh, None, None).is_err());
+        assert!(!fresh.exists());
+        assert!(needs_staging(&broken, &fresh).unwrap());
+    }
+
+    #[test]
+    fn free_space_preflight_counts_only_unstaged_files() {
+        let dir = tempdir().unwrap();
+        assert!(check_free_space(dir.path(), 0).is_ok());
+        #[cfg(unix)]
+        assert!(matches!(
+            check_free_space(dir.path(), u64::MAX),
+            Err(VMError::InsufficientDiskSpace { needed: u64::MAX, .. })
+        ));
+
+        let res = dir.path().join("res");
+        let vm_dir = dir.path().join("vm");
+        std::fs::create_dir_all(&res).unwrap();
+        std::fs::write(res.join("vmlinuz"), b"kernel").unwrap();
+        std::fs::write(res.join("initrd.img"), b"initrd").unwrap();
+        let paths = VMResourcePaths {
+            image: res.join("sandbox.img"), // downloaded, so not counted
+            image_sha256: None,
+            kernel: Some(res.join("vmlinuz")),
+            initrd: Some(res.join("initrd.img")),
+            vz_helper: None,
+        };
+        assert_eq!(pending_local_stages(&paths, &vm_dir).len(), 2);
+        stage_image(&res.join("vmlinuz"), &vm_dir, None).unwrap();
+        let pending = pending_local_stages(&paths, &vm_dir);
+        assert_eq!(pending, vec![(res.join("initrd.img"), vm_dir.join("initrd.img"))]);
+    }
+
+    #[test]
+    fn staging_decision_reports_why() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("kernel");
+        let dest = dir.path().join("staged-kernel");
+        std::fs::write(&src, b"kernel").unwrap();
+        std::fs::write(&dest, b"kernel, guest-modified").unwrap();
+
+        let d = staging_decision(&src, &dest).unwrap();
+        assert!(d.restage && d.reason == "no stamp" && d.stamp.is_none());
+        assert_eq!((d.src_size, d.dest_size), (Some(6), Some(22)));
+
+        std::fs::write(stamp_path(&dest), source_signature(&src).unwrap()).unwrap();
+        let d = staging_decision(&src, &dest).unwrap();
+        assert!(!d.restage && d.reason == "up to date", "{d:?}");
+
+        std::fs::write(&src, b"kernel v2").unwrap();
+        assert_eq!(staging_decision(&src, &dest).unwrap().reason, "source changed");
+
+        assert!(needs_staging(&src, &dest).unwrap());
+        let recorded = last_staging_decisions();
+        assert!(recorded.iter().any(|d| d.dest == dest.display().to_string() && d.restage));
+    }
+
     #[test]
     fn test_copy_file() {
         let dir = tempdir().unwrap();
@@ -576,9 +1936,276 @@ mod tests {
         let dest = dir.path().join("dest.img");
 
         std::fs::write(&src, b"test content").unwrap();
-        copy_file(&src, &dest).unwrap();
+        let mut watch = crate::vm::runtime::FreeSpaceWatch::new(dir.path());
+        let reported = std::cell::Cell::new((0, None));
+        copy_file(&src, &dest, &mut watch, Some(&|done, total| reported.set((done, total))))
+            .unwrap();
 
         let content = std::fs::read_to_string(&dest).unwrap();
         assert_eq!(content, "test content");
+        assert_eq!(reported.get(), (12, Some(12)));
+    }
+
+    #[test]
+    fn test_stage_image_decompresses_each_codec() {
+        let dir = tempdir().unwrap();
+        let image: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&image).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 1);
+        xz.write_all(&image).unwrap();
+        for (name, compressed) in [
+            ("sandbox.img.gz", gz.finish().unwrap()),
+            ("sandbox.img.zst", zstd::encode_all(&image[..], 3).unwrap()),
+            ("sandbox.img.xz", xz.finish().unwrap()),
+        ] {
+            let src = dir.path().join(name);
+            std::fs::write(&src, &compressed).unwrap();
+            assert!(validate_custom_image(&src).is_ok(), "{name}");
+            let out = dir.path().join(name.replace('.', "-"));
+            let staged = stage_image(&src, &out, None).unwrap();
+            assert_eq!(staged, out.join("sandbox.img"), "{name}");
+            assert!(std::fs::read(&staged).unwrap() == image, "{name}");
+        }
+        assert_eq!(Compression::from_path(Path::new("rootfs.tar.bz2")), None);
+    }
+
+    #[test]
+    fn test_staged_image_is_checked_against_its_sha256_file() {
+        let dir = tempdir().unwrap();
+        let image = b"a bootable disk image".repeat(1000);
+        let src = dir.path().join("sandbox.img.gz");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&image).unwrap();
+        std::fs::write(&src, gz.finish().unwrap()).unwrap();
+        let digest = hex_encode(Sha256::digest(&image).as_slice());
+        // `sha256sum sandbox.img.gz` names the compressed bytes; not used.
+        let compressed = hex_encode(Sha256::digest(std::fs::read(&src).unwrap()).as_slice());
+        std::fs::write(dir.path().join("sandbox.img.gz.sha256"), &compressed).unwrap();
+        assert_eq!(read_sha256_file(&src), None);
+        std::fs::write(dir.path().join("sandbox.img.sha256"), format!("{digest}  sandbox.img\n"))
+            .unwrap();
+
+        // Checked against the decompressed bytes.
+        let expected = read_sha256_file(&src).unwrap();
+        assert_eq!(expected, digest);
+        let dest = dir.path().join("staged.img");
+        stage_image_to(&src, &dest, Some(&expected), None).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), image);
+
+        // A corrupted source is refused and leaves nothing staged.
+        let corrupt = dir.path().join("corrupt.img");
+        let mut bytes = image.clone();
+        bytes[500] ^= 0xff;
+        std::fs::write(&corrupt, &bytes).unwrap();
+        let dest = dir.path().join("staged-corrupt.img");
+        match stage_image_to(&corrupt, &dest, Some(&expected), None) {
+            Err(VMError::ChecksumMismatch { expected: e, actual }) => {
+                assert_eq!(e, digest);
+                assert_ne!(actual, digest);
+            }
+            other => panic!("expected ChecksumMismatch, got {other:?}"),
+        }
+        assert!(!dest.exists());
+
+        std::fs::write(dir.path().join("corrupt.img.sha256"), "not a digest").unwrap();
+        assert_eq!(read_sha256_file(&corrupt), None);
+    }
+
+    #[test]
+    fn test_validate_custom_image_rejects_misnamed_gzip() {
+        let dir = tempdir().unwrap();
+        let img = dir.path().join("sandbox.img");
+        std::fs::write(&img, [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+        assert_eq!(sniff_image_format(&img).unwrap(), ImageFormat::Gzip);
+        assert!(validate_custom_image(&img).is_err());
+
+        let gz = dir.path().join("sandbox.img.gz");
+        std::fs::rename(&img, &gz).unwrap();
+        assert!(validate_custom_image(&gz).is_ok());
+
+        // Compressed, but named for a different codec.
+        let zst = dir.path().join("sandbox.img.zst");
+        std::fs::rename(&gz, &zst).unwrap();
+        assert!(validate_custom_image(&zst).is_err());
+    }
+
+    #[test]
+    fn test_parse_convert_progress() {
+        assert_eq!(parse_convert_progress("    (12.50/100%)"), Some(12.5));
+        assert_eq!(parse_convert_progress("(100.00/100%)"), Some(100.0));
+        assert_eq!(parse_convert_progress(""), None);
+        assert_eq!(parse_convert_progress("qemu-img: warning"), None);
+    }
+
+    /// A 64-bit little-endian ELF header for `machine`.
+    fn elf_header(machine: u16) -> Vec<u8> {
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(18, 0);
+        elf.extend(machine.to_le_bytes());
+        elf.resize(64, 0);
+        elf
+    }
+
+    /// A gzipped newc cpio holding `bin/busybox` with `contents`.
+    fn gzipped_initrd(contents: &[u8]) -> Vec<u8> {
+        let mut cpio = Vec::new();
+        for (name, data) in [("bin/busybox", contents), ("TRAILER!!!", &[][..])] {
+            let name = format!("{name}\0");
+            cpio.extend(format!("070701{:08x}", 0).as_bytes());
+            for field in [0o100755, 0, 0, 1, 0, data.len(), 0, 0, 0, 0, name.len(), 0] {
+                cpio.extend(format!("{:08x}", field).as_bytes());
+            }
+            cpio.extend(name.as_bytes());
+            cpio.resize(cpio.len().next_multiple_of(4), 0);
+            cpio.extend(data);
+            cpio.resize(cpio.len().next_multiple_of(4), 0);
+        }
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&cpio).unwrap();
+        gz.finish().unwrap()
+    }
+
+    #[test]
+    fn read_head_and_elf_arch_handle_short_and_big_endian_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bin");
+        fs::write(&path, elf_header(0x3e)).unwrap();
+        assert_eq!(read_head(&path, 20).unwrap().len(), 20);
+        // Shorter than asked for: what there is.
+        assert_eq!(read_head(&path, 4096).unwrap().len(), 64);
+        assert!(read_head(&dir.path().join("missing"), 20).is_err());
+
+        assert_eq!(elf_arch(&elf_header(0x3e)), Some("x86_64"));
+        assert_eq!(elf_arch(&elf_header(0xb7)), Some("aarch64"));
+        assert_eq!(elf_arch(&elf_header(0x28)), None);
+        // EI_DATA = 2: e_machine is big-endian.
+        let mut big = elf_header(0);
+        big[5] = 2;
+        big[18..20].copy_from_slice(&0xb7u16.to_be_bytes());
+        assert_eq!(elf_arch(&big), Some("aarch64"));
+        assert_eq!(elf_arch(&elf_header(0x3e)[..19]), None);
+        assert_eq!(elf_arch(b"MZ\x90\x00"), None);
+    }
+
+    #[test]
+    fn validate_boot_set_reads_headers_and_compares_arches() {
+        let (host, other) = if std::env::consts::ARCH == "aarch64" {
+            (0xb7, 0x3e)
+        } else {
+            (0x3e, 0xb7)
+        };
+        let dir = tempdir().unwrap();
+        let kernel = dir.path().join("vmlinux");
+        let initrd = dir.path().join("initrd.img");
+        let image = dir.path().join("rootfs.img");
+        fs::write(&kernel, elf_header(host)).unwrap();
+        let mut disk = vec![0u8; 512];
+        disk[510..].copy_from_slice(&[0x55, 0xaa]);
+        fs::write(&image, disk).unwrap();
+
+        fs::write(&initrd, gzipped_initrd(&elf_header(other))).unwrap();
+        let report = validate_boot_set(&kernel, &initrd, &image);
+        assert_eq!(report.kernel.kind, "elf");
+        assert_eq!(report.kernel.arch.as_deref(), Some(std::env::consts::ARCH));
+        assert_eq!(report.initrd.kind, "gzip");
+        assert!(report.initrd.arch.is_some_and(|a| a != std::env::consts::ARCH));
+        assert_eq!(report.image.kind, "raw");
+        assert_eq!(report.errors.len(), 2, "{:?}", report.errors);
+        assert!(!report.ok);
+
+        fs::write(&initrd, gzipped_initrd(&elf_header(host))).unwrap();
+        let report = validate_boot_set(&kernel, &initrd, &image);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.ok, "{report:?}");
+
+        let report = validate_boot_set(&dir.path().join("missing"), &image, &image);
+        assert!(!report.kernel.errors.is_empty() && !report.initrd.errors.is_empty());
+        assert!(!report.ok);
+    }
+
+    /// Serve `body` on loopback for `requests` connections, honouring `Range`
+    /// and cutting the first response off after `cut` bytes. Returns the URL
+    /// and the Range headers received.
+    fn serve(
+        body: Vec<u8>,
+        requests: usize,
+        cut: usize,
+    ) -> (String, std::thread::JoinHandle<Vec<Option<String>>>) {
+        use std::io::BufRead;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sandbox.img.gz", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut ranges = Vec::new();
+            for n in 0..requests {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+                let mut range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        range = Some(v.trim().trim_end_matches('-').to_string());
+                    }
+                }
+                let start: usize = range.as_deref().map_or(0, |r| r.parse().unwrap());
+                let rest = &body[start..];
+                let head = if range.is_some() {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n",
+                        start,
+                        body.len() - 1,
+                        body.len()
+                    )
+                } else {
+                    "HTTP/1.1 200 OK\r\n".to_string()
+                };
+                let mut stream = stream;
+                let sent = if n == 0 { &rest[..cut.min(rest.len())] } else { rest };
+                let _ = write!(
+                    stream,
+                    "{head}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    rest.len()
+                );
+                let _ = stream.write_all(sent);
+                ranges.push(range);
+            }
+            ranges
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn fetch_image_resumes_a_partial_download_and_verifies_it() {
+        let image: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&image).unwrap();
+        let gz = gz.finish().unwrap();
+        let sha = hex_encode(Sha256::digest(&gz).as_slice());
+
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("sandbox-test.img");
+        // Left by an earlier, cancelled run; the first request then drops
+        // halfway through the rest, and the retry finishes it.
+        fs::write(sibling(&dest, ".partial"), &gz[..1000]).unwrap();
+        let (url, server) = serve(gz.clone(), 2, (gz.len() - 1000) / 2);
+        fetch_image(&url, &sha, &dest, &|_, _| {}, &AtomicBool::new(false)).unwrap();
+
+        assert_eq!(fs::read(&dest).unwrap(), image);
+        assert!(!sibling(&dest, ".partial").exists());
+        let ranges = server.join().unwrap();
+        assert_eq!(ranges[0].as_deref(), Some("1000"));
+        assert!(ranges[1].as_deref().unwrap().parse::<usize>().unwrap() > 1000);
+
+        // A wrong digest is refused and the download discarded.
+        let (url, _server) = serve(gz, 1, usize::MAX);
+        let other = dir.path().join("other.img");
+        let err = fetch_image(&url, &"0".repeat(64), &other, &|_, _| {}, &AtomicBool::new(false));
+        assert!(matches!(err, Err(VMError::Download(_))));
+        assert!(!other.exists() && !sibling(&other, ".partial").exists());
+        assert_eq!(content_range_start("bytes 100-199/200"), Some(100));
     }
 }
@@ -4,84 +4,172 @@
 //! It uses user-mode networking for port forwarding and VirtioFS
 //! (via virtiofsd) for shared workspace access.
 
-use super::{VMConfig, VMError, VirtualMachine};
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use super::command::on_path;
+use super::reaper::ReapedChild;
+use super::{
+    PortMapping, QemuAccel, VMConfig, VMError, VirtualMachine, VmState, WorkspaceShare,
+};
 use std::process::{Child, Command, Stdio};
 use std::time::{Duration, Instant};
 
 /// Linux VM using QEMU/KVM.
 pub struct QemuVM {
     /// QEMU process handle
-    qemu_process: Option<Child>,
+    qemu_process: Option<ReapedChild>,
     /// virtiofsd process handle (for shared filesystem)
     virtiofsd_process: Option<Child>,
     /// Configuration used to start the VM
     config: Option<VMConfig>,
-    /// Whether the VM is currently running
-    running: bool,
+    /// Lifecycle state, as of the last start/health check/pause/stop
+    state: VmState,
     /// Host URL for sandbox access
     sandbox_url: String,
     /// Path to virtiofsd socket
     virtiofs_socket: Option<std::path::PathBuf>,
+    /// File the guest serial console is logged to (read for the debug-exec token)
+    console_log: Option<std::path::PathBuf>,
 }
 
 impl QemuVM {
+    /// Send QEMU and virtiofsd `signal`. SIGSTOP freezes every vCPU thread with
+    /// guest memory intact, and virtiofsd with it, so a paused sandbox does no
+    /// host I/O on the workspace; SIGCONT lets both carry on. virtiofsd
+    /// (`--sandbox=chroot`, a single process) is continued first and stopped
+    /// last, so QEMU never runs against a frozen file server.
+    fn signal_vm(&self, signal: libc::c_int) -> Result<(), VMError> {
+        let child = self
+            .qemu_process
+            .as_ref()
+            .filter(|_| self.state.is_active())
+            .ok_or_else(|| VMError::PauseFailed("the sandbox VM is not running".into()))?;
+        let virtiofsd = self.virtiofsd_process.as_ref().map(|c| c.id() as libc::pid_t);
+        let signal_virtiofsd = || match virtiofsd {
+            Some(pid) if unsafe { libc::kill(pid, signal) } != 0 => Err(VMError::PauseFailed(
+                format!("virtiofsd: {}", std::io::Error::last_os_error()),
+            )),
+            _ => Ok(()),
+        };
+        if signal == libc::SIGCONT {
+            signal_virtiofsd()?;
+        }
+        child
+            .signal(signal)
+            .map_err(|e| VMError::PauseFailed(e.to_string()))?;
+        if signal == libc::SIGSTOP {
+            if let Err(e) = signal_virtiofsd() {
+                // Don't leave QEMU frozen behind a half-done pause.
+                let _ = child.signal(libc::SIGCONT);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
     pub fn new() -> Self {
         Self {
             qemu_process: None,
             virtiofsd_process: None,
             config: None,
-            running: false,
+            state: VmState::Stopped,
             sandbox_url: "http://127.0.0.1:8080".to_string(),
             virtiofs_socket: None,
+            console_log: None,
         }
     }
 
-    /// Check if KVM is available.
-    fn is_kvm_available() -> bool {
-        std::path::Path::new("/dev/kvm").exists()
+    /// Poll the health endpoint until it answers, the VM exits or `timeout`.
+    fn poll_health(&mut self, timeout: Duration) -> Result<(), VMError> {
+        let start = Instant::now();
+        let addr = format!(
+            "127.0.0.1:{}",
+            self.config
+                .as_ref()
+                .map(|c| c.sandbox_port)
+                .unwrap_or(8080)
+        );
+
+        let console_log = self.console_log.clone();
+        while start.elapsed() < timeout {
+            super::check_vm_process(self.exit_status(), console_log.as_deref())?;
+            if super::health::probe_sandbox_health(&addr, self.config.as_ref()) {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(500));
+        }
+
+        Err(VMError::HealthTimeout(timeout))
     }
 
-    /// Check if QEMU is installed.
-    fn find_qemu_binary() -> Option<String> {
-        for binary in ["qemu-system-x86_64", "qemu-system-aarch64"] {
-            if Command::new("which")
-                .arg(binary)
-                .output()
-                .map(|o| o.status.success())
-                .unwrap_or(false)
-            {
-                return Some(binary.to_string());
+    /// Whether KVM can be used: `/dev/kvm` exists and we may open it
+    /// read-write. The error says which part is missing.
+    fn kvm_status() -> Result<(), String> {
+        let dev = std::path::Path::new("/dev/kvm");
+        if !dev.exists() {
+            return Err("/dev/kvm does not exist".to_string());
+        }
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(dev)
+            .map(|_| ())
+            .map_err(|e| format!("/dev/kvm is not usable ({e})"))
+    }
+
+    /// Resolve `accel` against `kvm` (from [`Self::kvm_status`]) to whether
+    /// QEMU runs with KVM.
+    fn use_kvm(accel: QemuAccel, kvm: Result<(), String>) -> Result<bool, VMError> {
+        match (accel, kvm) {
+            (QemuAccel::Tcg, _) => Ok(false),
+            (_, Ok(())) => Ok(true),
+            (QemuAccel::Auto, Err(why)) => {
+                eprintln!("Warning: KVM not available ({why}), using software emulation (slower)");
+                Ok(false)
             }
+            (QemuAccel::Kvm, Err(why)) => Err(VMError::StartFailed(format!(
+                "KVM is required (qemu_accel = kvm) but {why}. Enable virtualization \
+                 (VT-x/AMD-V) in the firmware, load kvm_intel or kvm_amd, and add your \
+                 user to the kvm group; or set qemu_accel to auto or tcg"
+            ))),
         }
-        None
+    }
+
+    /// Check if QEMU is installed.
+    fn find_qemu_binary() -> Option<String> {
+        ["qemu-system-x86_64", "qemu-system-aarch64"]
+            .into_iter()
+            .find(|binary| on_path(binary))
+            .map(str::to_string)
     }
 
     /// Check if virtiofsd is available.
     fn is_virtiofsd_available() -> bool {
-        Command::new("which")
-            .arg("virtiofsd")
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
+        on_path("virtiofsd")
     }
 
     /// Start virtiofsd for shared filesystem.
+    ///
+    /// The socket lives in the runtime dir (`ORCABOT_RUNTIME_DIR` /
+    /// `$XDG_RUNTIME_DIR`), not blindly in `/tmp`, which may be noexec, tiny, or
+    /// hidden from QEMU's sandbox on locked-down systems.
     fn start_virtiofsd(&mut self, workspace_path: &std::path::Path) -> Result<(), VMError> {
-        let socket_dir = std::env::temp_dir();
-        let socket_path = socket_dir.join(format!("orcabot-virtiofs-{}.sock", std::process::id()));
+        let socket_dir = super::runtime::runtime_dir()?;
+        let socket_path = socket_dir.join(format!(
+            "{}{}.sock",
+            super::VIRTIOFS_SOCKET_PREFIX,
+            std::process::id()
+        ));
 
         // Remove stale socket if exists
         let _ = std::fs::remove_file(&socket_path);
 
+        let mut socket_arg = std::ffi::OsString::from("--socket-path=");
+        socket_arg.push(&socket_path);
+        let mut shared_arg = std::ffi::OsString::from("--shared-dir=");
+        shared_arg.push(workspace_path);
         let child = Command::new("virtiofsd")
-            .args([
-                &format!("--socket-path={}", socket_path.display()),
-                &format!("--shared-dir={}", workspace_path.display()),
-                "--cache=auto",
-                "--sandbox=chroot",
-            ])
+            .arg(socket_arg)
+            .arg(shared_arg)
+            .args(["--cache=auto", "--sandbox=chroot"])
             .stdout(Stdio::null())
             .stderr(Stdio::inherit())
             .spawn()
@@ -97,9 +185,10 @@ impl QemuVM {
     }
 
     /// Build QEMU command with all necessary arguments.
-    fn build_qemu_command(&self, config: &VMConfig, use_kvm: bool) -> Command {
+    fn build_qemu_command(&self, config: &VMConfig, use_kvm: bool) -> Result<Command, VMError> {
         let qemu_binary = Self::find_qemu_binary().unwrap_or_else(|| "qemu-system-x86_64".into());
         let mut cmd = Command::new(&qemu_binary);
+        cmd.args(["-name", super::QEMU_NAME]);
 
         // Machine type and acceleration
         if use_kvm {
@@ -109,18 +198,18 @@ impl QemuVM {
         cmd.args(["-cpu", if use_kvm { "host" } else { "qemu64" }]);
 
         // CPU and memory
-        cmd.args(["-smp", &config.cpus.to_string()]);
+        cmd.args(["-smp", &config.qemu_smp()?]);
         cmd.args(["-m", &format!("{}M", config.memory_mb())]);
 
         // Kernel boot (if provided)
         if let Some(ref kernel) = config.kernel_path {
-            cmd.args(["-kernel", kernel.to_str().unwrap_or_default()]);
+            cmd.arg("-kernel").arg(kernel);
         }
         if let Some(ref initrd) = config.initrd_path {
-            cmd.args(["-initrd", initrd.to_str().unwrap_or_default()]);
+            cmd.arg("-initrd").arg(initrd);
         }
-        if let Some(ref cmdline) = config.kernel_cmdline {
-            cmd.args(["-append", cmdline]);
+        if let Some(cmdline) = config.cmdline("console=ttyS0") {
+            cmd.args(["-append", &cmdline]);
         }
 
         // Root filesystem (QCOW2 or raw)
@@ -133,52 +222,77 @@ impl QemuVM {
             "-drive",
             &format!(
                 "file={},format={},if=virtio",
-                config.image_path.display(),
+                super::qemu_opt_path(&config.image_path)?,
                 image_format
             ),
         ]);
 
         // Network with port forwarding: host TCP (config.sandbox_port, maybe
-        // dynamic if 8080 was busy) -> guest 8080 (fixed image default).
+        // dynamic if 8080 was busy) -> guest 8080 (fixed image default). Always
+        // on loopback, whatever `bind_address` says: the sandbox only checks a
+        // shared token. Explicit because slirp binds every interface without one.
         cmd.args([
             "-netdev",
             &format!(
-                "user,id=net0,hostfwd=tcp::{}-:{}",
-                config.sandbox_port, super::SANDBOX_GUEST_PORT
+                "user,id=net0,hostfwd=tcp:127.0.0.1:{}-:{}",
+                config.sandbox_port,
+                super::SANDBOX_GUEST_PORT
             ),
         ]);
         cmd.args(["-device", "virtio-net-pci,netdev=net0"]);
 
-        // VirtioFS for shared workspace (if virtiofsd is running)
-        if let Some(ref socket_path) = self.virtiofs_socket {
+        // Guest entropy (see VMConfig::enable_rng).
+        cmd.args(config.qemu_rng_args());
+
+        // Shared workspace: VirtioFS if virtiofsd is running, else 9p. None for a
+        // network share; the guest init mounts the export named by the
+        // `orcabot.share=` cmdline arg.
+        if config.workspace_share == WorkspaceShare::Local {
+            if let Some(ref socket_path) = self.virtiofs_socket {
+                cmd.args([
+                    "-chardev",
+                    &format!("socket,id=char0,path={}", super::qemu_opt_path(socket_path)?),
+                ]);
+                cmd.args(["-device", "vhost-user-fs-pci,chardev=char0,tag=workspace"]);
+                // Required for vhost-user
+                cmd.args(["-object", "memory-backend-memfd,id=mem,size=2G,share=on"]);
+                cmd.args(["-numa", "node,memdev=mem"]);
+            } else {
+                // Fallback to 9p if virtiofsd isn't available. mapped-xattr keeps
+                // guest ownership in xattrs, so a server running as `guest_uid` sees
+                // host files (no xattr) as the host's owner without QEMU needing root.
+                cmd.args([
+                    "-fsdev",
+                    &format!(
+                        "local,id=workspace,path={},security_model=mapped-xattr",
+                        super::qemu_opt_path(&config.workspace_path)?
+                    ),
+                ]);
+                cmd.args(["-device", "virtio-9p-pci,fsdev=workspace,mount_tag=workspace"]);
+            }
+        }
+
+        // No graphics. The serial console still goes to stdio, and is also
+        // logged to a file so the host can read the guest's debug-exec token.
+        if let Some(ref log) = self.console_log {
+            cmd.args(["-display", "none"]);
             cmd.args([
                 "-chardev",
-                &format!("socket,id=char0,path={}", socket_path.display()),
-            ]);
-            cmd.args(["-device", "vhost-user-fs-pci,chardev=char0,tag=workspace"]);
-            // Required for vhost-user
-            cmd.args(["-object", "memory-backend-memfd,id=mem,size=2G,share=on"]);
-            cmd.args(["-numa", "node,memdev=mem"]);
-        } else {
-            // Fallback to 9p if virtiofsd isn't available
-            cmd.args([
-                "-fsdev",
                 &format!(
-                    "local,id=workspace,path={},security_model=mapped-xattr",
-                    config.workspace_path.display()
+                    "stdio,id=console0,signal=off,logfile={}",
+                    super::qemu_opt_path(log)?
                 ),
             ]);
-            cmd.args(["-device", "virtio-9p-pci,fsdev=workspace,mount_tag=workspace"]);
+            cmd.args(["-serial", "chardev:console0"]);
+        } else {
+            cmd.args(["-nographic"]);
+            cmd.args(["-serial", "stdio"]);
         }
 
-        // No graphics
-        cmd.args(["-nographic"]);
-        cmd.args(["-serial", "stdio"]);
-
         // Daemonize option could be added here if needed
         // cmd.args(["-daemonize", "-pidfile", "/tmp/qemu.pid"]);
 
-        cmd
+        Ok(cmd)
     }
 }
 
@@ -190,10 +304,15 @@ impl Default for QemuVM {
 
 impl VirtualMachine for QemuVM {
     fn start(&mut self, config: &VMConfig) -> Result<(), VMError> {
-        if self.running {
-            return Err(VMError::StartFailed("VM is already running".into()));
+        match self.state {
+            VmState::Stopped => {}
+            // A failed boot's process may still be around.
+            VmState::Failed(_) => self.stop()?,
+            _ => return Err(VMError::StartFailed("VM is already running".into())),
         }
 
+        config.validate()?;
+
         // Verify QEMU is available
         if Self::find_qemu_binary().is_none() {
             return Err(VMError::UnsupportedPlatform(
@@ -205,13 +324,23 @@ impl VirtualMachine for QemuVM {
             return Err(VMError::ImageNotFound(config.image_path.clone()));
         }
 
-        let use_kvm = Self::is_kvm_available();
-        if !use_kvm {
-            eprintln!("Warning: KVM not available, using software emulation (slower)");
+        // Reject a bad CPU topology before spawning virtiofsd.
+        config.qemu_smp()?;
+
+        let use_kvm = Self::use_kvm(config.qemu_accel, Self::kvm_status())?;
+        if config.qemu_accel == QemuAccel::Tcg {
+            eprintln!("[vm] software emulation forced (qemu_accel = tcg)");
         }
 
-        // Start virtiofsd for shared filesystem (if available)
-        if Self::is_virtiofsd_available() {
+        // Start virtiofsd for shared filesystem (if available). A network share
+        // is mounted by the guest itself.
+        if let WorkspaceShare::Network { protocol, ref export } = config.workspace_share {
+            eprintln!(
+                "[vm] workspace from {} export {} (mounted by the guest)",
+                protocol.as_str(),
+                export
+            );
+        } else if Self::is_virtiofsd_available() {
             if let Err(e) = self.start_virtiofsd(&config.workspace_path) {
                 eprintln!("Warning: virtiofsd failed to start, falling back to 9p: {}", e);
             }
@@ -219,8 +348,17 @@ impl VirtualMachine for QemuVM {
             eprintln!("Warning: virtiofsd not found, using 9p for shared filesystem");
         }
 
+        // Per-boot console log; a stale one would hand out the last boot's token.
+        self.console_log = super::runtime::runtime_dir()
+            .map(|dir| dir.join(format!("orcabot-qemu-console-{}.log", std::process::id())))
+            .map_err(|e| eprintln!("Warning: no runtime dir for the QEMU console log: {}", e))
+            .ok();
+        if let Some(ref log) = self.console_log {
+            let _ = std::fs::remove_file(log);
+        }
+
         // Build and start QEMU
-        let mut cmd = self.build_qemu_command(config, use_kvm);
+        let mut cmd = self.build_qemu_command(config, use_kvm)?;
         cmd.stdout(Stdio::inherit());
         cmd.stderr(Stdio::inherit());
 
@@ -228,94 +366,183 @@ impl VirtualMachine for QemuVM {
             VMError::StartFailed(format!("Failed to start QEMU: {}", e))
         })?;
 
-        self.qemu_process = Some(child);
+        self.qemu_process = Some(ReapedChild::new(child));
         self.config = Some(config.clone());
-        self.running = true;
+        self.state = VmState::Starting;
         self.sandbox_url = format!("http://127.0.0.1:{}", config.sandbox_port);
 
         Ok(())
     }
 
     fn stop(&mut self) -> Result<(), VMError> {
+        if self.qemu_process.is_some() || self.virtiofsd_process.is_some() {
+            teardown_log!("QemuVM::stop: stopping (state {:?})", self.state);
+        }
         // Stop QEMU
-        if let Some(ref mut child) = self.qemu_process {
-            let _ = child.kill();
-            let _ = child.wait();
+        if let Some(mut child) = self.qemu_process.take() {
+            child.kill("QEMU");
         }
-        self.qemu_process = None;
 
         // Stop virtiofsd
-        if let Some(ref mut child) = self.virtiofsd_process {
-            let _ = child.kill();
-            let _ = child.wait();
+        if let Some(mut child) = self.virtiofsd_process.take() {
+            super::kill_logged("virtiofsd", &mut child);
         }
-        self.virtiofsd_process = None;
 
         // Clean up socket
-        if let Some(ref socket) = self.virtiofs_socket {
-            let _ = std::fs::remove_file(socket);
+        if let Some(socket) = self.virtiofs_socket.take() {
+            if let Err(e) = std::fs::remove_file(&socket) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    teardown_log!("removing {}: {}", socket.display(), e);
+                }
+            }
         }
-        self.virtiofs_socket = None;
 
-        self.running = false;
+        self.state = VmState::Stopped;
         Ok(())
     }
 
     fn is_running(&self) -> bool {
-        if let Some(ref child) = self.qemu_process {
-            // Check if process is still running
-            Command::new("kill")
-                .args(["-0", &child.id().to_string()])
-                .output()
-                .map(|o| o.status.success())
-                .unwrap_or(false)
-        } else {
-            false
-        }
+        self.qemu_process
+            .as_ref()
+            .is_some_and(|child| child.exit().is_none())
     }
 
     fn pid(&self) -> Option<u32> {
-        self.qemu_process.as_ref().map(|c| c.id())
+        self.qemu_process.as_ref().map(ReapedChild::id)
+    }
+
+    fn exit_status(&self) -> Option<super::VmExit> {
+        self.qemu_process.as_ref()?.exit()
+    }
+
+    fn metrics_pid(&self) -> Result<u32, VMError> {
+        self.pid()
+            .filter(|_| self.state.is_active())
+            .ok_or_else(|| VMError::CommandFailed("the sandbox VM is not running".into()))
+    }
+
+    fn state(&self) -> VmState {
+        match self.exit_status() {
+            Some(exit) if self.state.is_active() => {
+                VmState::Failed(format!("VM process exited ({})", exit.description))
+            }
+            _ => self.state.clone(),
+        }
+    }
+
+    fn pause(&mut self) -> Result<(), VMError> {
+        self.signal_vm(libc::SIGSTOP)?;
+        self.state = VmState::Paused;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), VMError> {
+        self.signal_vm(libc::SIGCONT)?;
+        self.state = VmState::Running;
+        Ok(())
+    }
+
+    fn port_mappings(&self) -> Vec<PortMapping> {
+        // SLIRP hostfwd inbound; outbound, SLIRP maps 10.0.2.2:<port> to the same
+        // port on host loopback, which is how the sandbox reaches the control plane.
+        match (&self.config, self.state.is_active()) {
+            (Some(config), true) => vec![
+                PortMapping::inbound(config.sandbox_port, super::SANDBOX_GUEST_PORT),
+                PortMapping::outbound(config.controlplane_host_port, config.controlplane_host_port),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
+    fn console_log(&self) -> Option<std::path::PathBuf> {
+        self.console_log.clone()
     }
 
     fn sandbox_url(&self) -> Option<String> {
-        if self.running {
+        if self.state.is_active() {
             Some(self.sandbox_url.clone())
         } else {
             None
         }
     }
 
-    fn wait_for_health(&self, timeout: Duration) -> Result<(), VMError> {
-        let start = Instant::now();
-        let addr = format!(
-            "127.0.0.1:{}",
-            self.config
-                .as_ref()
-                .map(|c| c.sandbox_port)
-                .unwrap_or(8080)
-        );
-
-        while start.elapsed() < timeout {
-            if let Ok(mut stream) = TcpStream::connect(&addr) {
-                let _ = stream.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
-                let mut buf = [0u8; 256];
-                if stream.read(&mut buf).is_ok() {
-                    let response = String::from_utf8_lossy(&buf);
-                    if response.contains("200 OK") || response.contains("ok") {
-                        return Ok(());
-                    }
-                }
-            }
-            std::thread::sleep(Duration::from_millis(500));
-        }
-
-        Err(VMError::HealthTimeout(timeout))
+    fn wait_for_health(&mut self, timeout: Duration) -> Result<(), VMError> {
+        let result = self.poll_health(timeout);
+        self.state = VmState::after_health(&result);
+        result
     }
 }
 
 impl Drop for QemuVM {
     fn drop(&mut self) {
+        if self.qemu_process.is_some() {
+            teardown_log!("QemuVM dropped while its VM was up; stopping it");
+        }
         let _ = self.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn qemu_args_escape_commas_in_paths() {
+        let dir = PathBuf::from("/home/me/Application Support/orca,bot");
+        let config = VMConfig::new(dir.join("sandbox, v2.img"), dir.join("work space"));
+        let cmd = QemuVM::new().build_qemu_command(&config, false).unwrap();
+        let args: Vec<String> = cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        let after = |flag: &str| {
+            let i = args.iter().position(|a| a == flag).unwrap();
+            args[i + 1].clone()
+        };
+        assert_eq!(
+            after("-drive"),
+            "file=/home/me/Application Support/orca,,bot/sandbox,, v2.img,format=raw,if=virtio"
+        );
+        assert_eq!(
+            after("-fsdev"),
+            "local,id=workspace,path=/home/me/Application Support/orca,,bot/work space,security_model=mapped-xattr"
+        );
+    }
+
+    #[test]
+    fn accel_mode_picks_kvm_or_fails_fast() {
+        let missing = || Err("/dev/kvm does not exist".to_string());
+        assert!(QemuVM::use_kvm(QemuAccel::Auto, Ok(())).unwrap());
+        assert!(!QemuVM::use_kvm(QemuAccel::Auto, missing()).unwrap());
+        assert!(QemuVM::use_kvm(QemuAccel::Kvm, Ok(())).unwrap());
+        let err = QemuVM::use_kvm(QemuAccel::Kvm, missing()).unwrap_err().to_string();
+        assert!(err.contains("/dev/kvm does not exist") && err.contains("kvm group"), "{err}");
+        assert!(!QemuVM::use_kvm(QemuAccel::Tcg, Ok(())).unwrap());
+
+        assert_eq!(QemuAccel::parse(" KVM ").unwrap(), QemuAccel::Kvm);
+        assert!(QemuAccel::parse("hvf").is_err());
+    }
+
+    #[test]
+    fn network_share_replaces_local_share_and_reaches_the_cmdline() {
+        let share = WorkspaceShare::parse("nfs:files.lan:/export/orcabot").unwrap();
+        let config = VMConfig::new("/vm/sandbox.img".into(), "/work".into())
+            .with_cmdline("root=/dev/vda rw")
+            .with_workspace_share(share);
+        config.validate().unwrap();
+        let cmd = QemuVM::new().build_qemu_command(&config, false).unwrap();
+        let args: Vec<String> = cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        assert!(!args.iter().any(|a| a == "-fsdev" || a.contains("vhost-user-fs")));
+        let append = &args[args.iter().position(|a| a == "-append").unwrap() + 1];
+        assert!(append.ends_with(" orcabot.share=nfs:files.lan:/export/orcabot"), "{append}");
+
+        assert!(WorkspaceShare::parse("smb://files.lan/orcabot").is_ok());
+        for bad in ["nfs:/export", "nfs:files.lan:/a b", "smb://files.lan", "smb:files.lan/x", "ftp://x/y"] {
+            assert!(WorkspaceShare::parse(bad).is_err(), "{bad}");
+        }
+    }
+}
@@ -9,11 +9,11 @@
 //! - com.apple.security.virtualization entitlement
 //! - Bootable disk image with kernel and initrd
 
-use super::{VMConfig, VMError, VirtualMachine};
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::path::Path;
-use std::process::{Child, Command, Stdio};
+use super::command::{on_path, run_command, TOOL_TIMEOUT};
+use super::reaper::ReapedChild;
+use super::{PortMapping, VMConfig, VMError, VirtualMachine, VmState};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
 
 /// Control-plane port for the guest→host reverse vsock bridge.
@@ -28,44 +28,108 @@ use std::time::{Duration, Instant};
 /// env channel first; until then both sides must use this constant.
 const CONTROLPLANE_PORT: u16 = 8787;
 
+/// Console for the native VZ backend: its virtio console appears as hvc0.
+const VZ_CONSOLE: &str = "console=hvc0 earlycon=virtio_console";
+
+/// Console for the QEMU fallback, which wires the guest serial port to stdio.
+const QEMU_CONSOLE: &str = "console=ttyS0";
+
+/// Where vz-helper writes the guest console (fixed in vz-helper/Sources/main.swift).
+const VZ_CONSOLE_LOG: &str = "/tmp/vz-console.log";
+
+/// Why the last start skipped Virtualization.framework, if it did; surfaced by
+/// `get_resource_info` so "fell back to QEMU" reports don't need the logs.
+static BACKEND_WARNING: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+/// See [`BACKEND_WARNING`].
+pub fn backend_warning() -> Option<String> {
+    BACKEND_WARNING.lock().ok().and_then(|w| w.clone())
+}
+
 /// macOS VM using Virtualization.framework.
 ///
 /// On macOS 13+, uses native Virtualization.framework for optimal performance.
 /// Falls back to QEMU with HVF acceleration if VZ is unavailable.
 pub struct MacOSVM {
     /// Child process handle (for helper process or QEMU fallback)
-    process: Option<Child>,
+    process: Option<ReapedChild>,
     /// Configuration used to start the VM
     config: Option<VMConfig>,
-    /// Whether the VM is currently running
-    running: bool,
+    /// Lifecycle state, as of the last start/health check/pause/stop
+    state: VmState,
     /// Host URL for sandbox access
     sandbox_url: String,
     /// Whether using native VZ or QEMU fallback
     using_native_vz: bool,
+    /// QEMU fallback's console log file (the VZ helper always uses `VZ_CONSOLE_LOG`)
+    qemu_console_log: Option<PathBuf>,
 }
 
 impl MacOSVM {
+    /// Pause (`true`) or resume the guest. vz-helper handles SIGUSR1/SIGUSR2
+    /// with `VZVirtualMachine.pause`/`resume`: stopping the helper itself would
+    /// not freeze the guest, which runs in Virtualization.framework's XPC
+    /// process. The QEMU fallback is frozen with SIGSTOP/SIGCONT.
+    fn set_paused(&self, paused: bool) -> Result<(), VMError> {
+        let child = self
+            .process
+            .as_ref()
+            .filter(|_| self.state.is_active())
+            .ok_or_else(|| VMError::PauseFailed("the sandbox VM is not running".into()))?;
+        let signal = match (self.using_native_vz, paused) {
+            (true, true) => libc::SIGUSR1,
+            (true, false) => libc::SIGUSR2,
+            (false, true) => libc::SIGSTOP,
+            (false, false) => libc::SIGCONT,
+        };
+        child
+            .signal(signal)
+            .map_err(|e| VMError::PauseFailed(e.to_string()))
+    }
+
     pub fn new() -> Self {
         Self {
             process: None,
             config: None,
-            running: false,
+            state: VmState::Stopped,
             sandbox_url: "http://127.0.0.1:8080".to_string(),
             using_native_vz: false,
+            qemu_console_log: None,
         }
     }
 
+    /// Poll the health endpoint until it answers, the VM exits or `timeout`.
+    fn poll_health(&mut self, timeout: Duration) -> Result<(), VMError> {
+        let start = Instant::now();
+        let addr = format!(
+            "127.0.0.1:{}",
+            self.config
+                .as_ref()
+                .map(|c| c.sandbox_port)
+                .unwrap_or(8080)
+        );
+
+        let mut delay = Duration::from_millis(500);
+        let max_delay = Duration::from_secs(5);
+        let console_log = self.console_log();
+        while start.elapsed() < timeout {
+            super::check_vm_process(self.exit_status(), console_log.as_deref())?;
+            if super::health::probe_sandbox_health(&addr, self.config.as_ref()) {
+                return Ok(());
+            }
+            std::thread::sleep(delay);
+            delay = std::cmp::min(delay * 2, max_delay);
+        }
+
+        Err(VMError::HealthTimeout(timeout))
+    }
+
     /// Check if Virtualization.framework is available.
     /// Requires macOS 13+ and the virtualization entitlement.
     fn is_vz_available() -> bool {
         // Check macOS version (13.0+)
-        if let Ok(output) = Command::new("sw_vers")
-            .arg("-productVersion")
-            .output()
-        {
-            let version = String::from_utf8_lossy(&output.stdout);
-            if let Some(major) = version.trim().split('.').next() {
+        if let Ok(output) = run_command("sw_vers", &["-productVersion"], TOOL_TIMEOUT) {
+            if let Some(major) = output.stdout.trim().split('.').next() {
                 if let Ok(major_num) = major.parse::<u32>() {
                     return major_num >= 13;
                 }
@@ -74,6 +138,40 @@ impl MacOSVM {
         false
     }
 
+    /// Whether this app binary is signed without `com.apple.security.virtualization`.
+    ///
+    /// Without it, VZ calls fail deep inside the framework with errors that don't
+    /// mention entitlements, so we check up front (`codesign -d --entitlements`)
+    /// and steer to QEMU with a clear message instead. Returns `None` when the
+    /// answer is unknown or moot: codesign unavailable, or an unsigned /
+    /// linker-signed dev build (those never carry entitlements and rely on the
+    /// separately-signed vz-helper). `ORCABOT_FORCE_VZ=1` skips the check.
+    fn missing_virtualization_entitlement() -> Option<String> {
+        if std::env::var("ORCABOT_FORCE_VZ").is_ok_and(|v| v == "1") {
+            return None;
+        }
+        let exe = std::env::current_exe().ok()?;
+        let output = run_command(
+            "codesign",
+            &["-dv", "--entitlements", "-", "--xml", exe.to_str()?],
+            TOOL_TIMEOUT,
+        )
+        .ok()?;
+        // Signing details go to stderr, the entitlements plist to stdout.
+        if output.stderr.contains("linker-signed") {
+            return None;
+        }
+        if output.stdout.contains("com.apple.security.virtualization") {
+            return None;
+        }
+        Some(format!(
+            "{} is signed without the com.apple.security.virtualization entitlement; \
+             Virtualization.framework will refuse to run. Re-sign the app with \
+             desktop/app/src-tauri/entitlements.plist (or set ORCABOT_FORCE_VZ=1 to try anyway)",
+            exe.display()
+        ))
+    }
+
     /// Check if QEMU is available (fallback).
     fn is_qemu_available() -> bool {
         let binary = if cfg!(target_arch = "aarch64") {
@@ -82,11 +180,7 @@ impl MacOSVM {
             "qemu-system-x86_64"
         };
 
-        Command::new("which")
-            .arg(binary)
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
+        on_path(binary)
     }
 
     /// Start VM using native Virtualization.framework via Swift helper.
@@ -124,31 +218,35 @@ impl MacOSVM {
             .as_ref()
             .ok_or_else(|| VMError::StartFailed("Initrd path required for VZ".into()))?;
 
+        if config.cpu_topology.is_some() {
+            eprintln!("[vm] cpu_topology ignored on Virtualization.framework (flat vCPU count only)");
+        }
+
         let cmdline = config
-            .kernel_cmdline
-            .as_ref()
-            .map(|s| s.as_str())
-            .unwrap_or("console=hvc0 root=/dev/vda rw");
+            .cmdline(VZ_CONSOLE)
+            .unwrap_or_else(|| format!("{} root=/dev/vda rw", VZ_CONSOLE));
+
+        // vz-helper splits the tag off at the first ':' only, so any path works.
+        let mut share = std::ffi::OsString::from("workspace:");
+        share.push(&config.workspace_path);
 
         let mut cmd = Command::new(&helper_path);
+        // Paths go in as OsStr, never through a lossy string conversion.
+        cmd.arg("--kernel").arg(kernel_path);
+        cmd.arg("--initrd").arg(initrd_path);
+        cmd.arg("--disk").arg(&config.image_path);
         cmd.args([
-            "--kernel",
-            kernel_path.to_str().unwrap_or_default(),
-            "--initrd",
-            initrd_path.to_str().unwrap_or_default(),
-            "--disk",
-            config.image_path.to_str().unwrap_or_default(),
             "--cmdline",
-            cmdline,
+            &cmdline,
             "--cpus",
             &config.cpus.to_string(),
             "--memory",
             &config.memory_mb().to_string(),
-            "--share",
-            &format!(
-                "workspace:{}",
-                config.workspace_path.display()
-            ),
+        ]);
+        cmd.arg("--share").arg(&share);
+        // Loopback only, whatever `bind_address` says (see the QEMU hostfwd).
+        cmd.args(["--port-forward-bind", "127.0.0.1"]);
+        cmd.args([
             // Port forward via vsock: host TCP port -> guest vsock port. The guest
             // runs socat to bridge vsock:8080 -> localhost:8080. The host side
             // (config.sandbox_port) may be dynamic if 8080 was busy on the host;
@@ -166,6 +264,11 @@ impl MacOSVM {
             &format!("{}:{}", CONTROLPLANE_PORT, config.controlplane_host_port),
         ]);
 
+        // vz-helper attaches a virtio entropy device unless told not to.
+        if !config.enable_rng {
+            cmd.arg("--no-entropy");
+        }
+
         cmd.stdout(Stdio::inherit());
         cmd.stderr(Stdio::inherit());
 
@@ -173,9 +276,9 @@ impl MacOSVM {
             VMError::StartFailed(format!("Failed to start VZ helper: {}", e))
         })?;
 
-        self.process = Some(child);
+        self.process = Some(ReapedChild::new(child));
         self.config = Some(config.clone());
-        self.running = true;
+        self.state = VmState::Starting;
         self.using_native_vz = true;
         self.sandbox_url = format!("http://127.0.0.1:{}", config.sandbox_port);
 
@@ -204,6 +307,7 @@ impl MacOSVM {
         };
 
         let mut cmd = Command::new(qemu_binary);
+        cmd.args(["-name", super::QEMU_NAME]);
 
         // Machine type with HVF acceleration
         if cfg!(target_arch = "aarch64") {
@@ -215,18 +319,18 @@ impl MacOSVM {
         }
 
         // CPU and memory
-        cmd.args(["-smp", &config.cpus.to_string()]);
+        cmd.args(["-smp", &config.qemu_smp()?]);
         cmd.args(["-m", &format!("{}M", config.memory_mb())]);
 
         // Kernel boot (direct boot without bootloader)
         if let Some(ref kernel) = config.kernel_path {
-            cmd.args(["-kernel", kernel.to_str().unwrap_or_default()]);
+            cmd.arg("-kernel").arg(kernel);
         }
         if let Some(ref initrd) = config.initrd_path {
-            cmd.args(["-initrd", initrd.to_str().unwrap_or_default()]);
+            cmd.arg("-initrd").arg(initrd);
         }
-        if let Some(ref cmdline) = config.kernel_cmdline {
-            cmd.args(["-append", cmdline]);
+        if let Some(cmdline) = config.cmdline(QEMU_CONSOLE) {
+            cmd.args(["-append", &cmdline]);
         }
 
         // Root filesystem
@@ -234,34 +338,57 @@ impl MacOSVM {
             "-drive",
             &format!(
                 "file={},format=raw,if=virtio",
-                config.image_path.display()
+                super::qemu_opt_path(&config.image_path)?
             ),
         ]);
 
         // Network with port forwarding
         cmd.args([
             "-netdev",
-            // host TCP (config.sandbox_port, maybe dynamic) -> guest 8080 (fixed).
+            // host TCP (config.sandbox_port, maybe dynamic) -> guest 8080 (fixed),
+            // on loopback only (slirp binds every interface without an address).
             &format!(
-                "user,id=net0,hostfwd=tcp::{}-:{}",
-                config.sandbox_port, super::SANDBOX_GUEST_PORT
+                "user,id=net0,hostfwd=tcp:127.0.0.1:{}-:{}",
+                config.sandbox_port,
+                super::SANDBOX_GUEST_PORT
             ),
         ]);
         cmd.args(["-device", "virtio-net-pci,netdev=net0"]);
 
+        // Guest entropy (see VMConfig::enable_rng).
+        cmd.args(config.qemu_rng_args());
+
         // 9p shared filesystem (VirtioFS requires virtiofsd which is complex on macOS)
         cmd.args([
             "-fsdev",
             &format!(
                 "local,id=workspace,path={},security_model=mapped-xattr",
-                config.workspace_path.display()
+                super::qemu_opt_path(&config.workspace_path)?
             ),
         ]);
         cmd.args(["-device", "virtio-9p-pci,fsdev=workspace,mount_tag=workspace"]);
 
-        // No graphics, serial console
-        cmd.args(["-nographic"]);
-        cmd.args(["-serial", "stdio"]);
+        // No graphics; serial console to stdio, also logged to a per-boot file so
+        // the host can read the guest's debug-exec token.
+        self.qemu_console_log = super::runtime::runtime_dir()
+            .map(|dir| dir.join(format!("orcabot-qemu-console-{}.log", std::process::id())))
+            .map_err(|e| eprintln!("Warning: no runtime dir for the QEMU console log: {}", e))
+            .ok();
+        if let Some(ref log) = self.qemu_console_log {
+            let _ = std::fs::remove_file(log);
+            cmd.args(["-display", "none"]);
+            cmd.args([
+                "-chardev",
+                &format!(
+                    "stdio,id=console0,signal=off,logfile={}",
+                    super::qemu_opt_path(log)?
+                ),
+            ]);
+            cmd.args(["-serial", "chardev:console0"]);
+        } else {
+            cmd.args(["-nographic"]);
+            cmd.args(["-serial", "stdio"]);
+        }
 
         cmd.stdout(Stdio::inherit());
         cmd.stderr(Stdio::inherit());
@@ -270,9 +397,9 @@ impl MacOSVM {
             VMError::StartFailed(format!("Failed to start QEMU: {}", e))
         })?;
 
-        self.process = Some(child);
+        self.process = Some(ReapedChild::new(child));
         self.config = Some(config.clone());
-        self.running = true;
+        self.state = VmState::Starting;
         self.using_native_vz = false;
         self.sandbox_url = format!("http://127.0.0.1:{}", config.sandbox_port);
 
@@ -288,17 +415,31 @@ impl Default for MacOSVM {
 
 impl VirtualMachine for MacOSVM {
     fn start(&mut self, config: &VMConfig) -> Result<(), VMError> {
-        if self.running {
-            return Err(VMError::StartFailed("VM is already running".into()));
+        match self.state {
+            VmState::Stopped => {}
+            // A failed boot's process may still be around.
+            VmState::Failed(_) => self.stop()?,
+            _ => return Err(VMError::StartFailed("VM is already running".into())),
         }
 
+        config.validate()?;
+        // Neither VZ nor the HVF QEMU fallback mounts a network export.
+        config.require_local_share("macOS")?;
+
         // Validate disk image exists
         if !config.image_path.exists() {
             return Err(VMError::ImageNotFound(config.image_path.clone()));
         }
 
         // Prefer Virtualization.framework with vsock port forwarding (no QEMU needed)
-        if Self::is_vz_available() {
+        let entitlement_problem = Self::missing_virtualization_entitlement();
+        if let Ok(mut warning) = BACKEND_WARNING.lock() {
+            *warning = entitlement_problem.clone();
+        }
+        if let Some(ref problem) = entitlement_problem {
+            eprintln!("[vm] WARNING: skipping Virtualization.framework: {}", problem);
+        }
+        if entitlement_problem.is_none() && Self::is_vz_available() {
             eprintln!("Starting sandbox VM using Virtualization.framework with vsock...");
             match self.start_native(config) {
                 Ok(()) => return Ok(()),
@@ -314,82 +455,121 @@ impl VirtualMachine for MacOSVM {
             return self.start_qemu(config);
         }
 
-        Err(VMError::UnsupportedPlatform(
-            "No VM backend available. macOS 13+ required for Virtualization.framework.".into(),
-        ))
+        Err(VMError::UnsupportedPlatform(match entitlement_problem {
+            Some(problem) => format!("No VM backend available (QEMU not installed). {}", problem),
+            None => {
+                "No VM backend available. macOS 13+ required for Virtualization.framework.".into()
+            }
+        }))
     }
 
     fn stop(&mut self) -> Result<(), VMError> {
-        if let Some(ref mut child) = self.process {
-            let _ = child.kill();
-            let _ = child.wait();
+        if let Some(mut child) = self.process.take() {
+            teardown_log!("MacOSVM::stop: stopping (state {:?})", self.state);
+            child.kill(self.backend_name());
         }
 
-        self.process = None;
-        self.running = false;
+        self.state = VmState::Stopped;
         Ok(())
     }
 
     fn is_running(&self) -> bool {
-        if let Some(ref child) = self.process {
-            Command::new("kill")
-                .args(["-0", &child.id().to_string()])
-                .output()
-                .map(|o| o.status.success())
-                .unwrap_or(false)
+        self.process
+            .as_ref()
+            .is_some_and(|child| child.exit().is_none())
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.process.as_ref().map(ReapedChild::id)
+    }
+
+    fn exit_status(&self) -> Option<super::VmExit> {
+        self.process.as_ref()?.exit()
+    }
+
+    /// The vz-helper's usage. VZ runs the guest in a separate XPC service, so
+    /// for VZ this undercounts the guest itself; for the QEMU fallback it is
+    /// the whole VM.
+    fn metrics_pid(&self) -> Result<u32, VMError> {
+        self.pid()
+            .filter(|_| self.state.is_active())
+            .ok_or_else(|| VMError::CommandFailed("the sandbox VM is not running".into()))
+    }
+
+    fn state(&self) -> VmState {
+        match self.exit_status() {
+            Some(exit) if self.state.is_active() => {
+                VmState::Failed(format!("VM process exited ({})", exit.description))
+            }
+            _ => self.state.clone(),
+        }
+    }
+
+    fn pause(&mut self) -> Result<(), VMError> {
+        self.set_paused(true)?;
+        self.state = VmState::Paused;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), VMError> {
+        self.set_paused(false)?;
+        self.state = VmState::Running;
+        Ok(())
+    }
+
+    fn backend_name(&self) -> &'static str {
+        if self.using_native_vz {
+            super::vm_backend_name()
         } else {
-            false
+            "QEMU (HVF fallback)"
         }
     }
 
-    fn pid(&self) -> Option<u32> {
-        self.process.as_ref().map(|c| c.id())
+    fn port_mappings(&self) -> Vec<PortMapping> {
+        let Some(config) = self.config.as_ref().filter(|_| self.state.is_active()) else {
+            return Vec::new();
+        };
+        // VZ: the reverse vsock bridge pins the guest side to CONTROLPLANE_PORT.
+        // QEMU: SLIRP maps 10.0.2.2:<port> to the same port on host loopback.
+        let guest_controlplane_port = if self.using_native_vz {
+            CONTROLPLANE_PORT
+        } else {
+            config.controlplane_host_port
+        };
+        vec![
+            PortMapping::inbound(config.sandbox_port, super::SANDBOX_GUEST_PORT),
+            PortMapping::outbound(config.controlplane_host_port, guest_controlplane_port),
+        ]
+    }
+
+    fn console_log(&self) -> Option<PathBuf> {
+        if self.using_native_vz {
+            Some(PathBuf::from(VZ_CONSOLE_LOG))
+        } else {
+            self.qemu_console_log.clone()
+        }
     }
 
     fn sandbox_url(&self) -> Option<String> {
-        if self.running {
+        if self.state.is_active() {
             Some(self.sandbox_url.clone())
         } else {
             None
         }
     }
 
-    fn wait_for_health(&self, timeout: Duration) -> Result<(), VMError> {
-        let start = Instant::now();
-        let addr = format!(
-            "127.0.0.1:{}",
-            self.config
-                .as_ref()
-                .map(|c| c.sandbox_port)
-                .unwrap_or(8080)
-        );
-
-        let mut delay = Duration::from_millis(500);
-        let max_delay = Duration::from_secs(5);
-        while start.elapsed() < timeout {
-            if let Ok(mut stream) = TcpStream::connect(&addr) {
-                let _ = stream.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
-                let mut buf = [0u8; 256];
-                if stream.read(&mut buf).is_ok() {
-                    let response = String::from_utf8_lossy(&buf);
-                    if response.contains("200 OK") || response.contains("ok") {
-                        return Ok(());
-                    }
-                }
-            }
-            std::thread::sleep(delay);
-            delay = std::cmp::min(delay * 2, max_delay);
-        }
-
-        Err(VMError::HealthTimeout(timeout))
+    fn wait_for_health(&mut self, timeout: Duration) -> Result<(), VMError> {
+        let result = self.poll_health(timeout);
+        self.state = VmState::after_health(&result);
+        result
     }
 }
 
 impl Drop for MacOSVM {
     fn drop(&mut self) {
-        if let Some(ref mut child) = self.process {
-            let _ = child.kill();
-            let _ = child.wait();
+        if let Some(mut child) = self.process.take() {
+            teardown_log!("MacOSVM dropped while its VM was up; stopping it");
+            child.kill(self.backend_name());
         }
     }
 }
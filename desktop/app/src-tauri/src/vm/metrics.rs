@@ -0,0 +1,178 @@
+//! CPU and memory usage of the VM process, for a live readout in the UI
+//! (`get_vm_metrics`). Linux reads procfs; macOS asks `ps`. Both read the
+//! process's cumulative CPU time twice, [`CPU_SAMPLE`] apart, so the figure is
+//! current usage — not `ps %cpu`, which macOS computes as a decaying average
+//! that lags a busy or newly idle guest by tens of seconds.
+
+use super::VMError;
+use std::time::Duration;
+
+/// Resource usage of the host process running the guest.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct VmMetrics {
+    /// Share of one host core, so a busy 4-vCPU guest can approach 400.
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    /// Since the VM process started.
+    #[serde(rename = "uptime_secs", serialize_with = "secs")]
+    pub uptime: Duration,
+}
+
+fn secs<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(d.as_secs())
+}
+
+/// CPU over this window. Linux counts CPU time in 10ms ticks; macOS `ps`
+/// prints it to the hundredth, so it gets a longer window for the same
+/// resolution per sample.
+#[cfg(target_os = "linux")]
+const CPU_SAMPLE: Duration = Duration::from_millis(250);
+#[cfg(target_os = "macos")]
+const CPU_SAMPLE: Duration = Duration::from_millis(500);
+
+/// Metrics for the VM process `pid` (from `VirtualMachine::metrics_pid`).
+/// Blocks for [`CPU_SAMPLE`].
+pub fn sample(pid: u32) -> Result<VmMetrics, VMError> {
+    #[cfg(target_os = "linux")]
+    return procfs_metrics(pid);
+    #[cfg(target_os = "macos")]
+    return ps_metrics(pid);
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    Err(VMError::UnsupportedPlatform(format!("VM metrics for pid {pid}")))
+}
+
+/// Metrics for `pid` from `/proc/<pid>/stat` and `/proc/<pid>/status`.
+#[cfg(target_os = "linux")]
+fn procfs_metrics(pid: u32) -> Result<VmMetrics, VMError> {
+    let read = |file: &str| {
+        std::fs::read_to_string(format!("/proc/{pid}/{file}"))
+            .map_err(|e| VMError::CommandFailed(format!("reading /proc/{pid}/{file}: {e}")))
+    };
+    let stat = |text: &str| {
+        parse_proc_stat(text)
+            .ok_or_else(|| VMError::CommandFailed(format!("unexpected /proc/{pid}/stat")))
+    };
+    // SAFETY: sysconf has no preconditions.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+
+    let (cpu_before, start_ticks) = stat(&read("stat")?)?;
+    std::thread::sleep(CPU_SAMPLE);
+    let (cpu_after, _) = stat(&read("stat")?)?;
+    let cpu_percent =
+        (cpu_after.saturating_sub(cpu_before) as f64 / ticks) / CPU_SAMPLE.as_secs_f64() * 100.0;
+
+    let rss_kib = read("status")?
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    let host_uptime = std::fs::read_to_string("/proc/uptime")
+        .ok()
+        .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let uptime = (host_uptime - start_ticks as f64 / ticks).max(0.0);
+    Ok(VmMetrics {
+        cpu_percent: cpu_percent as f32,
+        rss_bytes: rss_kib * 1024,
+        uptime: Duration::from_secs_f64(uptime),
+    })
+}
+
+/// `(utime + stime, starttime)` in clock ticks from a `/proc/<pid>/stat` line.
+/// The command name can contain spaces and parentheses, so fields are counted
+/// from its closing parenthesis.
+#[cfg(target_os = "linux")]
+fn parse_proc_stat(stat: &str) -> Option<(u64, u64)> {
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    // After the name, field 3 (state) is index 0: utime=14, stime=15, starttime=22.
+    let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+    Some((field(14)? + field(15)?, field(22)?))
+}
+
+/// Metrics for `pid` from two `ps -o time=,rss=,etime=` reads.
+#[cfg(target_os = "macos")]
+fn ps_metrics(pid: u32) -> Result<VmMetrics, VMError> {
+    let read = || {
+        let out = super::command::run_command(
+            "ps",
+            &["-o", "time=,rss=,etime=", "-p", &pid.to_string()],
+            super::command::TOOL_TIMEOUT,
+        )?;
+        parse_ps(&out.stdout).ok_or_else(|| {
+            VMError::CommandFailed(format!("unexpected ps output: {:?}", out.stdout.trim()))
+        })
+    };
+    let before = read()?;
+    let started = std::time::Instant::now();
+    std::thread::sleep(CPU_SAMPLE);
+    let after = read()?;
+    // The time between the reads, not CPU_SAMPLE: each `ps` takes a while too.
+    let window = started.elapsed().as_secs_f64().max(CPU_SAMPLE.as_secs_f64());
+    let cpu_percent = (after.cpu_secs - before.cpu_secs).max(0.0) / window * 100.0;
+    Ok(VmMetrics {
+        cpu_percent: cpu_percent as f32,
+        rss_bytes: after.rss_kib * 1024,
+        uptime: after.uptime,
+    })
+}
+
+/// One `ps` reading: cumulative CPU time, RSS and elapsed time.
+#[cfg(any(target_os = "macos", test))]
+#[derive(Debug, PartialEq)]
+struct PsSample {
+    cpu_secs: f64,
+    rss_kib: u64,
+    uptime: Duration,
+}
+
+/// One `time rss(KiB) etime` line; both times are `[[dd-]hh:]mm:ss[.ff]`.
+#[cfg(any(target_os = "macos", test))]
+fn parse_ps(line: &str) -> Option<PsSample> {
+    let mut fields = line.split_whitespace();
+    let cpu_secs = parse_ps_time(fields.next()?)?;
+    let rss_kib: u64 = fields.next()?.parse().ok()?;
+    let uptime = parse_ps_time(fields.next()?)?;
+    Some(PsSample {
+        cpu_secs,
+        rss_kib,
+        uptime: Duration::from_secs_f64(uptime),
+    })
+}
+
+#[cfg(any(target_os = "macos", test))]
+fn parse_ps_time(field: &str) -> Option<f64> {
+    let (days, clock) = match field.split_once('-') {
+        Some((d, rest)) => (d.parse::<f64>().ok()?, rest),
+        None => (0.0, field),
+    };
+    let secs = clock.split(':').try_fold(0.0, |acc, part| {
+        Some(acc * 60.0 + part.replace(',', ".").parse::<f64>().ok()?)
+    })?;
+    Some(days * 86_400.0 + secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ps_and_proc_stat() {
+        let m = parse_ps(" 1:02.50 204800 1-02:03:04\n").unwrap();
+        assert_eq!(m.cpu_secs, 62.5);
+        assert_eq!(m.rss_kib, 200 * 1024);
+        assert_eq!(
+            m.uptime,
+            Duration::from_secs(86_400 + 2 * 3600 + 3 * 60 + 4)
+        );
+        let m = parse_ps("0:00,25 1024 00:07").unwrap();
+        assert_eq!((m.cpu_secs, m.uptime), (0.25, Duration::from_secs(7)));
+        assert!(parse_ps("").is_none());
+
+        #[cfg(target_os = "linux")]
+        {
+            let stat = "4242 (qemu (x86) 1) S 1 4242 4242 0 -1 4194560 100 0 0 0 \
+                        700 300 0 0 20 0 5 0 123456 0 0";
+            assert_eq!(parse_proc_stat(stat), Some((1000, 123456)));
+        }
+    }
+}
@@ -5,8 +5,14 @@
 //! - Windows: WSL2
 //! - Linux: QEMU/KVM
 
+pub mod bench;
+pub mod boot_phase;
+pub mod clock;
+pub mod command;
 pub mod config;
 pub mod error;
+pub mod exec;
+pub mod health;
 
 #[cfg(target_os = "macos")]
 pub mod macos;
@@ -18,9 +24,19 @@ pub mod windows;
 pub mod linux;
 
 pub mod image;
+pub mod metrics;
+pub mod processes;
+pub mod provision;
+#[cfg(unix)]
+pub mod reaper;
+pub mod relay;
+pub mod runtime;
+pub mod watchdog;
 
-pub use config::VMConfig;
+pub use config::{qemu_opt_path, QemuAccel, VMConfig, WorkspaceShare};
 pub use error::VMError;
+pub use exec::{ExecChannel, ExecLine, ExecOutput, GuestInfo};
+pub use metrics::VmMetrics;
 
 /// The port the guest sandbox always binds (baked into the image default; the
 /// guest never receives a per-boot override — `config.env` isn't delivered to
@@ -28,8 +44,113 @@ pub use error::VMError;
 /// (`VMConfig.sandbox_port`) may be dynamic when 8080 is busy on the host.
 pub const SANDBOX_GUEST_PORT: u16 = 8080;
 
+/// `-name` of every QEMU the app starts. QEMU and virtiofsd are usually system
+/// binaries, so the orphan scan (`ORCABOT_ORPHAN_SCAN`) tells ours from a
+/// user's own by this, and by [`VIRTIOFS_SOCKET_PREFIX`] in virtiofsd's
+/// `--socket-path`, rather than by where the executable lives.
+pub const QEMU_NAME: &str = "orcabot-sandbox";
+
+/// File name prefix of the virtiofsd socket (followed by the app's pid).
+pub const VIRTIOFS_SOCKET_PREFIX: &str = "orcabot-virtiofs-";
+
+use std::path::PathBuf;
 use std::time::Duration;
 
+/// Why the last VM start passed over the platform's preferred backend (today
+/// only macOS, when the app lacks the virtualization entitlement).
+pub fn backend_warning() -> Option<String> {
+    #[cfg(target_os = "macos")]
+    {
+        macos::backend_warning()
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        None
+    }
+}
+
+/// An installed WSL distro (`windows::wsl_distros`), for diagnostics.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WslDistro {
+    pub name: String,
+    /// Carries Orcabot's marker. Only checked for `orcabot-sandbox*` names,
+    /// since checking has to start the distro.
+    pub orcabot: bool,
+}
+
+/// Which way a [`PortMapping`] carries connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortDirection {
+    /// Host connects to `host_port`, reaching the guest's `guest_port`.
+    HostToGuest,
+    /// Guest connects to `guest_port` (on its loopback under VZ, on `10.0.2.2`
+    /// under QEMU), reaching the host's `host_port`.
+    GuestToHost,
+}
+
+/// One port forward between host and guest, as the backend actually set it up.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PortMapping {
+    pub host_port: u16,
+    pub guest_port: u16,
+    pub protocol: &'static str, // "tcp"
+    pub direction: PortDirection,
+}
+
+impl PortMapping {
+    /// A TCP forward from the host into the guest.
+    pub fn inbound(host_port: u16, guest_port: u16) -> Self {
+        Self {
+            host_port,
+            guest_port,
+            protocol: "tcp",
+            direction: PortDirection::HostToGuest,
+        }
+    }
+
+    /// A TCP route from the guest to a host loopback service.
+    pub fn outbound(host_port: u16, guest_port: u16) -> Self {
+        Self {
+            host_port,
+            guest_port,
+            protocol: "tcp",
+            direction: PortDirection::GuestToHost,
+        }
+    }
+}
+
+/// Lifecycle of a backend's VM, as the backend last left it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "state", content = "error", rename_all = "lowercase")]
+pub enum VmState {
+    #[default]
+    Stopped,
+    /// Spawned; the sandbox hasn't answered a health check yet.
+    Starting,
+    Running,
+    Paused,
+    /// Health never came up or the VM exited while waiting. The process may
+    /// still be around until `stop`.
+    Failed(String),
+}
+
+impl VmState {
+    /// Started and not since stopped or failed: the process, its port forwards
+    /// and sandbox URL are live.
+    pub fn is_active(&self) -> bool {
+        matches!(self, Self::Starting | Self::Running | Self::Paused)
+    }
+
+    /// The state `wait_for_health` leaves behind.
+    pub(crate) fn after_health(result: &Result<(), VMError>) -> Self {
+        match result {
+            Ok(()) => Self::Running,
+            Err(e) => Self::Failed(e.to_string()),
+        }
+    }
+}
+
 /// Trait for platform-specific VM implementations.
 pub trait VirtualMachine: Send + Sync {
     /// Start the VM with the given configuration.
@@ -38,19 +159,169 @@ pub trait VirtualMachine: Send + Sync {
     /// Stop the VM gracefully (with timeout fallback to force kill).
     fn stop(&mut self) -> Result<(), VMError>;
 
+    /// Cycle the VM with `config`, e.g. to recover a hung guest. The default
+    /// stops it — `stop` waits for the old process to exit, so its
+    /// `sandbox_port` forward is free again — and starts it afresh. Backends
+    /// with a cheaper in-place restart override it.
+    fn restart(&mut self, config: &VMConfig) -> Result<(), VMError> {
+        self.stop()?;
+        self.start(config)
+    }
+
     /// Check if the VM is running.
     fn is_running(&self) -> bool;
 
+    /// Where the VM is in its lifecycle. The default only tells `Running` from
+    /// `Stopped`, by [`is_running`](Self::is_running).
+    fn state(&self) -> VmState {
+        if self.is_running() {
+            VmState::Running
+        } else {
+            VmState::Stopped
+        }
+    }
+
     /// Get the PID of the VM process (for PID file tracking).
     fn pid(&self) -> Option<u32>;
 
+    /// How the VM process ended, once it has (without a `stop`). `None` while
+    /// it runs, and where the backend can't tell.
+    fn exit_status(&self) -> Option<VmExit> {
+        None
+    }
+
     /// Get the host-accessible URL for the sandbox service.
     fn sandbox_url(&self) -> Option<String>;
 
-    /// Wait for the sandbox health endpoint to respond.
-    fn wait_for_health(&self, timeout: Duration) -> Result<(), VMError>;
+    /// Wait for the sandbox health endpoint to respond. Fails early with
+    /// [`VMError::VmExited`] if the VM process dies while waiting. Moves the
+    /// state from `Starting` to `Running`, or to `Failed` on error.
+    fn wait_for_health(&mut self, timeout: Duration) -> Result<(), VMError>;
+
+    /// Port forwards of the running VM (empty when stopped), from the ports it was
+    /// actually started with — so a dynamically reallocated host port shows up.
+    fn port_mappings(&self) -> Vec<PortMapping>;
+
+    /// Hypervisor actually running the guest. Defaults to the platform's
+    /// [`vm_backend_name`]; macOS reports its QEMU fallback.
+    fn backend_name(&self) -> &'static str {
+        vm_backend_name()
+    }
+
+    /// File the guest serial console is written to, if this backend keeps one.
+    fn console_log(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// How to run commands in the guest (see [`exec`]). Defaults to the sandbox's
+    /// debug-exec endpoint, authed from the console log.
+    fn exec_channel(&self) -> Option<ExecChannel> {
+        Some(ExecChannel::DebugExec {
+            sandbox_url: self.sandbox_url()?,
+            console_log: self.console_log()?,
+        })
+    }
+
+    /// The host process whose CPU, memory and uptime [`metrics::sample`]
+    /// reports. Cheap, so callers can read it under the `sandbox_vm` lock and
+    /// sample (which blocks for a moment) after releasing it.
+    /// `UnsupportedPlatform` where the backend can't measure it.
+    fn metrics_pid(&self) -> Result<u32, VMError> {
+        Err(VMError::UnsupportedPlatform(format!(
+            "VM metrics on {}",
+            self.backend_name()
+        )))
+    }
+
+    /// Freeze the running guest in place with its memory intact (the desktop's
+    /// `suspend_all`); [`resume`](Self::resume) lets it carry on. Backends that
+    /// can't pause without losing guest state return `PauseFailed` saying so.
+    /// Pausing leaves the process, its port forwards and `is_running` as they
+    /// are.
+    fn pause(&mut self) -> Result<(), VMError> {
+        Err(VMError::PauseFailed(format!(
+            "{} can't be paused without losing guest state",
+            self.backend_name()
+        )))
+    }
+
+    fn resume(&mut self) -> Result<(), VMError> {
+        Err(VMError::PauseFailed(format!("{} can't be paused", self.backend_name())))
+    }
+}
+
+/// `Err(VmExited)` if the VM process has already exited, so health polling can
+/// stop instead of waiting out the full timeout on a panicked kernel. Points at
+/// the console log when the backend keeps one, since that's where the panic is.
+pub(crate) fn check_vm_process(
+    exit: Option<VmExit>,
+    console_log: Option<&std::path::Path>,
+) -> Result<(), VMError> {
+    let Some(exit) = exit else {
+        return Ok(());
+    };
+    let hint = console_log
+        .map(|p| format!(" (guest console: {})", p.display()))
+        .unwrap_or_default();
+    Err(VMError::VmExited(format!("{}{}", exit.description, hint)))
+}
+
+/// Kill and reap a helper process (virtiofsd, wsl.exe), logging its pid and how
+/// it ended for post-mortems (see `crate::teardown`).
+pub(crate) fn kill_logged(name: &str, child: &mut std::process::Child) {
+    let pid = child.id();
+    let killed = child.kill();
+    match child.wait() {
+        Ok(status) => match killed {
+            Ok(()) => teardown_log!("{name} (pid {pid}) killed: {status}"),
+            // Already exited before we got to it.
+            Err(_) => teardown_log!("{name} (pid {pid}) had already exited: {status}"),
+        },
+        Err(e) => teardown_log!("{name} (pid {pid}) kill: {killed:?}, wait failed: {e}"),
+    }
+}
+
+/// How the VM process ended, sent to the frontend as `sandbox-vm-exited`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct VmExit {
+    /// Exit code, if it exited rather than being killed by a signal.
+    pub code: Option<i32>,
+    /// Terminating signal (Unix), e.g. 11 for a segfault, 9 for SIGKILL.
+    pub signal: Option<i32>,
+    /// As the OS puts it, e.g. "signal: 11 (SIGSEGV) (core dumped)".
+    pub description: String,
+}
+
+impl VmExit {
+    /// The process is gone but its status couldn't be read.
+    pub fn unknown(why: String) -> Self {
+        Self {
+            code: None,
+            signal: None,
+            description: format!("exit status unknown: {why}"),
+        }
+    }
 }
 
+impl From<std::process::ExitStatus> for VmExit {
+    fn from(status: std::process::ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+        Self {
+            code: status.code(),
+            signal,
+            description: status.to_string(),
+        }
+    }
+}
+
+/// Most bytes a health probe reads before judging the response. Enough for a
+/// status line behind verbose headers, small enough that a misbehaving listener
+/// can't make a probe buffer much.
+pub const HEALTH_READ_CAP: usize = 8 * 1024;
+
 /// Create a platform-specific VM instance.
 pub fn create_platform_vm() -> Box<dyn VirtualMachine> {
     #[cfg(target_os = "macos")]
@@ -88,7 +359,9 @@ pub fn host_loopback_url(port: &str) -> String {
     format!("http://10.0.2.2:{}", port)
 }
 
-/// Get the name of the current VM backend.
+/// Name of the backend compiled for this platform. A running VM's
+/// [`VirtualMachine::backend_name`] says which hypervisor it actually ended up
+/// on (macOS can fall back from VZ to QEMU).
 pub fn vm_backend_name() -> &'static str {
     #[cfg(target_os = "macos")]
     {
@@ -110,3 +383,79 @@ pub fn vm_backend_name() -> &'static str {
         "unsupported"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Refuses a second start like the real backends, so a restart that
+    /// skipped the stop would fail.
+    #[derive(Default)]
+    struct FakeVM {
+        running: bool,
+        starts: u32,
+    }
+
+    impl VirtualMachine for FakeVM {
+        fn start(&mut self, _config: &VMConfig) -> Result<(), VMError> {
+            if self.running {
+                return Err(VMError::StartFailed("VM is already running".into()));
+            }
+            self.running = true;
+            self.starts += 1;
+            Ok(())
+        }
+        fn stop(&mut self) -> Result<(), VMError> {
+            self.running = false;
+            Ok(())
+        }
+        fn is_running(&self) -> bool {
+            self.running
+        }
+        fn pid(&self) -> Option<u32> {
+            None
+        }
+        fn sandbox_url(&self) -> Option<String> {
+            None
+        }
+        fn wait_for_health(&mut self, _timeout: Duration) -> Result<(), VMError> {
+            Ok(())
+        }
+        fn port_mappings(&self) -> Vec<PortMapping> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn restart_stops_then_starts_again() {
+        let config = VMConfig::new("/img".into(), "/ws".into());
+        let mut vm = FakeVM::default();
+        vm.start(&config).unwrap();
+        vm.restart(&config).unwrap();
+        assert!(vm.is_running());
+        assert_eq!(vm.starts, 2);
+    }
+
+    #[test]
+    fn backends_without_pause_say_so_and_keep_running() {
+        let mut vm = FakeVM::default();
+        vm.start(&VMConfig::new("/img".into(), "/ws".into())).unwrap();
+        assert!(matches!(vm.pause(), Err(VMError::PauseFailed(_))));
+        assert!(vm.is_running());
+    }
+
+    #[test]
+    fn health_result_settles_the_state() {
+        assert_eq!(VmState::after_health(&Ok(())), VmState::Running);
+        let failed = VmState::after_health(&Err(VMError::HealthTimeout(Duration::from_secs(5))));
+        assert!(matches!(failed, VmState::Failed(ref msg) if !msg.is_empty()));
+        assert!(!failed.is_active());
+        assert!(VmState::Paused.is_active());
+        assert_eq!(
+            serde_json::to_value(&failed).unwrap()["state"],
+            serde_json::json!("failed")
+        );
+        assert_eq!(FakeVM::default().state(), VmState::Stopped);
+    }
+
+}
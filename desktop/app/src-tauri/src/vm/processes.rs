@@ -0,0 +1,145 @@
+//! Listing and killing guest processes, so a runaway process in the sandbox can
+//! be found and stopped without rebooting the VM. Both go through the exec
+//! channel (`ps`/`kill` in the guest; procps is in the image).
+
+use super::{ExecChannel, VMError};
+use std::time::Duration;
+
+const PS_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long `kill` waits after SIGTERM before sending SIGKILL, in 100ms steps.
+const TERM_GRACE_STEPS: u32 = 30;
+
+/// One guest process, as `ps` reports it.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct GuestProcess {
+    pub pid: u32,
+    pub ppid: u32,
+    pub user: String,
+    /// Averaged over the process's lifetime (what `ps` reports), so a process
+    /// that has only just started spinning can read low.
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    pub elapsed_secs: u64,
+    /// Full command line; kernel threads show as `[name]`.
+    pub command: String,
+    /// Init, a kernel thread or the sandbox server: [`kill`] refuses it
+    /// unless forced.
+    pub protected: bool,
+}
+
+/// Guest processes, busiest first. The `ps` doing the listing is left out.
+pub fn list(channel: &ExecChannel) -> Result<Vec<GuestProcess>, VMError> {
+    // `exec` keeps the shell's pid, so the first line names the ps to skip.
+    let out = channel.exec(
+        "echo $$; exec ps -eo pid=,ppid=,user=,pcpu=,rss=,etimes=,args=",
+        PS_TIMEOUT,
+    )?;
+    if out.exit_code != 0 {
+        return Err(VMError::CommandFailed(format!(
+            "ps exited with {}: {}",
+            out.exit_code,
+            out.stderr.trim()
+        )));
+    }
+    let mut lines = out.stdout.lines();
+    let own_pid = lines.next().and_then(|l| l.trim().parse::<u32>().ok());
+    let mut processes: Vec<GuestProcess> = lines
+        .filter_map(parse_ps_line)
+        .filter(|p| Some(p.pid) != own_pid)
+        .collect();
+    processes.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent));
+    Ok(processes)
+}
+
+/// Stop guest process `pid`: SIGTERM, then SIGKILL if it is still there after
+/// a few seconds. Protected processes (see [`GuestProcess::protected`]) are
+/// refused unless `force`.
+pub fn kill(channel: &ExecChannel, pid: u32, force: bool) -> Result<(), VMError> {
+    if pid == 0 {
+        return Err(VMError::CommandFailed("pid 0 is not a process".into()));
+    }
+    let process = list(channel)?
+        .into_iter()
+        .find(|p| p.pid == pid)
+        .ok_or_else(|| VMError::CommandFailed(format!("no guest process {pid}")))?;
+    if process.protected && !force {
+        return Err(VMError::CommandFailed(format!(
+            "guest process {pid} ({}) is protected; pass force to kill it anyway",
+            process.command
+        )));
+    }
+    let cmd = format!(
+        "kill -TERM {pid} || exit 1; i=0; \
+         while kill -0 {pid} 2>/dev/null && [ $i -lt {TERM_GRACE_STEPS} ]; do \
+         sleep 0.1; i=$((i+1)); done; \
+         if kill -0 {pid} 2>/dev/null; then kill -KILL {pid}; fi"
+    );
+    let out = channel.exec(&cmd, PS_TIMEOUT)?;
+    if out.exit_code != 0 {
+        return Err(VMError::CommandFailed(format!(
+            "killing guest process {pid} failed: {}",
+            out.stderr.trim()
+        )));
+    }
+    eprintln!(
+        "[vm] killed guest process {} ({}){}",
+        pid,
+        process.command,
+        if force { " (forced)" } else { "" }
+    );
+    Ok(())
+}
+
+/// One `pid ppid user %cpu rss(KiB) etimes args...` line.
+fn parse_ps_line(line: &str) -> Option<GuestProcess> {
+    let mut fields = line.split_whitespace();
+    let pid: u32 = fields.next()?.parse().ok()?;
+    let ppid: u32 = fields.next()?.parse().ok()?;
+    let user = fields.next()?.to_string();
+    let cpu_percent = fields.next()?.parse().ok()?;
+    let rss_kib: u64 = fields.next()?.parse().ok()?;
+    let elapsed_secs = fields.next()?.parse().ok()?;
+    let command = fields.collect::<Vec<_>>().join(" ");
+    // pid 2 is kthreadd, the parent of every kernel thread.
+    let kernel_thread = pid == 2 || ppid == 2;
+    let program = command.split_whitespace().next().unwrap_or("");
+    let server = program.rsplit('/').next() == Some("orcabot-server");
+    Some(GuestProcess {
+        pid,
+        ppid,
+        user,
+        cpu_percent,
+        rss_bytes: rss_kib * 1024,
+        elapsed_secs,
+        protected: pid == 1 || kernel_thread || server,
+        command,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ps_lines_parse_and_flag_protected_processes() {
+        let p = parse_ps_line("  4242  310 sandbox  97.5 204800   61 node /work/spin.js --forever")
+            .unwrap();
+        assert_eq!((p.pid, p.ppid, p.user.as_str()), (4242, 310, "sandbox"));
+        assert_eq!(p.cpu_percent, 97.5);
+        assert_eq!(p.rss_bytes, 200 * 1024 * 1024);
+        assert_eq!(p.command, "node /work/spin.js --forever");
+        assert!(!p.protected);
+
+        for line in [
+            "1 0 root 0.0 9000 500 /sbin/init",
+            "2 0 root 0.0 0 500 [kthreadd]",
+            "57 2 root 0.1 0 500 [kworker/0:1-events]",
+            "310 1 sandbox 1.2 40000 490 /usr/local/bin/orcabot-server",
+        ] {
+            assert!(parse_ps_line(line).unwrap().protected, "{line}");
+        }
+        assert!(parse_ps_line("").is_none());
+        assert!(parse_ps_line("PID PPID USER").is_none());
+    }
+}
@@ -0,0 +1,196 @@
+//! One-time guest provisioning (`VMConfig::provision_script`).
+//!
+//! After the sandbox is healthy, a host script is copied into the guest over the
+//! exec channel and run once as root — which is why `VMConfig::validate`
+//! refuses a script together with `guest_uid` outside WSL: debug-exec would run
+//! it as that user, unable to write [`GUEST_DIR`]. Success drops a marker named after the
+//! script's sha256 into the guest disk, so a later boot of the same disk (overlay)
+//! skips it, while a new disk or an edited script runs again. The script runs
+//! detached in its own session and is polled, since `/debug/exec` calls are
+//! capped at two minutes; its output is streamed to `on_event` as it arrives,
+//! and on timeout the whole session is killed.
+
+use super::{ExecChannel, VMError};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Guest directory holding the script, its log, and the success markers.
+const GUEST_DIR: &str = "/var/lib/orcabot/provision";
+
+/// Provisioning scripts are shell, not payloads; anything bigger is a mistake.
+const MAX_SCRIPT_BYTES: u64 = 1024 * 1024;
+
+/// How long the script may run before provisioning is reported as failed.
+const PROVISION_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Progress of a provisioning run, emitted to the frontend as `vm-provision`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProvisionEvent {
+    pub script: String,
+    pub phase: String, // "skipped" | "running" | "output" | "done" | "error"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ProvisionEvent {
+    fn new(script: &Path, phase: &str) -> Self {
+        Self {
+            script: script.display().to_string(),
+            phase: phase.to_string(),
+            output: None,
+            exit_code: None,
+            error: None,
+        }
+    }
+}
+
+/// Run `script` in the guest unless this disk has already run it successfully.
+/// Errors (unreadable script, exec failure, non-zero exit, timeout) are also
+/// reported through `on_event` as an "error" phase.
+pub fn run_once(
+    channel: &ExecChannel,
+    script: &Path,
+    on_event: &dyn Fn(ProvisionEvent),
+) -> Result<(), VMError> {
+    let result = provision(channel, script, on_event);
+    if let Err(ref e) = result {
+        on_event(ProvisionEvent {
+            error: Some(e.to_string()),
+            ..ProvisionEvent::new(script, "error")
+        });
+    }
+    result
+}
+
+fn provision(
+    channel: &ExecChannel,
+    script: &Path,
+    on_event: &dyn Fn(ProvisionEvent),
+) -> Result<(), VMError> {
+    let size = std::fs::metadata(script)?.len();
+    if size > MAX_SCRIPT_BYTES {
+        return Err(VMError::ProvisionFailed(format!(
+            "provision script {} is {} bytes (max {})",
+            script.display(),
+            size,
+            MAX_SCRIPT_BYTES
+        )));
+    }
+    let bytes = std::fs::read(script)?;
+    let hash: String = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let marker = format!("{}/done-{}", GUEST_DIR, hash);
+
+    if run(channel, &format!("test -f {}", marker))?.exit_code == 0 {
+        eprintln!("[provision] {} already applied to this disk; skipping", script.display());
+        on_event(ProvisionEvent::new(script, "skipped"));
+        return Ok(());
+    }
+
+    run_checked(
+        channel,
+        &format!("mkdir -p {d} && rm -f {d}/exit {d}/log {d}/pid", d = GUEST_DIR),
+    )?;
+    // The exec channel only carries command strings; write_file streams base64.
+    channel.write_file(&format!("{}/script", GUEST_DIR), &bytes, 0o700)?;
+
+    eprintln!("[provision] running {} in the guest", script.display());
+    on_event(ProvisionEvent::new(script, "running"));
+    // setsid from a background job doesn't fork, so `$!` is the session (and
+    // process group) leader that a timeout kills along with everything it started.
+    run_checked(
+        channel,
+        &format!(
+            "cd /root 2>/dev/null; nohup setsid /bin/sh -c \
+             '{d}/script > {d}/log 2>&1; echo $? > {d}/exit' > /dev/null 2>&1 & echo $! > {d}/pid",
+            d = GUEST_DIR
+        ),
+    )?;
+
+    let start = Instant::now();
+    let mut offset = 0u64;
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let exit = run(channel, &format!("cat {}/exit 2>/dev/null", GUEST_DIR))?;
+        // The log's size first, then exactly the bytes up to it: the offset
+        // counts raw bytes, whatever lossy decoding the output goes through.
+        let log = run(
+            channel,
+            &format!(
+                "n=$(wc -c 2>/dev/null < {d}/log || echo 0); echo $n; \
+                 tail -c +{} {d}/log 2>/dev/null | head -c $((n - {}))",
+                offset + 1,
+                offset,
+                d = GUEST_DIR
+            ),
+        )?;
+        let (size, output) = log.stdout.split_once('\n').unwrap_or((&log.stdout, ""));
+        if let Ok(size) = size.trim().parse::<u64>() {
+            offset = offset.max(size);
+        }
+        if !output.is_empty() {
+            on_event(ProvisionEvent {
+                output: Some(output.to_string()),
+                ..ProvisionEvent::new(script, "output")
+            });
+        }
+        if let Ok(code) = exit.stdout.trim().parse::<i32>() {
+            if code != 0 {
+                return Err(VMError::ProvisionFailed(format!(
+                    "provision script {} exited with status {} (log: {}/log in the guest)",
+                    script.display(),
+                    code,
+                    GUEST_DIR
+                )));
+            }
+            run_checked(channel, &format!("touch {}", marker))?;
+            eprintln!("[provision] {} completed", script.display());
+            on_event(ProvisionEvent {
+                exit_code: Some(0),
+                ..ProvisionEvent::new(script, "done")
+            });
+            return Ok(());
+        }
+        if start.elapsed() > PROVISION_TIMEOUT {
+            let kill = format!(
+                "pid=$(cat {d}/pid) && {{ kill -KILL -- -$pid 2>/dev/null || kill -KILL $pid; }}",
+                d = GUEST_DIR
+            );
+            if let Err(e) = run_checked(channel, &kill) {
+                eprintln!("[provision] could not kill {}: {}", script.display(), e);
+            }
+            return Err(VMError::ProvisionFailed(format!(
+                "provision script {} still running after {}s; killed",
+                script.display(),
+                PROVISION_TIMEOUT.as_secs()
+            )));
+        }
+    }
+}
+
+fn run(channel: &ExecChannel, cmd: &str) -> Result<super::ExecOutput, VMError> {
+    channel.exec(cmd, EXEC_TIMEOUT)
+}
+
+fn run_checked(channel: &ExecChannel, cmd: &str) -> Result<(), VMError> {
+    let out = run(channel, cmd)?;
+    if out.exit_code != 0 {
+        return Err(VMError::GuestExec(format!(
+            "command failed ({}): {}",
+            out.exit_code,
+            out.stderr.trim()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! Reaping the VM process on its own thread.
+//!
+//! A thread blocks in `waitid` until the process exits, so its real exit status
+//! is known the moment it dies (for `sandbox-vm-exited`) rather than inferred
+//! from polling with `kill -0`. `WNOWAIT` leaves the process a zombie until the
+//! status is recorded under the lock, and signals are only sent under that lock
+//! while no status is recorded, so a signal can never reach a recycled pid.
+
+use super::VmExit;
+use std::io;
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// A spawned VM process, owned by its reaper thread.
+pub struct ReapedChild {
+    pid: u32,
+    exit: Arc<Mutex<Option<VmExit>>>,
+    reaper: Option<JoinHandle<()>>,
+}
+
+impl ReapedChild {
+    pub fn new(mut child: Child) -> Self {
+        let pid = child.id();
+        let exit = Arc::new(Mutex::new(None));
+        let slot = Arc::clone(&exit);
+        let reaper = std::thread::spawn(move || {
+            wait_without_reaping(pid);
+            let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+            *slot = Some(match child.wait() {
+                Ok(status) => VmExit::from(status),
+                Err(e) => VmExit::unknown(e.to_string()),
+            });
+        });
+        Self {
+            pid,
+            exit,
+            reaper: Some(reaper),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.pid
+    }
+
+    /// How the process ended, once it has.
+    pub fn exit(&self) -> Option<VmExit> {
+        self.exit.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Send `signal` (e.g. `libc::SIGSTOP`); fails once the process has exited.
+    pub fn signal(&self, signal: libc::c_int) -> io::Result<()> {
+        let exit = self.exit.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(exit) = exit.as_ref() {
+            return Err(io::Error::other(format!(
+                "the process has exited ({})",
+                exit.description
+            )));
+        }
+        // SAFETY: `pid` is our child and, with no exit recorded, not yet reaped.
+        if unsafe { libc::kill(self.pid as libc::pid_t, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// SIGKILL the process if it's still running and wait until it's reaped.
+    /// `name` labels the teardown log line (see `crate::teardown`).
+    pub fn kill(&mut self, name: &str) -> Option<VmExit> {
+        let killed = self.signal(libc::SIGKILL);
+        if let Some(reaper) = self.reaper.take() {
+            let _ = reaper.join();
+        }
+        let exit = self.exit();
+        let status = exit.as_ref().map_or("exit status unknown", |e| &e.description);
+        match killed {
+            Ok(()) => teardown_log!("{} (pid {}) killed: {}", name, self.pid, status),
+            Err(_) => teardown_log!("{} (pid {}) had already exited: {}", name, self.pid, status),
+        }
+        exit
+    }
+}
+
+/// Block until `pid` exits, leaving it unreaped.
+fn wait_without_reaping(pid: u32) {
+    loop {
+        // SAFETY: a zeroed siginfo_t is a valid out-parameter for waitid.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let rc = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if rc == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn records_the_exit_code_or_signal() {
+        let child = ReapedChild::new(Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while child.exit().is_none() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let exit = child.exit().unwrap();
+        assert_eq!((exit.code, exit.signal), (Some(3), None));
+        assert!(child.signal(libc::SIGTERM).is_err());
+
+        let mut child = ReapedChild::new(Command::new("sleep").arg("30").spawn().unwrap());
+        assert!(child.exit().is_none());
+        let exit = child.kill("sleep").unwrap();
+        assert_eq!((exit.code, exit.signal), (None, Some(libc::SIGKILL)));
+    }
+}
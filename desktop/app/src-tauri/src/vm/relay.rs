@@ -0,0 +1,204 @@
+//! Host-side relay in front of the sandbox port forward, for the idle shutdown
+//! (`ORCABOT_SANDBOX_IDLE_MINS`).
+//!
+//! The forward itself lives in QEMU / vz-helper, so the app never sees the
+//! control plane's requests to the sandbox, terminal sessions or the CLI's
+//! calls. With an idle window set the VM's forward moves to a private loopback
+//! port and this relay takes the public sandbox port, marking [`Activity`] on
+//! every connection and every chunk it carries. Health probes go to the private
+//! port directly, so they never count as use.
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a new connection waits for the VM to accept it. Covers an idle
+/// wake, where the first request is what boots the VM again.
+pub const BACKEND_WAIT: Duration = Duration::from_secs(90);
+
+/// When the sandbox was last used. Cheap to mark from the relay's copy loops.
+pub struct Activity {
+    origin: Instant,
+    /// Milliseconds after `origin` of the latest use.
+    last_ms: AtomicU64,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+            last_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Activity {
+    /// Record use now.
+    pub fn note(&self) {
+        let now = self.origin.elapsed().as_millis() as u64;
+        self.last_ms.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// Time since the last use.
+    pub fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.origin.elapsed().saturating_sub(last)
+    }
+}
+
+/// Relay every connection `listener` accepts to `backend` on background
+/// threads, for the life of the app. `on_connect` runs for each new connection
+/// before the backend is dialled (the app wakes an idle-stopped VM there).
+pub fn spawn(
+    listener: TcpListener,
+    backend: SocketAddr,
+    activity: Arc<Activity>,
+    on_connect: impl Fn() + Send + Sync + 'static,
+) {
+    let on_connect = Arc::new(on_connect);
+    std::thread::spawn(move || {
+        for client in listener.incoming() {
+            let Ok(client) = client else {
+                continue;
+            };
+            activity.note();
+            on_connect();
+            let activity = Arc::clone(&activity);
+            std::thread::spawn(move || {
+                if let Err(e) = relay(client, backend, &activity) {
+                    eprintln!("[vm] sandbox relay: {}", e);
+                }
+            });
+        }
+    });
+}
+
+/// Dial `backend`, retrying until [`BACKEND_WAIT`] runs out.
+fn connect_backend(backend: SocketAddr) -> std::io::Result<TcpStream> {
+    let deadline = Instant::now() + BACKEND_WAIT;
+    loop {
+        match TcpStream::connect_timeout(&backend, Duration::from_secs(2)) {
+            Ok(stream) => return Ok(stream),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => std::thread::sleep(Duration::from_millis(250)),
+        }
+    }
+}
+
+fn relay(client: TcpStream, backend: SocketAddr, activity: &Arc<Activity>) -> std::io::Result<()> {
+    let server = connect_backend(backend)?;
+    let _ = client.set_nodelay(true);
+    let _ = server.set_nodelay(true);
+    let upstream = {
+        let (from, to) = (client.try_clone()?, server.try_clone()?);
+        let activity = Arc::clone(activity);
+        std::thread::spawn(move || pump(from, to, &activity))
+    };
+    pump(server, client, activity);
+    let _ = upstream.join();
+    Ok(())
+}
+
+/// Copy `from` into `to` until either side closes, marking activity per chunk,
+/// then half-close `to` so the other direction sees the end too.
+fn pump(mut from: TcpStream, mut to: TcpStream, activity: &Activity) {
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        match from.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                activity.note();
+                if to.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    let _ = to.shutdown(Shutdown::Write);
+    let _ = from.shutdown(Shutdown::Read);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// An echo server standing in for the guest sandbox.
+    fn echo_backend() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                std::thread::spawn(move || {
+                    let mut reader = stream.try_clone().unwrap();
+                    let mut writer = stream;
+                    let _ = std::io::copy(&mut reader, &mut writer);
+                });
+            }
+        });
+        addr
+    }
+
+    fn start_relay(backend: SocketAddr) -> (SocketAddr, Arc<Activity>, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let activity = Arc::new(Activity::default());
+        let connects = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&connects);
+        spawn(listener, backend, Arc::clone(&activity), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        (addr, activity, connects)
+    }
+
+    #[test]
+    fn traffic_through_the_relay_keeps_the_sandbox_active() {
+        let (addr, activity, connects) = start_relay(echo_backend());
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        // A long-lived session (a terminal, say): idle between exchanges, and
+        // each exchange resets the clock that `watch_sandbox_idle` reads.
+        let window = Duration::from_millis(400);
+        for round in 0..3 {
+            std::thread::sleep(Duration::from_millis(250));
+            client.write_all(format!("ping {round}\n").as_bytes()).unwrap();
+            let mut reply = [0u8; 7];
+            client.read_exact(&mut reply).unwrap();
+            assert_eq!(&reply, format!("ping {round}\n").as_bytes());
+            assert!(activity.idle_for() < window, "traffic must keep the VM alive");
+        }
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        // Without traffic the window runs out.
+        std::thread::sleep(window);
+        assert!(activity.idle_for() >= window);
+    }
+
+    #[test]
+    fn connection_waits_for_a_waking_backend() {
+        // Reserve a port, then start the backend on it only after the client
+        // connects, as when the first request wakes an idle-stopped VM.
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let backend: SocketAddr = ([127, 0, 0, 1], port).into();
+        let (addr, _activity, connects) = start_relay(backend);
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        let listener = TcpListener::bind(backend).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"ok").unwrap();
+        });
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).unwrap();
+        assert_eq!(reply, b"ok");
+    }
+}
@@ -0,0 +1,195 @@
+//! Host runtime directory for per-boot sockets and scratch files.
+//!
+//! `std::env::temp_dir()` is a poor home for the virtiofsd socket on locked-down
+//! systems: `/tmp` may be noexec, tiny, or invisible to QEMU's sandbox. Resolution
+//! order:
+//!  1. `ORCABOT_RUNTIME_DIR` (explicit override — must be usable, no fallback);
+//!  2. `$XDG_RUNTIME_DIR/orcabot` on Linux when `XDG_RUNTIME_DIR` is set;
+//!  3. the system temp dir (previous behavior).
+
+use super::VMError;
+use std::path::{Path, PathBuf};
+
+/// Minimum free space required in the runtime dir. Sockets are tiny, but a
+/// nearly-full tmpfs makes virtiofsd/QEMU fail in confusing ways later on.
+const MIN_RUNTIME_FREE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Resolve (and create) the runtime directory, validating that it's writable
+/// and has at least `MIN_RUNTIME_FREE_BYTES` available.
+pub fn runtime_dir() -> Result<PathBuf, VMError> {
+    if let Some(dir) = std::env::var_os("ORCABOT_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+        let dir = PathBuf::from(dir);
+        validate_runtime_dir(&dir).map_err(|e| {
+            VMError::StartFailed(format!(
+                "ORCABOT_RUNTIME_DIR={} is not usable: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+        return Ok(dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(xdg) = std::env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+        let dir = PathBuf::from(xdg).join("orcabot");
+        match validate_runtime_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) => eprintln!(
+                "[runtime] XDG_RUNTIME_DIR candidate {} unusable ({}); falling back to temp dir",
+                dir.display(),
+                e
+            ),
+        }
+    }
+
+    let dir = std::env::temp_dir();
+    validate_runtime_dir(&dir).map_err(|e| {
+        VMError::StartFailed(format!(
+            "temp dir {} is not usable for runtime files: {} (set ORCABOT_RUNTIME_DIR)",
+            dir.display(),
+            e
+        ))
+    })?;
+    Ok(dir)
+}
+
+/// Create `dir` (0700 on Unix) and confirm we can write to it and that it has
+/// enough free space.
+fn validate_runtime_dir(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("cannot create: {}", e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // Only tighten dirs we own outright (not a shared /tmp).
+        if dir.file_name().is_some_and(|n| n == "orcabot") {
+            let _ = std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700));
+        }
+    }
+
+    let probe = dir.join(format!(".orcabot-probe-{}", std::process::id()));
+    std::fs::write(&probe, b"").map_err(|e| format!("not writable: {}", e))?;
+    let _ = std::fs::remove_file(&probe);
+
+    if let Ok(avail) = available_bytes(dir) {
+        if avail < MIN_RUNTIME_FREE_BYTES {
+            return Err(format!(
+                "only {} bytes free (need at least {})",
+                avail, MIN_RUNTIME_FREE_BYTES
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Bytes available to an unprivileged user on the filesystem holding `dir`.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // statvfs field widths differ across platforms
+pub fn available_bytes(dir: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+pub fn available_bytes(dir: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+    let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut free_to_caller = 0u64;
+    // SAFETY: `wide` is NUL-terminated; the totals may be null.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free_to_caller,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(free_to_caller)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn available_bytes(_dir: &Path) -> std::io::Result<u64> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "free-space query not implemented on this platform",
+    ))
+}
+
+/// Default spare space [`FreeSpaceWatch`] keeps beyond what is still to be
+/// written; `ORCABOT_MIN_FREE_MB` overrides it.
+const DEFAULT_MIN_FREE_MB: u64 = 512;
+
+/// How often [`FreeSpaceWatch::check`] actually queries the filesystem.
+const FREE_SPACE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Space to leave free after long writes (imports, image staging), in bytes.
+pub fn min_free_bytes() -> u64 {
+    std::env::var("ORCABOT_MIN_FREE_MB")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MIN_FREE_MB)
+        .saturating_mul(1024 * 1024)
+}
+
+/// Periodic free-space check for a long write loop.
+///
+/// An upfront check can't see the disk being filled by something else halfway
+/// through a multi-GB copy; polling lets the loop stop with a clear message
+/// before a write fails with a bare ENOSPC (and leaves the system with no room).
+pub struct FreeSpaceWatch {
+    dir: PathBuf,
+    spare: u64,
+    last_check: Option<std::time::Instant>,
+}
+
+impl FreeSpaceWatch {
+    /// Watch the filesystem holding `dir`, keeping [`min_free_bytes`] spare.
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            spare: min_free_bytes(),
+            last_check: None,
+        }
+    }
+
+    /// Err once free space can't hold the `remaining` bytes still to be written
+    /// (0 when unknown) plus the spare. Cheap to call per file or chunk:
+    /// queries at most every couple of seconds, and passes when the platform
+    /// can't report free space.
+    pub fn check(&mut self, remaining: u64) -> Result<(), String> {
+        if self
+            .last_check
+            .is_some_and(|t| t.elapsed() < FREE_SPACE_CHECK_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_check = Some(std::time::Instant::now());
+        match available_bytes(&self.dir) {
+            Ok(avail) if avail < remaining.saturating_add(self.spare) => Err(format!(
+                "Low disk space: {} MB free on {}, {} MB still to write and {} MB to keep \
+                 spare (set ORCABOT_MIN_FREE_MB to change)",
+                avail / (1024 * 1024),
+                self.dir.display(),
+                remaining.div_ceil(1024 * 1024),
+                self.spare / (1024 * 1024)
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Restart policy for the sandbox VM health watchdog.
+//!
+//! The watchdog (`DesktopServices::watch_sandbox_health`) polls the guest's
+//! `/health` and restarts the VM after several misses in a row. A VM that is
+//! broken for good would otherwise restart in a tight loop, so restarts back off
+//! exponentially up to a cap, stop after a ceiling, and the count resets once
+//! the VM has stayed healthy for a while. `DesktopServices::watch_services`
+//! uses the same [`RestartBackoff`] for crashed host services.
+
+use std::time::{Duration, Instant};
+
+/// Watchdog timing, configured from the environment: `ORCABOT_VM_WATCHDOG=0`
+/// disables it, `ORCABOT_VM_WATCHDOG_MAX_RESTARTS` sets the ceiling (default 5).
+#[derive(Debug, Clone, Copy)]
+pub struct WatchdogPolicy {
+    /// Time between health probes.
+    pub interval: Duration,
+    /// Consecutive failed probes before a restart (a dead VM process restarts
+    /// on the first).
+    pub failures_before_restart: u32,
+    /// Delay before the first restart; doubled for each one after it.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Restarts without a stable period in between before giving up.
+    pub max_attempts: u32,
+    /// Healthy this long and the backoff starts over.
+    pub stable_after: Duration,
+}
+
+impl WatchdogPolicy {
+    pub fn from_env() -> Option<Self> {
+        if std::env::var("ORCABOT_VM_WATCHDOG").is_ok_and(|v| v.trim() == "0") {
+            return None;
+        }
+        let max_attempts = match std::env::var("ORCABOT_VM_WATCHDOG_MAX_RESTARTS") {
+            Ok(v) => v.trim().parse::<u32>().unwrap_or_else(|_| {
+                eprintln!("[vm] ignoring ORCABOT_VM_WATCHDOG_MAX_RESTARTS={v:?} (want a count)");
+                5
+            }),
+            Err(_) => 5,
+        };
+        Some(Self {
+            interval: Duration::from_secs(30),
+            failures_before_restart: 3,
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(5 * 60),
+            max_attempts,
+            stable_after: Duration::from_secs(10 * 60),
+        })
+    }
+}
+
+/// Restart bookkeeping for one watchdog.
+#[derive(Debug, Default)]
+pub struct RestartBackoff {
+    attempts: u32,
+    healthy_since: Option<Instant>,
+}
+
+impl RestartBackoff {
+    /// Note a healthy probe at `now`. Once the VM has been healthy for
+    /// `policy.stable_after`, earlier restarts stop counting.
+    pub fn healthy(&mut self, now: Instant, policy: &WatchdogPolicy) {
+        let since = *self.healthy_since.get_or_insert(now);
+        if self.attempts > 0 && now.duration_since(since) >= policy.stable_after {
+            self.attempts = 0;
+        }
+    }
+
+    /// Note a failed probe: the VM is no longer continuously healthy.
+    pub fn unhealthy(&mut self) {
+        self.healthy_since = None;
+    }
+
+    /// Count a restart and return how long to wait before it, or `None` once
+    /// `policy.max_attempts` restarts have happened without a stable period.
+    pub fn next_restart(&mut self, policy: &WatchdogPolicy) -> Option<Duration> {
+        if self.attempts >= policy.max_attempts {
+            return None;
+        }
+        let delay = policy
+            .base_delay
+            .saturating_mul(1u32 << self.attempts.min(16))
+            .min(policy.max_delay);
+        self.attempts += 1;
+        Some(delay)
+    }
+
+    /// Restarts since the VM was last stable.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> WatchdogPolicy {
+        WatchdogPolicy {
+            interval: Duration::from_secs(30),
+            failures_before_restart: 3,
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(30),
+            max_attempts: 5,
+            stable_after: Duration::from_secs(600),
+        }
+    }
+
+    #[test]
+    fn restarts_back_off_give_up_and_reset_after_stable_period() {
+        let policy = policy();
+        let mut backoff = RestartBackoff::default();
+        let delays: Vec<u64> = std::iter::from_fn(|| backoff.next_restart(&policy))
+            .map(|d| d.as_secs())
+            .collect();
+        assert_eq!(delays, [5, 10, 20, 30, 30]);
+        assert_eq!(backoff.attempts(), 5);
+
+        // Healthy, but not for long enough: still given up.
+        let start = Instant::now();
+        backoff.healthy(start, &policy);
+        backoff.healthy(start + Duration::from_secs(60), &policy);
+        assert_eq!(backoff.next_restart(&policy), None);
+
+        // A failed probe restarts the stable clock; a full stable period resets.
+        backoff.unhealthy();
+        backoff.healthy(start + Duration::from_secs(100), &policy);
+        backoff.healthy(start + Duration::from_secs(650), &policy);
+        assert_eq!(backoff.attempts(), 5);
+        backoff.healthy(start + Duration::from_secs(700), &policy);
+        assert_eq!(backoff.attempts(), 0);
+        assert_eq!(backoff.next_restart(&policy), Some(Duration::from_secs(5)));
+    }
+}
@@ -4,25 +4,65 @@
 //! the sandbox server. WSL2 automatically handles port forwarding
 //! from the guest to localhost on the host.
 
-use super::{VMConfig, VMError, VirtualMachine};
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use super::command::{run_command, TOOL_TIMEOUT};
+use super::{
+    ExecChannel, PortMapping, VMConfig, VMError, VirtualMachine, VmState, WslDistro,
+};
+use std::ffi::OsStr;
 use std::path::Path;
 use std::process::{Child, Command, Stdio};
 use std::time::{Duration, Instant};
 
 const DISTRO_NAME: &str = "orcabot-sandbox";
 
+/// Written into every distro we import, so a same-named distro from somewhere
+/// else is never mistaken for ours.
+const DISTRO_MARKER: &str = "/etc/orcabot-sandbox";
+
+/// `orcabot-sandbox-2` .. `-N` are tried when a foreign distro holds the name.
+const MAX_DISTRO_SUFFIX: u32 = 9;
+
+/// Checking a distro's marker starts it, which takes a few seconds cold.
+const DISTRO_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Names of the installed WSL distros.
+pub fn list_wsl_distros() -> Result<Vec<String>, VMError> {
+    let output = run_command("wsl", &["--list", "--quiet"], TOOL_TIMEOUT)?;
+    // wsl.exe writes UTF-16LE: drop the NULs between ASCII characters and the
+    // (lossily decoded) BOM.
+    Ok(output
+        .stdout
+        .replace(['\0', '\u{feff}', '\u{fffd}'], "")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// The installed distros, with ours flagged.
+pub fn wsl_distros() -> Result<Vec<WslDistro>, VMError> {
+    Ok(list_wsl_distros()?
+        .into_iter()
+        .map(|name| WslDistro {
+            orcabot: name.starts_with(DISTRO_NAME) && WslVM::is_our_distro(&name),
+            name,
+        })
+        .collect())
+}
+
 /// Windows VM using WSL2.
 pub struct WslVM {
     /// Child process handle for the sandbox server
     process: Option<Child>,
     /// Configuration used to start the VM
     config: Option<VMConfig>,
-    /// Whether the VM is currently running
-    running: bool,
+    /// Lifecycle state, as of the last start/health check/pause/stop
+    state: VmState,
     /// Host URL for sandbox access
     sandbox_url: String,
+    /// The distro in use: `DISTRO_NAME`, or a suffixed name if that one is taken.
+    distro: String,
 }
 
 impl WslVM {
@@ -30,54 +70,114 @@ impl WslVM {
         Self {
             process: None,
             config: None,
-            running: false,
+            state: VmState::Stopped,
             sandbox_url: "http://127.0.0.1:8080".to_string(),
+            distro: DISTRO_NAME.to_string(),
+        }
+    }
+
+    /// Poll the health endpoint until it answers, the VM exits or `timeout`.
+    fn poll_health(&mut self, timeout: Duration) -> Result<(), VMError> {
+        let start = Instant::now();
+        let addr = format!(
+            "127.0.0.1:{}",
+            self.config
+                .as_ref()
+                .map(|c| c.sandbox_port)
+                .unwrap_or(8080)
+        );
+
+        while start.elapsed() < timeout {
+            let exit = self.process.as_mut().and_then(|c| c.try_wait().ok().flatten());
+            super::check_vm_process(exit.map(super::VmExit::from), None)?;
+            if super::health::probe_sandbox_health(&addr, self.config.as_ref()) {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(500));
         }
+
+        Err(VMError::HealthTimeout(timeout))
     }
 
     /// Check if WSL2 is available on this system.
     fn is_wsl_available() -> bool {
-        Command::new("wsl")
-            .arg("--status")
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
+        run_command("wsl", &["--status"], TOOL_TIMEOUT).is_ok()
     }
 
-    /// Check if our distro is already installed.
-    fn is_distro_installed() -> bool {
-        if let Ok(output) = Command::new("wsl").args(["--list", "--quiet"]).output() {
-            let list = String::from_utf8_lossy(&output.stdout);
-            list.lines().any(|line| line.trim() == DISTRO_NAME)
-        } else {
-            false
+    /// Whether the installed distro `name` is one we imported: it has our marker,
+    /// or (imported before the marker existed) our server binary, in which case
+    /// the marker is added now.
+    fn is_our_distro(name: &str) -> bool {
+        let check = format!(
+            "test -f {m} || {{ test -x /usr/local/bin/orcabot-server && echo {name} > {m}; }}",
+            m = DISTRO_MARKER
+        );
+        run_command(
+            "wsl",
+            &["-d", name, "-u", "root", "--", "/bin/sh", "-c", &check],
+            DISTRO_CHECK_TIMEOUT,
+        )
+        .is_ok()
+    }
+
+    /// The distro to run: the first of `orcabot-sandbox`, `orcabot-sandbox-2`, ...
+    /// that is ours or not installed, and whether it is installed. Errs when
+    /// foreign distros hold every candidate name.
+    fn choose_distro(installed: &[String]) -> Result<(String, bool), VMError> {
+        let candidates = std::iter::once(DISTRO_NAME.to_string())
+            .chain((2..=MAX_DISTRO_SUFFIX).map(|n| format!("{}-{}", DISTRO_NAME, n)));
+        for name in candidates {
+            if !installed.contains(&name) {
+                return Ok((name, false));
+            }
+            if Self::is_our_distro(&name) {
+                return Ok((name, true));
+            }
+            eprintln!(
+                "[vm] WSL distro '{}' exists but wasn't installed by Orcabot; not using it",
+                name
+            );
         }
+        Err(VMError::StartFailed(format!(
+            "WSL distros named '{0}' through '{0}-{1}' all exist and weren't installed by Orcabot; \
+             unregister one (wsl --unregister <name>) to continue",
+            DISTRO_NAME, MAX_DISTRO_SUFFIX
+        )))
     }
 
-    /// Import a rootfs tarball as a WSL2 distro.
-    fn import_distro(tarball_path: &Path, install_dir: &Path) -> Result<(), VMError> {
+    /// Import a rootfs tarball as the WSL2 distro `name` and mark it as ours.
+    fn import_distro(name: &str, tarball_path: &Path, install_dir: &Path) -> Result<(), VMError> {
         // Create install directory
         std::fs::create_dir_all(install_dir)?;
 
-        let output = Command::new("wsl")
-            .args([
-                "--import",
-                DISTRO_NAME,
-                install_dir.to_str().unwrap_or_default(),
-                tarball_path.to_str().unwrap_or_default(),
-                "--version",
-                "2",
-            ])
-            .output()
-            .map_err(|e| VMError::StartFailed(format!("Failed to run wsl --import: {}", e)))?;
-
-        if !output.status.success() {
-            return Err(VMError::StartFailed(format!(
-                "WSL import failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            )));
-        }
-
+        // Unpacking the rootfs can take a while on a slow disk.
+        run_command(
+            "wsl",
+            &[
+                OsStr::new("--import"),
+                OsStr::new(name),
+                install_dir.as_os_str(),
+                tarball_path.as_os_str(),
+                OsStr::new("--version"),
+                OsStr::new("2"),
+            ],
+            Duration::from_secs(600),
+        )?;
+
+        run_command(
+            "wsl",
+            &[
+                "-d",
+                name,
+                "-u",
+                "root",
+                "--",
+                "/bin/sh",
+                "-c",
+                &format!("echo {} > {}", name, DISTRO_MARKER),
+            ],
+            DISTRO_CHECK_TIMEOUT,
+        )?;
         Ok(())
     }
 
@@ -124,13 +224,22 @@ impl WslVM {
         let child = Command::new("wsl")
             .args([
                 "-d",
-                DISTRO_NAME,
+                self.distro.as_str(),
                 "--",
                 "sh",
                 "-c",
                 &format!(
-                    "export {} && /usr/local/bin/orcabot-server",
-                    env_string
+                    "export {} && {}/usr/local/bin/orcabot-server",
+                    env_string,
+                    // No kernel cmdline under WSL: drop privileges right here.
+                    match config.guest_uid {
+                        Some(uid) => format!(
+                            "setpriv --reuid={} --regid={} --clear-groups ",
+                            uid,
+                            config.guest_gid.unwrap_or(uid)
+                        ),
+                        None => String::new(),
+                    }
                 ),
             ])
             .stdout(Stdio::inherit())
@@ -140,7 +249,7 @@ impl WslVM {
 
         self.process = Some(child);
         self.config = Some(config.clone());
-        self.running = true;
+        self.state = VmState::Starting;
         self.sandbox_url = format!("http://127.0.0.1:{}", config.sandbox_port);
 
         Ok(())
@@ -155,8 +264,23 @@ impl Default for WslVM {
 
 impl VirtualMachine for WslVM {
     fn start(&mut self, config: &VMConfig) -> Result<(), VMError> {
-        if self.running {
-            return Err(VMError::StartFailed("VM is already running".into()));
+        match self.state {
+            VmState::Stopped => {}
+            // A failed boot's process may still be around.
+            VmState::Failed(_) => self.stop()?,
+            _ => return Err(VMError::StartFailed("VM is already running".into())),
+        }
+
+        config.validate()?;
+        config.require_local_share("WSL")?;
+
+        if !config.dns_servers.is_empty() {
+            eprintln!(
+                "[vm] dns_servers ignored on WSL (distro uses WSL's generated resolv.conf)"
+            );
+        }
+        if config.cpu_topology.is_some() {
+            eprintln!("[vm] cpu_topology ignored on WSL (the WSL2 VM's CPUs are set in .wslconfig)");
         }
 
         // Check WSL availability
@@ -166,101 +290,144 @@ impl VirtualMachine for WslVM {
             ));
         }
 
-        // Check if distro needs to be installed
-        if !Self::is_distro_installed() {
+        // Reuse our distro, or install one under a free name. A distro that merely
+        // shares our name (installed by something else) is never launched.
+        let (distro, installed) = Self::choose_distro(&list_wsl_distros()?)?;
+        if !installed {
             if !config.image_path.exists() {
                 return Err(VMError::ImageNotFound(config.image_path.clone()));
             }
 
             // Install directory in user's local app data
+            let dir_name = match distro.strip_prefix(DISTRO_NAME) {
+                Some(suffix) if !suffix.is_empty() => format!("wsl{}", suffix),
+                _ => "wsl".to_string(),
+            };
             let install_dir = std::env::var("LOCALAPPDATA")
-                .map(|p| std::path::PathBuf::from(p).join("OrcabotDesktop").join("wsl"))
+                .map(|p| {
+                    std::path::PathBuf::from(p)
+                        .join("OrcabotDesktop")
+                        .join(dir_name)
+                })
                 .map_err(|_| {
                     VMError::StartFailed("Could not determine LOCALAPPDATA path".into())
                 })?;
 
-            eprintln!("Installing WSL2 distro '{}'...", DISTRO_NAME);
-            Self::import_distro(&config.image_path, &install_dir)?;
+            eprintln!("Installing WSL2 distro '{}'...", distro);
+            Self::import_distro(&distro, &config.image_path, &install_dir)?;
+        }
+        self.distro = distro;
+
+        // Killing an orphaned wsl.exe (stale cleanup) leaves its orcabot-server
+        // running inside the distro and holding the sandbox port; start clean.
+        if installed {
+            let _ = run_command("wsl", &["--terminate", &self.distro], TOOL_TIMEOUT);
         }
 
         self.start_sandbox(config)
     }
 
     fn stop(&mut self) -> Result<(), VMError> {
+        if self.process.is_some() {
+            teardown_log!("WslVM::stop: stopping distro {:?} (state {:?})", self.distro, self.state);
+        }
         // Kill the sandbox process
-        if let Some(ref mut child) = self.process {
-            let _ = child.kill();
-            let _ = child.wait();
+        if let Some(mut child) = self.process.take() {
+            super::kill_logged("wsl.exe", &mut child);
         }
 
         // Optionally terminate the WSL distro to free resources
-        let _ = Command::new("wsl")
-            .args(["--terminate", DISTRO_NAME])
-            .output();
+        if let Err(e) = run_command("wsl", &["--terminate", &self.distro], TOOL_TIMEOUT) {
+            teardown_log!("wsl --terminate {}: {}", self.distro, e);
+        }
 
         self.process = None;
-        self.running = false;
+        self.state = VmState::Stopped;
         Ok(())
     }
 
+    /// Restart only the server inside the distro, leaving the distro (and the
+    /// WSL VM under it) up, which is much quicker than `--terminate` + start.
+    fn restart(&mut self, config: &VMConfig) -> Result<(), VMError> {
+        if self.distro.is_empty() || self.process.is_none() {
+            self.stop()?;
+            return self.start(config);
+        }
+        config.validate()?;
+        if let Some(ref mut child) = self.process {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        self.process = None;
+        self.state = VmState::Stopped;
+        // The server outlives its wsl.exe; SIGKILL it so the port is released
+        // before the new one binds.
+        let _ = run_command(
+            "wsl",
+            &["-d", &self.distro, "-u", "root", "--", "pkill", "-KILL", "-x", "orcabot-server"],
+            TOOL_TIMEOUT,
+        );
+        self.start_sandbox(config)
+    }
+
     fn is_running(&self) -> bool {
         if let Some(ref child) = self.process {
             // Check if process is still running via tasklist
-            Command::new("tasklist")
-                .args(["/FI", &format!("PID eq {}", child.id())])
-                .output()
-                .map(|o| {
-                    let output = String::from_utf8_lossy(&o.stdout);
-                    output.contains(&child.id().to_string())
-                })
+            run_command("tasklist", &["/FI", &format!("PID eq {}", child.id())], TOOL_TIMEOUT)
+                .map(|o| o.stdout.contains(&child.id().to_string()))
                 .unwrap_or(false)
         } else {
             false
         }
     }
 
+    fn state(&self) -> VmState {
+        self.state.clone()
+    }
+
     fn pid(&self) -> Option<u32> {
         self.process.as_ref().map(|c| c.id())
     }
 
     fn sandbox_url(&self) -> Option<String> {
-        if self.running {
+        if self.state.is_active() {
             Some(self.sandbox_url.clone())
         } else {
             None
         }
     }
 
-    fn wait_for_health(&self, timeout: Duration) -> Result<(), VMError> {
-        let start = Instant::now();
-        let addr = format!(
-            "127.0.0.1:{}",
-            self.config
-                .as_ref()
-                .map(|c| c.sandbox_port)
-                .unwrap_or(8080)
-        );
-
-        while start.elapsed() < timeout {
-            if let Ok(mut stream) = TcpStream::connect(&addr) {
-                let _ = stream.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n");
-                let mut buf = [0u8; 256];
-                if stream.read(&mut buf).is_ok() {
-                    let response = String::from_utf8_lossy(&buf);
-                    if response.contains("200 OK") || response.contains("ok") {
-                        return Ok(());
-                    }
-                }
-            }
-            std::thread::sleep(Duration::from_millis(500));
+    fn port_mappings(&self) -> Vec<PortMapping> {
+        // The server binds PORT=sandbox_port inside the distro and WSL's localhost
+        // forwarding exposes the same port on the host.
+        match (&self.config, self.state.is_active()) {
+            (Some(config), true) => vec![PortMapping::inbound(
+                config.sandbox_port,
+                config.sandbox_port,
+            )],
+            _ => Vec::new(),
         }
+    }
 
-        Err(VMError::HealthTimeout(timeout))
+    fn exec_channel(&self) -> Option<ExecChannel> {
+        // No console log to read a token from; wsl.exe runs commands directly.
+        self.state.is_active().then(|| ExecChannel::Wsl {
+            distro: self.distro.clone(),
+        })
+    }
+
+    fn wait_for_health(&mut self, timeout: Duration) -> Result<(), VMError> {
+        let result = self.poll_health(timeout);
+        self.state = VmState::after_health(&result);
+        result
     }
 }
 
 impl Drop for WslVM {
     fn drop(&mut self) {
+        if self.process.is_some() {
+            teardown_log!("WslVM dropped while its VM was up; stopping it");
+        }
         let _ = self.stop();
     }
 }
@@ -0,0 +1,255 @@
+//! Where the workspace lives, and whether it is reachable right now.
+//!
+//! The workspace defaults to `<data_dir>/workspace`; `relocate_workspace` can
+//! point it anywhere else (`settings.json` `workspace_dir`), including an
+//! external drive. A drive that has been ejected leaves the path missing, and
+//! recreating it would quietly put a fresh, empty workspace on the boot disk
+//! (or under a stale `/mnt` mount point), so a missing workspace on an
+//! unmounted volume is reported as [`WorkspaceUnavailable`] instead. A
+//! workspace that exists is always available, whatever it is under: a plain
+//! directory or bind mount in `/mnt` is as good as a drive.
+
+// REVISION: workspace-v2-mount-detection
+
+use std::path::{Component, Path, PathBuf};
+
+/// `<data_dir>/workspace`, the location unless the user moved it.
+pub fn default_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("workspace")
+}
+
+/// The configured workspace directory (it may not exist).
+pub fn workspace_dir(data_dir: &Path) -> PathBuf {
+    crate::settings::load(data_dir)
+        .workspace_dir
+        .unwrap_or_else(|| default_dir(data_dir))
+}
+
+/// The workspace directory is missing, usually because the drive it is on has
+/// been disconnected. Its `Display` starts with `WorkspaceUnavailable:` so the
+/// frontend can tell it apart from other command errors and offer to relocate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceUnavailable {
+    pub path: PathBuf,
+    /// The unmounted volume, when the path is on one (see [`volume_root`]).
+    pub volume: Option<PathBuf>,
+}
+
+impl std::fmt::Display for WorkspaceUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.volume {
+            Some(volume) => write!(
+                f,
+                "WorkspaceUnavailable: the workspace ({}) is on {}, which is not connected. \
+                 Reconnect the drive, or move the workspace to another location.",
+                self.path.display(),
+                volume.display()
+            ),
+            None => write!(
+                f,
+                "WorkspaceUnavailable: the workspace directory {} does not exist. \
+                 Restore it, or move the workspace to another location.",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WorkspaceUnavailable {}
+
+/// The mount point of the removable volume `path` is on, by platform
+/// convention: `/Volumes/<name>` (macOS), `/media/<user>/<name>`,
+/// `/run/media/<user>/<name>`, `/media/<name>` or `/mnt/<name>` (Linux). `None`
+/// for anything else, which is taken to be on the boot volume.
+#[cfg(unix)]
+pub fn volume_root(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+    let parts: Vec<&str> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => s.to_str(),
+            _ => None,
+        })
+        .collect();
+    let user = std::env::var("USER").unwrap_or_default();
+    let depth = match parts.as_slice() {
+        ["Volumes", ..] | ["mnt", ..] => 2,
+        ["media", owner, ..] if !user.is_empty() && *owner == user => 3,
+        ["media", ..] => 2,
+        ["run", "media", ..] => 4,
+        _ => return None,
+    };
+    let root: PathBuf = parts.get(..depth)?.iter().collect();
+    Some(Path::new("/").join(root))
+}
+
+/// The drive `path` is on (`D:\`, `\\server\share`), unless it is the system
+/// drive.
+#[cfg(windows)]
+pub fn volume_root(path: &Path) -> Option<PathBuf> {
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return None;
+    };
+    let drive = prefix.as_os_str().to_string_lossy().into_owned();
+    let system = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+    (!drive.eq_ignore_ascii_case(&system)).then(|| PathBuf::from(format!("{drive}\\")))
+}
+
+/// Whether a volume is mounted at `root`. An ejected volume's mount point is
+/// either gone or left behind as an empty directory on the parent filesystem,
+/// so `root` counts as mounted when it is listed as a mount point (a bind mount
+/// shares its parent's device), is on another device than its parent, or isn't
+/// empty — a directory with contents is a plain one, not a stale mount point.
+#[cfg(unix)]
+fn is_mounted(root: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let (Ok(meta), Some(parent)) = (std::fs::metadata(root), root.parent()) else {
+        return false;
+    };
+    listed_mount_point(root)
+        || std::fs::metadata(parent).is_ok_and(|p| p.dev() != meta.dev())
+        || std::fs::read_dir(root).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Whether `/proc/self/mountinfo` has a mount at `root`.
+#[cfg(target_os = "linux")]
+fn listed_mount_point(root: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(info) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return false;
+    };
+    let root = root.as_os_str().as_bytes();
+    info.lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .any(|mount_point| unescape_mount_point(mount_point) == root)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn listed_mount_point(_root: &Path) -> bool {
+    false
+}
+
+/// A mountinfo path field, whose space, tab, newline and backslash bytes are
+/// written as octal escapes (`\040`).
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(field: &str) -> Vec<u8> {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|d| d.iter().all(|b| (b'0'..=b'7').contains(b)));
+        match octal {
+            Some(digits) if bytes[i] == b'\\' => {
+                out.push(
+                    digits
+                        .iter()
+                        .fold(0u8, |n, d| n.wrapping_mul(8) + (d - b'0')),
+                );
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(windows)]
+fn is_mounted(root: &Path) -> bool {
+    root.exists()
+}
+
+/// `Ok` if `path` is an existing directory. Otherwise it is unavailable, and
+/// `volume` names the drive when that is what's missing.
+pub fn check_available(path: &Path) -> Result<(), WorkspaceUnavailable> {
+    if path.is_dir() {
+        return Ok(());
+    }
+    Err(WorkspaceUnavailable {
+        path: path.to_path_buf(),
+        volume: volume_root(path).filter(|v| !is_mounted(v)),
+    })
+}
+
+/// Make sure the workspace directory exists before it is used or mounted:
+/// create it when missing, unless its volume is the thing that's missing.
+pub fn prepare(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        return Ok(());
+    }
+    if let Some(volume) = volume_root(path).filter(|v| !is_mounted(v)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            WorkspaceUnavailable {
+                path: path.to_path_buf(),
+                volume: Some(volume),
+            },
+        ));
+    }
+    std::fs::create_dir_all(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmounted_volumes_are_unavailable_not_recreated() {
+        assert_eq!(
+            volume_root(Path::new("/Volumes/Backup/orcabot/workspace")),
+            Some(PathBuf::from("/Volumes/Backup"))
+        );
+        assert_eq!(
+            volume_root(Path::new("/run/media/sam/USB/ws")),
+            Some(PathBuf::from("/run/media/sam/USB"))
+        );
+        assert_eq!(volume_root(Path::new("/mnt")), None);
+        assert_eq!(volume_root(Path::new("/home/sam/workspace")), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_available(dir.path()).is_ok());
+        let missing = dir.path().join("gone");
+        assert_eq!(check_available(&missing).unwrap_err().volume, None);
+
+        let on_drive = Path::new("/mnt/orcabot-no-such-drive/workspace");
+        let err = check_available(on_drive).unwrap_err();
+        assert_eq!(
+            err.volume,
+            Some(PathBuf::from("/mnt/orcabot-no-such-drive"))
+        );
+        assert!(
+            err.to_string().starts_with("WorkspaceUnavailable:"),
+            "{err}"
+        );
+        assert!(prepare(on_drive).is_err());
+        assert!(!on_drive.exists());
+    }
+
+    #[test]
+    fn only_an_empty_unlisted_directory_is_a_stale_mount_point() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("USB");
+        std::fs::create_dir(&empty).unwrap();
+        assert!(!is_mounted(&empty));
+        // A plain directory in use (the workspace's parent, say) is not one.
+        std::fs::create_dir(empty.join("orcabot")).unwrap();
+        assert!(is_mounted(&empty));
+        assert!(!is_mounted(&dir.path().join("gone")));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mountinfo_paths_are_unescaped() {
+        assert_eq!(unescape_mount_point("/mnt/my\\040drive"), b"/mnt/my drive");
+        assert_eq!(unescape_mount_point("/mnt/a\\134b\\9"), b"/mnt/a\\b\\9");
+        if Path::new("/proc/self/mountinfo").exists() {
+            assert!(listed_mount_point(Path::new("/")));
+        }
+    }
+}
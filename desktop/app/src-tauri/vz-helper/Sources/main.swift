@@ -7,6 +7,7 @@ import Network
 var globalVM: VZVirtualMachine?
 var globalForwarders: [TCPToVsockForwarder] = []
 var globalVsockListeners: [VsockListener] = []
+var globalSignalSources: [DispatchSourceSignal] = []
 var globalConsolePipe: Pipe?
 var globalConsoleLogFile: FileHandle?
 
@@ -41,6 +42,9 @@ struct VZHelper: ParsableCommand {
     @Option(name: .long, help: "Port forward via vsock (hostPort:guestPort)")
     var portForward: [String] = []
 
+    @Option(name: .long, help: "Address the port forwards listen on: 127.0.0.1 (default) or 0.0.0.0 for every interface")
+    var portForwardBind: String = "127.0.0.1"
+
     @Option(name: .long, help: "Reverse forward: guest vsock port to host TCP port (vsockPort:hostPort). Lets the guest reach host loopback services, e.g. the control plane.")
     var reversePortForward: [String] = []
 
@@ -50,6 +54,9 @@ struct VZHelper: ParsableCommand {
     @Flag(name: .long, help: "Disable disk attachment (for debugging)")
     var noDisk: Bool = false
 
+    @Flag(name: .long, help: "Don't attach the virtio entropy device")
+    var noEntropy: Bool = false
+
     @Flag(name: .long, help: "Minimal config: just kernel+initrd+serial (for debugging)")
     var minimal: Bool = false
 
@@ -223,8 +230,8 @@ struct VZHelper: ParsableCommand {
         config.directorySharingDevices = fsDevices
 
         // Entropy device (for /dev/random)
-        if minimal {
-            print("[VZ] Entropy device DISABLED (--minimal flag)")
+        if minimal || noEntropy {
+            print("[VZ] Entropy device DISABLED (\(minimal ? "--minimal" : "--no-entropy") flag)")
             config.entropyDevices = []
         } else {
             config.entropyDevices = [VZVirtioEntropyDeviceConfiguration()]
@@ -322,6 +329,7 @@ struct VZHelper: ParsableCommand {
             let forwarder = TCPToVsockForwarder(
                 hostPort: pf.hostPort,
                 guestVsockPort: pf.guestPort,
+                bindAddress: portForwardBind,
                 vm: vm
             )
             do {
@@ -372,6 +380,32 @@ struct VZHelper: ParsableCommand {
             Foundation.exit(0)
         }
 
+        // SIGUSR1/SIGUSR2 pause/resume the guest in place (the desktop app's
+        // suspend_all/resume_all). SIGSTOP on this process wouldn't freeze it:
+        // the guest runs in Virtualization.framework's own XPC process.
+        for (sig, pause) in [(SIGUSR1, true), (SIGUSR2, false)] {
+            signal(sig, SIG_IGN)
+            let source = DispatchSource.makeSignalSource(signal: sig, queue: .main)
+            source.setEventHandler {
+                let done: (Result<Void, Error>) -> Void = { result in
+                    switch result {
+                    case .success: print("[VZ] VM \(pause ? "paused" : "resumed")")
+                    case .failure(let error): print("[VZ] ERROR: \(pause ? "pause" : "resume") failed: \(error)")
+                    }
+                    fflush(stdout)
+                }
+                if pause {
+                    guard vm.canPause else { print("[VZ] ERROR: VM cannot be paused in state \(vm.state.rawValue)"); fflush(stdout); return }
+                    vm.pause(completionHandler: done)
+                } else {
+                    guard vm.canResume else { print("[VZ] ERROR: VM cannot be resumed in state \(vm.state.rawValue)"); fflush(stdout); return }
+                    vm.resume(completionHandler: done)
+                }
+            }
+            source.resume()
+            globalSignalSources.append(source)
+        }
+
         // Run until VM stops
         print("VM is running. Press Ctrl+C to stop.")
         RunLoop.main.run()
@@ -384,6 +418,7 @@ struct VZHelper: ParsableCommand {
 class TCPToVsockForwarder {
     let hostPort: UInt16
     let guestVsockPort: UInt32
+    let bindAddress: String
     weak var vm: VZVirtualMachine?
     private var listener: NWListener?
     private var connections: [UUID: ConnectionBridge] = [:]
@@ -392,9 +427,10 @@ class TCPToVsockForwarder {
     private static var lastFailureLog: Date = .distantPast
     private static var suppressedFailures: Int = 0
 
-    init(hostPort: UInt16, guestVsockPort: UInt32, vm: VZVirtualMachine) {
+    init(hostPort: UInt16, guestVsockPort: UInt32, bindAddress: String, vm: VZVirtualMachine) {
         self.hostPort = hostPort
         self.guestVsockPort = guestVsockPort
+        self.bindAddress = bindAddress
         self.vm = vm
     }
 
@@ -406,13 +442,15 @@ class TCPToVsockForwarder {
             throw NSError(domain: "TCPForwarder", code: 1, userInfo: [NSLocalizedDescriptionKey: "Invalid port: \(hostPort)"])
         }
 
-        // Bind LOOPBACK only. The forwarder is reached solely by host-local
-        // services (control plane, health checks), never the network, so exposing
-        // it on all interfaces (`*:port`) was needless attack surface. It also
-        // makes the bind match the Rust free-port probe (127.0.0.1): a busy port
-        // is now actually detected, so a dynamic one is chosen instead of the VM
+        // Bind LOOPBACK by default. The forwarder is normally reached only by
+        // host-local services (control plane, health checks), so exposing it on
+        // all interfaces (`*:port`) is needless attack surface unless the user
+        // opted in (--port-forward-bind 0.0.0.0, the app's bind_address setting).
+        // Loopback also matches the Rust free-port probe (127.0.0.1): a busy port
+        // is actually detected, so a dynamic one is chosen instead of the VM
         // colliding on the wildcard.
-        params.requiredLocalEndpoint = NWEndpoint.hostPort(host: .ipv4(.loopback), port: port)
+        let host: NWEndpoint.Host = bindAddress == "0.0.0.0" ? .ipv4(.any) : .ipv4(.loopback)
+        params.requiredLocalEndpoint = NWEndpoint.hostPort(host: host, port: port)
 
         listener = try NWListener(using: params)
 
@@ -4,6 +4,7 @@ import (
 	"database/sql"
 	"encoding/json"
 	"errors"
+	"fmt"
 	"log"
 	"net/http"
 	"os"
@@ -49,6 +50,10 @@ type d1ExecResult struct {
 func main() {
 	dbPath := getenv("D1_SQLITE_PATH", defaultDbPath())
 	debugEnabled := getenv("D1_SHIM_DEBUG", "") != ""
+	if len(os.Args) > 1 && os.Args[1] == "--integrity-check" {
+		quick := len(os.Args) > 2 && os.Args[2] == "--quick"
+		os.Exit(integrityCheck(dbPath, quick))
+	}
 	if err := ensureDir(dbPath); err != nil {
 		log.Fatalf("failed to create db dir: %v", err)
 	}
@@ -393,6 +398,69 @@ func isBusyError(err error) bool {
 	return strings.Contains(msg, "SQLITE_BUSY") || strings.Contains(msg, "database is locked")
 }
 
+// integrityCheck runs PRAGMA integrity_check (PRAGMA quick_check with quick,
+// which skips the index cross-checks and is much faster on a big database) and
+// prints the result. The desktop host runs the quick form before starting the
+// shim so a DB corrupted by a hard kill can be moved aside instead of taking the
+// control plane down, and the full one when a reset was requested.
+// Exit codes: 0 ok (or no database yet), 2 corrupt, 1 could not check.
+func integrityCheck(dbPath string, quick bool) int {
+	if _, err := os.Stat(dbPath); errors.Is(err, os.ErrNotExist) {
+		fmt.Println("ok (no database)")
+		return 0
+	}
+	db, err := sql.Open("sqlite", dbPath)
+	if err != nil {
+		fmt.Println(err)
+		return 1
+	}
+	defer db.Close()
+
+	pragma := "PRAGMA integrity_check"
+	if quick {
+		pragma = "PRAGMA quick_check"
+	}
+	rows, err := db.Query(pragma)
+	if err != nil {
+		fmt.Println(err)
+		return corruptionExit(err)
+	}
+	defer rows.Close()
+	var problems []string
+	for rows.Next() {
+		var line string
+		if err := rows.Scan(&line); err != nil {
+			fmt.Println(err)
+			return corruptionExit(err)
+		}
+		if line != "ok" {
+			problems = append(problems, line)
+		}
+	}
+	if err := rows.Err(); err != nil {
+		fmt.Println(err)
+		return corruptionExit(err)
+	}
+	if len(problems) > 0 {
+		fmt.Println(strings.Join(problems, "\n"))
+		return 2
+	}
+	fmt.Println("ok")
+	return 0
+}
+
+// corruptionExit maps a SQLite error to integrityCheck's exit code: 2 when the
+// file itself is damaged, 1 for anything else (locked, permissions, ...).
+func corruptionExit(err error) int {
+	msg := strings.ToLower(err.Error())
+	for _, marker := range []string{"malformed", "not a database", "corrupt"} {
+		if strings.Contains(msg, marker) {
+			return 2
+		}
+	}
+	return 1
+}
+
 func ensureDir(path string) error {
 	dir := filepath.Dir(path)
 	return os.MkdirAll(dir, 0o755)
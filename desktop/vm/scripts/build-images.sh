@@ -151,7 +151,8 @@ echo "Cleanup complete."
 df -h /mnt/rootfs || true
 
 # =============================================================================
-# Copy ONLY the kernel modules the VM needs (virtio, vsock, ext4, fuse).
+# Copy ONLY the kernel modules the VM needs (virtio, vsock, ext4, fuse, and the
+# NFS/CIFS clients for a network workspace share).
 # The full module tree is ~1.2GB; we only need ~5-10MB.
 # =============================================================================
 KERNEL_VERSION=$(ls /lib/modules 2>/dev/null | sort | tail -1)
@@ -163,7 +164,9 @@ if [ -n "$KERNEL_VERSION" ]; then
   find . -type f \( -name "virtio*" -o -name "vsock*" -o -name "vmw_vsock*" \
          -o -name "ext4*" -o -name "fuse*" -o -name "virtiofs*" \
          -o -name "jbd2*" -o -name "mbcache*" -o -name "crc16*" \
-         -o -name "crc32*" \) | while read f; do
+         -o -name "crc32*" -o -name "nfs*" -o -name "sunrpc*" -o -name "lockd*" \
+         -o -name "grace*" -o -name "auth_rpcgss*" -o -name "cifs*" -o -name "netfs*" \
+         -o -name "fscache*" \) | while read f; do
     mkdir -p "/mnt/rootfs/lib/modules/$KERNEL_VERSION/kernel/$(dirname "$f")"
     cp "$f" "/mnt/rootfs/lib/modules/$KERNEL_VERSION/kernel/$f"
   done
@@ -262,6 +265,22 @@ if [ -n "$NETIF" ]; then
       "$IP_BIN" link set dev "$NETIF" up 2>/dev/null
       if "$DHC_BIN" -1 "$NETIF" > /dev/console 2>&1; then
         echo "[init] DHCP lease acquired on $NETIF (attempt $n)" > /dev/console
+        # Host-configured DNS (VMConfig::dns_servers → orcabot.dns=a,b on the
+        # cmdline) replaces the DHCP-provided resolver.
+        DNS=$(sed -n 's/.*orcabot\.dns=\([^ ]*\).*/\1/p' /proc/cmdline)
+        if [ -n "$DNS" ]; then
+          echo "$DNS" | tr ',' '\n' | sed 's/^/nameserver /' > /etc/resolv.conf
+        fi
+        # Network workspace share (VMConfig::workspace_share → orcabot.share=
+        # nfs:HOST:/PATH or smb://HOST/SHARE), in place of virtiofs/9p. Needs the
+        # link, so it is mounted here rather than with the local share above.
+        SHARE=$(sed -n 's/.*orcabot\.share=\([^ ]*\).*/\1/p' /proc/cmdline)
+        S_UID=$(sed -n 's/.*orcabot\.uid=\([0-9]*\).*/\1/p' /proc/cmdline)
+        S_GID=$(sed -n 's/.*orcabot\.gid=\([0-9]*\).*/\1/p' /proc/cmdline)
+        case "$SHARE" in
+          nfs:*) mount -t nfs -o soft,nolock "${SHARE#nfs:}" /workspace ;;
+          smb:*) mount -t cifs -o "guest,uid=${S_UID:-0},gid=${S_GID:-${S_UID:-0}}" "${SHARE#smb:}" /workspace ;;
+        esac > /dev/console 2>&1 || echo "[init] mounting workspace share $SHARE failed" > /dev/console
         "$IP_BIN" -4 addr show "$NETIF" > /dev/console 2>&1
         "$IP_BIN" route show > /dev/console 2>&1
         cat /etc/resolv.conf > /dev/console 2>&1
@@ -296,8 +315,22 @@ if [ -f /etc/orcabot.env ]; then
   set +a
 fi
 
+# Host-requested server identity (VMConfig::guest_uid/gid → orcabot.uid=N
+# orcabot.gid=N on the cmdline), so workspace files match the host user.
+RUN_AS=""
+G_UID=$(sed -n 's/.*orcabot\.uid=\([0-9]*\).*/\1/p' /proc/cmdline)
+G_GID=$(sed -n 's/.*orcabot\.gid=\([0-9]*\).*/\1/p' /proc/cmdline)
+if [ -n "$G_UID" ]; then
+  if command -v setpriv >/dev/null 2>&1; then
+    RUN_AS="setpriv --reuid=$G_UID --regid=${G_GID:-$G_UID} --clear-groups"
+    echo "[init] running orcabot-server as uid $G_UID gid ${G_GID:-$G_UID}" > /dev/console
+  else
+    echo "[init] orcabot.uid=$G_UID requested but setpriv is missing; running as root" > /dev/console
+  fi
+fi
+
 echo "[init] starting orcabot-server" > /dev/console
-/usr/local/bin/orcabot-server &
+$RUN_AS /usr/local/bin/orcabot-server &
 SERVER_PID=$!
 
 # PID 1 must not exit — wait for the server (primary process).
@@ -439,6 +472,21 @@ if [ -n "$NETIF" ]; then
       # CLI flag — passing one makes it exit immediately with "Unknown command".
       if "$DHC_BIN" -1 "$NETIF" > /dev/console 2>&1; then
         echo "DHCP lease acquired on $NETIF (attempt $n)" > /dev/console
+        # Host-configured DNS (orcabot.dns=a,b on the cmdline; see MININIT).
+        DNS=$(sed -n 's/.*orcabot\.dns=\([^ ]*\).*/\1/p' /proc/cmdline)
+        if [ -n "$DNS" ]; then
+          echo "$DNS" | tr ',' '\n' | sed 's/^/nameserver /' > /etc/resolv.conf
+          echo "Using host-configured DNS: $DNS" > /dev/console
+        fi
+        # Network workspace share (orcabot.share=; see MININIT).
+        SHARE=$(sed -n 's/.*orcabot\.share=\([^ ]*\).*/\1/p' /proc/cmdline)
+        S_UID=$(sed -n 's/.*orcabot\.uid=\([0-9]*\).*/\1/p' /proc/cmdline)
+        S_GID=$(sed -n 's/.*orcabot\.gid=\([0-9]*\).*/\1/p' /proc/cmdline)
+        mkdir -p /workspace
+        case "$SHARE" in
+          nfs:*) mount -t nfs -o soft,nolock "${SHARE#nfs:}" /workspace ;;
+          smb:*) mount -t cifs -o "guest,uid=${S_UID:-0},gid=${S_GID:-${S_UID:-0}}" "${SHARE#smb:}" /workspace ;;
+        esac > /dev/console 2>&1 || echo "Mounting workspace share $SHARE failed" > /dev/console
         "$IP_BIN" -4 addr show "$NETIF" > /dev/console 2>&1
         "$IP_BIN" route show > /dev/console 2>&1
         exit 0
@@ -478,7 +526,15 @@ export INTERNAL_API_TOKEN=${INTERNAL_API_TOKEN:-dev-internal-token}
 # starts orcabot-server via its own path, never this rc.local, so it stays off there.
 export ORCABOT_DEBUG_EXEC=1
 touch /var/log/orcabot.log /var/log/vsock-bridge.log
-/usr/local/bin/orcabot-server >> /var/log/orcabot.log 2>&1 &
+# Host-requested server identity (orcabot.uid=/orcabot.gid=; see MININIT).
+RUN_AS=""
+G_UID=$(sed -n 's/.*orcabot\.uid=\([0-9]*\).*/\1/p' /proc/cmdline)
+G_GID=$(sed -n 's/.*orcabot\.gid=\([0-9]*\).*/\1/p' /proc/cmdline)
+if [ -n "$G_UID" ] && command -v setpriv >/dev/null 2>&1; then
+  RUN_AS="setpriv --reuid=$G_UID --regid=${G_GID:-$G_UID} --clear-groups"
+  echo "Running orcabot-server as uid $G_UID gid ${G_GID:-$G_UID}" > /dev/console
+fi
+$RUN_AS /usr/local/bin/orcabot-server >> /var/log/orcabot.log 2>&1 &
 echo $! > /run/orcabot.pid
 
 # Start vsock-to-TCP bridge
@@ -710,7 +766,9 @@ if [ -d "$KVER_DIR/kernel" ]; then
   find . -type f \( -name "virtio*" -o -name "vsock*" -o -name "vmw_vsock*" \
          -o -name "ext4*" -o -name "fuse*" -o -name "virtiofs*" \
          -o -name "jbd2*" -o -name "mbcache*" -o -name "crc16*" \
-         -o -name "crc32*" \) | while read f; do
+         -o -name "crc32*" -o -name "nfs*" -o -name "sunrpc*" -o -name "lockd*" \
+         -o -name "grace*" -o -name "auth_rpcgss*" -o -name "cifs*" -o -name "netfs*" \
+         -o -name "fscache*" \) | while read f; do
     mkdir -p "/tmp/keep-modules/$KVER/kernel/$(dirname "$f")"
     cp "$f" "/tmp/keep-modules/$KVER/kernel/$f"
   done
@@ -75,6 +75,8 @@ export function ImportProgressBar({
             <span className="truncate">
               {progress?.phase === "scanning"
                 ? "Scanning folder..."
+                : progress?.phase === "verifying"
+                ? "Verifying copied files..."
                 : `Importing: ${progress?.processed ?? 0}/${progress?.total ?? "?"} files`}
             </span>
           </div>
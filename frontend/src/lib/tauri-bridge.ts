@@ -50,6 +50,24 @@ async function getTauriInvoke(): Promise<TauriInvoke | null> {
 export interface WorkspaceInfo {
   path: string;
   exists: boolean;
+  /**
+   * Why the workspace can't be used, e.g. its external drive is disconnected.
+   * Commands that need the workspace fail with this same message, which
+   * starts with "WorkspaceUnavailable:".
+   */
+  unavailable: string | null;
+  /** The disconnected drive, when that is the cause. */
+  volume: string | null;
+}
+
+export interface WorkspaceRelocation {
+  workspace: WorkspaceInfo;
+  previous_path: string;
+  /** Whether the files moved too (false when only repointed). */
+  moved: boolean;
+  /** The sandbox VM is booting again on the new location. */
+  vm_restarting: boolean;
+  errors: string[];
 }
 
 export interface ImportResult {
@@ -57,15 +75,89 @@ export interface ImportResult {
   files_copied: number;
   bytes_copied: number;
   dest_path: string;
+  /**
+   * "path: message". A path that is not valid UTF-8 is shown lossily, followed
+   * by its percent-encoded raw bytes: "caf\uFFFD.txt [raw: caf%E9.txt]".
+   * Sorted, de-duplicated, and capped at 1000; files under a directory that
+   * couldn't be created share one "dir path: message (N files not copied)".
+   */
   errors: string[];
+  /** Distinct errors before the cap. */
+  errors_total: number;
+  /** More errors occurred than `errors` holds. */
+  errors_truncated: boolean;
+  /** Files skipped because their mtime was older than `modifiedSince`. */
+  files_not_modified: number;
+  /** Files the destination already had, left alone (`conflictMode: "skip"`). */
+  files_skipped: number;
+  /** Files and folders left out by `ignorePatterns` (a folder counts once). */
+  files_ignored: number;
+  /**
+   * Case-only path collisions on a case-insensitive destination, with another
+   * source file or an existing destination file ("path (destination has
+   * NAME)"): skipped ("path") or, with `caseCollisions: "rename"`, renamed
+   * ("path -> new path").
+   */
+  collisions: string[];
+  /** The `caseCollisions: "rename"` renames: source path → destination path. */
+  case_renames: Record<string, string>;
+  /** FIFOs, sockets and device nodes in the source, never copied: "path (fifo)". */
+  special_files: string[];
+  /** Whether copies were fsynced (the `durable` option). */
+  durable: boolean;
+  /**
+   * Stopped by `cancelImport`: the counts cover what was copied before it, and
+   * empty directories, `preserveDirTimes` and `verify` were skipped.
+   */
+  cancelled: boolean;
 }
 
 export interface ImportProgress {
   import_id: string;
   processed: number;
   total: number;
+  /** Bytes copied so far, out of the size of every file the scan found. */
+  bytes_processed: number;
+  bytes_total: number;
+  /** Estimated seconds left; only on "copying" events, after the first second. */
+  eta_secs: number | null;
   current_file: string;
-  phase: "scanning" | "copying" | "done" | "error";
+  /** Percent-encoded raw bytes of `current_file` when it is not valid UTF-8. */
+  current_file_raw: string | null;
+  phase: "scanning" | "copying" | "verifying" | "done" | "error" | "cancelled";
+}
+
+export interface ExportResult {
+  export_id: string;
+  files_copied: number;
+  bytes_copied: number;
+  dest_path: string;
+  /** As in `ImportResult.errors`. */
+  errors: string[];
+  errors_total: number;
+  errors_truncated: boolean;
+  /** Symlinks in the exported tree, which are neither followed nor copied. */
+  symlinks_skipped: number;
+  /** FIFOs, sockets and device nodes, never copied: "path (fifo)". */
+  special_files: string[];
+  /**
+   * Stopped by `cancelExport`: the counts cover what was copied before it, and
+   * empty directories were not created.
+   */
+  cancelled: boolean;
+}
+
+export interface ExportProgress {
+  export_id: string;
+  processed: number;
+  total: number;
+  /** As in `ImportProgress`. */
+  bytes_processed: number;
+  bytes_total: number;
+  eta_secs: number | null;
+  current_file: string;
+  current_file_raw: string | null;
+  phase: "scanning" | "copying" | "done" | "error" | "cancelled";
 }
 
 export interface UpdateProgress {
@@ -84,6 +176,28 @@ export async function getWorkspacePath(): Promise<WorkspaceInfo | null> {
   return invoke("get_workspace_path") as Promise<WorkspaceInfo>;
 }
 
+/** Whether a command error means the workspace's drive or folder is missing. */
+export function isWorkspaceUnavailable(error: unknown): boolean {
+  return String(error).startsWith("WorkspaceUnavailable:");
+}
+
+/**
+ * Move the workspace to `newPath` (an absolute folder path), or with
+ * `moveContents: false` just point it there, e.g. when the drive it was on is
+ * gone. The sandbox VM restarts to pick up the new location.
+ */
+export async function relocateWorkspace(
+  newPath: string,
+  moveContents?: boolean
+): Promise<WorkspaceRelocation> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("relocate_workspace", {
+    newPath,
+    moveContents: moveContents ?? null,
+  }) as Promise<WorkspaceRelocation>;
+}
+
 /** The running app version (e.g. "0.5.0"); null on web/Cloudflare builds. */
 export async function getAppVersion(): Promise<string | null> {
   const invoke = await getTauriInvoke();
@@ -231,19 +345,571 @@ export async function revealWorkspace(): Promise<void> {
   }
 }
 
-/** Import a folder (or file) from source_path into the workspace. */
+/**
+ * Import a folder (or file) from source_path into the workspace.
+ * `modifiedSince` (unix seconds) skips files last modified before the cutoff.
+ * `verify` re-checks every copied file's presence and size against its source;
+ * problems are reported in `errors`. `caseCollisions` picks what happens to
+ * files whose paths differ only by case (from each other or from a file
+ * already there) on a case-insensitive destination.
+ * `durable` fsyncs every copy (and the directories it and any new folders sit
+ * in) so a power cut can't lose imported files, at a throughput cost.
+ * `preserveDirTimes` gives imported directories their source's timestamps
+ * instead of the time of the import.
+ * `conflictMode` decides what happens to files the workspace already has:
+ * "overwrite" (the default), "skip", or "rename" to `name (1).ext`.
+ * `ignorePatterns` are gitignore-style globs (`node_modules`, `.git/`, `*.log`)
+ * left out of the import; a trailing "/" matches folders only, and "!" negation
+ * is rejected.
+ */
 export async function importFolder(
   sourcePath: string,
-  destSubpath?: string
+  destSubpath?: string,
+  modifiedSince?: number,
+  verify?: boolean,
+  caseCollisions?: "skip" | "rename",
+  durable?: boolean,
+  preserveDirTimes?: boolean,
+  conflictMode?: "overwrite" | "skip" | "rename",
+  ignorePatterns?: string[]
 ): Promise<ImportResult> {
   const invoke = await getTauriInvoke();
   if (!invoke) throw new Error("Not in desktop mode");
   return invoke("import_folder", {
     sourcePath,
     destSubpath: destSubpath ?? null,
+    modifiedSince: modifiedSince ?? null,
+    verify: verify ?? null,
+    caseCollisions: caseCollisions ?? null,
+    durable: durable ?? null,
+    preserveDirTimes: preserveDirTimes ?? null,
+    conflictMode: conflictMode ?? null,
+    ignorePatterns: ignorePatterns ?? null,
   }) as Promise<ImportResult>;
 }
 
+/**
+ * Copy a file or folder out of the workspace into the host directory
+ * `destPath` (e.g. from a folder picker), as `destPath/<name>`. `sourceSubpath`
+ * is relative to the workspace; "" exports all of it. Existing files are
+ * overwritten; symlinks in the workspace are not followed.
+ */
+export async function exportFolder(
+  sourceSubpath: string,
+  destPath: string
+): Promise<ExportResult> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("export_folder", { sourceSubpath, destPath }) as Promise<ExportResult>;
+}
+
+/** Exports still in flight, with their latest progress. Empty on web. */
+export async function activeExports(): Promise<ExportProgress[]> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return [];
+  return invoke("active_exports") as Promise<ExportProgress[]>;
+}
+
+/**
+ * Cancel an export, as `cancelImport` does an import. The export's promise
+ * resolves with the files copied so far and `cancelled` set.
+ */
+export async function cancelExport(exportId: string, force?: boolean): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  await invoke("cancel_export", { exportId, force: force ?? null });
+}
+
+export interface ImportTreeNode {
+  name: string;
+  is_dir: boolean;
+  /** File size; null for directories. */
+  bytes: number | null;
+  children: ImportTreeNode[];
+}
+
+export interface ImportTreePreview {
+  dest_path: string;
+  /** The source folder (or file) under its own name in the destination. */
+  root: ImportTreeNode;
+  /** Totals for the whole import, including anything past the node cap. */
+  files: number;
+  dirs: number;
+  bytes: number;
+  /** The tree stopped at `maxNodes`; directories are listed before files. */
+  truncated: boolean;
+  /** Files and folders left out by `excludeGlobs` (a folder counts once). */
+  excluded: number;
+  collisions: string[];
+  case_renames: Record<string, string>;
+  special_files: string[];
+}
+
+/**
+ * Preview the tree `importFolder(sourcePath, destSubpath)` would create,
+ * without copying anything. `excludeGlobs` are gitignore-style patterns
+ * (`node_modules/`, `*.log`, `/build`). The tree is capped at `maxNodes`
+ * (default 1000).
+ */
+export async function previewImportTree(
+  sourcePath: string,
+  destSubpath?: string,
+  excludeGlobs?: string[],
+  maxNodes?: number
+): Promise<ImportTreePreview> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("preview_import_tree", {
+    sourcePath,
+    destSubpath: destSubpath ?? null,
+    excludeGlobs: excludeGlobs ?? null,
+    maxNodes: maxNodes ?? null,
+  }) as Promise<ImportTreePreview>;
+}
+
+/**
+ * Imports still in flight, with their latest progress. Used to block a
+ * conflicting import and to warn before quitting mid-import. Empty on web.
+ */
+export async function activeImports(): Promise<ImportProgress[]> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return [];
+  return invoke("active_imports") as Promise<ImportProgress[]>;
+}
+
+/**
+ * Cancel an import. By default the file being copied is finished first, so the
+ * workspace only holds whole files; `force` abandons it too. The import's
+ * promise then resolves with the files copied so far and `cancelled` set,
+ * after a "cancelled" event.
+ */
+export async function cancelImport(importId: string, force?: boolean): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  await invoke("cancel_import", { importId, force: force ?? null });
+}
+
+export interface ClearWorkspacePreview {
+  confirm_token: string;
+  entries: number;
+  bytes: number;
+}
+
+export interface ClearWorkspaceResult {
+  entries_removed: number;
+  bytes_freed: number;
+  trash_path: string | null;
+  errors: string[];
+}
+
+/**
+ * Step one of "start over": what clearWorkspace would remove, plus the
+ * single-use token (valid two minutes) it must be given.
+ */
+export async function prepareClearWorkspace(): Promise<ClearWorkspacePreview> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("prepare_clear_workspace") as Promise<ClearWorkspacePreview>;
+}
+
+/**
+ * Remove all workspace contents (moved to the app's trash when possible).
+ * Rejects while an import is running or if the token doesn't match.
+ */
+export async function clearWorkspace(confirmToken: string): Promise<ClearWorkspaceResult> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("clear_workspace", { confirmToken }) as Promise<ClearWorkspaceResult>;
+}
+
+export interface PortMapping {
+  host_port: number;
+  guest_port: number;
+  protocol: "tcp";
+  direction: "host_to_guest" | "guest_to_host";
+}
+
+export type VmState =
+  | { state: "stopped" | "starting" | "running" | "paused" }
+  | { state: "failed"; error: string };
+
+export interface SandboxStatus {
+  backend: string;
+  running: boolean;
+  /** The backend's lifecycle state; `stopped` while no VM is registered. */
+  vm_state: VmState;
+  sandbox_url: string | null;
+  port_mappings: PortMapping[];
+  /** Frozen by `suspendAll` (the VM is paused, not stopped). */
+  suspended: boolean;
+}
+
+/**
+ * Sandbox VM state, including its host↔guest port forwards as actually
+ * configured (e.g. "localhost:8081 → guest:8080" after a port reallocation).
+ * Null on web.
+ */
+export async function getSandboxStatus(): Promise<SandboxStatus | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_sandbox_status") as Promise<SandboxStatus>;
+}
+
+export interface VmConfigExport {
+  backend: string;
+  app_version: string;
+  /**
+   * The effective `VMConfig` (paths, cpus, memory_bytes, ports, env,
+   * workspace_share, ...). Secret-looking env values read "<redacted>".
+   */
+  config: Record<string, unknown>;
+  port_mappings: PortMapping[];
+}
+
+/**
+ * The config the running sandbox VM was started with, for attaching to bug
+ * reports. Null when no VM is running, or on web.
+ */
+export async function getVmConfig(): Promise<VmConfigExport | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_vm_config") as Promise<VmConfigExport | null>;
+}
+
+export interface ServiceHealth {
+  name: "d1-shim" | "workerd" | "frontend" | "sandbox";
+  healthy: boolean;
+  latency_ms: number;
+}
+
+export interface AggregateHealth {
+  overall: boolean;
+  services: ServiceHealth[];
+}
+
+/**
+ * Probe every local service (d1-shim, control plane, frontend, sandbox VM) in
+ * parallel, for a full-stack status indicator. Takes at most ~2s. Null on web.
+ */
+export async function aggregateHealth(): Promise<AggregateHealth | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("aggregate_health") as Promise<AggregateHealth>;
+}
+
+export interface GuestInfo {
+  kernel: string | null;
+  os_release: string | null;
+  server_revision: string | null;
+}
+
+/**
+ * What's running inside the sandbox VM (kernel, OS, sandbox server revision),
+ * for support. Rejects while the VM isn't running; null on web.
+ */
+export async function getGuestInfo(): Promise<GuestInfo | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_guest_info") as Promise<GuestInfo>;
+}
+
+export interface IoBenchmark {
+  /** Guest filesystem type backing the workspace ("virtiofs", "9p", ...). */
+  share: string | null;
+  guest_path: string;
+  sequential_bytes: number;
+  sequential_write_mib_s: number;
+  sequential_read_mib_s: number;
+  small_files: number;
+  small_file_bytes: number;
+  /** Per-file latencies, in ms. */
+  small_file_create_ms: number;
+  small_file_read_ms: number;
+  small_file_delete_ms: number;
+  exec_overhead_ms: number;
+}
+
+/**
+ * Benchmark the shared workspace from inside the sandbox VM (a few seconds).
+ * Rejects while the VM isn't running; null on web.
+ */
+export async function benchmarkWorkspaceIo(): Promise<IoBenchmark | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("benchmark_workspace_io") as Promise<IoBenchmark>;
+}
+
+export interface GuestProcess {
+  pid: number;
+  ppid: number;
+  user: string;
+  /** Averaged over the process's lifetime, as `ps` reports it. */
+  cpu_percent: number;
+  rss_bytes: number;
+  elapsed_secs: number;
+  command: string;
+  /** Init, a kernel thread or the sandbox server; killing needs `force`. */
+  protected: boolean;
+}
+
+/** Processes in the sandbox guest, busiest first. Null on web. */
+export async function listGuestProcesses(): Promise<GuestProcess[] | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("list_guest_processes") as Promise<GuestProcess[]>;
+}
+
+/**
+ * SIGTERM a guest process, then SIGKILL after a few seconds. Protected
+ * processes are refused unless `force`.
+ */
+export async function killGuestProcess(pid: number, force = false): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("kill_guest_process", { pid, force });
+}
+
+export interface ResourceInfo {
+  /** Resolved resource root, or null if no candidate has a valid layout. */
+  root: string | null;
+  source: "env" | "resource-dir" | "dev" | null;
+  candidates: { source: string; path: string; valid: boolean }[];
+  /** Artifact name (workerd, d1-shim, vm-image, kernel, ...) -> exists. */
+  artifacts: Record<string, boolean>;
+  /** Why each VM resource was (or wasn't) re-staged this run. */
+  staging: StagingDecision[];
+  /**
+   * Why the last VM start skipped the preferred backend, e.g. the macOS app is
+   * signed without the virtualization entitlement and fell back to QEMU.
+   */
+  backend_warning: string | null;
+}
+
+export interface StagingDecision {
+  src: string;
+  dest: string;
+  restage: boolean;
+  reason: "dest missing" | "no stamp" | "source changed" | "up to date";
+  src_mtime_ns: number | null;
+  src_size: number | null;
+  dest_mtime_ns: number | null;
+  dest_size: number | null;
+  /** Source signature (`mtime_ns:size`) recorded at the last stage. */
+  stamp: string | null;
+}
+
+/**
+ * Which resource root the desktop app resolved and which bundled artifacts it
+ * found there, for diagnosing packaging issues. Null on web.
+ */
+export async function getResourceInfo(): Promise<ResourceInfo | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_resource_info") as Promise<ResourceInfo>;
+}
+
+export interface WslDistro {
+  name: string;
+  /** Installed by Orcabot (only checked for `orcabot-sandbox*` names). */
+  orcabot: boolean;
+}
+
+/**
+ * Installed WSL distros, for diagnosing a distro name conflict. Rejects off
+ * Windows; null on web.
+ */
+export async function getWslDistros(): Promise<WslDistro[] | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_wsl_distros") as Promise<WslDistro[]>;
+}
+
+/**
+ * Stage the sandbox VM's resources (downloading the image on first run) without
+ * booting it, so the first sandbox start is quick. `force` restages from
+ * scratch. No-op on web.
+ */
+export async function prewarmResources(force?: boolean): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("prewarm_resources", { force });
+}
+
+/**
+ * Download the VM image from `url`, verify it against `expectedSha256` and stage
+ * it for the next sandbox start. Resumes a partial download left by an earlier
+ * call. Resolves to the staged path; null on web.
+ */
+export async function fetchAndStageImage(
+  url: string,
+  expectedSha256: string
+): Promise<string | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("fetch_and_stage_image", { url, expectedSha256 }) as Promise<string>;
+}
+
+/** Stop an in-flight `fetchAndStageImage`, keeping its partial download. */
+export async function cancelImageFetch(): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("cancel_image_fetch");
+}
+
+/**
+ * Restart the running sandbox VM (e.g. a wedged guest) and wait until it is
+ * healthy again. Rejects if it isn't running or doesn't come back.
+ */
+export async function restartSandboxVm(): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("restart_sandbox_vm");
+}
+
+export interface VmMetrics {
+  /** Share of one host core (a busy multi-vCPU guest can exceed 100). */
+  cpu_percent: number;
+  rss_bytes: number;
+  uptime_secs: number;
+}
+
+/**
+ * CPU, memory and uptime of the sandbox VM process; poll every few seconds.
+ * Rejects while the VM isn't running or where unsupported (WSL). Null on web.
+ */
+export async function getVmMetrics(): Promise<VmMetrics | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_vm_metrics") as Promise<VmMetrics>;
+}
+
+export interface VmBootPhase {
+  /** "bootloader" | "kernel" | "init" | "network" | "server" by default. */
+  phase: string;
+  index: number;
+  total: number;
+  /** Since the VM process started. */
+  elapsed_ms: number;
+  /** The console line that marked the phase. */
+  line: string;
+  /** No later phase for 30s: show "stuck at <phase>". */
+  stalled: boolean;
+}
+
+/** Listen for boot phases read off the VM console while it starts. */
+export async function onVmBootPhase(
+  callback: (phase: VmBootPhase) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<VmBootPhase>("vm-boot-phase", callback);
+}
+
+/** Listen for `prewarmResources` download progress, in bytes. */
+export async function onVmPrewarmProgress(
+  callback: (progress: { done: number; total: number }) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<{ done: number; total: number }>("vm-prewarm-progress", callback);
+}
+
+/** Listen for `fetchAndStageImage` download progress, in bytes. */
+export async function onVmImageFetchProgress(
+  callback: (progress: { done: number; total: number }) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<{ done: number; total: number }>("vm-image-fetch-progress", callback);
+}
+
+/**
+ * Listen for a local VM image being copied or decompressed into place (VM start
+ * and `prewarmResources`), in bytes. `total` is null while decompressing.
+ */
+export async function onVmStagingProgress(
+  callback: (progress: { done: number; total: number | null }) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<{ done: number; total: number | null }>("vm-staging-progress", callback);
+}
+
+export type DataDirCategoryName =
+  | "vm"
+  | "d1"
+  | "durable_objects"
+  | "workspace"
+  | "logs"
+  | "bin";
+
+export interface DataDirCategory {
+  name: DataDirCategoryName;
+  paths: string[];
+  bytes: number;
+  /** Why it can't be pruned right now, e.g. "the sandbox VM is running". */
+  in_use: string | null;
+}
+
+export interface DataDirUsage {
+  data_dir: string;
+  categories: DataDirCategory[];
+  total_bytes: number;
+}
+
+export interface PruneDataDirResult {
+  pruned: DataDirCategoryName[];
+  bytes_freed: number;
+  errors: string[];
+}
+
+/** Size of each app data dir category. Null on web. */
+export async function getDataDirUsage(): Promise<DataDirUsage | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_data_dir_usage") as Promise<DataDirUsage>;
+}
+
+/**
+ * Empty the given data dir categories to reclaim space. Rejects if any of them
+ * is in use. `d1` and `durable_objects` are user data and cannot be recovered.
+ * `workspace` is rejected; empty it with `clearWorkspace` instead. Null on web.
+ */
+export async function pruneDataDir(
+  categories: DataDirCategoryName[]
+): Promise<PruneDataDirResult | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("prune_data_dir", { categories }) as Promise<PruneDataDirResult>;
+}
+
+/**
+ * Mark the sandbox as in use, resetting the idle-shutdown timer. Requests to the
+ * sandbox already count; call this to wake the VM ahead of work. If the VM was
+ * stopped for idleness it boots again and this resolves true. False on web.
+ */
+export async function noteSandboxActivity(): Promise<boolean> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return false;
+  return invoke("note_sandbox_activity") as Promise<boolean>;
+}
+
+/**
+ * Freeze the whole stack (sandbox VM paused, host services stopped) to save
+ * CPU and battery while keeping its state in memory. Not supported on Windows.
+ */
+export async function suspendAll(): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  await invoke("suspend_all");
+}
+
+/** Undo `suspendAll`. On failure the stack stays suspended and it can be retried. */
+export async function resumeAll(): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  await invoke("resume_all");
+}
+
+/** Fires with the new state after `suspendAll` / `resumeAll`. */
+export async function onServicesSuspended(
+  callback: (suspended: boolean) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<boolean>("services-suspended", callback);
+}
+
 /**
  * Switch from the desktop GUI to the CLI surface: opens a terminal running
  * `orcabot cli` (same session) and hides the GUI. Desktop-only; no-op on web.
@@ -436,6 +1102,13 @@ export async function onImportProgress(
   return listenGlobal<ImportProgress>("folder-import-progress", callback);
 }
 
+/** Listen for export progress events emitted from the Rust backend. */
+export async function onExportProgress(
+  callback: (progress: ExportProgress) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<ExportProgress>("folder-export-progress", callback);
+}
+
 export interface CloudWorkspaceProgress {
   cloud_id: string;
   /** "starting" | "booting" | "copying" */
@@ -464,6 +1137,348 @@ export async function onUpdateProgress(
   return listenGlobal<UpdateProgress>("update-progress", callback);
 }
 
+/**
+ * Listen for the sandbox VM being stopped after `ORCABOT_SANDBOX_IDLE_MINS`
+ * without activity. The payload is how long it had been idle, in seconds.
+ */
+export async function onSandboxIdleStopped(
+  callback: (idleSecs: number) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<number>("sandbox-idle-stopped", callback);
+}
+
+export interface SandboxRestartGivingUp {
+  /** Restarts tried since the VM was last stable. */
+  attempts: number;
+  /** Why the last restart was needed, or the error it failed with. */
+  error: string;
+}
+
+/**
+ * Listen for the VM watchdog giving up on an unhealthy sandbox VM after its
+ * restart ceiling. Sandbox features stay unavailable until the app restarts.
+ */
+export async function onSandboxRestartGivingUp(
+  callback: (info: SandboxRestartGivingUp) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<SandboxRestartGivingUp>("sandbox-restart-giving-up", callback);
+}
+
+export interface SandboxVmExited {
+  pid: number;
+  backend: string;
+  /** Exit code, when it exited rather than being killed by a signal. */
+  code: number | null;
+  /** Terminating signal, e.g. 11 for a segfault. Null on WSL. */
+  signal: number | null;
+  /** As the OS describes it, e.g. "signal: 11 (SIGSEGV) (core dumped)". */
+  status: string;
+}
+
+/**
+ * Listen for the sandbox VM process dying on its own. Requests to the sandbox
+ * will fail until the watchdog restarts it.
+ */
+export async function onSandboxVmExited(
+  callback: (info: SandboxVmExited) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<SandboxVmExited>("sandbox-vm-exited", callback);
+}
+
+export interface ServiceRestartGivingUp {
+  /** "d1-shim" | "workerd-frontend" | "workerd" */
+  service: string;
+  attempts: number;
+}
+
+/**
+ * Listen for a crashed host service that won't be restarted again: it kept
+ * exiting through five restarts. The app needs a restart to recover it.
+ */
+export async function onServiceRestartGivingUp(
+  callback: (info: ServiceRestartGivingUp) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<ServiceRestartGivingUp>("service-restart-giving-up", callback);
+}
+
+/**
+ * Read a persisted desktop setting by name (e.g. "custom_vm_image"). Null on web
+ * or when unset.
+ */
+export async function getSetting<T = unknown>(key: string): Promise<T | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_setting", { key }) as Promise<T | null>;
+}
+
+/**
+ * Persist a desktop setting. Rejects for unknown keys or a value of the wrong
+ * type, and for "workspace_dir", "custom_vm_image" and "bind_address", which
+ * have their own commands (`relocateWorkspace`, the `set_custom_vm_image` command,
+ * `setBindAddress`). No-op on web.
+ */
+export async function setSetting(key: string, value: unknown): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("set_setting", { key, value });
+}
+
+/**
+ * Listen on "127.0.0.1" (this machine) or "0.0.0.0" (the network) from the
+ * next launch; null goes back to loopback. Resolves with a warning to show for
+ * "0.0.0.0", else null.
+ */
+export async function setBindAddress(address: string | null): Promise<string | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("set_bind_address", { address }) as Promise<string | null>;
+}
+
+export interface DevFlags {
+  dev_auth: boolean;
+  dev_mode: boolean;
+  /** Services given a flag that weren't running; it applies on their next start. */
+  pending: string[];
+}
+
+/**
+ * Toggle dev-auth (control plane) and/or dev mode (frontend) at runtime. Only
+ * the workerd whose flag is given restarts, and the flag is saved to settings
+ * once it applied (a failed restart leaves settings as they were). Resolves
+ * with the flags now in effect. Null on web.
+ */
+export async function setDevFlags(flags: {
+  devAuth?: boolean;
+  devMode?: boolean;
+}): Promise<DevFlags | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("set_dev_flags", {
+    devAuth: flags.devAuth ?? null,
+    devMode: flags.devMode ?? null,
+  }) as Promise<DevFlags>;
+}
+
+/**
+ * Force Linux QEMU's acceleration: "kvm" (fail to start without KVM), "tcg"
+ * (software emulation) or "auto". Applies from the next VM start.
+ */
+export async function setQemuAccel(mode: "auto" | "kvm" | "tcg"): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("set_qemu_accel", { mode });
+}
+
+/**
+ * Set the QEMU vCPU layout as [sockets, cores, threads]; the VM gets their
+ * product as its vCPU count. null goes back to a flat count. Applies from the
+ * next VM start; ignored on VZ and WSL.
+ */
+export async function setVmCpuTopology(
+  topology: [number, number, number] | null,
+): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("set_vm_cpu_topology", { topology });
+}
+
+/**
+ * Start over with an empty local control-plane database (the old one is kept as
+ * a backup in the app data dir). Restarts the desktop app. No-op on web.
+ */
+export async function resetControlplaneDb(): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("reset_controlplane_db");
+}
+
+/**
+ * Copy a host file into the running sandbox VM at an absolute guest path
+ * (replacing any file there). Resolves to the bytes written; null on web.
+ */
+export async function putSandboxFile(
+  hostPath: string,
+  guestPath: string
+): Promise<number | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("put_sandbox_file", { hostPath, guestPath }) as Promise<number>;
+}
+
+export interface SandboxExecLine {
+  stream: "stdout" | "stderr";
+  /** One output line, without its trailing newline. */
+  line: string;
+}
+
+/**
+ * Run `argv` (no shell) in the sandbox VM, calling `onLine` for each stdout /
+ * stderr line as it's produced. Resolves with the exit code once the command
+ * finishes. Rejects if the VM isn't running, or if the channel stops being
+ * deliverable (e.g. the page is torn down) — the guest command is killed then.
+ * Pass an `execId` to be able to stop it with `cancelSandboxExec`, which works
+ * even while the command prints nothing. Null when not in desktop mode.
+ */
+export async function sandboxExecStreaming(
+  argv: string[],
+  onLine: (line: SandboxExecLine) => void,
+  execId?: string
+): Promise<number | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  let ChannelCtor = (window as any).__TAURI__?.core?.Channel;
+  if (typeof ChannelCtor !== "function") {
+    try {
+      ChannelCtor = (await import(/* webpackIgnore: true */ TAURI_CORE)).Channel;
+    } catch {
+      return null;
+    }
+  }
+  const onOutput = new ChannelCtor();
+  onOutput.onmessage = onLine;
+  return invoke("sandbox_exec_streaming", { argv, onOutput, execId }) as Promise<number>;
+}
+
+/** Stop the `sandboxExecStreaming` run started with `execId`; it then rejects. */
+export async function cancelSandboxExec(execId: string): Promise<void> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return;
+  await invoke("cancel_sandbox_exec", { execId });
+}
+
+export interface GuestClockSkew {
+  /** Guest time minus host time; positive = guest ahead. */
+  skew_secs: number;
+  threshold_secs: number;
+  /** Whether the guest clock was reset to host time (ORCABOT_CLOCK_AUTOSYNC=1). */
+  synced: boolean;
+}
+
+/** Fired when the sandbox VM's clock has drifted past the skew threshold. */
+export async function onGuestClockSkew(
+  callback: (skew: GuestClockSkew) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<GuestClockSkew>("guest-clock-skew", callback);
+}
+
+export interface StartupPhase {
+  phase:
+    | "staging"
+    | "db_check"
+    | "d1_shim_start"
+    | "frontend_health"
+    | "workerd_health"
+    | "vm_staging"
+    | "vm_boot"
+    | "vm_health"
+    | "vm_prewarm"
+    | "vm_restart"
+    | "vm_image_fetch";
+  /** When the phase began, in ms since the app launched. */
+  started_ms: number;
+  duration_ms: number;
+  ok: boolean;
+}
+
+/** Startup phases completed so far, in order. Empty on web. */
+export async function getStartupTimings(): Promise<StartupPhase[]> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return [];
+  return invoke("get_startup_timings") as Promise<StartupPhase[]>;
+}
+
+/** Fired as each startup phase finishes, for a detailed loading screen. */
+export async function onStartupPhase(
+  callback: (phase: StartupPhase) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<StartupPhase>("startup-phase", callback);
+}
+
+export interface BootArtifactCheck {
+  path: string;
+  bytes: number;
+  /** Header kind: "elf", "bzimage", "arm64-image", "gzip", "cpio", "raw", ... */
+  kind: string;
+  arch: string | null;
+  errors: string[];
+  warnings: string[];
+}
+
+export interface BootSetReport {
+  kernel: BootArtifactCheck;
+  initrd: BootArtifactCheck;
+  image: BootArtifactCheck;
+  /** Architecture mismatches between the files or with this host. */
+  errors: string[];
+  ok: boolean;
+}
+
+/**
+ * Check a custom kernel, initrd and disk image together (headers, formats,
+ * architecture agreement) before booting them. Changes nothing.
+ */
+export async function validateBootSet(
+  kernel: string,
+  initrd: string,
+  image: string
+): Promise<BootSetReport> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("validate_boot_set", { kernel, initrd, image }) as Promise<BootSetReport>;
+}
+
+export interface SupportedFormats {
+  /** Compressions a custom image may use (staging decompresses them). */
+  image_compression: string[];
+  /** Disk-image formats bootable as-is ("raw", "qcow2"; "tar" on WSL). */
+  image_formats: string[];
+  /** Targets `convertVmImage` can write; empty without qemu-img. */
+  convert_to: string[];
+  kernel_compression: string[];
+  boot_modes: ("vz-direct-kernel" | "qemu-direct-kernel" | "qemu-disk" | "wsl-import")[];
+  qemu_img: boolean;
+  /** Why something is missing, e.g. how to install qemu-img. */
+  notes: string[];
+}
+
+/**
+ * What this build can do with custom VM images given the installed tooling,
+ * for validating a file before it's picked. Null on web.
+ */
+export async function getSupportedFormats(): Promise<SupportedFormats | null> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) return null;
+  return invoke("get_supported_formats") as Promise<SupportedFormats>;
+}
+
+export interface ConvertedVmImage {
+  path: string;
+  format: "raw" | "qcow2";
+  bytes: number;
+}
+
+/**
+ * Convert a VM disk image between raw and qcow2 with qemu-img. Rejects if the
+ * destination is an image the app may be using.
+ */
+export async function convertVmImage(
+  src: string,
+  dst: string,
+  format: "raw" | "qcow2"
+): Promise<ConvertedVmImage> {
+  const invoke = await getTauriInvoke();
+  if (!invoke) throw new Error("Not in desktop mode");
+  return invoke("convert_vm_image", { src, dst, format }) as Promise<ConvertedVmImage>;
+}
+
+/** Listen for `convertVmImage` progress (0-100). */
+export async function onVmImageConvertProgress(
+  callback: (progress: { dst: string; percent: number }) => void
+): Promise<(() => void) | null> {
+  return listenGlobal<{ dst: string; percent: number }>("vm-image-convert-progress", callback);
+}
+
 /** Listen for native drag-drop events on the Tauri webview. */
 export async function onDragDrop(
   callback: (event: {
@@ -1,12 +1,13 @@
 // Copyright 2026 Rob Macrae. All rights reserved.
 // SPDX-License-Identifier: LicenseRef-Proprietary
 
-// REVISION: main-v40-import-gzip-bomb-cap
+// REVISION: main-v41-debug-exec-stream
 
 package main
 
 import (
 	"archive/tar"
+	"bufio"
 	"bytes"
 	"compress/gzip"
 	"context"
@@ -40,7 +41,7 @@ import (
 	"github.com/Hyper-Int/OrcaBot/sandbox/internal/ws"
 )
 
-const mainRevision = "main-v40-import-gzip-bomb-cap"
+const mainRevision = "main-v41-debug-exec-stream"
 
 // debugExecToken is a random per-boot secret guarding POST /debug/exec. Generated
 // once at startup when ORCABOT_DEBUG_EXEC=1 and delivered to the host ONLY via the
@@ -104,17 +105,7 @@ func init() {
 // egress-enabled config), it would otherwise be a privilege-escalation path. The
 // token is checked first thing in the handler, constant-time.
 func (s *Server) handleDebugExec(w http.ResponseWriter, r *http.Request) {
-	if os.Getenv("ORCABOT_DEBUG_EXEC") != "1" {
-		http.Error(w, "debug exec disabled (set ORCABOT_DEBUG_EXEC=1)", http.StatusForbidden)
-		return
-	}
-	// Auth via the random per-boot token (X-Debug-Exec-Token), NOT the internal
-	// token — on desktop the internal token is a guessable dev constant. The
-	// per-boot token is delivered only over the root-only VM console, so a non-root
-	// agent can neither read nor guess it. Constant-time compare; empty = reject.
-	if debugExecToken == "" ||
-		subtle.ConstantTimeCompare([]byte(r.Header.Get("X-Debug-Exec-Token")), []byte(debugExecToken)) != 1 {
-		http.Error(w, "forbidden: missing or invalid X-Debug-Exec-Token", http.StatusForbidden)
+	if !debugExecAllowed(w, r) {
 		return
 	}
 	var req struct {
@@ -159,6 +150,139 @@ func (s *Server) handleDebugExec(w http.ResponseWriter, r *http.Request) {
 	})
 }
 
+// debugExecAllowed applies both /debug/exec gates and writes the 403 itself when
+// either fails.
+func debugExecAllowed(w http.ResponseWriter, r *http.Request) bool {
+	if os.Getenv("ORCABOT_DEBUG_EXEC") != "1" {
+		http.Error(w, "debug exec disabled (set ORCABOT_DEBUG_EXEC=1)", http.StatusForbidden)
+		return false
+	}
+	// Auth via the random per-boot token (X-Debug-Exec-Token), NOT the internal
+	// token — on desktop the internal token is a guessable dev constant. The
+	// per-boot token is delivered only over the root-only VM console, so a non-root
+	// agent can neither read nor guess it. Constant-time compare; empty = reject.
+	if debugExecToken == "" ||
+		subtle.ConstantTimeCompare([]byte(r.Header.Get("X-Debug-Exec-Token")), []byte(debugExecToken)) != 1 {
+		http.Error(w, "forbidden: missing or invalid X-Debug-Exec-Token", http.StatusForbidden)
+		return false
+	}
+	return true
+}
+
+// maxStreamLineBytes bounds one output line of /debug/exec/stream; anything past
+// it on the same line is dropped rather than buffered.
+const maxStreamLineBytes = 1024 * 1024
+
+// handleDebugExecStream is the streaming form of /debug/exec: it runs argv
+// (no shell) and writes NDJSON as output is produced — one
+// {"stream":"stdout"|"stderr","line":"..."} per line, then a final
+// {"exit_code":N,"timed_out":bool}. There is no default timeout (timeout_ms is
+// optional); the command lives as long as the client stays connected, and a
+// disconnect kills its whole process group. Same gates as /debug/exec.
+func (s *Server) handleDebugExecStream(w http.ResponseWriter, r *http.Request) {
+	if !debugExecAllowed(w, r) {
+		return
+	}
+	var req struct {
+		Argv      []string `json:"argv"`
+		TimeoutMs int      `json:"timeout_ms"`
+	}
+	if err := json.NewDecoder(r.Body).Decode(&req); err != nil || len(req.Argv) == 0 || req.Argv[0] == "" {
+		http.Error(w, "bad request: need JSON {\"argv\": [\"...\"]}", http.StatusBadRequest)
+		return
+	}
+	ctx, cancel := context.WithCancel(r.Context())
+	defer cancel()
+	if req.TimeoutMs > 0 {
+		ctx, cancel = context.WithTimeout(ctx, time.Duration(req.TimeoutMs)*time.Millisecond)
+		defer cancel()
+	}
+
+	cmd := exec.CommandContext(ctx, req.Argv[0], req.Argv[1:]...)
+	// Own process group, so cancelling takes down anything the command spawned
+	// too — otherwise a grandchild holding the pipes keeps Wait blocked.
+	cmd.SysProcAttr = &syscall.SysProcAttr{Setpgid: true}
+	cmd.Cancel = func() error { return syscall.Kill(-cmd.Process.Pid, syscall.SIGKILL) }
+	cmd.WaitDelay = 2 * time.Second
+	stdout, err := cmd.StdoutPipe()
+	if err != nil {
+		http.Error(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	stderr, err := cmd.StderrPipe()
+	if err != nil {
+		http.Error(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	if err := cmd.Start(); err != nil {
+		http.Error(w, "start failed: "+err.Error(), http.StatusBadRequest)
+		return
+	}
+
+	type outLine struct {
+		Stream string `json:"stream"`
+		Line   string `json:"line"`
+	}
+	lines := make(chan outLine, 64)
+	var readers sync.WaitGroup
+	scan := func(name string, pipe io.Reader) {
+		defer readers.Done()
+		sc := bufio.NewScanner(pipe)
+		sc.Buffer(make([]byte, 64*1024), maxStreamLineBytes)
+		for sc.Scan() {
+			select {
+			case lines <- outLine{Stream: name, Line: sc.Text()}:
+			case <-ctx.Done():
+			}
+		}
+		// Keep draining after an over-long line so the command never blocks on a
+		// full pipe.
+		_, _ = io.Copy(io.Discard, pipe)
+	}
+	readers.Add(2)
+	go scan("stdout", stdout)
+	go scan("stderr", stderr)
+	go func() {
+		readers.Wait()
+		close(lines)
+	}()
+
+	w.Header().Set("Content-Type", "application/x-ndjson")
+	w.WriteHeader(http.StatusOK)
+	flusher, _ := w.(http.Flusher)
+	enc := json.NewEncoder(w)
+	for line := range lines {
+		if ctx.Err() != nil {
+			continue
+		}
+		if err := enc.Encode(line); err != nil {
+			// Client went away: kill the command, keep draining until the pipes close.
+			cancel()
+			continue
+		}
+		if flusher != nil {
+			flusher.Flush()
+		}
+	}
+
+	exitCode := 0
+	if runErr := cmd.Wait(); runErr != nil {
+		var ee *exec.ExitError
+		if errors.As(runErr, &ee) {
+			exitCode = ee.ExitCode()
+		} else {
+			exitCode = -1
+		}
+	}
+	_ = enc.Encode(map[string]any{
+		"exit_code": exitCode,
+		"timed_out": ctx.Err() == context.DeadlineExceeded,
+	})
+	if flusher != nil {
+		flusher.Flush()
+	}
+}
+
 // initDebugExecToken mints the random per-boot secret for POST /debug/exec and
 // delivers it to the host out of band, where no in-guest agent can read it.
 func initDebugExecToken() {
@@ -609,6 +733,7 @@ func (s *Server) Handler() http.Handler {
 	// token checked inside the handler, so it is absent in production and
 	// unreachable by in-guest agents. No requireMachine: desktop has no Fly machine id.
 	mux.HandleFunc("POST /debug/exec", s.handleDebugExec)
+	mux.HandleFunc("POST /debug/exec/stream", s.handleDebugExecStream)
 
 	// Debug profiling - machine routing first, then auth
 	mux.HandleFunc("GET /debug/pprof/", s.requireMachine(s.auth.RequireAuthFunc(pprof.Index)))